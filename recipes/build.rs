@@ -22,6 +22,12 @@
 
 use std::env;
 use std::error::Error;
+use std::fs;
+use std::fs::File;
+use std::path::Path;
+
+/// The images bundled into the kernel, which are converted into raw RGBA pixels at build time.
+const IMAGES: [(&str, &str, u32); 1] = [("COVER", "static/cover.png", 480)];
 
 #[cfg(target_arch = "x86_64")]
 fn bake_configurations() -> Result<(), Box<dyn Error>> {
@@ -37,12 +43,93 @@ fn bake_configurations() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+/// Decodes a PNG image into RGBA pixels with 8 bits per channel.
+fn decode_png(path: &str) -> Result<(u32, u32, Vec<u8>), Box<dyn Error>> {
+    let mut decoder = png::Decoder::new(File::open(path)?);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+    let buf = &buf[..info.buffer_size()];
+
+    let pixels = match info.color_type {
+        png::ColorType::Rgba => buf.to_vec(),
+        png::ColorType::Rgb => buf.chunks(3).flat_map(|p| [p[0], p[1], p[2], 0xFF]).collect(),
+        png::ColorType::GrayscaleAlpha => buf.chunks(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+        png::ColorType::Grayscale => buf.iter().flat_map(|p| [*p, *p, *p, 0xFF]).collect(),
+        png::ColorType::Indexed => return Err(format!("{}: palette has not been expanded", path).into()),
+    };
+
+    Ok((info.width, info.height, pixels))
+}
+
+/// Shrinks an image to the given width by averaging the pixels covered by each pixel of the result.
+fn downscale(width: u32, height: u32, pixels: &[u8], max_width: u32) -> (u32, u32, Vec<u8>) {
+    if width <= max_width {
+        return (width, height, pixels.to_vec());
+    }
+
+    let (new_width, new_height) = (max_width, (height as u64 * max_width as u64 / width as u64).max(1) as u32);
+    let mut result = Vec::with_capacity((new_width * new_height * 4) as usize);
+    for y in 0..new_height {
+        let (y0, y1) = (y * height / new_height, ((y + 1) * height / new_height).max(y * height / new_height + 1));
+        for x in 0..new_width {
+            let (x0, x1) = (x * width / new_width, ((x + 1) * width / new_width).max(x * width / new_width + 1));
+
+            // The colors are weighted by their opacity, so that transparent pixels don't darken the edges.
+            let mut sum = [0u64; 4];
+            for sy in y0..y1 {
+                for sx in x0..x1 {
+                    let offset = ((sy * width + sx) * 4) as usize;
+                    let alpha = pixels[offset + 3] as u64;
+                    (0..3).for_each(|i| sum[i] += pixels[offset + i] as u64 * alpha);
+                    sum[3] += alpha;
+                }
+            }
+
+            let count = ((x1 - x0) * (y1 - y0)) as u64;
+            let alpha = sum[3];
+            let color = |i: usize| if alpha == 0 { 0 } else { (sum[i] / alpha) as u8 };
+            result.extend_from_slice(&[color(0), color(1), color(2), (alpha / count) as u8]);
+        }
+    }
+
+    (new_width, new_height, result)
+}
+
+fn bake_images() -> Result<(), Box<dyn Error>> {
+    let out_dir = env::var("OUT_DIR")?;
+
+    let mut generated = String::new();
+    for (name, path, max_width) in IMAGES {
+        println!("cargo:rerun-if-changed={}", path);
+
+        let (width, height, pixels) = decode_png(path)?;
+        let (width, height, pixels) = downscale(width, height, &pixels, max_width);
+
+        let raw_path = Path::new(&out_dir).join(format!("{}.rgba", name.to_lowercase()));
+        fs::write(&raw_path, pixels)?;
+
+        generated += &format!(
+            "pub const {name}_WIDTH: usize = {width};\n\
+             pub const {name}_HEIGHT: usize = {height};\n\
+             pub static {name}_PIXELS: &[u8] = include_bytes!({raw_path:?});\n"
+        );
+    }
+
+    fs::write(Path::new(&out_dir).join("Images.rs"), generated)?;
+
+    Ok(())
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     println!("cargo:rustc-env=TARGET={}", env::var("TARGET").unwrap());
 
     println!("cargo:rerun-if-changed=cfg");
 
     bake_configurations()?;
+    bake_images()?;
 
     Ok(())
 }
@@ -19,19 +19,49 @@ PROFILE=$(echo "$KERNEL" | cut -d'/' -f3)
 LOG_FILE="target/$(echo "${PROFILE}" | tr '[:lower:]' '[:upper:]').LOG"
 MEMORY_SIZE="4G"
 
+# The files unpacked into the root filesystem at boot, which are archived and loaded by GRUB as a module.
+INITRAMFS_DIR="${SRC_ISO_DIR}/initramfs"
+INITRAMFS_FILE="initramfs.tar"
+
+# A scratch disk attached as a virtio block device, which is created on the first run and kept between runs.
+DISK_IMAGE="target/disk.img"
+DISK_SIZE="64M"
+
+# A FAT volume attached as a second virtio block device and mounted at /mnt, which can be filled on the host with
+# mtools, e.g., `mcopy -i target/fat.img README.md ::`.
+FAT_IMAGE="target/fat.img"
+FAT_SIZE_KIB="32768"
+
+# The in-kernel GDB stub listens on the second serial port (COM2), which is exposed through this TCP port, once
+# enabled by passing `gdb` on the kernel command line. Attaching a debugger with `target remote :1235` stops the
+# kernel, which can be stopped again later with Ctrl-C.
+GDB_STUB_PORT="1235"
+
 # Copy the needed files into an ISO image.
 mkdir -p "${DEST_ISO_DIR}/${GRUB_DIR}"
 cp "${KERNEL}" "${DEST_ISO_DIR}/${BOOT_DIR}"
 cp "${SRC_ISO_DIR}/${GRUB_DIR}/${GRUB_CONFIG_FILE}" "${DEST_ISO_DIR}/${GRUB_DIR}"
+tar --format=ustar --owner=0 --group=0 -cf "${DEST_ISO_DIR}/${BOOT_DIR}/${INITRAMFS_FILE}" -C "${INITRAMFS_DIR}" .
 
 grub-mkrescue -o "${KERNEL_ISO}" "${DEST_ISO_DIR}"
 
+if [ ! -f "${DISK_IMAGE}" ]; then
+  qemu-img create -f raw "${DISK_IMAGE}" "${DISK_SIZE}"
+fi
+
+if [ ! -f "${FAT_IMAGE}" ]; then
+  mkfs.fat -C "${FAT_IMAGE}" "${FAT_SIZE_KIB}"
+fi
+
 # Run the created image with QEMU.
 qemu-system-"${ARCH}" \
   -m "${MEMORY_SIZE}" \
   -drive file="${KERNEL_ISO}",format=raw \
+  -drive file="${DISK_IMAGE}",if=virtio,format=raw \
+  -drive file="${FAT_IMAGE}",if=virtio,format=raw \
   -no-reboot -no-shutdown \
   -D "${LOG_FILE}" \
   -d int \
   -serial stdio \
+  -serial tcp::"${GDB_STUB_PORT}",server,nowait \
   -s
@@ -20,10 +20,84 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+use core::fmt::{Arguments, Write};
+
 use log::Log;
 use log::{Level, LevelFilter, Metadata, Record, SetLoggerError};
 
-use crate::{serial_print, serial_println};
+use crate::kernel::cmdline;
+use crate::kernel::cmdline::{Arg, Param, ParamError};
+use crate::kernel::sync::{Mutex, RwLock};
+use crate::serial_print;
+
+/// `loglevel=<off|error|warn|info|debug|trace>` limits the messages which are logged to the given level.
+static LOGLEVEL: Param = Param::new("loglevel", set_max_level);
+
+/// The maximum number of sinks which can be registered in addition to the serial console.
+const MAX_SINKS: usize = 4;
+
+/// A destination of the log messages in addition to the serial console, which receives every message as a whole
+/// line, including the ANSI escape sequences used for highlighting.
+pub type LogSink = fn(Arguments);
+
+// A reader-writer lock allows the sinks to be used by an interrupt handler which has preempted the logger.
+static SINKS: RwLock<[Option<LogSink>; MAX_SINKS]> = RwLock::new([None; MAX_SINKS]);
+
+/// The number of bytes of the most recent messages which are kept for later inspection.
+const HISTORY_SIZE: usize = 16 * 1024;
+
+/// The most recent messages, without the escape sequences, in a ring which overwrites the oldest bytes.
+struct History {
+    buf: [u8; HISTORY_SIZE],
+    /// The position at which the next byte is written.
+    head: usize,
+    len: usize,
+}
+
+impl History {
+    const fn new() -> Self {
+        Self {
+            buf: [0; HISTORY_SIZE],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Returns the contents in two consecutive parts, beginning with the oldest complete message.
+    fn as_slices(&self) -> (&[u8], &[u8]) {
+        let start = (self.head + HISTORY_SIZE - self.len) % HISTORY_SIZE;
+        let (first, second) = match start + self.len <= HISTORY_SIZE {
+            true => (&self.buf[start..start + self.len], &[][..]),
+            false => (&self.buf[start..], &self.buf[..self.head]),
+        };
+        if self.len < HISTORY_SIZE {
+            return (first, second);
+        }
+
+        // The oldest message has been partly overwritten once the ring is full.
+        match first.iter().position(|byte| *byte == b'\n') {
+            Some(end) => (&first[end + 1..], second),
+            None => {
+                let end = second.iter().position(|byte| *byte == b'\n');
+                (&[], &second[end.map_or(second.len(), |end| end + 1)..])
+            }
+        }
+    }
+}
+
+impl Write for History {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for byte in s.bytes() {
+            self.buf[self.head] = byte;
+            self.head = (self.head + 1) % HISTORY_SIZE;
+            self.len = (self.len + 1).min(HISTORY_SIZE);
+        }
+
+        Ok(())
+    }
+}
+
+static HISTORY: Mutex<History> = Mutex::new(History::new());
 
 struct Logger;
 
@@ -37,19 +111,56 @@ impl Log for Logger {
             return;
         }
 
-        match record.level() {
-            Level::Debug => serial_print!("\x1b[1;32m debug:\x1b[0m "),
-            Level::Error => serial_print!("\x1b[1;31m error:\x1b[0m "),
-            Level::Info => serial_print!("\x1b[1;36m info:\x1b[0m "),
-            Level::Warn => serial_print!("\x1b[1;33m warn:\x1b[0m "),
-            Level::Trace => serial_print!("\x1b[1;37m trace:\x1b[0m "),
+        let prefix = match record.level() {
+            Level::Debug => "\x1b[1;32m debug:\x1b[0m ",
+            Level::Error => "\x1b[1;31m error:\x1b[0m ",
+            Level::Info => "\x1b[1;36m info:\x1b[0m ",
+            Level::Warn => "\x1b[1;33m warn:\x1b[0m ",
+            Level::Trace => "\x1b[1;37m trace:\x1b[0m ",
+        };
+        serial_print!("{}{}\n", prefix, record.args());
+
+        for sink in SINKS.read().iter().flatten() {
+            sink(format_args!("{}{}\n", prefix, record.args()));
+        }
+
+        // A message logged while the history is being read, e.g. by an interrupt handler, is left out of it.
+        if let Some(mut history) = HISTORY.try_lock() {
+            let _ = writeln!(history, "{:<5} {}", record.level(), record.args());
         }
-        serial_println!("{}", record.args());
     }
 
     fn flush(&self) {}
 }
 
+fn set_max_level(arg: &Arg) -> Result<(), ParamError> {
+    log::set_max_level(arg.parse::<LevelFilter>()?);
+
+    Ok(())
+}
+
+pub fn register_sink(sink: LogSink) -> Result<(), ()> {
+    let mut sinks = SINKS.write();
+    let slot = sinks.iter_mut().find(|slot| slot.is_none()).ok_or(())?;
+    *slot = Some(sink);
+
+    Ok(())
+}
+
+/// Passes the most recent messages to a closure, oldest first, in two consecutive parts.
+pub fn with_history<F, R>(f: F) -> R
+where
+    F: FnOnce(&[u8], &[u8]) -> R,
+{
+    let history = HISTORY.lock();
+    let (first, second) = history.as_slices();
+    f(first, second)
+}
+
+pub fn register_params() -> Result<(), ()> {
+    cmdline::register(&LOGLEVEL).map_err(|_| ())
+}
+
 pub fn init() -> Result<(), SetLoggerError> {
     log::set_logger(&Logger)?;
     log::set_max_level(LevelFilter::Trace);
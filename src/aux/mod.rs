@@ -20,8 +20,10 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-mod log;
+pub mod log;
+pub mod ring_buffer;
 
 pub fn init() {
     log::init().expect("logger can only be initialized once");
+    log::register_params().expect("logger failed to register its parameters");
 }
@@ -0,0 +1,249 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::mem::size_of;
+use core::ptr;
+use core::slice;
+
+use spin::Once;
+use x86_64::PhysAddr;
+
+use super::elf;
+use super::paging;
+
+/// The size of the entries of the Root System Description Table (RSDT), which are 32-bit physical addresses.
+const RSDT_ENTRY_SIZE: usize = 4;
+/// The size of the entries of the Extended System Description Table (XSDT), which are 64-bit physical addresses.
+const XSDT_ENTRY_SIZE: usize = 8;
+
+/// The header shared by every System Description Table (SDT).
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+pub struct SdtHeader {
+    pub signature: [u8; 4],
+    pub length: u32,
+    pub revision: u8,
+    pub checksum: u8,
+    pub oem_id: [u8; 6],
+    pub oem_table_id: [u8; 8],
+    pub oem_revision: u32,
+    pub creator_id: u32,
+    pub creator_revision: u32,
+}
+
+/// A System Description Table, which is accessed through the direct mapping of the physical memory.
+#[derive(Clone, Copy, Debug)]
+pub struct Sdt {
+    pub phys_addr: u64,
+    header: &'static SdtHeader,
+}
+
+impl Sdt {
+    /// Maps the table at the given physical address, provided that it's valid.
+    fn at(phys_addr: u64) -> Option<Self> {
+        let header_addr =
+            paging::phys_range_to_virt(PhysAddr::new(phys_addr), size_of::<SdtHeader>())?;
+        let header = unsafe { &*header_addr.as_ptr::<SdtHeader>() };
+
+        let length = header.length as usize;
+        if length < size_of::<SdtHeader>() {
+            return None;
+        }
+        let addr = paging::phys_range_to_virt(PhysAddr::new(phys_addr), length)?;
+        let bytes = unsafe { slice::from_raw_parts(addr.as_ptr::<u8>(), length) };
+
+        // The bytes of a valid table, including its checksum, add up to zero.
+        let sum = bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        (sum == 0).then_some(Self { phys_addr, header })
+    }
+
+    pub fn signature(&self) -> &'static str {
+        core::str::from_utf8(&self.header.signature).unwrap_or("????")
+    }
+
+    /// Returns the length of the table, including its header.
+    pub fn length(&self) -> usize {
+        self.header.length as usize
+    }
+
+    pub fn revision(&self) -> u8 {
+        self.header.revision
+    }
+
+    /// Returns the OEM which has supplied the table, along with its own identifier of the table.
+    pub fn oem(&self) -> (&'static str, &'static str) {
+        let oem_id = core::str::from_utf8(&self.header.oem_id).unwrap_or_default();
+        let oem_table_id = core::str::from_utf8(&self.header.oem_table_id).unwrap_or_default();
+        (oem_id.trim_end(), oem_table_id.trim_end())
+    }
+
+    /// Returns the contents of the table following its header.
+    pub fn data(&self) -> &'static [u8] {
+        let start = self.header as *const SdtHeader as *const u8;
+        let bytes = unsafe { slice::from_raw_parts(start, self.header.length as usize) };
+        &bytes[size_of::<SdtHeader>()..]
+    }
+}
+
+/// The table listing the other tables, either the RSDT or the XSDT.
+#[derive(Clone, Copy, Debug)]
+struct RootTable {
+    table: Sdt,
+    entry_size: usize,
+}
+
+static ROOT: Once<RootTable> = Once::new();
+
+/// Returns the physical tables listed by the root table.
+pub fn tables() -> impl Iterator<Item = Sdt> {
+    let (data, entry_size) = match ROOT.get() {
+        Some(root) => (root.table.data(), root.entry_size),
+        None => (&[][..], RSDT_ENTRY_SIZE),
+    };
+
+    data.chunks(entry_size).filter_map(|entry| {
+        let addr = match entry.len() {
+            RSDT_ENTRY_SIZE => u32::from_le_bytes(entry.try_into().ok()?) as u64,
+            XSDT_ENTRY_SIZE => u64::from_le_bytes(entry.try_into().ok()?),
+            _ => return None,
+        };
+        Sdt::at(addr)
+    })
+}
+
+/// Finds a table by its signature, e.g. `b"MCFG"`.
+pub fn find(signature: &[u8; 4]) -> Option<Sdt> {
+    tables().find(|table| table.header.signature == *signature)
+}
+
+/// Returns the revision of ACPI and the OEM reported by the Root System Description Pointer (RSDP).
+pub fn revision() -> Option<(u8, &'static str)> {
+    let info = elf::multiboot_info();
+    match (info.rsdp_v2_tag(), info.rsdp_v1_tag()) {
+        (Some(rsdp), _) => Some((rsdp.revision(), rsdp.oem_id().unwrap_or_default())),
+        (None, Some(rsdp)) => Some((rsdp.revision(), rsdp.oem_id().unwrap_or_default())),
+        (None, None) => None,
+    }
+}
+
+/// An area of the memory-mapped configuration space of PCI Express (ECAM), covering a range of buses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct McfgEntry {
+    /// The physical address of the configuration space of bus 0 of the segment.
+    pub base: u64,
+    pub segment: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+}
+
+/// Returns the areas of the memory-mapped configuration space described by the MCFG table, if any.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/PCI_Express
+pub fn mcfg_entries() -> impl Iterator<Item = McfgEntry> {
+    // The entries follow a reserved field of 8 bytes.
+    const RESERVED: usize = 8;
+    const ENTRY_SIZE: usize = 16;
+
+    let data = find(b"MCFG")
+        .and_then(|table| table.data().get(RESERVED..))
+        .unwrap_or_default();
+
+    (0..data.len() / ENTRY_SIZE).map(move |index| {
+        let entry = &data[index * ENTRY_SIZE..(index + 1) * ENTRY_SIZE];
+        McfgEntry {
+            base: unsafe { ptr::read_unaligned(entry.as_ptr() as *const u64) },
+            segment: u16::from_le_bytes([entry[8], entry[9]]),
+            start_bus: entry[10],
+            end_bus: entry[11],
+        }
+    })
+}
+
+/// Returns the IDs of the local APICs of the usable processors, as listed by the MADT.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/MADT
+pub fn local_apic_ids() -> impl Iterator<Item = u8> {
+    // The entries follow the address of the local APIC and the flags.
+    const ENTRIES_OFFSET: usize = 8;
+    const ENTRY_LOCAL_APIC: u8 = 0;
+    const LOCAL_APIC_ENABLED: u8 = 1 << 0;
+    const LOCAL_APIC_ONLINE_CAPABLE: u8 = 1 << 1;
+
+    let mut data = find(b"APIC")
+        .and_then(|table| table.data().get(ENTRIES_OFFSET..))
+        .unwrap_or_default();
+
+    core::iter::from_fn(move || loop {
+        let (kind, len) = (*data.first()?, *data.get(1)? as usize);
+        let entry = data.get(..len).filter(|_| len >= 2)?;
+        data = &data[len..];
+
+        let usable = LOCAL_APIC_ENABLED | LOCAL_APIC_ONLINE_CAPABLE;
+        if kind == ENTRY_LOCAL_APIC && len >= 8 && entry[4] & usable != 0 {
+            return Some(entry[3]);
+        }
+    })
+}
+
+/// Advanced Configuration and Power Interface (ACPI)
+///
+/// Locates the root table through the copy of the Root System Description Pointer (RSDP) which the bootloader
+/// passes in the multiboot information. The XSDT is preferred over the RSDT on ACPI 2.0 and later, as its entries can
+/// address the whole physical address space.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/RSDP
+pub(crate) fn init() -> Result<(), ()> {
+    let info = elf::multiboot_info();
+    let root = match (info.rsdp_v2_tag(), info.rsdp_v1_tag()) {
+        (Some(rsdp), _) if rsdp.checksum_is_valid() && rsdp.xsdt_address() != 0 => RootTable {
+            table: Sdt::at(rsdp.xsdt_address() as u64).ok_or(())?,
+            entry_size: XSDT_ENTRY_SIZE,
+        },
+        (_, Some(rsdp)) if rsdp.checksum_is_valid() => RootTable {
+            table: Sdt::at(rsdp.rsdt_address() as u64).ok_or(())?,
+            entry_size: RSDT_ENTRY_SIZE,
+        },
+        _ => {
+            log::warn!("no ACPI tables were passed by the bootloader");
+            return Ok(());
+        }
+    };
+    ROOT.call_once(|| root);
+
+    let (revision, oem) = revision().unwrap_or_default();
+    log::info!(
+        "ACPI: revision {}, OEM {:?}, root table {}",
+        revision,
+        oem,
+        root.table.signature()
+    );
+    for table in tables() {
+        log::debug!(
+            "ACPI: {} at {:#X}, {} bytes",
+            table.signature(),
+            table.phys_addr,
+            { table.header.length }
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,81 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use super::paging;
+
+/// The maximum number of frames walked, guarding against corrupted frame pointer chains.
+const MAX_DEPTH: usize = 32;
+
+/// Stack Backtrace
+///
+/// The kernel is built with frame pointers (`-Cforce-frame-pointers=yes`), hence every stack frame begins with the
+/// frame pointer of its caller followed by the return address into it. Walking this chain yields the return
+/// addresses of all active functions.
+pub struct Backtrace {
+    rbp: u64,
+    depth: usize,
+}
+
+impl Backtrace {
+    /// Creates a backtrace beginning at the frame pointed to by the given frame pointer.
+    pub fn new(rbp: u64) -> Self {
+        Self { rbp, depth: 0 }
+    }
+}
+
+impl Iterator for Backtrace {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.depth >= MAX_DEPTH || self.rbp == 0 || self.rbp % 8 != 0 {
+            return None;
+        }
+
+        // Both the saved frame pointer and the return address must be readable without faulting.
+        if !paging::is_accessible(self.rbp, 16) {
+            return None;
+        }
+
+        let (caller_rbp, return_addr) = unsafe {
+            let frame = self.rbp as *const u64;
+            (*frame, *frame.add(1))
+        };
+        if return_addr == 0 {
+            return None;
+        }
+
+        // The stack grows downwards, hence the frame of a caller always lies above the frame of its callee.
+        self.rbp = if caller_rbp > self.rbp { caller_rbp } else { 0 };
+        self.depth += 1;
+
+        Some(return_addr)
+    }
+}
+
+/// Logs the backtrace of a context given its instruction and frame pointers.
+pub fn log(rip: u64, rbp: u64) {
+    log::info!("backtrace:");
+    log::info!("  #0 {:#018X}", rip);
+    for (depth, return_addr) in Backtrace::new(rbp).enumerate() {
+        log::info!("  #{} {:#018X}", depth + 1, return_addr);
+    }
+}
@@ -0,0 +1,128 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::arch::global_asm;
+use core::mem;
+
+use x86_64::registers::model_specific::{FsBase, GsBase};
+use x86_64::VirtAddr;
+
+use crate::kernel::sync;
+
+use super::gdt;
+use super::preliminary;
+use super::stack::Stack;
+
+// This assembly file contains the routine switching between the stacks of two threads and the entry trampoline of
+// new threads.
+global_asm!(include_str!("context.s"));
+
+extern "C" {
+    fn context_switch(from: *mut u64, to: u64);
+    fn context_start();
+}
+
+/// The entry point of a thread, which is called with the argument given upon the creation of its context.
+pub type Entry = extern "C" fn(usize) -> !;
+
+/// Switch Frame
+///
+/// The registers of a suspended thread as pushed onto its stack by `context_switch`, from the lowest address.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+struct SwitchFrame {
+    r15: u64,
+    r14: u64,
+    r13: u64,
+    r12: u64,
+    rbx: u64,
+    rbp: u64,
+    rip: u64,
+}
+
+/// Context
+///
+/// The state of a thread which isn't running: its stack pointer, below which `context_switch` has saved the registers
+/// preserved across calls, the bases of the FS and GS segments, the top of its stack, which the processor is to
+/// switch to upon an interrupt from a lower privilege level, and its preemption count.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Context {
+    rsp: u64,
+    fs_base: u64,
+    gs_base: u64,
+    kernel_stack: u64,
+    preempt_count: usize,
+}
+
+impl Context {
+    /// Returns the context of the thread running on the stack set up during boot, which is filled in once the thread
+    /// is switched away from for the first time.
+    pub fn boot() -> Self {
+        Self {
+            kernel_stack: preliminary::stack::top().as_u64(),
+            ..Self::default()
+        }
+    }
+
+    /// Prepares the context of a new thread, which begins by calling the entry point with the given argument on an
+    /// otherwise empty stack.
+    pub fn new(stack: &Stack, entry: Entry, arg: usize) -> Self {
+        let frame = SwitchFrame {
+            r12: entry as usize as u64,
+            r13: arg as u64,
+            rip: context_start as unsafe extern "C" fn() as usize as u64,
+            ..SwitchFrame::default()
+        };
+
+        let top = stack.top();
+        let rsp = top - mem::size_of::<SwitchFrame>();
+        unsafe { rsp.as_mut_ptr::<SwitchFrame>().write(frame) };
+
+        Self {
+            rsp: rsp.as_u64(),
+            fs_base: 0,
+            gs_base: 0,
+            kernel_stack: top.as_u64(),
+            preempt_count: 0,
+        }
+    }
+}
+
+/// Suspends the running thread into `from` and resumes the one suspended in `to`, returning once the former is
+/// resumed in turn.
+///
+/// # Safety
+///
+/// Both contexts must stay in place until the switch back, and `to` must have been prepared by `Context::new` or
+/// saved by an earlier switch. The interrupts must be disabled, so that the switch isn't interleaved with another.
+pub unsafe fn switch(from: *mut Context, to: *const Context) {
+    (*from).fs_base = FsBase::read().as_u64();
+    (*from).gs_base = GsBase::read().as_u64();
+    (*from).preempt_count = sync::preempt_count();
+
+    FsBase::write(VirtAddr::new((*to).fs_base));
+    GsBase::write(VirtAddr::new((*to).gs_base));
+    gdt::set_kernel_stack(VirtAddr::new((*to).kernel_stack));
+    sync::set_preempt_count((*to).preempt_count);
+
+    context_switch(&mut (*from).rsp, (*to).rsp);
+}
@@ -0,0 +1,70 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+// Context Switch
+//
+// `context_switch(from, to)` suspends the running thread by pushing the registers which the System V ABI requires a
+// callee to preserve onto its stack and storing the stack pointer into `*from`. It then resumes the thread whose
+// stack pointer is `to` by popping the same registers from its stack and returning to where it was suspended. The
+// other registers are saved by the caller as part of the call anyway.
+//
+// Stack layout of a suspended thread (growing downwards):
+//
+//      RIP                                 <- pushed by the call to `context_switch`
+//      RBP, RBX, R12, ..., R15             <- pushed by `context_switch`
+//
+// A new thread is given the same layout with `context_start` as the return address, and the entry point and its
+// argument in R12 and R13. The stack pointer is aligned to 16 bytes once `context_start` has been entered, hence
+// the entry point is called with the alignment required by the System V ABI.
+
+.global context_switch
+.global context_start
+
+.section .text, "ax", @progbits
+.code64
+
+context_switch:
+    push rbp
+    push rbx
+    push r12
+    push r13
+    push r14
+    push r15
+
+    mov [rdi], rsp
+    mov rsp, rsi
+
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop rbx
+    pop rbp
+    ret
+
+context_start:
+    mov rdi, r13
+    call r12
+
+    // The entry point never returns.
+    ud2
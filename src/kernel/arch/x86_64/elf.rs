@@ -20,7 +20,12 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-use multiboot2::BootInformation;
+use core::slice;
+
+use multiboot2::{BootInformation, MemoryAreaType};
+use x86_64::PhysAddr;
+
+use super::paging;
 
 macro_rules! foreign_symbol {
     ($symbol:ident) => {
@@ -30,6 +35,8 @@ macro_rules! foreign_symbol {
 
 extern "C" {
     static KERNEL_OFFSET: u8;
+    static KERNEL_START: u8;
+    static KERNEL_END: u8;
 }
 
 static mut MULTIBOOT_INFO: Option<BootInformation> = None;
@@ -42,12 +49,107 @@ pub fn init(boot_info_addr: usize) -> Result<(), ()> {
     Ok(())
 }
 
-#[allow(dead_code)]
 pub fn multiboot_info() -> &'static BootInformation {
     unsafe { MULTIBOOT_INFO.as_ref().unwrap() }
 }
 
+/// Returns the command line passed to the kernel by the bootloader.
+pub fn command_line() -> &'static str {
+    multiboot_info()
+        .command_line_tag()
+        .and_then(|tag| tag.command_line().ok())
+        .unwrap_or_default()
+}
+
+/// Returns the name of the bootloader which has loaded the kernel, e.g. `GRUB 2.06`.
+pub fn boot_loader_name() -> &'static str {
+    multiboot_info()
+        .boot_loader_name_tag()
+        .and_then(|tag| tag.name().ok())
+        .unwrap_or_default()
+}
+
+/// The use of an area of the physical memory, as reported by the firmware.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryKind {
+    Available,
+    Reserved,
+    /// Holds the ACPI tables, and becomes available once they're no longer needed.
+    AcpiReclaimable,
+    /// Must be preserved across hibernation.
+    AcpiNvs,
+    Defective,
+}
+
+/// An area of the memory map provided by the bootloader.
+#[derive(Clone, Copy, Debug)]
+pub struct MemoryArea {
+    pub start: PhysAddr,
+    pub len: u64,
+    pub kind: MemoryKind,
+}
+
+/// Returns every area of the memory map, including those which aren't available.
+pub fn memory_map() -> impl Iterator<Item = MemoryArea> {
+    let areas = multiboot_info()
+        .memory_map_tag()
+        .into_iter()
+        .flat_map(|tag| tag.memory_areas());
+
+    areas.map(|area| MemoryArea {
+        start: PhysAddr::new(area.start_address()),
+        len: area.size(),
+        kind: match area.typ() {
+            MemoryAreaType::Available => MemoryKind::Available,
+            MemoryAreaType::Reserved => MemoryKind::Reserved,
+            MemoryAreaType::AcpiAvailable => MemoryKind::AcpiReclaimable,
+            MemoryAreaType::ReservedHibernate => MemoryKind::AcpiNvs,
+            MemoryAreaType::Defective => MemoryKind::Defective,
+        },
+    })
+}
+
+/// A file loaded into memory by the bootloader along with the kernel, e.g., an initial RAM filesystem.
+#[derive(Clone, Copy, Debug)]
+pub struct BootModule {
+    pub start: PhysAddr,
+    pub len: usize,
+    /// The string following the path of the module in the configuration of the bootloader.
+    pub cmdline: &'static str,
+}
+
+impl BootModule {
+    /// Returns the contents of the module, which remain in place for the lifetime of the kernel.
+    pub fn data(&self) -> Option<&'static [u8]> {
+        if self.len == 0 {
+            return Some(&[]);
+        }
+
+        let virt = paging::phys_range_to_virt(self.start, self.len)?;
+        Some(unsafe { slice::from_raw_parts(virt.as_ptr(), self.len) })
+    }
+}
+
+/// Returns the modules loaded by the bootloader.
+pub fn modules() -> impl Iterator<Item = BootModule> {
+    multiboot_info().module_tags().map(|tag| BootModule {
+        start: PhysAddr::new(tag.start_address() as u64),
+        len: tag.module_size() as usize,
+        cmdline: tag.cmdline().unwrap_or_default(),
+    })
+}
+
 #[allow(dead_code)]
 pub fn kernel_offset() -> usize {
     foreign_symbol!(KERNEL_OFFSET)
 }
+
+/// Returns the physical address at which the kernel image begins.
+pub fn kernel_start() -> usize {
+    foreign_symbol!(KERNEL_START)
+}
+
+/// Returns the virtual address at which the kernel image ends, including its uninitialized data.
+pub fn kernel_end() -> usize {
+    foreign_symbol!(KERNEL_END)
+}
@@ -20,10 +20,101 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+use x86_64::registers::control::Cr2;
+use x86_64::registers::debug::{Dr6, Dr6Flags};
 use x86_64::structures::idt::InterruptStackFrame;
 
 use crate::serial_println;
 
+use super::backtrace;
+use super::gdb;
+use super::monitor;
+use super::stack;
+use super::trap::TrapFrame;
+use super::watchpoint;
+use super::watchpoint::{WatchpointAction, WatchpointKind};
+
+/// Trap Flag (TF) of the RFLAGS register.
+const RFLAGS_TF: u64 = 1 << 8;
+/// Resume Flag (RF) of the RFLAGS register.
+const RFLAGS_RF: u64 = 1 << 16;
+
+/// Debug Exception (#DB, 0x01)
+///
+/// A debug exception occurs when a debug condition is met, such as a single-stepped instruction when the trap flag
+/// is set or a hit on one of the hardware breakpoints configured through the debug registers. The cause of the
+/// exception is reported in the debug status register (DR6).
+///
+/// OS Dev Wiki: https://wiki.osdev.org/Exceptions#Debug
+pub struct DebugException;
+
+impl DebugException {
+    pub const CODE: u8 = 0x01;
+    pub const MNEMONIC: &'static str = "#DB";
+
+    pub fn handler(frame: &mut TrapFrame) {
+        let status = Dr6::read();
+
+        // The processor does not clear DR6 on its own, hence it must be done before returning from the handler.
+        clear_debug_status();
+
+        let mut hit = false;
+        let mut break_on = None;
+        for (slot, watchpoint) in watchpoint::hits(status) {
+            log::warn!(
+                "watchpoint {} hit: {:?} access to {:#X} ({} bytes) @ {:#X}",
+                slot,
+                watchpoint.kind,
+                watchpoint.addr.as_u64(),
+                watchpoint.len,
+                frame.rip
+            );
+            backtrace::log(frame.rip, frame.rbp);
+
+            // Instruction breakpoints are faults, hence the instruction would trigger the breakpoint again upon
+            // return unless the resume flag is set.
+            if watchpoint.kind == WatchpointKind::Execute {
+                frame.rflags |= RFLAGS_RF;
+            }
+            if watchpoint.action == WatchpointAction::Break {
+                break_on = Some(watchpoint);
+            }
+            hit = true;
+        }
+
+        // Without a debugger attached, the hit is handed to the monitor, as is a breakpoint.
+        if let Some(watchpoint) = break_on {
+            match gdb::is_attached() {
+                true => gdb::enter_watchpoint(frame, watchpoint),
+                false => monitor::enter(Some(frame)),
+            }
+            return;
+        }
+
+        if status.contains(Dr6Flags::STEP) && gdb::is_stepping() {
+            gdb::enter(frame);
+            return;
+        }
+
+        if hit {
+            return;
+        }
+
+        // Stop single-stepping the interrupted context, since nobody is interested in it.
+        if status.contains(Dr6Flags::STEP) {
+            frame.rflags &= !RFLAGS_TF;
+        }
+
+        serial_println!(
+            "({}, {:#04X}) @ {:#?}, DR6={:?}",
+            Self::MNEMONIC,
+            Self::CODE,
+            frame,
+            status
+        );
+    }
+}
+
 /// Breakpoint Exception (#BP, 0x03)
 ///
 /// A breakpoint exception occurs when the processor encounters a debug breakpoint instruction in enabling the
@@ -36,13 +127,17 @@ impl BreakpointException {
     pub const CODE: u8 = 0x03;
     pub const MNEMONIC: &'static str = "#BP";
 
-    pub extern "x86-interrupt" fn handler(stack_frame: InterruptStackFrame) {
-        serial_println!(
-            "({}, {:#04X}) @ {:#?}",
-            Self::MNEMONIC,
-            Self::CODE,
-            stack_frame
-        );
+    pub fn handler(frame: &mut TrapFrame) {
+        // Without a debugger attached, the breakpoint is handed to the monitor rather than to a stub which would wait
+        // for a debugger forever.
+        if gdb::is_attached() {
+            gdb::enter(frame);
+            return;
+        }
+
+        serial_println!("({}, {:#04X}) @ {:#?}", Self::MNEMONIC, Self::CODE, frame);
+
+        monitor::enter(Some(frame));
     }
 }
 
@@ -60,6 +155,18 @@ impl DoubleFaultException {
     pub const MNEMONIC: &'static str = "#DF";
 
     pub extern "x86-interrupt" fn handler(stack_frame: InterruptStackFrame, err_code: u64) -> ! {
+        // A page fault on a guard page can't be delivered on the overflowed stack, hence it ends up here.
+        let addr = Cr2::read();
+        if stack::is_guard_page(addr) {
+            panic!(
+                "({}, {:#04X}) kernel stack overflow @ {:#?}, address {:#X}",
+                Self::MNEMONIC,
+                Self::CODE,
+                stack_frame,
+                addr.as_u64()
+            );
+        }
+
         panic!(
             "({}, {:#04X}) @ {:#?}, E={}",
             Self::MNEMONIC,
@@ -69,3 +176,9 @@ impl DoubleFaultException {
         );
     }
 }
+
+fn clear_debug_status() {
+    unsafe {
+        core::arch::asm!("mov dr6, {}", in(reg) 0u64, options(nomem, nostack, preserves_flags));
+    }
+}
@@ -0,0 +1,251 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use multiboot2::MemoryAreaType;
+use x86_64::instructions::interrupts;
+use x86_64::PhysAddr;
+
+use crate::kernel::cmdline;
+use crate::kernel::cmdline::{Arg, Param, ParamError};
+use crate::kernel::sync::Mutex;
+
+use super::elf;
+
+pub const FRAME_SIZE: usize = 4096;
+
+/// The amount of physical memory managed by the allocator, which is the part covered by the direct mapping.
+const MANAGED_MEMORY: u64 = 4 << 30;
+
+const FRAME_COUNT: usize = (MANAGED_MEMORY / FRAME_SIZE as u64) as usize;
+
+/// The memory below 1 MiB is left alone, as it's scattered with the data structures of the firmware.
+const LOW_MEMORY: u64 = 1 << 20;
+
+/// `mem=<size>` limits the physical memory used by the kernel to the given amount, e.g., `mem=512M`.
+static MEM: Param = Param::new("mem", set_memory_limit);
+
+static MEMORY_LIMIT: AtomicU64 = AtomicU64::new(MANAGED_MEMORY);
+
+/// The number of frames in use and in total.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    pub total: usize,
+    pub free: usize,
+}
+
+/// Physical Frame Allocator
+///
+/// Keeps track of the physical memory in frames of 4 KiB through a bitmap, with one bit per frame which is set while
+/// the frame is in use. Every frame is in use to begin with, and the available areas of the memory map are released
+/// into the allocator during initialization.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/Page_Frame_Allocation
+struct FrameAllocator {
+    bitmap: [u64; FRAME_COUNT / 64],
+    stats: FrameStats,
+    // The frame at which the search for free frames begins, which moves past the most recent allocation.
+    next: usize,
+}
+
+impl FrameAllocator {
+    const fn new() -> Self {
+        Self {
+            bitmap: [u64::MAX; FRAME_COUNT / 64],
+            stats: FrameStats { total: 0, free: 0 },
+            next: 0,
+        }
+    }
+
+    fn is_free(&self, frame: usize) -> bool {
+        self.bitmap[frame / 64] & (1 << (frame % 64)) == 0
+    }
+
+    /// Marks a range of frames, returning the number of frames whose state has changed.
+    fn mark(&mut self, frames: core::ops::Range<usize>, used: bool) -> usize {
+        let mut changed = 0;
+        for frame in frames.start..frames.end.min(FRAME_COUNT) {
+            if self.is_free(frame) == used {
+                self.bitmap[frame / 64] ^= 1 << (frame % 64);
+                changed += 1;
+            }
+        }
+
+        changed
+    }
+
+    fn allocate(&mut self, count: usize) -> Option<usize> {
+        if count == 0 || count > self.stats.free {
+            return None;
+        }
+
+        // The search wraps around once, so that the frames before the hint are considered as well.
+        let mut start = self.next;
+        let mut run = 0;
+        let mut wrapped = false;
+        let mut frame = self.next;
+        loop {
+            if frame == FRAME_COUNT {
+                if wrapped {
+                    return None;
+                }
+                wrapped = true;
+                frame = 0;
+                run = 0;
+            }
+            if wrapped && frame >= self.next + count {
+                return None;
+            }
+
+            // Whole words of frames in use are skipped at once.
+            if run == 0 && frame % 64 == 0 && self.bitmap[frame / 64] == u64::MAX {
+                frame += 64;
+                continue;
+            }
+
+            if self.is_free(frame) {
+                if run == 0 {
+                    start = frame;
+                }
+                run += 1;
+                if run == count {
+                    break;
+                }
+            } else {
+                run = 0;
+            }
+            frame += 1;
+        }
+
+        self.mark(start..start + count, true);
+        self.stats.free -= count;
+        self.next = start + count;
+
+        Some(start)
+    }
+
+    fn deallocate(&mut self, start: usize, count: usize) {
+        self.stats.free += self.mark(start..start + count, false);
+    }
+
+    fn reserve(&mut self, start: usize, count: usize) {
+        self.stats.free -= self.mark(start..start + count, true);
+    }
+}
+
+static ALLOCATOR: Mutex<FrameAllocator> = Mutex::new(FrameAllocator::new());
+
+fn set_memory_limit(arg: &Arg) -> Result<(), ParamError> {
+    let limit = arg.parse_size()?;
+    if limit < LOW_MEMORY {
+        return Err(ParamError::InvalidValue);
+    }
+    MEMORY_LIMIT.store(limit, Ordering::Relaxed);
+
+    Ok(())
+}
+
+/// Returns the range of frames which contains the given range of physical memory.
+fn frames_containing(addr: u64, len: u64) -> core::ops::Range<usize> {
+    let start = addr / FRAME_SIZE as u64;
+    let end = addr.saturating_add(len).div_ceil(FRAME_SIZE as u64);
+
+    start.min(FRAME_COUNT as u64) as usize..end.min(FRAME_COUNT as u64) as usize
+}
+
+/// Allocates physically contiguous frames, returning the address of the first of them.
+pub fn allocate(count: usize) -> Option<PhysAddr> {
+    interrupts::without_interrupts(|| ALLOCATOR.lock().allocate(count))
+        .map(|frame| PhysAddr::new((frame * FRAME_SIZE) as u64))
+}
+
+/// Releases frames obtained through [`allocate`].
+pub fn deallocate(addr: PhysAddr, count: usize) {
+    let frame = (addr.as_u64() / FRAME_SIZE as u64) as usize;
+    interrupts::without_interrupts(|| ALLOCATOR.lock().deallocate(frame, count));
+}
+
+/// Keeps a range of physical memory from being allocated, e.g., as it's occupied by data handed over by the
+/// bootloader.
+pub fn reserve(addr: PhysAddr, len: usize) {
+    let frames = frames_containing(addr.as_u64(), len as u64);
+    interrupts::without_interrupts(|| ALLOCATOR.lock().reserve(frames.start, frames.len()));
+}
+
+pub fn stats() -> FrameStats {
+    interrupts::without_interrupts(|| ALLOCATOR.lock().stats)
+}
+
+pub(crate) fn init() -> Result<(), ()> {
+    cmdline::register(&MEM).map_err(|_| ())?;
+    let limit = MEMORY_LIMIT.load(Ordering::Relaxed);
+
+    let memory_map = elf::multiboot_info().memory_map_tag().ok_or(())?;
+
+    let mut allocator = ALLOCATOR.lock();
+    let areas = memory_map
+        .memory_areas()
+        .filter(|area| area.typ() == MemoryAreaType::Available);
+    for area in areas {
+        let start = area.start_address().max(LOW_MEMORY);
+        let end = area.end_address().min(limit);
+        if start >= end {
+            continue;
+        }
+
+        // Only whole frames within the area can be used.
+        let frames = start.div_ceil(FRAME_SIZE as u64) as usize..(end / FRAME_SIZE as u64) as usize;
+        let frames = frames.start.min(FRAME_COUNT)..frames.end.min(FRAME_COUNT);
+        let count = allocator.mark(frames, false);
+        allocator.stats.total += count;
+        allocator.stats.free += count;
+    }
+
+    // The kernel image, as well as the multiboot information, must remain intact.
+    let kernel_start = elf::kernel_start() as u64;
+    let kernel_end = (elf::kernel_end() - elf::kernel_offset()) as u64;
+    let frames = frames_containing(kernel_start, kernel_end - kernel_start);
+    allocator.reserve(frames.start, frames.len());
+
+    let multiboot_info = elf::multiboot_info();
+    let multiboot_start = (multiboot_info.start_address() - elf::kernel_offset()) as u64;
+    let frames = frames_containing(multiboot_start, multiboot_info.total_size() as u64);
+    allocator.reserve(frames.start, frames.len());
+
+    // The modules are kept as well, as their contents are used in place, e.g., by the initial RAM filesystem.
+    for module in elf::modules() {
+        let frames = frames_containing(module.start.as_u64(), module.len as u64);
+        allocator.reserve(frames.start, frames.len());
+    }
+
+    let stats = allocator.stats;
+    drop(allocator);
+
+    log::info!(
+        "physical memory: {} KiB available, {} KiB free",
+        stats.total * FRAME_SIZE / 1024,
+        stats.free * FRAME_SIZE / 1024
+    );
+
+    Ok(())
+}
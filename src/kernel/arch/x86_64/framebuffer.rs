@@ -0,0 +1,87 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use multiboot2::FramebufferType;
+use x86_64::PhysAddr;
+
+use crate::kernel::video::framebuffer;
+use crate::kernel::video::framebuffer::{ColorField, Framebuffer, FramebufferKind, PixelFormat};
+
+use super::elf;
+use super::paging;
+
+/// Retrieves the framebuffer set up by the bootloader from the multiboot information.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/Multiboot#Framebuffer_info
+pub fn init() -> Result<(), ()> {
+    let tag = match elf::multiboot_info().framebuffer_tag() {
+        Some(Ok(tag)) => tag,
+        Some(Err(_)) => {
+            log::warn!("framebuffer is of an unknown type");
+            return Ok(());
+        }
+        None => return Ok(()),
+    };
+
+    let kind = match tag.buffer_type {
+        FramebufferType::RGB { red, green, blue } => {
+            let field = |field: multiboot2::FramebufferField| ColorField {
+                position: field.position,
+                size: field.size,
+            };
+            FramebufferKind::Rgb(PixelFormat {
+                red: field(red),
+                green: field(green),
+                blue: field(blue),
+            })
+        }
+        FramebufferType::Text => FramebufferKind::Text,
+        FramebufferType::Indexed { .. } => {
+            log::warn!("indexed framebuffers are not supported");
+            return Ok(());
+        }
+    };
+
+    let size = tag.pitch as usize * tag.height as usize;
+    let base = paging::map_mmio(PhysAddr::new(tag.address), size).ok_or(())?;
+
+    framebuffer::init(Framebuffer {
+        base: base.as_u64() as usize,
+        phys_addr: tag.address,
+        width: tag.width as usize,
+        height: tag.height as usize,
+        pitch: tag.pitch as usize,
+        bpp: tag.bpp,
+        kind,
+    })?;
+
+    log::info!(
+        "framebuffer: {}x{}x{} at {:#X} ({:?})",
+        tag.width,
+        tag.height,
+        tag.bpp,
+        tag.address,
+        kind
+    );
+
+    Ok(())
+}
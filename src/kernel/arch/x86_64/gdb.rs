@@ -0,0 +1,737 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::fmt;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use x86_64::instructions::segmentation::{Segment, DS, ES, FS, GS};
+use x86_64::structures::idt::InterruptStackFrame;
+use x86_64::VirtAddr;
+
+use crate::kernel::cmdline;
+use crate::kernel::cmdline::{Arg, Param, ParamError};
+use crate::kernel::sync::Mutex;
+use crate::kernel::thread;
+use crate::kernel::thread::{ThreadId, ThreadState};
+
+use super::paging;
+use super::serial;
+use super::trap::TrapFrame;
+use super::uart;
+use super::uart::ComPort;
+use super::watchpoint;
+use super::watchpoint::{Watchpoint, WatchpointAction, WatchpointKind, MAX_WATCHPOINTS};
+
+/// The serial port through which the remote debugger is attached.
+const GDB_PORT: ComPort = ComPort::Com2;
+
+/// The bytes with which the remote debugger breaks in: Ctrl-C, or the start of a packet, e.g. once it has connected.
+const BREAK_IN_KEYS: [u8; 2] = [0x03, b'$'];
+
+/// The maximum size of a packet exchanged with the remote debugger.
+const PACKET_SIZE: usize = 1024;
+
+const MAX_BREAKPOINTS: usize = 32;
+
+/// The thread ID reported to the remote debugger until the threads have been set up, which is the one the boot thread
+/// gets once it's adopted.
+const BOOT_THREAD_ID: u64 = 1;
+
+const SIGTRAP: u8 = 5;
+const INT3: u8 = 0xCC;
+
+/// Trap Flag (TF) of the RFLAGS register.
+const RFLAGS_TF: u64 = 1 << 8;
+
+/// `gdb` lets the stub claim its serial port, so that a remote debugger can break in by sending Ctrl-C.
+static GDB: Param = Param::new("gdb", enable);
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+// Set once the remote debugger has broken in, until it detaches. Breakpoints are only reported to the debugger while
+// it's attached, as the stub would otherwise wait for a debugger which may never show up.
+static ATTACHED: AtomicBool = AtomicBool::new(false);
+static STEPPING: AtomicBool = AtomicBool::new(false);
+// Set by the interrupt handler of the port when the remote debugger breaks in, until the interrupted context stops.
+static BREAKING_IN: AtomicBool = AtomicBool::new(false);
+
+static STUB: Mutex<Option<Stub>> = Mutex::new(None);
+
+#[derive(Clone, Copy)]
+struct Breakpoint {
+    addr: u64,
+    saved: u8,
+}
+
+/// GDB Stub
+///
+/// An implementation of the GDB Remote Serial Protocol over a dedicated serial port, which allows the kernel to be
+/// debugged by a remote GDB instance, even on real hardware.
+///
+/// GDB Docs: https://sourceware.org/gdb/onlinedocs/gdb/Remote-Protocol.html
+struct Stub {
+    port: ComPort,
+    breakpoints: [Option<Breakpoint>; MAX_BREAKPOINTS],
+    // Slots of the hardware watchpoints inserted by the remote debugger.
+    watchpoints: [bool; MAX_WATCHPOINTS],
+    // The watchpoint that caused the last stop, if any.
+    stop_watchpoint: Option<Watchpoint>,
+    // Whether the remote debugger resumed execution and is waiting for a stop reply.
+    resumed: bool,
+}
+
+enum Action {
+    Stay,
+    Resume,
+    Detach,
+}
+
+/// Response packet being assembled for the remote debugger.
+struct Response {
+    buf: [u8; PACKET_SIZE],
+    len: usize,
+}
+
+impl Response {
+    fn new() -> Self {
+        Self {
+            buf: [0; PACKET_SIZE],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, byte: u8) {
+        if self.len < self.buf.len() {
+            self.buf[self.len] = byte;
+            self.len += 1;
+        }
+    }
+
+    fn push_hex(&mut self, byte: u8) {
+        self.push(hex_digit(byte >> 4));
+        self.push(hex_digit(byte & 0xF));
+    }
+
+    /// Pushes the lowest `size` bytes of a value in target (little-endian) byte order.
+    fn push_le(&mut self, value: u64, size: usize) {
+        for byte in &value.to_le_bytes()[..size] {
+            self.push_hex(*byte);
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl Write for Response {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        s.bytes().for_each(|byte| self.push(byte));
+
+        Ok(())
+    }
+}
+
+impl Stub {
+    fn new(port: ComPort) -> Self {
+        Self {
+            port,
+            breakpoints: [None; MAX_BREAKPOINTS],
+            watchpoints: [false; MAX_WATCHPOINTS],
+            stop_watchpoint: None,
+            resumed: false,
+        }
+    }
+
+    fn run(&mut self, frame: &mut TrapFrame, stop_watchpoint: Option<Watchpoint>) {
+        self.stop_watchpoint = stop_watchpoint;
+
+        // The data received while the stub wasn't running is stale, e.g. the packet with which the remote debugger
+        // broke in, which it sends again as it hasn't been acknowledged.
+        while let Ok(Some(_)) = uart::read_byte(self.port) {}
+
+        // The processor reports the address following the `int3` instruction, which must be rewound for the
+        // breakpoints inserted by the stub so that the original instruction gets executed upon resumption.
+        if frame.vector == 0x03 && self.find_breakpoint(frame.rip.wrapping_sub(1)).is_some() {
+            frame.rip -= 1;
+        }
+
+        if STEPPING.swap(false, Ordering::Relaxed) {
+            frame.rflags &= !RFLAGS_TF;
+        }
+
+        if self.resumed {
+            let mut response = Response::new();
+            self.stop_reply(&mut response);
+            self.write_packet(response.as_bytes());
+        }
+
+        let mut packet = [0; PACKET_SIZE];
+        loop {
+            let len = self.read_packet(&mut packet);
+
+            let mut response = Response::new();
+            let action = self.handle(&packet[..len], frame, &mut response);
+
+            match action {
+                Action::Stay => self.write_packet(response.as_bytes()),
+                Action::Resume => {
+                    self.resumed = true;
+                    return;
+                }
+                Action::Detach => {
+                    self.resumed = false;
+                    ATTACHED.store(false, Ordering::Relaxed);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn handle(&mut self, packet: &[u8], frame: &mut TrapFrame, response: &mut Response) -> Action {
+        let (command, args) = match packet.split_first() {
+            Some((command, args)) => (*command, args),
+            None => return Action::Stay,
+        };
+
+        match command {
+            b'?' => self.stop_reply(response),
+            b'g' => {
+                for n in 0..REGISTER_COUNT {
+                    let (value, size) = read_register(frame, n);
+                    response.push_le(value, size);
+                }
+            }
+            b'G' => {
+                let mut offset = 0;
+                for n in 0..REGISTER_COUNT {
+                    let size = register_size(n);
+                    if let Some(value) = args.get(offset..offset + size * 2).and_then(parse_le) {
+                        write_register(frame, n, value);
+                    }
+                    offset += size * 2;
+                }
+                let _ = response.write_str("OK");
+            }
+            b'p' => match parse_hex(args).map(|n| n as usize) {
+                Some(n) if n < REGISTER_COUNT => {
+                    let (value, size) = read_register(frame, n);
+                    response.push_le(value, size);
+                }
+                _ => error(response, 0x01),
+            },
+            b'P' => {
+                let mut fields = args.splitn(2, |byte| *byte == b'=');
+                let n = fields.next().and_then(parse_hex).map(|n| n as usize);
+                let value = fields.next().and_then(parse_le);
+                match (n, value) {
+                    (Some(n), Some(value)) if n < REGISTER_COUNT => {
+                        write_register(frame, n, value);
+                        let _ = response.write_str("OK");
+                    }
+                    _ => error(response, 0x01),
+                }
+            }
+            b'm' => match parse_range(args) {
+                Some((addr, len)) if paging::is_accessible(addr, len) => {
+                    let len = len.min(PACKET_SIZE / 2);
+                    for offset in 0..len {
+                        let byte = unsafe { *((addr + offset as u64) as *const u8) };
+                        response.push_hex(byte);
+                    }
+                }
+                _ => error(response, 0x14),
+            },
+            b'M' => {
+                let mut fields = args.splitn(2, |byte| *byte == b':');
+                let range = fields.next().and_then(parse_range);
+                let data = fields.next().unwrap_or_default();
+                match range {
+                    Some((addr, len))
+                        if data.len() == len * 2 && paging::is_accessible(addr, len) =>
+                    {
+                        for (offset, digits) in data.chunks(2).enumerate() {
+                            let byte = parse_hex(digits).unwrap_or_default() as u8;
+                            unsafe { *((addr + offset as u64) as *mut u8) = byte };
+                        }
+                        let _ = response.write_str("OK");
+                    }
+                    _ => error(response, 0x14),
+                }
+            }
+            b'c' => {
+                if let Some(addr) = parse_hex(args) {
+                    frame.rip = addr;
+                }
+                return Action::Resume;
+            }
+            b's' => {
+                if let Some(addr) = parse_hex(args) {
+                    frame.rip = addr;
+                }
+                frame.rflags |= RFLAGS_TF;
+                STEPPING.store(true, Ordering::Relaxed);
+                return Action::Resume;
+            }
+            b'Z' | b'z' => {
+                let mut fields = args.splitn(3, |byte| *byte == b',');
+                let kind = fields.next();
+                let addr = fields.next().and_then(parse_hex);
+                let len = fields.next().and_then(parse_hex).unwrap_or(1) as usize;
+                let watchpoint_kind = match kind {
+                    Some(b"1") => Some(WatchpointKind::Execute),
+                    Some(b"2") => Some(WatchpointKind::Write),
+                    Some(b"4") => Some(WatchpointKind::ReadWrite),
+                    _ => None,
+                };
+                let result = match (kind, watchpoint_kind, addr) {
+                    (Some(b"0"), _, Some(addr)) if command == b'Z' => self.insert_breakpoint(addr),
+                    (Some(b"0"), _, Some(addr)) => self.remove_breakpoint(addr),
+                    (_, Some(kind), Some(addr)) if command == b'Z' => {
+                        self.insert_watchpoint(addr, kind, len)
+                    }
+                    (_, Some(kind), Some(addr)) => self.remove_watchpoint(addr, kind),
+                    // Other kinds of breakpoints (e.g. read watchpoints) are not supported and are reported with
+                    // an empty response.
+                    _ => return Action::Stay,
+                };
+                match result {
+                    Ok(()) => {
+                        let _ = response.write_str("OK");
+                    }
+                    Err(()) => error(response, 0x0E),
+                }
+            }
+            b'q' => query(args, response),
+            // The thread is selected for the following operations, while -1 stands for every thread and 0 for any. Only
+            // the registers of the stopped thread are known, which are reported whichever thread is selected.
+            b'H' => match args.get(1..) {
+                Some(b"-1" | b"0") => {
+                    let _ = response.write_str("OK");
+                }
+                Some(id) if parse_hex(id).is_some_and(is_thread) => {
+                    let _ = response.write_str("OK");
+                }
+                _ => error(response, 0x01),
+            },
+            b'T' => match parse_hex(args) {
+                Some(id) if is_thread(id) => {
+                    let _ = response.write_str("OK");
+                }
+                _ => error(response, 0x01),
+            },
+            b'D' => {
+                self.remove_all_breakpoints();
+                self.write_packet(b"OK");
+                return Action::Detach;
+            }
+            b'k' => {
+                self.remove_all_breakpoints();
+                return Action::Detach;
+            }
+            // Unsupported commands are reported with an empty response.
+            _ => {}
+        }
+
+        Action::Stay
+    }
+
+    fn find_breakpoint(&self, addr: u64) -> Option<usize> {
+        self.breakpoints.iter().position(
+            |breakpoint| matches!(breakpoint, Some(breakpoint) if breakpoint.addr == addr),
+        )
+    }
+
+    fn insert_breakpoint(&mut self, addr: u64) -> Result<(), ()> {
+        if self.find_breakpoint(addr).is_some() {
+            return Ok(());
+        }
+        if !paging::is_accessible(addr, 1) {
+            return Err(());
+        }
+
+        let slot = self
+            .breakpoints
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(())?;
+        unsafe {
+            let ptr = addr as *mut u8;
+            *slot = Some(Breakpoint { addr, saved: *ptr });
+            *ptr = INT3;
+        }
+
+        Ok(())
+    }
+
+    fn remove_breakpoint(&mut self, addr: u64) -> Result<(), ()> {
+        let index = self.find_breakpoint(addr).ok_or(())?;
+        if let Some(breakpoint) = self.breakpoints[index].take() {
+            unsafe { *(breakpoint.addr as *mut u8) = breakpoint.saved };
+        }
+
+        Ok(())
+    }
+
+    fn insert_watchpoint(&mut self, addr: u64, kind: WatchpointKind, len: usize) -> Result<(), ()> {
+        let addr = VirtAddr::try_new(addr).map_err(|_| ())?;
+        let slot = watchpoint::set(addr, kind, len, WatchpointAction::Break).map_err(|_| ())?;
+        self.watchpoints[slot] = true;
+
+        Ok(())
+    }
+
+    fn remove_watchpoint(&mut self, addr: u64, kind: WatchpointKind) -> Result<(), ()> {
+        let addr = VirtAddr::try_new(addr).map_err(|_| ())?;
+        let slot = watchpoint::find(addr, kind).ok_or(())?;
+        if !self.watchpoints[slot] {
+            return Err(());
+        }
+
+        watchpoint::clear(slot);
+        self.watchpoints[slot] = false;
+
+        Ok(())
+    }
+
+    fn remove_all_breakpoints(&mut self) {
+        for slot in self.breakpoints.iter_mut() {
+            if let Some(breakpoint) = slot.take() {
+                unsafe { *(breakpoint.addr as *mut u8) = breakpoint.saved };
+            }
+        }
+
+        for (slot, inserted) in self.watchpoints.iter_mut().enumerate() {
+            if core::mem::take(inserted) {
+                watchpoint::clear(slot);
+            }
+        }
+    }
+
+    fn stop_reply(&self, response: &mut Response) {
+        let _ = write!(response, "T{:02x}", SIGTRAP);
+
+        // The remote debugger is informed about the data address that triggered one of its watchpoints.
+        if let Some(watchpoint) = self.stop_watchpoint {
+            let _ = match watchpoint.kind {
+                WatchpointKind::Write => write!(response, "watch:{:x};", watchpoint.addr.as_u64()),
+                WatchpointKind::ReadWrite => {
+                    write!(response, "awatch:{:x};", watchpoint.addr.as_u64())
+                }
+                WatchpointKind::Execute => Ok(()),
+            };
+        }
+
+        let _ = write!(response, "thread:{:x};", current_thread());
+    }
+
+    fn receive(&self) -> u8 {
+        uart::receive(self.port).unwrap_or_default()
+    }
+
+    fn send(&self, byte: u8) {
+        let _ = uart::write(self.port, &[byte]);
+    }
+
+    /// Receives a packet and acknowledges it, returning the length of its payload.
+    fn read_packet(&mut self, buf: &mut [u8]) -> usize {
+        loop {
+            // Discard everything until the start of a packet.
+            while self.receive() != b'$' {}
+
+            let mut len = 0;
+            let mut checksum: u8 = 0;
+            let mut overflow = false;
+            loop {
+                let byte = self.receive();
+                if byte == b'#' {
+                    break;
+                }
+                checksum = checksum.wrapping_add(byte);
+                match buf.get_mut(len) {
+                    Some(slot) => {
+                        *slot = byte;
+                        len += 1;
+                    }
+                    None => overflow = true,
+                }
+            }
+
+            let digits = [self.receive(), self.receive()];
+            if !overflow && parse_hex(&digits) == Some(checksum as u64) {
+                self.send(b'+');
+                return len;
+            }
+            self.send(b'-');
+        }
+    }
+
+    /// Sends a packet and waits until the remote debugger acknowledges it.
+    fn write_packet(&mut self, data: &[u8]) {
+        loop {
+            let checksum = data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+
+            self.send(b'$');
+            data.iter().for_each(|byte| self.send(*byte));
+            self.send(b'#');
+            self.send(hex_digit(checksum >> 4));
+            self.send(hex_digit(checksum & 0xF));
+
+            loop {
+                match self.receive() {
+                    b'+' => return,
+                    b'-' => break,
+                    _ => continue,
+                }
+            }
+        }
+    }
+}
+
+// GDB's register layout for x86_64: RAX, RBX, RCX, RDX, RSI, RDI, RBP, RSP, R8-R15, RIP (64-bit each), followed by
+// EFLAGS, CS, SS, DS, ES, FS, GS (32-bit each). The x87 and SSE registers are not reported, since the kernel does not
+// use them.
+const REGISTER_COUNT: usize = 24;
+
+fn register_size(n: usize) -> usize {
+    if n <= 16 {
+        8
+    } else {
+        4
+    }
+}
+
+fn register_mut(frame: &mut TrapFrame, n: usize) -> Option<&mut u64> {
+    let register = match n {
+        0 => &mut frame.rax,
+        1 => &mut frame.rbx,
+        2 => &mut frame.rcx,
+        3 => &mut frame.rdx,
+        4 => &mut frame.rsi,
+        5 => &mut frame.rdi,
+        6 => &mut frame.rbp,
+        7 => &mut frame.rsp,
+        8 => &mut frame.r8,
+        9 => &mut frame.r9,
+        10 => &mut frame.r10,
+        11 => &mut frame.r11,
+        12 => &mut frame.r12,
+        13 => &mut frame.r13,
+        14 => &mut frame.r14,
+        15 => &mut frame.r15,
+        16 => &mut frame.rip,
+        17 => &mut frame.rflags,
+        18 => &mut frame.cs,
+        19 => &mut frame.ss,
+        _ => return None,
+    };
+
+    Some(register)
+}
+
+fn read_register(frame: &mut TrapFrame, n: usize) -> (u64, usize) {
+    let value = match register_mut(frame, n) {
+        Some(register) => *register,
+        // The data segment registers are not saved in the trap frame, since they never change in the kernel.
+        None => {
+            let selector = match n {
+                20 => DS::get_reg(),
+                21 => ES::get_reg(),
+                22 => FS::get_reg(),
+                _ => GS::get_reg(),
+            };
+            selector.0 as u64
+        }
+    };
+
+    (value, register_size(n))
+}
+
+fn write_register(frame: &mut TrapFrame, n: usize, value: u64) {
+    // Changes to the segment registers are not supported and are silently ignored.
+    if n > 17 {
+        return;
+    }
+
+    if let Some(register) = register_mut(frame, n) {
+        *register = value;
+    }
+}
+
+/// Converts the ID of a thread to the one reported to the remote debugger, to which zero stands for any thread.
+fn thread_id(id: ThreadId) -> u64 {
+    id.as_u64() + 1
+}
+
+/// Returns the ID of the running thread, as reported to the remote debugger.
+fn current_thread() -> u64 {
+    thread::threads()
+        .find(|info| info.state == ThreadState::Running)
+        .map_or(BOOT_THREAD_ID, |info| thread_id(info.id))
+}
+
+/// Checks whether a thread reported to the remote debugger still exists.
+fn is_thread(id: u64) -> bool {
+    id == current_thread()
+        || thread::threads()
+            .any(|info| info.state != ThreadState::Exited && thread_id(info.id) == id)
+}
+
+fn query(args: &[u8], response: &mut Response) {
+    let name = args.split(|byte| *byte == b':').next().unwrap_or_default();
+
+    let _ = match name {
+        b"Supported" => write!(response, "PacketSize={:x}", PACKET_SIZE),
+        b"C" => write!(response, "QC{:x}", current_thread()),
+        // Every thread is listed at once, hence the subsequent query ends the list.
+        b"fThreadInfo" => {
+            let current = current_thread();
+            let _ = write!(response, "m{:x}", current);
+            thread::threads()
+                .filter(|info| info.state != ThreadState::Exited)
+                .map(|info| thread_id(info.id))
+                .filter(|id| *id != current)
+                .try_for_each(|id| write!(response, ",{:x}", id))
+        }
+        b"sThreadInfo" => response.write_str("l"),
+        b"Attached" => response.write_str("1"),
+        // Unsupported queries are reported with an empty response.
+        _ => Ok(()),
+    };
+}
+
+fn error(response: &mut Response, code: u8) {
+    response.push(b'E');
+    response.push_hex(code);
+}
+
+fn hex_digit(nibble: u8) -> u8 {
+    b"0123456789abcdef"[(nibble & 0xF) as usize]
+}
+
+fn parse_hex(digits: &[u8]) -> Option<u64> {
+    if digits.is_empty() || digits.len() > 16 {
+        return None;
+    }
+
+    digits.iter().try_fold(0u64, |value, digit| {
+        let nibble = (*digit as char).to_digit(16)?;
+        Some(value << 4 | nibble as u64)
+    })
+}
+
+/// Parses a value encoded in target (little-endian) byte order.
+fn parse_le(digits: &[u8]) -> Option<u64> {
+    if digits.len() % 2 != 0 || digits.len() > 16 {
+        return None;
+    }
+
+    digits
+        .chunks(2)
+        .rev()
+        .try_fold(0u64, |value, byte| Some(value << 8 | parse_hex(byte)?))
+}
+
+/// Parses an `addr,length` pair.
+fn parse_range(args: &[u8]) -> Option<(u64, usize)> {
+    let mut fields = args.splitn(2, |byte| *byte == b',');
+    let addr = fields.next().and_then(parse_hex)?;
+    let len = fields.next().and_then(parse_hex)?;
+
+    Some((addr, len as usize))
+}
+
+/// Asks for the context interrupted by the port to be stopped, which happens once the interrupt returns.
+fn interrupt() {
+    ATTACHED.store(true, Ordering::Relaxed);
+    BREAKING_IN.store(true, Ordering::Relaxed);
+}
+
+fn enable(arg: &Arg) -> Result<(), ParamError> {
+    arg.flag()?;
+    REQUESTED.store(true, Ordering::Relaxed);
+
+    Ok(())
+}
+
+pub fn init() -> Result<(), ()> {
+    cmdline::register(&GDB).map_err(|_| ())?;
+
+    // The port is left alone if it's been taken over by the console.
+    if !REQUESTED.load(Ordering::Relaxed)
+        || serial::console_port() == GDB_PORT
+        || !uart::is_present(GDB_PORT)
+    {
+        return Ok(());
+    }
+
+    uart::set_attention(GDB_PORT, &BREAK_IN_KEYS, interrupt).map_err(|_| ())?;
+
+    *STUB.lock() = Some(Stub::new(GDB_PORT));
+
+    log::info!("GDB stub is waiting for a remote debugger on COM2");
+
+    Ok(())
+}
+
+/// Checks whether a remote debugger has broken in and hasn't detached since.
+pub fn is_attached() -> bool {
+    ATTACHED.load(Ordering::Relaxed)
+}
+
+/// Stops the context interrupted by an IRQ if the remote debugger is breaking in, by single-stepping it upon the
+/// return from the interrupt, so that the debugger is given its registers rather than those of the interrupt handler.
+///
+/// Returns whether the context is to be stopped.
+pub fn break_in(stack_frame: &mut InterruptStackFrame) -> bool {
+    if !BREAKING_IN.swap(false, Ordering::Relaxed) {
+        return false;
+    }
+
+    STEPPING.store(true, Ordering::Relaxed);
+    unsafe {
+        stack_frame
+            .as_mut()
+            .update(|frame| frame.cpu_flags |= RFLAGS_TF)
+    };
+
+    true
+}
+
+/// Checks whether the remote debugger requested to single-step the interrupted context.
+pub fn is_stepping() -> bool {
+    STEPPING.load(Ordering::Relaxed)
+}
+
+/// Hands control over to the remote debugger until it resumes the execution of the interrupted context.
+pub fn enter(frame: &mut TrapFrame) {
+    if let Some(stub) = STUB.lock().as_mut() {
+        stub.run(frame, None);
+    }
+}
+
+/// Hands control over to the remote debugger after a hit on a hardware watchpoint.
+pub fn enter_watchpoint(frame: &mut TrapFrame, watchpoint: Watchpoint) {
+    if let Some(stub) = STUB.lock().as_mut() {
+        stub.run(frame, Some(watchpoint));
+    }
+}
@@ -20,6 +20,8 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+use core::ptr::{addr_of, addr_of_mut};
+
 use lazy_static::lazy_static;
 use x86_64::instructions;
 use x86_64::instructions::segmentation::Segment;
@@ -29,31 +31,21 @@ use x86_64::structures::tss::TaskStateSegment;
 use x86_64::VirtAddr;
 
 use super::exceptions::DoubleFaultException;
+use super::preliminary;
 
 pub const STACK_SIZE: usize = 8192;
 
-lazy_static! {
-    /// Task State Segment (TSS)
-    ///
-    /// The TSS is a binary data structure specific to the IA-32 and x86-64 architectures that holds information
-    /// about a task. In Long Mode, the TSS has a separate structure and is used to change the Stack Pointer after
-    /// an interrupt or permission level change. It's important to update the TSS manually in the multitasking
-    /// function since it does not save registers automatically.
-    ///
-    /// OS Dev Wiki: https://wiki.osdev.org/Task_State_Segment
-    static ref TSS: TaskStateSegment = {
-        let mut tss = TaskStateSegment::new();
-
-        tss.interrupt_stack_table[DoubleFaultException::IST_INDEX] = {
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-            let stack_bottom = VirtAddr::from_ptr(unsafe { &STACK });
-            let stack_top = stack_bottom + STACK_SIZE;
-            stack_top
-        };
-
-        tss
-    };
-}
+/// Task State Segment (TSS)
+///
+/// The TSS is a binary data structure specific to the IA-32 and x86-64 architectures that holds information
+/// about a task. In Long Mode, the TSS has a separate structure and is used to change the Stack Pointer after
+/// an interrupt or permission level change. It's important to update the TSS manually in the multitasking
+/// function since it does not save registers automatically.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/Task_State_Segment
+static mut TSS: TaskStateSegment = TaskStateSegment::new();
+
+static mut DOUBLE_FAULT_STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
 
 lazy_static! {
     /// Global Descriptor Table (GDT)
@@ -71,7 +63,7 @@ lazy_static! {
 
         let k_code_segment_selector = gdt.add_entry(Descriptor::kernel_code_segment());
         let k_data_segment_selector = gdt.add_entry(Descriptor::kernel_data_segment());
-        let tss_selector = gdt.add_entry(Descriptor::tss_segment(&TSS));
+        let tss_selector = gdt.add_entry(Descriptor::tss_segment(unsafe { &*addr_of!(TSS) }));
 
         (
             gdt,
@@ -91,7 +83,20 @@ pub enum GDTEntry {
     TaskStateSegment,
 }
 
+/// Sets the stack onto which the processor switches when an interrupt arrives at a lower privilege level, which has to
+/// follow the thread running on the processor.
+pub fn set_kernel_stack(top: VirtAddr) {
+    unsafe { (*addr_of_mut!(TSS)).privilege_stack_table[0] = top };
+}
+
 pub fn init() -> Result<(), ()> {
+    unsafe {
+        let tss = &mut *addr_of_mut!(TSS);
+        tss.interrupt_stack_table[DoubleFaultException::IST_INDEX] =
+            VirtAddr::from_ptr(addr_of!(DOUBLE_FAULT_STACK)) + STACK_SIZE;
+    }
+    set_kernel_stack(preliminary::stack::top());
+
     // Load the GDT into the processor's Global Descriptor Table Register (GDTR).
     GDT.0.load();
     unsafe {
@@ -0,0 +1,401 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use x86_64::instructions;
+use x86_64::instructions::port::Port;
+
+use crate::kernel::sync::Mutex;
+
+use super::irq;
+
+const DATA_PORT: u16 = 0x60;
+// The same port is used for reading the status and for writing commands.
+const STATUS_PORT: u16 = 0x64;
+const COMMAND_PORT: u16 = 0x64;
+
+const STATUS_OUTPUT_FULL: u8 = 1 << 0;
+const STATUS_INPUT_FULL: u8 = 1 << 1;
+// The data in the output buffer has been received from the second port.
+const STATUS_AUX_DATA: u8 = 1 << 5;
+
+const CMD_READ_CONFIG: u8 = 0x20;
+const CMD_WRITE_CONFIG: u8 = 0x60;
+const CMD_DISABLE_PORT_2: u8 = 0xA7;
+const CMD_ENABLE_PORT_2: u8 = 0xA8;
+const CMD_TEST_PORT_2: u8 = 0xA9;
+const CMD_SELF_TEST: u8 = 0xAA;
+const CMD_TEST_PORT_1: u8 = 0xAB;
+const CMD_DISABLE_PORT_1: u8 = 0xAD;
+const CMD_ENABLE_PORT_1: u8 = 0xAE;
+const CMD_WRITE_PORT_2: u8 = 0xD4;
+
+const CONFIG_PORT_1_INTERRUPT: u8 = 1 << 0;
+const CONFIG_PORT_2_INTERRUPT: u8 = 1 << 1;
+const CONFIG_PORT_2_CLOCK_DISABLED: u8 = 1 << 5;
+// The controller translates the scancodes received from the first port from set 2 into set 1.
+const CONFIG_TRANSLATION: u8 = 1 << 6;
+
+const SELF_TEST_PASSED: u8 = 0x55;
+const PORT_TEST_PASSED: u8 = 0x00;
+
+const DEVICE_CMD_RESET: u8 = 0xFF;
+const DEVICE_SELF_TEST_PASSED: u8 = 0xAA;
+
+/// The response of a device which has accepted a command.
+pub const ACK: u8 = 0xFA;
+/// The response of a device which asks for the last byte to be sent again.
+pub const RESEND: u8 = 0xFE;
+
+/// The number of times the status is polled before giving up on the controller or a device.
+const TIMEOUT: usize = 100_000;
+/// The number of times a byte is sent to a device which keeps asking for it again.
+const MAX_RESENDS: usize = 3;
+/// The number of times a device is waited upon after a reset, which takes much longer than other commands.
+const RESET_ATTEMPTS: usize = 10;
+
+/// The ports of the controller, to which a keyboard and a mouse are usually attached, respectively.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ps2Port {
+    First,
+    Second,
+}
+
+impl Ps2Port {
+    pub fn irq(self) -> u8 {
+        match self {
+            Ps2Port::First => 1,
+            Ps2Port::Second => 12,
+        }
+    }
+
+    fn interrupt_flag(self) -> u8 {
+        match self {
+            Ps2Port::First => CONFIG_PORT_1_INTERRUPT,
+            Ps2Port::Second => CONFIG_PORT_2_INTERRUPT,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ps2Error {
+    /// No working device port is present.
+    NotPresent,
+    /// The controller or the device did not respond in time.
+    Timeout,
+    /// The device kept asking for a byte to be sent again.
+    Resend,
+    /// The device responded with something other than an acknowledgement.
+    Unexpected(u8),
+    /// The port has already been claimed by a driver.
+    Claimed,
+}
+
+/// A function receiving the bytes of a device, invoked from the interrupt handler of its port.
+pub type DataHandler = fn(u8);
+
+/// PS/2 Controller (Intel 8042)
+///
+/// The controller connects up to two serial devices to the data port, and raises IRQ 1 and IRQ 12 when a byte has
+/// been received from the first and the second port, respectively. The drivers of the devices claim a port with a
+/// handler, which is fed with the data as it arrives.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/%228042%22_PS/2_Controller
+struct Controller {
+    data: Port<u8>,
+    status: Port<u8>,
+    command: Port<u8>,
+    ports: [bool; 2],
+    handlers: [Option<DataHandler>; 2],
+}
+
+impl Controller {
+    const fn new() -> Self {
+        Self {
+            data: Port::new(DATA_PORT),
+            status: Port::new(STATUS_PORT),
+            command: Port::new(COMMAND_PORT),
+            ports: [false; 2],
+            handlers: [None; 2],
+        }
+    }
+
+    fn wait_for(&mut self, mask: u8, set: bool) -> Result<(), Ps2Error> {
+        for _ in 0..TIMEOUT {
+            if (unsafe { self.status.read() } & mask != 0) == set {
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+
+        Err(Ps2Error::Timeout)
+    }
+
+    fn send_command(&mut self, command: u8) -> Result<(), Ps2Error> {
+        self.wait_for(STATUS_INPUT_FULL, false)?;
+        unsafe { self.command.write(command) };
+
+        Ok(())
+    }
+
+    fn write_data(&mut self, value: u8) -> Result<(), Ps2Error> {
+        self.wait_for(STATUS_INPUT_FULL, false)?;
+        unsafe { self.data.write(value) };
+
+        Ok(())
+    }
+
+    fn read_data(&mut self) -> Result<u8, Ps2Error> {
+        self.wait_for(STATUS_OUTPUT_FULL, true)?;
+
+        Ok(unsafe { self.data.read() })
+    }
+
+    fn try_read_data(&mut self) -> Option<(Ps2Port, u8)> {
+        let status = unsafe { self.status.read() };
+        if status & STATUS_OUTPUT_FULL == 0 {
+            return None;
+        }
+
+        let port = match status & STATUS_AUX_DATA != 0 {
+            true => Ps2Port::Second,
+            false => Ps2Port::First,
+        };
+        Some((port, unsafe { self.data.read() }))
+    }
+
+    fn flush(&mut self) {
+        while self.try_read_data().is_some() {}
+    }
+
+    fn read_config(&mut self) -> Result<u8, Ps2Error> {
+        self.send_command(CMD_READ_CONFIG)?;
+        self.read_data()
+    }
+
+    fn write_config(&mut self, config: u8) -> Result<(), Ps2Error> {
+        self.send_command(CMD_WRITE_CONFIG)?;
+        self.write_data(config)
+    }
+
+    fn write_device(&mut self, port: Ps2Port, value: u8) -> Result<(), Ps2Error> {
+        if !self.ports[port as usize] {
+            return Err(Ps2Error::NotPresent);
+        }
+        if port == Ps2Port::Second {
+            self.send_command(CMD_WRITE_PORT_2)?;
+        }
+
+        self.write_data(value)
+    }
+
+    /// Runs the self-tests of the controller and of its ports, and leaves the working ports enabled with their
+    /// interrupts disabled.
+    fn initialize(&mut self) -> Result<(), Ps2Error> {
+        self.send_command(CMD_DISABLE_PORT_1)?;
+        self.send_command(CMD_DISABLE_PORT_2)?;
+        self.flush();
+
+        let mut config = self.read_config()?;
+        config &= !(CONFIG_PORT_1_INTERRUPT | CONFIG_PORT_2_INTERRUPT | CONFIG_TRANSLATION);
+        self.write_config(config)?;
+
+        self.send_command(CMD_SELF_TEST)?;
+        match self.read_data()? {
+            SELF_TEST_PASSED => {}
+            response => return Err(Ps2Error::Unexpected(response)),
+        }
+        // The self-test may reset the controller on some hardware.
+        self.write_config(config)?;
+
+        // The clock of the second port is only enabled on a dual-channel controller.
+        self.send_command(CMD_ENABLE_PORT_2)?;
+        let dual_channel = self.read_config()? & CONFIG_PORT_2_CLOCK_DISABLED == 0;
+        self.send_command(CMD_DISABLE_PORT_2)?;
+
+        self.send_command(CMD_TEST_PORT_1)?;
+        self.ports[Ps2Port::First as usize] = self.read_data()? == PORT_TEST_PASSED;
+        if dual_channel {
+            self.send_command(CMD_TEST_PORT_2)?;
+            self.ports[Ps2Port::Second as usize] = self.read_data()? == PORT_TEST_PASSED;
+        }
+
+        if self.ports[Ps2Port::First as usize] {
+            self.send_command(CMD_ENABLE_PORT_1)?;
+        }
+        if self.ports[Ps2Port::Second as usize] {
+            self.send_command(CMD_ENABLE_PORT_2)?;
+        }
+        self.flush();
+
+        Ok(())
+    }
+}
+
+static CONTROLLER: Mutex<Controller> = Mutex::new(Controller::new());
+
+fn with_controller<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut Controller) -> R,
+{
+    // The lock is also acquired by the interrupt handlers.
+    instructions::interrupts::without_interrupts(|| f(&mut CONTROLLER.lock()))
+}
+
+fn dispatch(port: Ps2Port, value: u8) {
+    // The handler is invoked without holding the lock, so that it can send commands to its device.
+    if let Some(handler) = with_controller(|controller| controller.handlers[port as usize]) {
+        handler(value);
+    }
+}
+
+/// Passes the byte which raised the interrupt to the handler of the port it came from, as told by the controller
+/// rather than by the IRQ.
+fn on_irq() {
+    // The lock must be released before dispatching, as the handler may use the controller.
+    let data = with_controller(Controller::try_read_data);
+    if let Some((port, value)) = data {
+        dispatch(port, value);
+    }
+}
+
+/// Checks whether the port has passed its self-test, which suggests that a device may be attached to it.
+pub fn is_present(port: Ps2Port) -> bool {
+    with_controller(|controller| controller.ports[port as usize])
+}
+
+/// Sends a byte to the device, without waiting for its response.
+pub fn write(port: Ps2Port, value: u8) -> Result<(), Ps2Error> {
+    with_controller(|controller| controller.write_device(port, value))
+}
+
+/// Waits for a byte from the device.
+///
+/// This is meant for the initialization of a device, before its port has been claimed.
+pub fn read(port: Ps2Port) -> Result<u8, Ps2Error> {
+    with_controller(|controller| {
+        for _ in 0..TIMEOUT {
+            match controller.try_read_data() {
+                Some((source, value)) if source == port => return Ok(value),
+                _ => core::hint::spin_loop(),
+            }
+        }
+
+        Err(Ps2Error::Timeout)
+    })
+}
+
+/// Sends a byte to the device and waits for it to be acknowledged, sending it again as requested by the device.
+///
+/// This is meant for the initialization of a device, before its port has been claimed.
+pub fn command(port: Ps2Port, value: u8) -> Result<(), Ps2Error> {
+    for _ in 0..MAX_RESENDS {
+        write(port, value)?;
+        match read(port)? {
+            ACK => return Ok(()),
+            RESEND => continue,
+            response => return Err(Ps2Error::Unexpected(response)),
+        }
+    }
+
+    Err(Ps2Error::Resend)
+}
+
+/// Resets the device and waits for it to pass its self-test.
+///
+/// This is meant for the initialization of a device, before its port has been claimed.
+pub fn reset(port: Ps2Port) -> Result<(), Ps2Error> {
+    command(port, DEVICE_CMD_RESET)?;
+
+    for _ in 0..RESET_ATTEMPTS {
+        match read(port) {
+            Ok(DEVICE_SELF_TEST_PASSED) => return Ok(()),
+            Ok(response) => return Err(Ps2Error::Unexpected(response)),
+            Err(Ps2Error::Timeout) => continue,
+            Err(error) => return Err(error),
+        }
+    }
+
+    Err(Ps2Error::Timeout)
+}
+
+/// Enables or disables the translation of the scancodes received from the first port into scancode set 1.
+pub fn set_translation(enabled: bool) -> Result<(), Ps2Error> {
+    with_controller(|controller| {
+        let config = controller.read_config()?;
+        match enabled {
+            true => controller.write_config(config | CONFIG_TRANSLATION),
+            false => controller.write_config(config & !CONFIG_TRANSLATION),
+        }
+    })
+}
+
+/// Claims the port for a driver, whose handler receives the data of the device from the interrupt handler.
+pub fn claim(port: Ps2Port, handler: DataHandler) -> Result<(), Ps2Error> {
+    with_controller(|controller| {
+        if !controller.ports[port as usize] {
+            return Err(Ps2Error::NotPresent);
+        }
+        if controller.handlers[port as usize].is_some() {
+            return Err(Ps2Error::Claimed);
+        }
+        controller.handlers[port as usize] = Some(handler);
+
+        let config = controller.read_config()?;
+        controller.write_config(config | port.interrupt_flag())
+    })?;
+
+    irq::register(port.irq(), on_irq).map_err(|_| Ps2Error::Claimed)
+}
+
+/// Feeds the pending data to the handlers of the ports, which keeps the devices usable while the interrupts are
+/// disabled (e.g. in the kernel monitor).
+pub fn poll() {
+    while let Some((port, value)) = with_controller(Controller::try_read_data) {
+        dispatch(port, value);
+    }
+}
+
+pub(crate) fn init() -> Result<(), ()> {
+    // Not every machine has a PS/2 controller, in which case there's nothing to drive.
+    if let Err(error) = with_controller(Controller::initialize) {
+        log::warn!("no working PS/2 controller: {:?}", error);
+        return Ok(());
+    }
+
+    with_controller(|controller| {
+        log::info!(
+            "PS/2 controller: first port {}, second port {}",
+            if controller.ports[0] {
+                "working"
+            } else {
+                "absent"
+            },
+            if controller.ports[1] {
+                "working"
+            } else {
+                "absent"
+            },
+        );
+    });
+
+    Ok(())
+}
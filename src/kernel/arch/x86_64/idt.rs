@@ -23,7 +23,10 @@
 use lazy_static::lazy_static;
 use x86_64::structures::idt::InterruptDescriptorTable;
 
-use super::exceptions::{BreakpointException, DoubleFaultException};
+use super::exceptions::DoubleFaultException;
+use super::irq;
+use super::lapic;
+use super::trap::TrapEntry;
 
 lazy_static! {
     /// Interrupt Descriptor Table (IDT)
@@ -38,8 +41,12 @@ lazy_static! {
     static ref IDT: InterruptDescriptorTable = {
         let mut idt = InterruptDescriptorTable::new();
 
-        // Set breakpoint handler.
-        idt.breakpoint.set_handler_fn(BreakpointException::handler);
+        // Set debug and breakpoint handlers. These are reached through the trap entry stubs, which preserve the
+        // complete register state of the interrupted context.
+        unsafe {
+            idt.debug.set_handler_addr(TrapEntry::Debug.addr());
+            idt.breakpoint.set_handler_addr(TrapEntry::Breakpoint.addr());
+        }
 
         // Set double fault handler and a dedicated stack index for it.
         unsafe {
@@ -47,6 +54,10 @@ lazy_static! {
                             .set_stack_index(DoubleFaultException::IST_INDEX as u16);
         }
 
+        // Set handlers of the hardware interrupts.
+        irq::install(&mut idt);
+        lapic::install(&mut idt);
+
         idt
     };
 }
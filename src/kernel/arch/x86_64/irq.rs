@@ -0,0 +1,255 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use x86_64::instructions;
+use x86_64::structures::idt::{HandlerFunc, InterruptDescriptorTable, InterruptStackFrame};
+
+use crate::kernel::sync::Mutex;
+use crate::kernel::thread;
+
+use super::gdb;
+use super::lapic;
+use super::pic;
+use super::pic::{PIC_1_OFFSET, PIC_2_OFFSET};
+
+/// The number of IRQ lines served by the PICs.
+pub const LEGACY_IRQ_COUNT: u8 = pic::IRQ_COUNT;
+/// The number of IRQs which aren't connected to any line, but are allocated to the devices signaling their
+/// interrupts through the vectors directly (e.g. MSI).
+pub const ALLOCATABLE_IRQ_COUNT: u8 = 32;
+pub const IRQ_COUNT: u8 = LEGACY_IRQ_COUNT + ALLOCATABLE_IRQ_COUNT;
+
+/// The interrupt vector of the first allocatable IRQ, which follow the vectors of the PICs.
+const ALLOCATABLE_VECTOR_BASE: u8 = PIC_2_OFFSET + 8;
+
+/// A function servicing the interrupts of an IRQ line.
+pub type IrqHandler = fn();
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IrqError {
+    /// The IRQ does not exist, or it hasn't been allocated.
+    InvalidIrq,
+    /// The IRQ has already been claimed by a handler.
+    Claimed,
+    /// Every allocatable IRQ is in use.
+    Exhausted,
+}
+
+static HANDLERS: Mutex<[Option<IrqHandler>; IRQ_COUNT as usize]> =
+    Mutex::new([None; IRQ_COUNT as usize]);
+
+// The allocatable IRQs which are in use, one bit each.
+static ALLOCATED: Mutex<u32> = Mutex::new(0);
+
+// The number of interrupts of each IRQ, apart from the spurious ones.
+static COUNTS: [AtomicU64; IRQ_COUNT as usize] = [const { AtomicU64::new(0) }; IRQ_COUNT as usize];
+
+// Every IRQ requires a dedicated entry in the IDT, since the handlers using the `x86-interrupt` calling convention
+// don't receive the vector they were invoked for.
+macro_rules! irq_entries {
+    ($($irq:literal => $entry:ident),* $(,)?) => {
+        $(
+            extern "x86-interrupt" fn $entry(mut stack_frame: InterruptStackFrame) {
+                dispatch($irq, &mut stack_frame);
+            }
+        )*
+
+        const ENTRIES: [HandlerFunc; IRQ_COUNT as usize] = [$($entry),*];
+    };
+}
+
+irq_entries! {
+    0 => irq_0,
+    1 => irq_1,
+    2 => irq_2,
+    3 => irq_3,
+    4 => irq_4,
+    5 => irq_5,
+    6 => irq_6,
+    7 => irq_7,
+    8 => irq_8,
+    9 => irq_9,
+    10 => irq_10,
+    11 => irq_11,
+    12 => irq_12,
+    13 => irq_13,
+    14 => irq_14,
+    15 => irq_15,
+    16 => irq_16,
+    17 => irq_17,
+    18 => irq_18,
+    19 => irq_19,
+    20 => irq_20,
+    21 => irq_21,
+    22 => irq_22,
+    23 => irq_23,
+    24 => irq_24,
+    25 => irq_25,
+    26 => irq_26,
+    27 => irq_27,
+    28 => irq_28,
+    29 => irq_29,
+    30 => irq_30,
+    31 => irq_31,
+    32 => irq_32,
+    33 => irq_33,
+    34 => irq_34,
+    35 => irq_35,
+    36 => irq_36,
+    37 => irq_37,
+    38 => irq_38,
+    39 => irq_39,
+    40 => irq_40,
+    41 => irq_41,
+    42 => irq_42,
+    43 => irq_43,
+    44 => irq_44,
+    45 => irq_45,
+    46 => irq_46,
+    47 => irq_47,
+}
+
+fn dispatch(irq: u8, stack_frame: &mut InterruptStackFrame) {
+    if is_legacy(irq) && pic::is_spurious(irq) {
+        return;
+    }
+    COUNTS[irq as usize].fetch_add(1, Ordering::Relaxed);
+
+    let handler = HANDLERS.lock()[irq as usize];
+    match handler {
+        Some(handler) => handler(),
+        None => log::warn!("unhandled IRQ {}", irq),
+    }
+
+    // The allocatable IRQs are delivered by the local APIC rather than the PICs.
+    match is_legacy(irq) {
+        true => pic::end_of_interrupt(irq),
+        false => lapic::end_of_interrupt(),
+    }
+
+    // A remote debugger breaking in stops the interrupted thread right away, rather than once it's picked again.
+    if gdb::break_in(stack_frame) {
+        return;
+    }
+
+    // The interrupted thread may only be switched away from once the interrupt has been acknowledged, as it isn't
+    // resumed until the scheduler picks it again.
+    thread::preempt();
+}
+
+/// Checks whether an IRQ is a line of the PICs, as opposed to an allocatable IRQ.
+pub fn is_legacy(irq: u8) -> bool {
+    irq < LEGACY_IRQ_COUNT
+}
+
+/// Returns the interrupt vector to which an IRQ is mapped.
+pub fn vector(irq: u8) -> u8 {
+    match is_legacy(irq) {
+        true => PIC_1_OFFSET + irq,
+        false => ALLOCATABLE_VECTOR_BASE + (irq - LEGACY_IRQ_COUNT),
+    }
+}
+
+/// Returns the number of interrupts raised by an IRQ since boot.
+pub fn count(irq: u8) -> u64 {
+    COUNTS
+        .get(irq as usize)
+        .map_or(0, |count| count.load(Ordering::Relaxed))
+}
+
+/// Checks whether a handler has been registered for an IRQ.
+pub fn is_registered(irq: u8) -> bool {
+    instructions::interrupts::without_interrupts(|| {
+        HANDLERS
+            .lock()
+            .get(irq as usize)
+            .is_some_and(|handler| handler.is_some())
+    })
+}
+
+/// Installs the entries of the IRQs into the IDT.
+pub(crate) fn install(idt: &mut InterruptDescriptorTable) {
+    for (irq, entry) in ENTRIES.iter().enumerate() {
+        idt[vector(irq as u8) as usize].set_handler_fn(*entry);
+    }
+}
+
+/// Allocates an IRQ which isn't connected to any line, for a device which signals its interrupts by writing the
+/// vector of the IRQ to the local APIC (e.g. through MSI).
+///
+/// The handler is registered as for any other IRQ.
+pub fn allocate() -> Result<u8, IrqError> {
+    let mut allocated = ALLOCATED.lock();
+    let index = (0..ALLOCATABLE_IRQ_COUNT)
+        .find(|index| *allocated & (1 << index) == 0)
+        .ok_or(IrqError::Exhausted)?;
+    *allocated |= 1 << index;
+
+    Ok(LEGACY_IRQ_COUNT + index)
+}
+
+/// Releases an allocated IRQ along with its handler.
+pub fn free(irq: u8) -> Result<(), IrqError> {
+    if is_legacy(irq) || irq >= IRQ_COUNT {
+        return Err(IrqError::InvalidIrq);
+    }
+
+    instructions::interrupts::without_interrupts(|| {
+        HANDLERS.lock()[irq as usize] = None;
+        *ALLOCATED.lock() &= !(1 << (irq - LEGACY_IRQ_COUNT));
+    });
+
+    Ok(())
+}
+
+/// Registers the handler of an IRQ, unmasking it if it's a line of the PICs.
+///
+/// Fails if the IRQ does not exist, if it has not been allocated, or if it has already been claimed.
+pub fn register(irq: u8, handler: IrqHandler) -> Result<(), IrqError> {
+    if !is_legacy(irq) && irq < IRQ_COUNT {
+        let index = irq - LEGACY_IRQ_COUNT;
+        if *ALLOCATED.lock() & (1 << index) == 0 {
+            return Err(IrqError::InvalidIrq);
+        }
+    }
+
+    instructions::interrupts::without_interrupts(|| {
+        let mut handlers = HANDLERS.lock();
+        let slot = handlers.get_mut(irq as usize).ok_or(IrqError::InvalidIrq)?;
+        if slot.is_some() {
+            return Err(IrqError::Claimed);
+        }
+        *slot = Some(handler);
+
+        if is_legacy(irq) {
+            pic::unmask(irq);
+        }
+
+        Ok(())
+    })
+}
+
+pub(crate) fn init() -> Result<(), ()> {
+    pic::init()
+}
@@ -0,0 +1,156 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::kernel::input;
+use crate::kernel::input::keyboard::{KeyCode, KeyState, Keyboard, Modifiers, ScancodeSet};
+use crate::kernel::input::InputEvent;
+use crate::kernel::sync::Mutex;
+
+use super::i8042;
+use super::i8042::{Ps2Error, Ps2Port};
+
+const PORT: Ps2Port = Ps2Port::First;
+
+const CMD_SET_LEDS: u8 = 0xED;
+const CMD_SCANCODE_SET: u8 = 0xF0;
+const CMD_ENABLE_SCANNING: u8 = 0xF4;
+const CMD_DISABLE_SCANNING: u8 = 0xF5;
+// The keyboard reports an error or a buffer overrun with these bytes.
+const KEY_ERROR: u8 = 0x00;
+const KEY_ERROR_ALT: u8 = 0xFF;
+
+/// The argument of the scancode set command which queries the current set.
+const GET_SCANCODE_SET: u8 = 0x00;
+
+const LED_SCROLL_LOCK: u8 = 1 << 0;
+const LED_NUM_LOCK: u8 = 1 << 1;
+const LED_CAPS_LOCK: u8 = 1 << 2;
+
+static KEYBOARD: Mutex<Option<Keyboard>> = Mutex::new(None);
+
+// The state of the LEDs which is sent to the keyboard once it has acknowledged the command.
+static PENDING_LEDS: Mutex<Option<u8>> = Mutex::new(None);
+
+/// Switches the keyboard to scancode set 2, which is checked since some keyboards ignore the command.
+fn select_set_2() -> Result<(), Ps2Error> {
+    i8042::command(PORT, CMD_SCANCODE_SET)?;
+    i8042::command(PORT, 2)?;
+
+    i8042::command(PORT, CMD_SCANCODE_SET)?;
+    i8042::command(PORT, GET_SCANCODE_SET)?;
+    match i8042::read(PORT)? {
+        2 => Ok(()),
+        response => Err(Ps2Error::Unexpected(response)),
+    }
+}
+
+/// Resets the keyboard and sets it up to send scancodes, returning the set in which they arrive.
+fn probe() -> Result<ScancodeSet, Ps2Error> {
+    i8042::reset(PORT)?;
+    i8042::command(PORT, CMD_DISABLE_SCANNING)?;
+
+    // Otherwise, the controller translates the scancodes of the default set into set 1.
+    let set = match select_set_2() {
+        Ok(()) => ScancodeSet::Set2,
+        Err(_) => {
+            i8042::set_translation(true)?;
+            ScancodeSet::Set1
+        }
+    };
+
+    i8042::command(PORT, CMD_SET_LEDS)?;
+    i8042::command(PORT, 0)?;
+    i8042::command(PORT, CMD_ENABLE_SCANNING)?;
+
+    Ok(set)
+}
+
+fn update_leds(modifiers: Modifiers) {
+    let mut leds = 0;
+    if modifiers.scroll_lock() {
+        leds |= LED_SCROLL_LOCK;
+    }
+    if modifiers.num_lock() {
+        leds |= LED_NUM_LOCK;
+    }
+    if modifiers.caps_lock() {
+        leds |= LED_CAPS_LOCK;
+    }
+
+    // The state is sent by the handler upon the acknowledgement of the command, as it can't wait for it here.
+    *PENDING_LEDS.lock() = Some(leds);
+    let _ = i8042::write(PORT, CMD_SET_LEDS);
+}
+
+fn on_data(byte: u8) {
+    match byte {
+        i8042::ACK => {
+            if let Some(leds) = PENDING_LEDS.lock().take() {
+                let _ = i8042::write(PORT, leds);
+            }
+            return;
+        }
+        i8042::RESEND | KEY_ERROR | KEY_ERROR_ALT => return,
+        _ => {}
+    }
+
+    let (event, previous) = {
+        let mut keyboard = KEYBOARD.lock();
+        let Some(keyboard) = keyboard.as_mut() else {
+            return;
+        };
+        let previous = keyboard.modifiers();
+        (keyboard.process(byte), previous)
+    };
+    let Some(event) = event else {
+        return;
+    };
+
+    let locks = [KeyCode::CapsLock, KeyCode::NumLock, KeyCode::ScrollLock];
+    if event.state == KeyState::Pressed && locks.contains(&event.key) && event.modifiers != previous
+    {
+        update_leds(event.modifiers);
+    }
+
+    input::push(InputEvent::Key(event));
+}
+
+pub(crate) fn init() -> Result<(), ()> {
+    if !i8042::is_present(PORT) {
+        return Ok(());
+    }
+
+    let set = match probe() {
+        Ok(set) => set,
+        Err(error) => {
+            log::warn!("no working PS/2 keyboard: {:?}", error);
+            return Ok(());
+        }
+    };
+
+    *KEYBOARD.lock() = Some(Keyboard::new(set));
+    i8042::claim(PORT, on_data).map_err(|_| ())?;
+
+    log::info!("PS/2 keyboard using scancode {:?}", set);
+
+    Ok(())
+}
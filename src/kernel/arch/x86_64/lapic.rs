@@ -0,0 +1,128 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use x86_64::registers::model_specific::Msr;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+use x86_64::PhysAddr;
+
+use super::acpi;
+use super::paging;
+
+const IA32_APIC_BASE: u32 = 0x1B;
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+const APIC_BASE_ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+const REG_ID: usize = 0x20;
+const REG_VERSION: usize = 0x30;
+const REG_END_OF_INTERRUPT: usize = 0xB0;
+const REG_SPURIOUS: usize = 0xF0;
+
+const SPURIOUS_ENABLE: u32 = 1 << 8;
+
+/// The interrupt vector of the spurious interrupts, whose lowest four bits must be set on older processors.
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+
+const REGISTERS_SIZE: usize = 0x400;
+
+// The virtual address of the registers, or zero until the local APIC has been enabled.
+static BASE: AtomicUsize = AtomicUsize::new(0);
+
+fn register(offset: usize) -> Option<*mut u32> {
+    match BASE.load(Ordering::Acquire) {
+        0 => None,
+        base => Some((base + offset) as *mut u32),
+    }
+}
+
+fn read(offset: usize) -> u32 {
+    register(offset).map_or(0, |register| unsafe { ptr::read_volatile(register) })
+}
+
+fn write(offset: usize, value: u32) {
+    if let Some(register) = register(offset) {
+        unsafe { ptr::write_volatile(register, value) };
+    }
+}
+
+pub fn is_enabled() -> bool {
+    BASE.load(Ordering::Acquire) != 0
+}
+
+/// Returns the ID of the local APIC of the current processor.
+pub fn id() -> u8 {
+    (read(REG_ID) >> 24) as u8
+}
+
+/// Returns the IDs of the local APICs of the usable processors.
+pub fn cpus() -> impl Iterator<Item = u8> {
+    // Without a MADT, the current processor is the only one known to exist.
+    let mut ids = acpi::local_apic_ids().peekable();
+    let current = (ids.peek().is_none() && is_enabled()).then(id);
+    ids.chain(current)
+}
+
+/// Checks whether a processor with the given ID of its local APIC is usable, e.g. as the target of an interrupt.
+pub fn is_cpu(apic_id: u8) -> bool {
+    cpus().any(|id| id == apic_id)
+}
+
+/// Acknowledges the interrupt which is being serviced.
+pub fn end_of_interrupt() {
+    write(REG_END_OF_INTERRUPT, 0);
+}
+
+// The spurious interrupts must not be acknowledged.
+extern "x86-interrupt" fn on_spurious(_stack_frame: InterruptStackFrame) {}
+
+pub(crate) fn install(idt: &mut InterruptDescriptorTable) {
+    idt[SPURIOUS_VECTOR as usize].set_handler_fn(on_spurious);
+}
+
+/// Local Advanced Programmable Interrupt Controller (Local APIC)
+///
+/// Every processor has a local APIC, which receives the interrupts sent to it as messages, e.g. from the devices
+/// using MSI. The legacy IRQs are still delivered through the PICs, which the local APIC passes through.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/APIC
+pub(crate) fn init() -> Result<(), ()> {
+    let mut msr = Msr::new(IA32_APIC_BASE);
+    let apic_base = unsafe { msr.read() };
+    let phys_addr = PhysAddr::new(apic_base & APIC_BASE_ADDRESS_MASK);
+    let base = paging::map_mmio(phys_addr, REGISTERS_SIZE).ok_or(())?;
+
+    unsafe { msr.write(apic_base | APIC_BASE_ENABLE) };
+    BASE.store(base.as_u64() as usize, Ordering::Release);
+
+    write(REG_SPURIOUS, SPURIOUS_ENABLE | SPURIOUS_VECTOR as u32);
+
+    log::info!(
+        "local APIC {} (version {:#X}) at {:#X}",
+        id(),
+        read(REG_VERSION) & 0xFF,
+        phys_addr.as_u64()
+    );
+
+    Ok(())
+}
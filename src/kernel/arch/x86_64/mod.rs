@@ -22,19 +22,89 @@
 
 use x86_64::instructions;
 
+use crate::kernel::cmdline;
+
+mod backtrace;
 mod elf;
 mod exceptions;
+mod framebuffer;
+mod gdb;
 mod gdt;
+mod i8042;
 mod idt;
+mod keyboard;
+mod lapic;
+mod monitor;
+mod mouse;
+mod pci;
+mod pic;
 mod preliminary;
+mod trap;
 
+pub mod acpi;
+pub mod context;
+pub mod frame;
+pub mod irq;
+pub mod msi;
+pub mod paging;
+pub mod port;
 pub mod serial;
+pub mod stack;
+pub mod timer;
+pub mod uart;
+pub mod vga;
+pub mod watchpoint;
+
+pub use x86_64::{PhysAddr, VirtAddr};
+
+pub use self::elf::{boot_loader_name, memory_map, modules, MemoryKind};
+pub use self::preliminary::configurations::CONFIGURATION;
 
 pub fn init(boot_info_addr: usize) {
     elf::init(boot_info_addr).expect("kernel failed to retrieve metadata");
+    vga::init().expect("kernel failed to initialize VGA text console");
+    cmdline::init(elf::command_line()).expect("kernel failed to parse command line");
+    paging::init().expect("kernel failed to map physical memory");
+    frame::init().expect("kernel failed to initialize frame allocator");
+    framebuffer::init().expect("kernel failed to retrieve framebuffer");
+    acpi::init().expect("kernel failed to locate ACPI tables");
+    pci::init().expect("kernel failed to set up PCI configuration access");
 
     gdt::init().expect("kernel failed to initialize GDT");
     idt::init().expect("kernel failed to initialize IDT");
+    irq::init().expect("kernel failed to initialize IRQs");
+    lapic::init().expect("kernel failed to initialize local APIC");
+    timer::init().expect("kernel failed to initialize timer");
+    uart::init().expect("kernel failed to initialize UARTs");
+    serial::init().expect("kernel failed to initialize serial console");
+    i8042::init().expect("kernel failed to initialize PS/2 controller");
+    keyboard::init().expect("kernel failed to initialize PS/2 keyboard");
+    mouse::init().expect("kernel failed to initialize PS/2 mouse");
+
+    gdb::init().expect("kernel failed to initialize GDB stub");
+    monitor::init().expect("kernel failed to initialize monitor");
+
+    instructions::interrupts::enable();
+}
+
+/// Runs a closure with the maskable interrupts disabled, e.g., to keep an interrupt handler from contending for a lock
+/// held by the code it has interrupted.
+pub fn without_interrupts<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    instructions::interrupts::without_interrupts(f)
+}
+
+/// Enables the maskable interrupts, e.g., in a thread which has been switched to with the interrupts disabled.
+pub fn enable_interrupts() {
+    instructions::interrupts::enable();
+}
+
+/// Enables the maskable interrupts and halts the processor until the next one arrives, without missing an interrupt
+/// arriving in between.
+pub fn wait_for_interrupt() {
+    instructions::interrupts::enable_and_hlt();
 }
 
 pub fn hlt_loop() -> ! {
@@ -0,0 +1,441 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::arch::asm;
+use core::ptr;
+use core::slice;
+use core::str::FromStr;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use log::LevelFilter;
+use x86_64::instructions::tables::{sgdt, sidt};
+use x86_64::VirtAddr;
+
+use crate::kernel::input;
+use crate::{serial_print, serial_println};
+
+use super::backtrace::Backtrace;
+use super::elf;
+use super::i8042;
+use super::paging;
+use super::serial;
+use super::trap::TrapFrame;
+use super::uart;
+
+/// The key which enters the monitor when it is received through the serial console (Ctrl-]).
+pub const MONITOR_KEY: u8 = 0x1D;
+
+const LINE_SIZE: usize = 128;
+
+/// The maximum number of units displayed by a single `x` command.
+const MAX_EXAMINE_COUNT: usize = 1024;
+
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7F;
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+const HELP: &str = "\
+commands:
+  x/NFU <addr>      examine N units of memory, U: b|h|w|g (1|2|4|8 bytes), F: x|d|u (hex|signed|unsigned)
+  pt <addr>         walk the page tables for a virtual address
+  idt               list the present entries of the IDT
+  gdt               list the entries of the GDT
+  mb                dump the Multiboot information
+  log [level]       show or set the maximum log level (off|error|warn|info|debug|trace)
+  regs              show the registers of the interrupted context
+  bt                show the backtrace of the interrupted context
+  trap <bp|db|df>   trigger a test exception (`df` halts the kernel)
+  c                 leave the monitor and continue";
+
+enum Flow {
+    Stay,
+    Leave,
+}
+
+/// Enters the interactive monitor on the serial console, until it is left with the `c` command.
+///
+/// The register state of the interrupted context is available to the commands when the monitor is entered from
+/// a trap. Nested attempts to enter the monitor (e.g. through the `trap bp` command) are ignored.
+pub fn enter(frame: Option<&TrapFrame>) {
+    if ACTIVE.swap(true, Ordering::Acquire) {
+        return;
+    }
+
+    serial_println!("Entering kernel monitor, type `help` for the list of commands.");
+
+    let mut line = [0; LINE_SIZE];
+    loop {
+        serial_print!("monitor> ");
+        let len = read_line(&mut line);
+        let command = core::str::from_utf8(&line[..len]).unwrap_or_default();
+
+        if let Flow::Leave = execute(command, frame) {
+            break;
+        }
+    }
+
+    ACTIVE.store(false, Ordering::Release);
+}
+
+/// Blocks until a byte is received from either the serial console or the keyboard.
+fn receive() -> u8 {
+    loop {
+        if let Ok(Some(byte)) = uart::read_byte(serial::console_port()) {
+            return byte;
+        }
+
+        // The keyboard is polled, as the monitor usually runs with the interrupts disabled.
+        i8042::poll();
+        if let Some(c) = input::read_char().filter(char::is_ascii) {
+            return c as u8;
+        }
+
+        core::hint::spin_loop();
+    }
+}
+
+fn read_line(buf: &mut [u8]) -> usize {
+    let mut len = 0;
+    loop {
+        match receive() {
+            b'\r' | b'\n' => {
+                serial_println!();
+                return len;
+            }
+            BACKSPACE | DELETE if len > 0 => {
+                len -= 1;
+                serial_print!("\x08 \x08");
+            }
+            byte @ 0x20..=0x7E if len < buf.len() => {
+                buf[len] = byte;
+                len += 1;
+                serial_print!("{}", byte as char);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn execute(command: &str, frame: Option<&TrapFrame>) -> Flow {
+    let mut args = command.split_whitespace();
+    let name = match args.next() {
+        Some(name) => name,
+        None => return Flow::Stay,
+    };
+
+    match name {
+        "help" => serial_println!("{}", HELP),
+        "c" | "continue" => return Flow::Leave,
+        "pt" => walk_page_tables(args.next()),
+        "idt" => list_idt(),
+        "gdt" => list_gdt(),
+        "mb" => serial_println!("{:#X?}", elf::multiboot_info()),
+        "log" => log_level(args.next()),
+        "regs" => match frame {
+            Some(frame) => serial_println!("{:#X?}", frame),
+            None => serial_println!("no interrupted context"),
+        },
+        "bt" => backtrace(frame),
+        "trap" => trigger_exception(args.next()),
+        _ if name == "x" || name.starts_with("x/") => examine(&name[1..], args.next()),
+        _ => serial_println!(
+            "unknown command `{}`, type `help` for the list of commands",
+            name
+        ),
+    }
+
+    Flow::Stay
+}
+
+/// Parses a number given either in hexadecimal with the `0x` prefix or in decimal.
+fn parse_number(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(digits) => u64::from_str_radix(digits, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+fn examine(format: &str, addr: Option<&str>) {
+    const USAGE: &str = "usage: x/NFU <addr>";
+
+    let format = format.strip_prefix('/').unwrap_or(format);
+    let digits = format
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(format.len());
+    let count = match &format[..digits] {
+        "" => 1,
+        count => match count.parse::<usize>() {
+            Ok(count) => count.min(MAX_EXAMINE_COUNT),
+            Err(_) => {
+                serial_println!("{}", USAGE);
+                return;
+            }
+        },
+    };
+
+    let (mut size, mut radix) = (4, 'x');
+    for c in format[digits..].chars() {
+        match c {
+            'b' => size = 1,
+            'h' => size = 2,
+            'w' => size = 4,
+            'g' => size = 8,
+            'x' | 'd' | 'u' => radix = c,
+            _ => {
+                serial_println!("{}", USAGE);
+                return;
+            }
+        }
+    }
+
+    let addr = match addr.and_then(parse_number) {
+        Some(addr) => addr,
+        None => {
+            serial_println!("{}", USAGE);
+            return;
+        }
+    };
+    if !paging::is_accessible(addr, count * size) {
+        serial_println!("cannot access memory at {:#X}", addr);
+        return;
+    }
+
+    let per_line = 16 / size;
+    for i in 0..count {
+        let unit = addr + (i * size) as u64;
+        if i % per_line == 0 {
+            if i != 0 {
+                serial_println!();
+            }
+            serial_print!("{:#018X}:", unit);
+        }
+
+        let value = unsafe {
+            match size {
+                1 => ptr::read_volatile(unit as *const u8) as u64,
+                2 => ptr::read_volatile(unit as *const u16) as u64,
+                4 => ptr::read_volatile(unit as *const u32) as u64,
+                _ => ptr::read_volatile(unit as *const u64),
+            }
+        };
+
+        match radix {
+            'x' => serial_print!(" {:#0width$X}", value, width = size * 2 + 2),
+            'd' => {
+                // Sign-extend the unit to 64 bits.
+                let shift = 64 - size * 8;
+                serial_print!(" {}", ((value << shift) as i64) >> shift);
+            }
+            _ => serial_print!(" {}", value),
+        }
+    }
+    serial_println!();
+}
+
+fn walk_page_tables(addr: Option<&str>) {
+    let addr = match addr
+        .and_then(parse_number)
+        .and_then(|addr| VirtAddr::try_new(addr).ok())
+    {
+        Some(addr) => addr,
+        None => {
+            serial_println!("usage: pt <addr>");
+            return;
+        }
+    };
+
+    let phys_addr = paging::walk(addr, |level, index, entry| {
+        serial_println!(
+            "PT{}[{:3}]: {:#018X} {:?}",
+            level,
+            u16::from(index),
+            entry.addr().as_u64(),
+            entry.flags()
+        );
+    });
+
+    match phys_addr {
+        Some(phys_addr) => serial_println!("{:#X} -> {:#X}", addr.as_u64(), phys_addr.as_u64()),
+        None => serial_println!("{:#X} is not mapped", addr.as_u64()),
+    }
+}
+
+fn list_idt() {
+    let pointer = sidt();
+    let count = (pointer.limit as usize + 1) / 16;
+
+    // In Long Mode, every entry occupies 16 bytes.
+    let table = unsafe { slice::from_raw_parts(pointer.base.as_ptr::<[u64; 2]>(), count) };
+    for (vector, [low, high]) in table.iter().enumerate() {
+        let attributes = (low >> 40) & 0xFF;
+        if attributes & 0x80 == 0 {
+            continue;
+        }
+
+        let handler = (low & 0xFFFF) | ((low >> 48) & 0xFFFF) << 16 | (high & 0xFFFF_FFFF) << 32;
+        let selector = (low >> 16) & 0xFFFF;
+        let ist = (low >> 32) & 0x7;
+        let dpl = (attributes >> 5) & 0x3;
+        let gate = match attributes & 0xF {
+            0xE => "interrupt",
+            0xF => "trap",
+            _ => "unknown",
+        };
+
+        serial_println!(
+            "{:#04X}: {:#018X} SEL={:#06X} IST={} DPL={} ({} gate)",
+            vector,
+            handler,
+            selector,
+            ist,
+            dpl,
+            gate
+        );
+    }
+}
+
+fn list_gdt() {
+    let pointer = sgdt();
+    let count = (pointer.limit as usize + 1) / 8;
+    let table = unsafe { slice::from_raw_parts(pointer.base.as_ptr::<u64>(), count) };
+
+    let mut index = 0;
+    while index < count {
+        let descriptor = table[index];
+        let selector = index * 8;
+        let access = (descriptor >> 40) & 0xFF;
+        let flags = (descriptor >> 52) & 0xF;
+        let present = access & 0x80 != 0;
+        let dpl = (access >> 5) & 0x3;
+
+        if descriptor == 0 {
+            serial_println!("{:#06X}: null", selector);
+            index += 1;
+        } else if access & 0x10 != 0 {
+            let kind = match (access & 0x08 != 0, flags & 0x2 != 0) {
+                (true, true) => "code (64-bit)",
+                (true, false) => "code",
+                (false, _) => "data",
+            };
+            serial_println!(
+                "{:#06X}: {:#018X} {} DPL={} P={}",
+                selector,
+                descriptor,
+                kind,
+                dpl,
+                present as u8
+            );
+            index += 1;
+        } else {
+            // In Long Mode, system descriptors (e.g. the TSS) occupy two entries.
+            let high = table.get(index + 1).copied().unwrap_or_default();
+            let base = ((descriptor >> 16) & 0xFF_FFFF)
+                | ((descriptor >> 56) & 0xFF) << 24
+                | (high & 0xFFFF_FFFF) << 32;
+            let limit = (descriptor & 0xFFFF) | ((descriptor >> 48) & 0xF) << 16;
+            let kind = match access & 0xF {
+                0x2 => "LDT",
+                0x9 => "TSS (available)",
+                0xB => "TSS (busy)",
+                _ => "system",
+            };
+            serial_println!(
+                "{:#06X}: {} BASE={:#X} LIMIT={:#X} DPL={} P={}",
+                selector,
+                kind,
+                base,
+                limit,
+                dpl,
+                present as u8
+            );
+            index += 2;
+        }
+    }
+}
+
+fn log_level(level: Option<&str>) {
+    if let Some(level) = level {
+        match LevelFilter::from_str(level) {
+            Ok(level) => log::set_max_level(level),
+            Err(_) => {
+                serial_println!("usage: log [off|error|warn|info|debug|trace]");
+                return;
+            }
+        }
+    }
+
+    serial_println!("log level: {}", log::max_level());
+}
+
+fn backtrace(frame: Option<&TrapFrame>) {
+    let rbp = match frame {
+        Some(frame) => {
+            serial_println!("  #0 {:#018X}", frame.rip);
+            frame.rbp
+        }
+        None => {
+            let rbp;
+            unsafe { asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack, preserves_flags)) };
+            rbp
+        }
+    };
+
+    let offset = frame.is_some() as usize;
+    for (depth, return_addr) in Backtrace::new(rbp).enumerate() {
+        serial_println!("  #{} {:#018X}", depth + offset, return_addr);
+    }
+}
+
+fn trigger_exception(kind: Option<&str>) {
+    match kind {
+        Some("bp") => x86_64::instructions::interrupts::int3(),
+        Some("db") => unsafe { asm!("int 0x01", options(nomem, nostack)) },
+        // There's no handler for invalid opcodes, which escalates into a double fault.
+        Some("df") => unsafe { asm!("ud2", options(nomem, nostack)) },
+        _ => serial_println!("usage: trap <bp|db|df>"),
+    }
+}
+
+fn on_monitor_key() {
+    enter(None);
+}
+
+pub fn init() -> Result<(), ()> {
+    let port = serial::console_port();
+    if !uart::is_present(port) {
+        return Ok(());
+    }
+
+    // The monitor is entered at boot if the key has been received while the kernel was initializing.
+    while let Ok(Some(byte)) = uart::read_byte(port) {
+        if byte == MONITOR_KEY {
+            enter(None);
+        }
+    }
+
+    uart::set_attention(port, &[MONITOR_KEY], on_monitor_key).map_err(|_| ())?;
+
+    log::info!("press Ctrl-] on the serial console to enter the kernel monitor");
+
+    Ok(())
+}
@@ -0,0 +1,128 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::kernel::input;
+use crate::kernel::input::mouse::{Decoder, MouseProtocol};
+use crate::kernel::input::InputEvent;
+use crate::kernel::sync::Mutex;
+
+use super::i8042;
+use super::i8042::{Ps2Error, Ps2Port};
+
+const PORT: Ps2Port = Ps2Port::Second;
+
+const CMD_SET_RESOLUTION: u8 = 0xE8;
+const CMD_GET_DEVICE_ID: u8 = 0xF2;
+const CMD_SET_SAMPLE_RATE: u8 = 0xF3;
+const CMD_ENABLE_REPORTING: u8 = 0xF4;
+const CMD_SET_DEFAULTS: u8 = 0xF6;
+
+const ID_STANDARD: u8 = 0x00;
+const ID_WHEEL: u8 = 0x03;
+const ID_FIVE_BUTTON: u8 = 0x04;
+
+/// The sequences of sample rates which unlock the IntelliMouse extensions on the mice supporting them.
+const WHEEL_SEQUENCE: [u8; 3] = [200, 100, 80];
+const FIVE_BUTTON_SEQUENCE: [u8; 3] = [200, 200, 80];
+
+/// The number of samples per second.
+const SAMPLE_RATE: u8 = 100;
+/// The resolution of 4 counts per millimeter.
+const RESOLUTION: u8 = 2;
+
+static DECODER: Mutex<Option<Decoder>> = Mutex::new(None);
+
+fn device_id() -> Result<u8, Ps2Error> {
+    i8042::command(PORT, CMD_GET_DEVICE_ID)?;
+    i8042::read(PORT)
+}
+
+fn set_sample_rate(rate: u8) -> Result<(), Ps2Error> {
+    i8042::command(PORT, CMD_SET_SAMPLE_RATE)?;
+    i8042::command(PORT, rate)
+}
+
+/// Tries to unlock an extension by sending its sequence of sample rates, and returns the resulting device ID.
+fn knock(sequence: [u8; 3]) -> Result<u8, Ps2Error> {
+    for rate in sequence {
+        set_sample_rate(rate)?;
+    }
+
+    device_id()
+}
+
+/// Resets the mouse, identifies its protocol and enables its reports.
+fn probe() -> Result<MouseProtocol, Ps2Error> {
+    i8042::reset(PORT)?;
+    // The reset is followed by the ID of the device, which tells apart a mouse from a keyboard.
+    match i8042::read(PORT)? {
+        ID_STANDARD => {}
+        id => return Err(Ps2Error::Unexpected(id)),
+    }
+    i8042::command(PORT, CMD_SET_DEFAULTS)?;
+
+    let mut protocol = MouseProtocol::Standard;
+    if knock(WHEEL_SEQUENCE)? == ID_WHEEL {
+        protocol = MouseProtocol::Wheel;
+        if knock(FIVE_BUTTON_SEQUENCE)? == ID_FIVE_BUTTON {
+            protocol = MouseProtocol::FiveButton;
+        }
+    }
+
+    set_sample_rate(SAMPLE_RATE)?;
+    i8042::command(PORT, CMD_SET_RESOLUTION)?;
+    i8042::command(PORT, RESOLUTION)?;
+    i8042::command(PORT, CMD_ENABLE_REPORTING)?;
+
+    Ok(protocol)
+}
+
+fn on_data(byte: u8) {
+    let event = DECODER
+        .lock()
+        .as_mut()
+        .and_then(|decoder| decoder.decode(byte));
+    if let Some(event) = event {
+        input::push(InputEvent::Mouse(event));
+    }
+}
+
+pub(crate) fn init() -> Result<(), ()> {
+    if !i8042::is_present(PORT) {
+        return Ok(());
+    }
+
+    let protocol = match probe() {
+        Ok(protocol) => protocol,
+        Err(error) => {
+            log::warn!("no working PS/2 mouse: {:?}", error);
+            return Ok(());
+        }
+    };
+
+    *DECODER.lock() = Some(Decoder::new(protocol));
+    i8042::claim(PORT, on_data).map_err(|_| ())?;
+
+    log::info!("PS/2 mouse using the {:?} protocol", protocol);
+
+    Ok(())
+}
@@ -0,0 +1,271 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::ptr;
+
+use x86_64::PhysAddr;
+
+use crate::kernel::pci::capability::MsiXCapability;
+use crate::kernel::pci::{Bar, PciDevice, COMMAND_MEMORY_SPACE};
+
+use super::irq;
+use super::irq::{IrqError, IrqHandler};
+use super::lapic;
+use super::paging;
+
+/// The address range of the local APICs, which receive the messages.
+const MESSAGE_ADDRESS_BASE: u64 = 0xFEE0_0000;
+
+// The registers of the MSI capability, relative to its offset.
+const MSI_CONTROL: u16 = 0x02;
+const MSI_ADDRESS_LOW: u16 = 0x04;
+const MSI_ADDRESS_HIGH: u16 = 0x08;
+const MSI_DATA_32_BIT: u16 = 0x08;
+const MSI_DATA_64_BIT: u16 = 0x0C;
+
+const MSI_ENABLE: u16 = 1 << 0;
+const MSI_MULTIPLE_MESSAGE_ENABLE: u16 = 0b111 << 4;
+
+const MSI_X_CONTROL: u16 = 0x02;
+const MSI_X_ENABLE: u16 = 1 << 15;
+const MSI_X_FUNCTION_MASK: u16 = 1 << 14;
+
+// The entries of the MSI-X table, made of four registers each.
+const MSI_X_ENTRY_SIZE: usize = 16;
+const MSI_X_ENTRY_ADDRESS_LOW: usize = 0;
+const MSI_X_ENTRY_ADDRESS_HIGH: usize = 1;
+const MSI_X_ENTRY_DATA: usize = 2;
+const MSI_X_ENTRY_CONTROL: usize = 3;
+const MSI_X_ENTRY_MASKED: u32 = 1 << 0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MsiError {
+    /// The function lacks the capability.
+    Unsupported,
+    /// No usable processor has a local APIC with the ID.
+    InvalidCpu,
+    /// The entry is beyond the end of the MSI-X table.
+    InvalidEntry,
+    /// The MSI-X table lies in a BAR which isn't accessible.
+    TableNotMapped,
+    Irq(IrqError),
+}
+
+impl From<IrqError> for MsiError {
+    fn from(error: IrqError) -> Self {
+        MsiError::Irq(error)
+    }
+}
+
+/// The message which a function writes to interrupt a processor.
+///
+/// The address selects the local APIC of the processor, while the data holds the interrupt vector, which is
+/// delivered as an edge-triggered interrupt in the fixed delivery mode.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/PCI#Message_Signaled_Interrupts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MsiMessage {
+    pub addr: u64,
+    pub data: u32,
+}
+
+impl MsiMessage {
+    pub fn new(apic_id: u8, vector: u8) -> Self {
+        Self {
+            addr: MESSAGE_ADDRESS_BASE | (apic_id as u64) << 12,
+            data: vector as u32,
+        }
+    }
+}
+
+/// Returns the ID of the local APIC of the current processor, to which the interrupts of a function are usually sent.
+pub fn current_cpu() -> u8 {
+    lapic::id()
+}
+
+/// Returns the IDs of the local APICs of the processors to which the interrupts can be sent.
+pub fn cpus() -> impl Iterator<Item = u8> {
+    lapic::cpus()
+}
+
+/// Allocates an IRQ for the handler, and returns it along with the message which triggers it on the processor.
+fn allocate(apic_id: u8, handler: IrqHandler) -> Result<(u8, MsiMessage), MsiError> {
+    if !lapic::is_enabled() || !lapic::is_cpu(apic_id) {
+        return Err(MsiError::InvalidCpu);
+    }
+
+    let irq = irq::allocate()?;
+    if let Err(error) = irq::register(irq, handler) {
+        let _ = irq::free(irq);
+        return Err(error.into());
+    }
+
+    Ok((irq, MsiMessage::new(apic_id, irq::vector(irq))))
+}
+
+fn set_msi_enabled(device: &PciDevice, enabled: bool) {
+    if let Some(msi) = device.msi() {
+        let register = msi.offset as u16 + MSI_CONTROL;
+        let control = device.addr.read_u16(register) & !MSI_ENABLE;
+        let control = if enabled {
+            control | MSI_ENABLE
+        } else {
+            control
+        };
+        device.addr.write_u16(register, control);
+    }
+}
+
+fn set_msi_x_control(device: &PciDevice, msi_x: &MsiXCapability, set: u16, clear: u16) {
+    let register = msi_x.offset as u16 + MSI_X_CONTROL;
+    let control = device.addr.read_u16(register);
+    device.addr.write_u16(register, (control & !clear) | set);
+}
+
+/// Returns the entry of the MSI-X table, which is accessed through the direct mapping.
+fn msi_x_entry(
+    device: &PciDevice,
+    msi_x: &MsiXCapability,
+    entry: u16,
+) -> Result<*mut u32, MsiError> {
+    if entry >= msi_x.table_size {
+        return Err(MsiError::InvalidEntry);
+    }
+
+    let bar_addr = match device.bars.get(msi_x.table_bar as usize) {
+        Some(Some(Bar::Memory { addr, .. })) => *addr,
+        _ => return Err(MsiError::TableNotMapped),
+    };
+    let table_size = msi_x.table_size as usize * MSI_X_ENTRY_SIZE;
+    let table_addr = PhysAddr::try_new(bar_addr + msi_x.table_offset as u64)
+        .map_err(|_| MsiError::TableNotMapped)?;
+    let table = paging::map_mmio(table_addr, table_size).ok_or(MsiError::TableNotMapped)?;
+
+    Ok(unsafe {
+        table
+            .as_mut_ptr::<u32>()
+            .add(entry as usize * MSI_X_ENTRY_SIZE / 4)
+    })
+}
+
+/// Makes the function signal its interrupts through MSI, with a single vector on the given processor.
+///
+/// Returns the IRQ which has been allocated for the handler, which must be freed once MSI has been disabled.
+pub fn enable_msi(device: &PciDevice, apic_id: u8, handler: IrqHandler) -> Result<u8, MsiError> {
+    let msi = device.msi().ok_or(MsiError::Unsupported)?;
+    let (irq, message) = allocate(apic_id, handler)?;
+
+    // The function can't use both mechanisms at once.
+    if let Some(msi_x) = device.msi_x() {
+        set_msi_x_control(device, &msi_x, 0, MSI_X_ENABLE);
+    }
+
+    let base = msi.offset as u16;
+    device
+        .addr
+        .write_u32(base + MSI_ADDRESS_LOW, message.addr as u32);
+    let data_register = match msi.is_64_bit {
+        true => {
+            device
+                .addr
+                .write_u32(base + MSI_ADDRESS_HIGH, (message.addr >> 32) as u32);
+            base + MSI_DATA_64_BIT
+        }
+        false => base + MSI_DATA_32_BIT,
+    };
+    device.addr.write_u16(data_register, message.data as u16);
+
+    // A single vector is requested.
+    let control = device.addr.read_u16(base + MSI_CONTROL) & !MSI_MULTIPLE_MESSAGE_ENABLE;
+    device
+        .addr
+        .write_u16(base + MSI_CONTROL, control | MSI_ENABLE);
+    device.set_legacy_interrupt(false);
+
+    Ok(irq)
+}
+
+/// Routes an entry of the MSI-X table of the function to the handler on the given processor, and enables MSI-X.
+///
+/// Returns the IRQ which has been allocated for the handler, which must be freed once MSI-X has been disabled.
+pub fn enable_msi_x(
+    device: &PciDevice,
+    entry: u16,
+    apic_id: u8,
+    handler: IrqHandler,
+) -> Result<u8, MsiError> {
+    let msi_x = device.msi_x().ok_or(MsiError::Unsupported)?;
+    let register = msi_x_entry(device, &msi_x, entry)?;
+    let (irq, message) = allocate(apic_id, handler)?;
+
+    // The table is only accessible while the function decodes its memory.
+    device.set_command(device.command() | COMMAND_MEMORY_SPACE);
+    set_msi_enabled(device, false);
+
+    // The vectors are masked as a whole while the entry is being programmed.
+    set_msi_x_control(device, &msi_x, MSI_X_ENABLE | MSI_X_FUNCTION_MASK, 0);
+    unsafe {
+        ptr::write_volatile(register.add(MSI_X_ENTRY_ADDRESS_LOW), message.addr as u32);
+        ptr::write_volatile(
+            register.add(MSI_X_ENTRY_ADDRESS_HIGH),
+            (message.addr >> 32) as u32,
+        );
+        ptr::write_volatile(register.add(MSI_X_ENTRY_DATA), message.data);
+        let control = ptr::read_volatile(register.add(MSI_X_ENTRY_CONTROL));
+        ptr::write_volatile(
+            register.add(MSI_X_ENTRY_CONTROL),
+            control & !MSI_X_ENTRY_MASKED,
+        );
+    }
+    set_msi_x_control(device, &msi_x, 0, MSI_X_FUNCTION_MASK);
+    device.set_legacy_interrupt(false);
+
+    Ok(irq)
+}
+
+/// Masks or unmasks an entry of the MSI-X table of the function.
+pub fn set_msi_x_masked(device: &PciDevice, entry: u16, masked: bool) -> Result<(), MsiError> {
+    let msi_x = device.msi_x().ok_or(MsiError::Unsupported)?;
+    let register = msi_x_entry(device, &msi_x, entry)?;
+
+    unsafe {
+        let register = register.add(MSI_X_ENTRY_CONTROL);
+        let control = ptr::read_volatile(register) & !MSI_X_ENTRY_MASKED;
+        let control = if masked {
+            control | MSI_X_ENTRY_MASKED
+        } else {
+            control
+        };
+        ptr::write_volatile(register, control);
+    }
+
+    Ok(())
+}
+
+/// Disables both MSI and MSI-X, which returns the function to its legacy interrupt.
+pub fn disable(device: &PciDevice) {
+    set_msi_enabled(device, false);
+    if let Some(msi_x) = device.msi_x() {
+        set_msi_x_control(device, &msi_x, 0, MSI_X_ENABLE);
+    }
+    device.set_legacy_interrupt(true);
+}
@@ -0,0 +1,340 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::ptr::addr_of_mut;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use x86_64::instructions::{interrupts, tlb};
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::page_table::PageTableEntry;
+use x86_64::structures::paging::{PageTable, PageTableFlags, PageTableIndex};
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::kernel::sync::Mutex;
+
+use super::elf;
+use super::frame;
+use super::preliminary::configurations::CONFIG_CORE_MEMORY_INITIAL_MAPPING_SIZE;
+
+/// The virtual address at which the physical address space is mapped, i.e., the beginning of the higher half.
+const DIRECT_MAPPING_OFFSET: u64 = 0xFFFF_8000_0000_0000;
+
+/// The size of the physical address space covered by the direct mapping, which includes the memory-mapped devices
+/// below 4 GiB, e.g., the framebuffer.
+const DIRECT_MAPPING_SIZE: u64 = 4 << 30;
+
+const HUGE_PAGE_SIZE: u64 = 2 << 20;
+
+/// The beginning of the region of the address space in which the memory-mapped devices beyond the direct mapping are
+/// mapped on demand, i.e., the entry of the level 4 table right below the stack region.
+const MMIO_REGION_START: u64 = 0xFFFF_FE80_0000_0000;
+
+const MMIO_REGION_SIZE: u64 = 512 << 30;
+
+const MAX_MMIO_MAPPINGS: usize = 64;
+
+const EMPTY_TABLE: PageTable = PageTable::new();
+
+static mut DIRECT_MAPPING_PT3: PageTable = PageTable::new();
+static mut DIRECT_MAPPING_PT2: [PageTable; (DIRECT_MAPPING_SIZE >> 30) as usize] =
+    [EMPTY_TABLE; (DIRECT_MAPPING_SIZE >> 30) as usize];
+
+static DIRECT_MAPPING: AtomicBool = AtomicBool::new(false);
+
+// Serializes the changes to the page tables made through `map` and `unmap`.
+static LOCK: Mutex<()> = Mutex::new(());
+
+static MMIO: Mutex<MmioRegion> = Mutex::new(MmioRegion {
+    mappings: [None; MAX_MMIO_MAPPINGS],
+    next: MMIO_REGION_START,
+});
+
+/// A physical memory range mapped into the MMIO region, in whole pages.
+#[derive(Clone, Copy, Debug)]
+struct MmioMapping {
+    phys_addr: u64,
+    len: u64,
+    virt_addr: u64,
+}
+
+/// The mappings made into the MMIO region, which are never removed, as the devices keep their registers for good.
+struct MmioRegion {
+    mappings: [Option<MmioMapping>; MAX_MMIO_MAPPINGS],
+    // The beginning of the part of the region not used so far.
+    next: u64,
+}
+
+/// Returns the virtual address through which the given physical address is accessible.
+///
+/// NOTE: Until the direct mapping has been set up, only the physical memory covered by the initial mapping of the
+/// higher half kernel is accessible.
+pub fn phys_to_virt(addr: PhysAddr) -> Option<VirtAddr> {
+    if DIRECT_MAPPING.load(Ordering::Acquire) && addr.as_u64() < DIRECT_MAPPING_SIZE {
+        return Some(VirtAddr::new(addr.as_u64() + DIRECT_MAPPING_OFFSET));
+    }
+
+    if addr.as_u64() >= CONFIG_CORE_MEMORY_INITIAL_MAPPING_SIZE as u64 {
+        return None;
+    }
+
+    Some(VirtAddr::new(addr.as_u64() + elf::kernel_offset() as u64))
+}
+
+/// Returns the virtual address through which a physical memory range is accessible as a whole.
+pub fn phys_range_to_virt(addr: PhysAddr, len: usize) -> Option<VirtAddr> {
+    let offset = (len as u64).checked_sub(1)?;
+    let last = PhysAddr::try_new(addr.as_u64().checked_add(offset)?).ok()?;
+
+    let virt = phys_to_virt(addr)?;
+    (phys_to_virt(last)? == virt + offset).then_some(virt)
+}
+
+/// Returns the virtual address through which a memory-mapped device is accessible, mapping its range uncacheable into
+/// the MMIO region if it lies beyond the direct mapping, e.g., a 64-bit BAR or a framebuffer above 4 GiB.
+///
+/// The mappings are reused, so that mapping a range again, or a part of it, doesn't take up more of the region.
+pub fn map_mmio(addr: PhysAddr, len: usize) -> Option<VirtAddr> {
+    if let Some(virt) = phys_range_to_virt(addr, len) {
+        return Some(virt);
+    }
+
+    let end = PhysAddr::try_new(addr.as_u64().checked_add(len as u64)?).ok()?;
+    let start = addr.align_down(0x1000u64);
+    let size = end.align_up(0x1000u64) - start;
+
+    interrupts::without_interrupts(|| {
+        let mut mmio = MMIO.lock();
+
+        let existing = mmio.mappings.iter().flatten().find(|mapping| {
+            mapping.phys_addr <= addr.as_u64() && end.as_u64() <= mapping.phys_addr + mapping.len
+        });
+        if let Some(mapping) = existing {
+            return Some(VirtAddr::new(
+                mapping.virt_addr + (addr.as_u64() - mapping.phys_addr),
+            ));
+        }
+
+        let slot = mmio.mappings.iter().position(Option::is_none)?;
+        let virt_addr = mmio.next;
+        if size > MMIO_REGION_START + MMIO_REGION_SIZE - virt_addr {
+            return None;
+        }
+        // The range is taken up even if mapping it fails halfway, as the pages mapped so far are left in place.
+        mmio.next += size;
+
+        let flags = PageTableFlags::WRITABLE
+            | PageTableFlags::NO_CACHE
+            | PageTableFlags::WRITE_THROUGH
+            | PageTableFlags::GLOBAL
+            | PageTableFlags::NO_EXECUTE;
+        for offset in (0..size).step_by(0x1000) {
+            map(VirtAddr::new(virt_addr + offset), start + offset, flags).ok()?;
+        }
+
+        mmio.mappings[slot] = Some(MmioMapping {
+            phys_addr: start.as_u64(),
+            len: size,
+            virt_addr,
+        });
+
+        Some(VirtAddr::new(virt_addr + (addr.as_u64() - start.as_u64())))
+    })
+}
+
+/// Walks the active page tables for a virtual address, visiting the entry used at each level of the hierarchy,
+/// beginning with the level 4 table.
+///
+/// Returns the physical address to which the virtual address is mapped, if any.
+pub fn walk<F>(addr: VirtAddr, mut visit: F) -> Option<PhysAddr>
+where
+    F: FnMut(usize, PageTableIndex, &PageTableEntry),
+{
+    let (frame, _) = Cr3::read();
+
+    let indices = [
+        addr.p4_index(),
+        addr.p3_index(),
+        addr.p2_index(),
+        addr.p1_index(),
+    ];
+
+    let mut table_addr = frame.start_address();
+    for (depth, index) in indices.into_iter().enumerate() {
+        let table = unsafe { &*phys_to_virt(table_addr)?.as_ptr::<PageTable>() };
+        let entry = &table[index];
+
+        // Each level of the hierarchy covers 9 more bits of the address than the level below it.
+        let level = indices.len() - depth;
+        visit(level, index, entry);
+
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            return None;
+        }
+
+        if level == 1 || flags.contains(PageTableFlags::HUGE_PAGE) {
+            let page_size = 1u64 << (12 + 9 * (level - 1));
+            return Some(entry.addr() + (addr.as_u64() & (page_size - 1)));
+        }
+
+        table_addr = entry.addr();
+    }
+
+    None
+}
+
+/// Translates a virtual address to its physical address by walking the active page tables.
+///
+/// Returns `None` if the address is not mapped.
+pub fn translate(addr: VirtAddr) -> Option<PhysAddr> {
+    walk(addr, |_, _, _| {})
+}
+
+/// Checks whether every page of a memory range is mapped, so that it can be accessed without faulting.
+pub fn is_accessible(addr: u64, len: usize) -> bool {
+    let end = match addr.checked_add(len as u64) {
+        Some(end) => end,
+        None => return false,
+    };
+
+    let mut page = addr & !0xFFF;
+    while page < end {
+        if VirtAddr::try_new(page).ok().and_then(translate).is_none() {
+            return false;
+        }
+        page = match page.checked_add(0x1000) {
+            Some(page) => page,
+            None => break,
+        };
+    }
+
+    true
+}
+
+/// Returns the table referenced by an entry of the level above, allocating an empty one if the entry is not present
+/// and `allocate` is set.
+fn next_table(entry: &mut PageTableEntry, allocate: bool) -> Option<&'static mut PageTable> {
+    let flags = entry.flags();
+    if flags.contains(PageTableFlags::HUGE_PAGE) {
+        return None;
+    }
+
+    if !flags.contains(PageTableFlags::PRESENT) {
+        if !allocate {
+            return None;
+        }
+
+        let addr = frame::allocate(1)?;
+        let table = unsafe { &mut *phys_to_virt(addr)?.as_mut_ptr::<PageTable>() };
+        table.zero();
+        entry.set_addr(addr, PageTableFlags::PRESENT | PageTableFlags::WRITABLE);
+    }
+
+    Some(unsafe { &mut *phys_to_virt(entry.addr())?.as_mut_ptr::<PageTable>() })
+}
+
+/// Returns the level 1 entry of a page, optionally allocating the missing tables on the way.
+fn page_entry(page: VirtAddr, allocate: bool) -> Option<&'static mut PageTableEntry> {
+    let (frame, _) = Cr3::read();
+    let pt4 = unsafe { &mut *phys_to_virt(frame.start_address())?.as_mut_ptr::<PageTable>() };
+
+    let pt3 = next_table(&mut pt4[page.p4_index()], allocate)?;
+    let pt2 = next_table(&mut pt3[page.p3_index()], allocate)?;
+    let pt1 = next_table(&mut pt2[page.p2_index()], allocate)?;
+
+    Some(&mut pt1[page.p1_index()])
+}
+
+/// Maps a page of 4 KiB to a frame, allocating the intermediate page tables from the frame allocator as needed.
+///
+/// The page must lie outside of the memory covered by huge pages, e.g., the direct mapping, and must not be mapped
+/// already.
+pub fn map(page: VirtAddr, addr: PhysAddr, flags: PageTableFlags) -> Result<(), ()> {
+    interrupts::without_interrupts(|| {
+        let _lock = LOCK.lock();
+
+        let entry = page_entry(page.align_down(0x1000u64), true).ok_or(())?;
+        if !entry.is_unused() {
+            return Err(());
+        }
+        entry.set_addr(addr, flags | PageTableFlags::PRESENT);
+
+        Ok(())
+    })
+}
+
+/// Removes the mapping of a page of 4 KiB, returning the frame to which it was mapped.
+///
+/// The page tables themselves are kept, as the regions mapped this way tend to be reused.
+pub fn unmap(page: VirtAddr) -> Option<PhysAddr> {
+    interrupts::without_interrupts(|| {
+        let _lock = LOCK.lock();
+
+        let page = page.align_down(0x1000u64);
+        let entry = page_entry(page, false)?;
+        if !entry.flags().contains(PageTableFlags::PRESENT) {
+            return None;
+        }
+
+        let addr = entry.addr();
+        entry.set_unused();
+        tlb::flush(page);
+
+        Some(addr)
+    })
+}
+
+/// Maps the lower 4 GiB of the physical address space into the higher half using huge pages.
+///
+/// The mapping is write-back cacheable as far as the page tables are concerned, which leaves it to the MTRRs set up
+/// by the firmware to keep the memory-mapped devices uncacheable.
+pub(crate) fn init() -> Result<(), ()> {
+    let (frame, _) = Cr3::read();
+    let pt4 = unsafe {
+        &mut *phys_to_virt(frame.start_address())
+            .ok_or(())?
+            .as_mut_ptr::<PageTable>()
+    };
+    let pt3 = unsafe { &mut *addr_of_mut!(DIRECT_MAPPING_PT3) };
+    let pt2s = unsafe { &mut *addr_of_mut!(DIRECT_MAPPING_PT2) };
+
+    let table_flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    let page_flags = table_flags
+        | PageTableFlags::HUGE_PAGE
+        | PageTableFlags::GLOBAL
+        | PageTableFlags::NO_EXECUTE;
+
+    for (i, pt2) in pt2s.iter_mut().enumerate() {
+        for (j, entry) in pt2.iter_mut().enumerate() {
+            let addr = (i * 512 + j) as u64 * HUGE_PAGE_SIZE;
+            entry.set_addr(PhysAddr::new(addr), page_flags);
+        }
+        pt3[i].set_addr(translate(VirtAddr::from_ptr(pt2)).ok_or(())?, table_flags);
+    }
+
+    let index = VirtAddr::new(DIRECT_MAPPING_OFFSET).p4_index();
+    pt4[index].set_addr(translate(VirtAddr::from_ptr(pt3)).ok_or(())?, table_flags);
+
+    DIRECT_MAPPING.store(true, Ordering::Release);
+
+    Ok(())
+}
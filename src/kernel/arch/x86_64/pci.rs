@@ -0,0 +1,116 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use x86_64::instructions;
+use x86_64::instructions::port::Port;
+use x86_64::PhysAddr;
+
+use crate::kernel::pci::config;
+use crate::kernel::pci::config::{EcamRegion, LegacyAccess, PciAddress};
+use crate::kernel::sync::Mutex;
+
+use super::acpi;
+use super::paging;
+
+const CONFIG_ADDRESS: u16 = 0xCF8;
+const CONFIG_DATA: u16 = 0xCFC;
+
+const CONFIG_ENABLE: u32 = 1 << 31;
+
+/// The size of the configuration space of a bus through ECAM.
+const ECAM_BUS_SIZE: u64 = 1 << 20;
+
+struct ConfigPorts {
+    address: Port<u32>,
+    data: Port<u32>,
+}
+
+static PORTS: Mutex<ConfigPorts> = Mutex::new(ConfigPorts {
+    address: Port::new(CONFIG_ADDRESS),
+    data: Port::new(CONFIG_DATA),
+});
+
+fn config_address(addr: PciAddress, offset: u16) -> u32 {
+    CONFIG_ENABLE
+        | (addr.bus as u32) << 16
+        | (addr.device as u32) << 11
+        | (addr.function as u32) << 8
+        | (offset as u32 & 0xFC)
+}
+
+/// Reads a register through the legacy configuration mechanism, which selects it through the address port and
+/// accesses it through the data port.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/PCI#Configuration_Space_Access_Mechanism_.231
+fn read(addr: PciAddress, offset: u16) -> u32 {
+    instructions::interrupts::without_interrupts(|| {
+        let mut ports = PORTS.lock();
+        unsafe {
+            ports.address.write(config_address(addr, offset));
+            ports.data.read()
+        }
+    })
+}
+
+fn write(addr: PciAddress, offset: u16, value: u32) {
+    instructions::interrupts::without_interrupts(|| {
+        let mut ports = PORTS.lock();
+        unsafe {
+            ports.address.write(config_address(addr, offset));
+            ports.data.write(value);
+        }
+    })
+}
+
+pub(crate) fn init() -> Result<(), ()> {
+    config::register_legacy(LegacyAccess { read, write });
+
+    for entry in acpi::mcfg_entries() {
+        let base = entry.base + entry.start_bus as u64 * ECAM_BUS_SIZE;
+        let size = (entry.end_bus as u64 - entry.start_bus as u64 + 1) * ECAM_BUS_SIZE;
+
+        let Some(virt) = paging::map_mmio(PhysAddr::new(base), size as usize) else {
+            log::warn!(
+                "ECAM of segment {} at {:#X} could not be mapped",
+                entry.segment,
+                base
+            );
+            continue;
+        };
+        config::register_ecam(EcamRegion {
+            base: virt.as_u64() as usize,
+            segment: entry.segment,
+            start_bus: entry.start_bus,
+            end_bus: entry.end_bus,
+        })?;
+
+        log::info!(
+            "ECAM of segment {} (buses {}-{}) at {:#X}",
+            entry.segment,
+            entry.start_bus,
+            entry.end_bus,
+            base
+        );
+    }
+
+    Ok(())
+}
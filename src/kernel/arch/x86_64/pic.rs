@@ -0,0 +1,178 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use x86_64::instructions::port::Port;
+
+use crate::kernel::sync::Mutex;
+
+/// The interrupt vector onto which IRQ 0 of the master PIC is remapped.
+///
+/// The first 32 vectors are reserved for exceptions, hence the IRQs are remapped right after them.
+pub const PIC_1_OFFSET: u8 = 0x20;
+/// The interrupt vector onto which IRQ 8 (the first IRQ of the slave PIC) is remapped.
+pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
+
+/// The number of IRQ lines served by both PICs.
+pub const IRQ_COUNT: u8 = 16;
+
+/// The IRQ line of the master PIC to which the slave PIC is cascaded.
+const CASCADE_IRQ: u8 = 2;
+
+const CMD_INIT: u8 = 0x11;
+const CMD_END_OF_INTERRUPT: u8 = 0x20;
+const CMD_READ_ISR: u8 = 0x0B;
+const MODE_8086: u8 = 0x01;
+
+static PICS: Mutex<ChainedPics> = Mutex::new(ChainedPics::new());
+
+struct Pic {
+    command: Port<u8>,
+    data: Port<u8>,
+}
+
+impl Pic {
+    const fn new(base: u16) -> Self {
+        Self {
+            command: Port::new(base),
+            data: Port::new(base + 1),
+        }
+    }
+}
+
+/// Chained Programmable Interrupt Controllers (8259 PIC)
+///
+/// The legacy PC architecture routes the hardware interrupts through two 8259 PICs, where the slave PIC is cascaded
+/// through IRQ 2 of the master PIC. Their default vectors collide with the exceptions of the processor in Protected
+/// Mode and Long Mode, hence they must be remapped before the interrupts are enabled.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/8259_PIC
+struct ChainedPics {
+    master: Pic,
+    slave: Pic,
+}
+
+impl ChainedPics {
+    const fn new() -> Self {
+        Self {
+            master: Pic::new(0x20),
+            slave: Pic::new(0xA0),
+        }
+    }
+
+    unsafe fn initialize(&mut self) {
+        // Writing to an unused port gives the PICs time to react to the previous command on older machines.
+        let mut wait_port = Port::<u8>::new(0x80);
+        let mut wait = || wait_port.write(0);
+
+        // Start the initialization sequence in cascade mode.
+        self.master.command.write(CMD_INIT);
+        wait();
+        self.slave.command.write(CMD_INIT);
+        wait();
+
+        // Set up the vector offsets.
+        self.master.data.write(PIC_1_OFFSET);
+        wait();
+        self.slave.data.write(PIC_2_OFFSET);
+        wait();
+
+        // Configure the chaining between the master and the slave.
+        self.master.data.write(1 << CASCADE_IRQ);
+        wait();
+        self.slave.data.write(CASCADE_IRQ);
+        wait();
+
+        self.master.data.write(MODE_8086);
+        wait();
+        self.slave.data.write(MODE_8086);
+        wait();
+
+        // Mask every IRQ except the cascade until a handler is registered for it.
+        self.master.data.write(!(1 << CASCADE_IRQ));
+        self.slave.data.write(0xFF);
+    }
+
+    unsafe fn set_masked(&mut self, irq: u8, masked: bool) {
+        let (pic, line) = if irq < 8 {
+            (&mut self.master, irq)
+        } else {
+            (&mut self.slave, irq - 8)
+        };
+
+        let mask = pic.data.read();
+        let mask = if masked {
+            mask | 1 << line
+        } else {
+            mask & !(1 << line)
+        };
+        pic.data.write(mask);
+    }
+
+    unsafe fn in_service(&mut self, irq: u8) -> bool {
+        let (pic, line) = if irq < 8 {
+            (&mut self.master, irq)
+        } else {
+            (&mut self.slave, irq - 8)
+        };
+
+        pic.command.write(CMD_READ_ISR);
+        pic.command.read() & 1 << line != 0
+    }
+
+    unsafe fn end_of_interrupt(&mut self, irq: u8) {
+        if irq >= 8 {
+            self.slave.command.write(CMD_END_OF_INTERRUPT);
+        }
+        self.master.command.write(CMD_END_OF_INTERRUPT);
+    }
+}
+
+pub fn init() -> Result<(), ()> {
+    unsafe { PICS.lock().initialize() };
+
+    Ok(())
+}
+
+pub fn unmask(irq: u8) {
+    unsafe { PICS.lock().set_masked(irq, false) };
+}
+
+/// Checks whether an interrupt on the given IRQ line is spurious.
+///
+/// The PICs signal IRQ 7 (or IRQ 15) when an interrupt disappears before it is acknowledged. Such an interrupt is not
+/// marked as being in service and must not be acknowledged, except for the cascade on the master in case of IRQ 15.
+pub fn is_spurious(irq: u8) -> bool {
+    let mut pics = PICS.lock();
+    match irq {
+        7 | 15 if unsafe { !pics.in_service(irq) } => {
+            if irq == 15 {
+                unsafe { pics.master.command.write(CMD_END_OF_INTERRUPT) };
+            }
+            true
+        }
+        _ => false,
+    }
+}
+
+pub fn end_of_interrupt(irq: u8) {
+    unsafe { PICS.lock().end_of_interrupt(irq) };
+}
@@ -0,0 +1,77 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use x86_64::instructions::port::Port;
+
+/// Reads a byte from an I/O port.
+///
+/// # Safety
+///
+/// Reading from an I/O port may have side effects on the device behind it.
+pub unsafe fn read_u8(port: u16) -> u8 {
+    Port::<u8>::new(port).read()
+}
+
+/// Reads a word from an I/O port.
+///
+/// # Safety
+///
+/// Reading from an I/O port may have side effects on the device behind it.
+pub unsafe fn read_u16(port: u16) -> u16 {
+    Port::<u16>::new(port).read()
+}
+
+/// Reads a double word from an I/O port.
+///
+/// # Safety
+///
+/// Reading from an I/O port may have side effects on the device behind it.
+pub unsafe fn read_u32(port: u16) -> u32 {
+    Port::<u32>::new(port).read()
+}
+
+/// Writes a byte to an I/O port.
+///
+/// # Safety
+///
+/// Writing to an I/O port may have side effects on the device behind it.
+pub unsafe fn write_u8(port: u16, value: u8) {
+    Port::<u8>::new(port).write(value)
+}
+
+/// Writes a word to an I/O port.
+///
+/// # Safety
+///
+/// Writing to an I/O port may have side effects on the device behind it.
+pub unsafe fn write_u16(port: u16, value: u16) {
+    Port::<u16>::new(port).write(value)
+}
+
+/// Writes a double word to an I/O port.
+///
+/// # Safety
+///
+/// Writing to an I/O port may have side effects on the device behind it.
+pub unsafe fn write_u32(port: u16, value: u32) {
+    Port::<u32>::new(port).write(value)
+}
@@ -34,3 +34,15 @@ macro_rules! load_generated_config {
 }
 
 load_generated_config!();
+
+/// The settings chosen at build time, by name, for reporting them at run time.
+pub const CONFIGURATION: &[(&str, usize)] = &[
+    (
+        "CONFIG_CORE_MEMORY_STACK_SIZE",
+        CONFIG_CORE_MEMORY_STACK_SIZE,
+    ),
+    (
+        "CONFIG_CORE_MEMORY_INITIAL_MAPPING_SIZE",
+        CONFIG_CORE_MEMORY_INITIAL_MAPPING_SIZE,
+    ),
+];
@@ -109,21 +109,21 @@ macro_rules! header_tag_framebuffer {
 //         0
 //     };
 // }
-// macro_rules! tag_type_cmdline {
-//     () => {
-//         1
-//     };
-// }
-// macro_rules! tag_type_boot_loader_name {
-//     () => {
-//         2
-//     };
-// }
-// macro_rules! tag_type_module {
-//     () => {
-//         3
-//     };
-// }
+macro_rules! tag_type_cmdline {
+    () => {
+        1
+    };
+}
+macro_rules! tag_type_boot_loader_name {
+    () => {
+        2
+    };
+}
+macro_rules! tag_type_module {
+    () => {
+        3
+    };
+}
 // macro_rules! tag_type_basic_mem_info {
 //     () => {
 //         4
@@ -302,7 +302,12 @@ static MULTIBOOT_HEADER: MultibootHeader = MultibootHeader {
     checksum: header_checksum!(),
     info_request: MultibootInfoRequest {
         tag: tag_info_request!(),
-        request_type: tag_type_mem_map!(),
+        request_types: [
+            tag_type_cmdline!(),
+            tag_type_boot_loader_name!(),
+            tag_type_module!(),
+            tag_type_mem_map!(),
+        ],
     },
     console_request: MultibootConsoleRequest {
         tag: tag_console_request!(),
@@ -327,7 +332,7 @@ struct MultibootHeaderTag {
 #[repr(C)]
 struct MultibootInfoRequest {
     tag: MultibootHeaderTag,
-    request_type: u32,
+    request_types: [u32; 4],
 }
 
 #[repr(C)]
@@ -20,6 +20,10 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+use core::ptr::addr_of;
+
+use x86_64::VirtAddr;
+
 use super::configurations::CONFIG_CORE_MEMORY_STACK_SIZE;
 
 // The `stack_default` macro generates a default stack with a specific size.
@@ -39,3 +43,8 @@ struct Stack<const SIZE: usize>([u8; SIZE]);
 
 #[no_mangle]
 static mut KERNEL_STACK: Stack<{ CONFIG_CORE_MEMORY_STACK_SIZE }> = stack_default!();
+
+/// Returns the address right past the end of the stack on which the kernel has been booted.
+pub fn top() -> VirtAddr {
+    VirtAddr::from_ptr(addr_of!(KERNEL_STACK)) + CONFIG_CORE_MEMORY_STACK_SIZE
+}
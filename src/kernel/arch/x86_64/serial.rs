@@ -22,31 +22,81 @@
 
 use core::fmt::Arguments;
 use core::fmt::Write;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
-use lazy_static::lazy_static;
-use spin::Mutex;
-use uart_16550::SerialPort;
-use x86_64::instructions;
+use crate::kernel::cmdline;
+use crate::kernel::cmdline::{Arg, Param, ParamError};
 
-lazy_static! {
-    /// Serial communication through 16550 UART interface.
-    static ref UART_3F8: Mutex<SerialPort> = {
-        // On x86_64 architecture, the UART serial device is accessed through port-mapped I/O.
-        const SERIAL_IO_PORT: u16 = 0x3F8;
+use super::uart;
+use super::uart::ComPort;
 
-        let mut port = unsafe { SerialPort::new(SERIAL_IO_PORT) };
-        port.init();
+/// `console=ttyS<n>[,<baud rate>]` selects the serial port used as the console, and optionally its baud rate.
+static CONSOLE: Param = Param::new("console", set_console);
 
-        Mutex::new(port)
+/// The index of the serial port used as the console of the kernel, which is COM1 unless specified otherwise.
+static CONSOLE_PORT: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the serial port used as the console of the kernel.
+pub fn console_port() -> ComPort {
+    ComPort::ALL[CONSOLE_PORT.load(Ordering::Relaxed)]
+}
+
+fn set_console(arg: &Arg) -> Result<(), ParamError> {
+    let value = arg.value.ok_or(ParamError::MissingValue)?;
+    let (device, baud_rate) = match value.split_once(',') {
+        Some((device, baud_rate)) => (device, Some(baud_rate)),
+        None => (value, None),
     };
+
+    let index = device
+        .strip_prefix("ttyS")
+        .and_then(|index| index.parse::<usize>().ok())
+        .filter(|index| *index < ComPort::ALL.len())
+        .ok_or(ParamError::InvalidValue)?;
+    let port = ComPort::ALL[index];
+    if !uart::is_present(port) {
+        return Err(ParamError::InvalidValue);
+    }
+
+    if let Some(baud_rate) = baud_rate {
+        let baud_rate = baud_rate.parse().map_err(|_| ParamError::InvalidValue)?;
+        let config = uart::config(port).map_err(|_| ParamError::InvalidValue)?;
+        uart::configure(
+            port,
+            uart::LineConfig {
+                baud_rate,
+                ..config
+            },
+        )
+        .map_err(|_| ParamError::InvalidValue)?;
+    }
+
+    CONSOLE_PORT.store(index, Ordering::Relaxed);
+
+    Ok(())
 }
 
 #[doc(hidden)]
 pub fn _print(args: Arguments) {
-    instructions::interrupts::without_interrupts(|| {
-        UART_3F8
-            .lock()
+    let port = console_port();
+    // The output is discarded if there's no UART attached to the console port.
+    if uart::is_present(port) {
+        uart::Writer(port)
             .write_fmt(args)
             .expect("failed to print to serial output");
-    });
+    }
+}
+
+/// Reads the data received through the console port into the buffer without blocking.
+pub fn read(buf: &mut [u8]) -> usize {
+    uart::read(console_port(), buf).unwrap_or_default()
+}
+
+/// Returns the next byte received through the console port, if any, without blocking.
+pub fn read_byte() -> Option<u8> {
+    uart::read_byte(console_port()).unwrap_or_default()
+}
+
+pub(crate) fn init() -> Result<(), ()> {
+    cmdline::register(&CONSOLE).map_err(|_| ())
 }
@@ -0,0 +1,115 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use x86_64::instructions::interrupts;
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::VirtAddr;
+
+use crate::kernel::sync::Mutex;
+
+use super::frame;
+use super::frame::FRAME_SIZE;
+use super::paging;
+
+/// The beginning of the region of the address space set aside for kernel stacks, i.e., the last but one entry of the
+/// level 4 table, which lies between the direct mapping and the kernel image.
+const REGION_START: u64 = 0xFFFF_FF00_0000_0000;
+
+/// The size of a kernel stack, excluding its guard pages.
+pub const STACK_SIZE: usize = 64 * 1024;
+
+/// The amount of address space taken by each stack, whose part below the stack itself is never mapped.
+const SLOT_SIZE: u64 = 2 * STACK_SIZE as u64;
+
+const MAX_STACKS: usize = 256;
+
+static SLOTS: Mutex<[bool; MAX_STACKS]> = Mutex::new([false; MAX_STACKS]);
+
+/// Kernel Stack
+///
+/// A stack mapped page by page into its own slot of the stack region. The pages below the stack are left unmapped as
+/// guard pages, so that an overflow faults instead of silently corrupting the memory of its neighbour. The stack is
+/// unmapped and its frames are released when it's dropped.
+#[derive(Debug)]
+pub struct Stack {
+    slot: usize,
+}
+
+impl Stack {
+    pub fn allocate() -> Option<Self> {
+        let slot = interrupts::without_interrupts(|| {
+            let mut slots = SLOTS.lock();
+            let slot = slots.iter().position(|used| !used)?;
+            slots[slot] = true;
+            Some(slot)
+        })?;
+
+        // Should the frames run out halfway, dropping the stack unmaps the pages mapped so far and releases the slot.
+        let stack = Self { slot };
+        let flags = PageTableFlags::WRITABLE | PageTableFlags::GLOBAL | PageTableFlags::NO_EXECUTE;
+        for page in stack.pages() {
+            let addr = frame::allocate(1)?;
+            if paging::map(page, addr, flags).is_err() {
+                frame::deallocate(addr, 1);
+                return None;
+            }
+        }
+
+        Some(stack)
+    }
+
+    pub fn bottom(&self) -> VirtAddr {
+        VirtAddr::new(REGION_START + (self.slot as u64 + 1) * SLOT_SIZE - STACK_SIZE as u64)
+    }
+
+    /// Returns the initial stack pointer, i.e., the address right past the end of the stack.
+    pub fn top(&self) -> VirtAddr {
+        self.bottom() + STACK_SIZE
+    }
+
+    fn pages(&self) -> impl Iterator<Item = VirtAddr> {
+        let bottom = self.bottom();
+        (0..STACK_SIZE / FRAME_SIZE).map(move |i| bottom + i * FRAME_SIZE)
+    }
+}
+
+impl Drop for Stack {
+    fn drop(&mut self) {
+        for page in self.pages() {
+            if let Some(addr) = paging::unmap(page) {
+                frame::deallocate(addr, 1);
+            }
+        }
+
+        interrupts::without_interrupts(|| SLOTS.lock()[self.slot] = false);
+    }
+}
+
+/// Checks whether an address lies within the guard pages of a stack, e.g., the faulting address of an overflow.
+pub fn is_guard_page(addr: VirtAddr) -> bool {
+    let addr = addr.as_u64();
+    if !(REGION_START..REGION_START + MAX_STACKS as u64 * SLOT_SIZE).contains(&addr) {
+        return false;
+    }
+
+    (addr - REGION_START) % SLOT_SIZE < SLOT_SIZE - STACK_SIZE as u64
+}
@@ -0,0 +1,75 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use x86_64::instructions::port::Port;
+
+use crate::kernel::thread;
+
+use super::irq;
+
+/// The number of timer interrupts per second, i.e., a tick lasts one millisecond.
+pub const TICK_RATE: u64 = 1000;
+
+/// The frequency of the oscillator driving the PIT, in Hz.
+const PIT_FREQUENCY: u64 = 1_193_182;
+
+const PIT_CHANNEL_0: u16 = 0x40;
+const PIT_COMMAND: u16 = 0x43;
+
+/// Channel 0, low byte followed by high byte of the reload value, mode 2 (rate generator), binary counting.
+const CMD_RATE_GENERATOR: u8 = 0x34;
+
+const TIMER_IRQ: u8 = 0;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the number of ticks since the timer was started.
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+fn on_tick() {
+    let now = TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+    thread::tick(now);
+}
+
+/// System Timer
+///
+/// Channel 0 of the Programmable Interval Timer (PIT) is set up as a rate generator raising IRQ 0 at `TICK_RATE`,
+/// which keeps the time since boot and drives the time slices of the scheduler.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/Programmable_Interval_Timer
+pub(crate) fn init() -> Result<(), ()> {
+    let divisor = (PIT_FREQUENCY / TICK_RATE) as u16;
+
+    let mut command = Port::<u8>::new(PIT_COMMAND);
+    let mut channel = Port::<u8>::new(PIT_CHANNEL_0);
+    unsafe {
+        command.write(CMD_RATE_GENERATOR);
+        channel.write(divisor as u8);
+        channel.write((divisor >> 8) as u8);
+    }
+
+    irq::register(TIMER_IRQ, on_tick).map_err(|_| ())
+}
@@ -0,0 +1,92 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::arch::global_asm;
+
+use x86_64::VirtAddr;
+
+use super::exceptions::{BreakpointException, DebugException};
+
+// This assembly file contains the entry points of the exceptions which require access to the full register
+// state of the interrupted context (e.g. for debugging).
+global_asm!(include_str!("trap.s"));
+
+extern "C" {
+    fn trap_breakpoint();
+    fn trap_debug();
+}
+
+/// Trap Frame
+///
+/// The register state of the interrupted context as saved by the trap entry stubs. Any modification made to the
+/// frame is restored into the processor upon return from the trap.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TrapFrame {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    pub vector: u64,
+    pub err_code: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+pub enum TrapEntry {
+    Debug,
+    Breakpoint,
+}
+
+impl TrapEntry {
+    pub fn addr(self) -> VirtAddr {
+        let entry = match self {
+            TrapEntry::Debug => trap_debug,
+            TrapEntry::Breakpoint => trap_breakpoint,
+        };
+
+        VirtAddr::new(entry as usize as u64)
+    }
+}
+
+#[no_mangle]
+extern "C" fn trap_dispatch(frame: &mut TrapFrame) {
+    match frame.vector as u8 {
+        DebugException::CODE => DebugException::handler(frame),
+        BreakpointException::CODE => BreakpointException::handler(frame),
+        vector => panic!("unexpected trap vector {:#04X}", vector),
+    }
+}
@@ -0,0 +1,95 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+// Trap Entry Points
+//
+// Unlike the handlers using the `x86-interrupt` calling convention, these stubs save every general purpose
+// register of the interrupted context into a `TrapFrame` before calling into Rust. This allows debuggers to
+// inspect and modify the complete register state, and the changes take effect once the stub returns with `iretq`.
+//
+// Stack layout after the stub has saved the registers (growing downwards):
+//
+//      SS, RSP, RFLAGS, CS, RIP            <- pushed by the processor
+//      Error Code                          <- pushed by the processor or the stub (0)
+//      Vector                              <- pushed by the stub
+//      RAX, RBX, ..., R15                  <- pushed by the stub
+//
+// The processor aligns the stack to 16 bytes before pushing the interrupt stack frame, hence the stack remains
+// aligned as required by the System V ABI when `trap_dispatch` is called.
+
+.global trap_breakpoint
+.global trap_debug
+
+.section .text, "ax", @progbits
+.code64
+
+.macro TRAP_ENTRY name, vector
+\name:
+    push 0
+    push \vector
+    jmp trap_common
+.endm
+
+TRAP_ENTRY trap_debug, 0x01
+TRAP_ENTRY trap_breakpoint, 0x03
+
+trap_common:
+    push rax
+    push rbx
+    push rcx
+    push rdx
+    push rsi
+    push rdi
+    push rbp
+    push r8
+    push r9
+    push r10
+    push r11
+    push r12
+    push r13
+    push r14
+    push r15
+
+    // Argument for `trap_dispatch` function.
+    mov rdi, rsp
+    cld
+    call trap_dispatch
+
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop r11
+    pop r10
+    pop r9
+    pop r8
+    pop rbp
+    pop rdi
+    pop rsi
+    pop rdx
+    pop rcx
+    pop rbx
+    pop rax
+
+    // Discard the vector and the error code.
+    add rsp, 16
+    iretq
@@ -0,0 +1,556 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::fmt;
+use core::fmt::Write;
+
+use lazy_static::lazy_static;
+use x86_64::instructions;
+use x86_64::instructions::port::Port;
+
+use crate::aux::ring_buffer::RingBuffer;
+use crate::kernel::sync::Mutex;
+
+use super::irq;
+
+const RX_BUFFER_SIZE: usize = 1024;
+const TX_BUFFER_SIZE: usize = 4096;
+
+/// The frequency of the clock from which the baud rate is divided.
+const CLOCK_FREQUENCY: u32 = 115200;
+/// The depth of the transmitter FIFO of the 16550.
+const TX_FIFO_SIZE: usize = 16;
+/// The maximum number of conditions serviced by a single interrupt, guarding against misbehaving hardware.
+const MAX_SERVICE_ITERATIONS: usize = 512;
+
+// Offsets of the registers from the base port.
+const REG_DATA: u16 = 0;
+const REG_INTERRUPT_ENABLE: u16 = 1;
+const REG_INTERRUPT_IDENTIFICATION: u16 = 2;
+const REG_FIFO_CONTROL: u16 = 2;
+const REG_LINE_CONTROL: u16 = 3;
+const REG_MODEM_CONTROL: u16 = 4;
+const REG_LINE_STATUS: u16 = 5;
+const REG_MODEM_STATUS: u16 = 6;
+// With the Divisor Latch Access Bit (DLAB) set, the first two registers hold the divisor of the baud rate.
+const REG_DIVISOR_LOW: u16 = 0;
+const REG_DIVISOR_HIGH: u16 = 1;
+
+const IER_RX_AVAILABLE: u8 = 1 << 0;
+const IER_TX_EMPTY: u8 = 1 << 1;
+const IER_LINE_STATUS: u8 = 1 << 2;
+const IER_MODEM_STATUS: u8 = 1 << 3;
+
+const IIR_NO_INTERRUPT: u8 = 1 << 0;
+
+// Enable and clear the FIFOs, with the receiver interrupt triggered at 14 bytes.
+const FCR_ENABLE: u8 = 0xC7;
+
+const LCR_DLAB: u8 = 1 << 7;
+
+const MCR_DTR: u8 = 1 << 0;
+const MCR_RTS: u8 = 1 << 1;
+// The auxiliary output 2 connects the interrupt line of the UART to the PIC.
+const MCR_OUT_2: u8 = 1 << 3;
+const MCR_LOOPBACK: u8 = 1 << 4;
+
+const LSR_DATA_READY: u8 = 1 << 0;
+const LSR_TX_EMPTY: u8 = 1 << 5;
+
+const MSR_CTS: u8 = 1 << 4;
+const MSR_DSR: u8 = 1 << 5;
+const MSR_RI: u8 = 1 << 6;
+const MSR_DCD: u8 = 1 << 7;
+
+/// The legacy serial ports of the PC architecture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ComPort {
+    Com1,
+    Com2,
+    Com3,
+    Com4,
+}
+
+impl ComPort {
+    pub const ALL: [ComPort; 4] = [ComPort::Com1, ComPort::Com2, ComPort::Com3, ComPort::Com4];
+
+    pub fn base(self) -> u16 {
+        match self {
+            ComPort::Com1 => 0x3F8,
+            ComPort::Com2 => 0x2F8,
+            ComPort::Com3 => 0x3E8,
+            ComPort::Com4 => 0x2E8,
+        }
+    }
+
+    /// Returns the IRQ line of the port, which is shared by COM1 and COM3, as well as COM2 and COM4.
+    pub fn irq(self) -> u8 {
+        match self {
+            ComPort::Com1 | ComPort::Com3 => 4,
+            ComPort::Com2 | ComPort::Com4 => 3,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+    Mark,
+    Space,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopBits {
+    One,
+    Two,
+}
+
+/// The configuration of the serial line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LineConfig {
+    pub baud_rate: u32,
+    /// The number of data bits in a character, between 5 and 8.
+    pub data_bits: u8,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+}
+
+impl Default for LineConfig {
+    fn default() -> Self {
+        Self {
+            baud_rate: 38400,
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+        }
+    }
+}
+
+impl LineConfig {
+    fn divisor(&self) -> Option<u16> {
+        if self.baud_rate == 0 || CLOCK_FREQUENCY % self.baud_rate != 0 {
+            return None;
+        }
+
+        u16::try_from(CLOCK_FREQUENCY / self.baud_rate).ok()
+    }
+
+    fn line_control(&self) -> Option<u8> {
+        let word_length = match self.data_bits {
+            5..=8 => self.data_bits - 5,
+            _ => return None,
+        };
+        let stop_bits = match self.stop_bits {
+            StopBits::One => 0,
+            StopBits::Two => 1 << 2,
+        };
+        let parity = match self.parity {
+            Parity::None => 0b000,
+            Parity::Odd => 0b001,
+            Parity::Even => 0b011,
+            Parity::Mark => 0b101,
+            Parity::Space => 0b111,
+        } << 3;
+
+        Some(word_length | stop_bits | parity)
+    }
+}
+
+/// The state of the modem status lines.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ModemStatus {
+    /// Clear To Send (CTS)
+    pub clear_to_send: bool,
+    /// Data Set Ready (DSR)
+    pub data_set_ready: bool,
+    /// Ring Indicator (RI)
+    pub ring_indicator: bool,
+    /// Data Carrier Detect (DCD)
+    pub data_carrier_detect: bool,
+}
+
+impl From<u8> for ModemStatus {
+    fn from(msr: u8) -> Self {
+        Self {
+            clear_to_send: msr & MSR_CTS != 0,
+            data_set_ready: msr & MSR_DSR != 0,
+            ring_indicator: msr & MSR_RI != 0,
+            data_carrier_detect: msr & MSR_DCD != 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UartError {
+    /// No UART is attached to the port.
+    NotPresent,
+    /// The configuration is not supported by the UART.
+    InvalidConfig,
+}
+
+/// The received bytes which are not buffered, but trigger the handler instead.
+type Attention = (&'static [u8], fn());
+
+/// Universal Asynchronous Receiver-Transmitter (16550 UART)
+///
+/// The received data is buffered by the interrupt handler until it is read, while the data to be transmitted is
+/// buffered until the transmitter is ready to accept it. When the interrupts are disabled, the transmitter is polled
+/// instead, so that the output is never lost.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/Serial_Ports
+struct Uart {
+    base: u16,
+    config: LineConfig,
+    rx: RingBuffer<u8, RX_BUFFER_SIZE>,
+    tx: RingBuffer<u8, TX_BUFFER_SIZE>,
+    interrupt_enable: u8,
+    modem_status: u8,
+    // Whether the IRQ line of the port is serviced.
+    irq_driven: bool,
+    attention: Option<Attention>,
+    // The number of received bytes dropped due to a full buffer.
+    dropped: usize,
+}
+
+impl Uart {
+    fn register(&self, offset: u16) -> Port<u8> {
+        Port::new(self.base + offset)
+    }
+
+    unsafe fn read(&self, offset: u16) -> u8 {
+        self.register(offset).read()
+    }
+
+    unsafe fn write(&self, offset: u16, value: u8) {
+        self.register(offset).write(value)
+    }
+
+    /// Probes for a UART at the given port and initializes it with the default configuration.
+    fn probe(port: ComPort) -> Option<Self> {
+        let mut uart = Self {
+            base: port.base(),
+            config: LineConfig::default(),
+            rx: RingBuffer::new(),
+            tx: RingBuffer::new(),
+            interrupt_enable: IER_RX_AVAILABLE | IER_LINE_STATUS | IER_MODEM_STATUS,
+            modem_status: 0,
+            irq_driven: false,
+            attention: None,
+            dropped: 0,
+        };
+
+        const PATTERN: u8 = 0xAE;
+
+        unsafe {
+            uart.write(REG_INTERRUPT_ENABLE, 0);
+            uart.apply(LineConfig::default()).ok()?;
+            uart.write(REG_FIFO_CONTROL, FCR_ENABLE);
+
+            // In loopback mode, the transmitted data is received back by the UART, if there's one.
+            uart.write(REG_MODEM_CONTROL, MCR_RTS | MCR_OUT_2 | MCR_LOOPBACK);
+            uart.write(REG_DATA, PATTERN);
+            if uart.read(REG_DATA) != PATTERN {
+                return None;
+            }
+
+            uart.write(REG_MODEM_CONTROL, MCR_DTR | MCR_RTS | MCR_OUT_2);
+            uart.modem_status = uart.read(REG_MODEM_STATUS);
+            uart.write(REG_INTERRUPT_ENABLE, uart.interrupt_enable);
+        }
+
+        Some(uart)
+    }
+
+    unsafe fn apply(&mut self, config: LineConfig) -> Result<(), UartError> {
+        let divisor = config.divisor().ok_or(UartError::InvalidConfig)?;
+        let line_control = config.line_control().ok_or(UartError::InvalidConfig)?;
+
+        self.write(REG_LINE_CONTROL, LCR_DLAB);
+        self.write(REG_DIVISOR_LOW, divisor as u8);
+        self.write(REG_DIVISOR_HIGH, (divisor >> 8) as u8);
+        self.write(REG_LINE_CONTROL, line_control);
+
+        self.config = config;
+
+        Ok(())
+    }
+
+    fn set_interrupt_enable(&mut self, interrupt_enable: u8) {
+        if self.interrupt_enable != interrupt_enable {
+            self.interrupt_enable = interrupt_enable;
+            unsafe { self.write(REG_INTERRUPT_ENABLE, interrupt_enable) };
+        }
+    }
+
+    fn send_polled(&mut self, byte: u8) {
+        unsafe {
+            while self.read(REG_LINE_STATUS) & LSR_TX_EMPTY == 0 {
+                core::hint::spin_loop();
+            }
+            self.write(REG_DATA, byte);
+        }
+    }
+
+    /// Transmits the buffered data by polling the transmitter.
+    fn flush_polled(&mut self) {
+        while let Some(byte) = self.tx.pop() {
+            self.send_polled(byte);
+        }
+    }
+
+    fn try_receive(&mut self) -> Option<u8> {
+        if let Some(byte) = self.rx.pop() {
+            return Some(byte);
+        }
+
+        unsafe {
+            if self.read(REG_LINE_STATUS) & LSR_DATA_READY == 0 {
+                return None;
+            }
+            Some(self.read(REG_DATA))
+        }
+    }
+
+    /// Services the pending interrupts of the UART and returns the attention handler, if it has been triggered.
+    fn service(&mut self) -> Option<fn()> {
+        let mut attention = None;
+
+        unsafe {
+            for _ in 0..MAX_SERVICE_ITERATIONS {
+                if self.read(REG_INTERRUPT_IDENTIFICATION) & IIR_NO_INTERRUPT != 0 {
+                    break;
+                }
+
+                // Reading the line status acknowledges the line status interrupt.
+                let line_status = self.read(REG_LINE_STATUS);
+
+                if line_status & LSR_DATA_READY != 0 {
+                    let byte = self.read(REG_DATA);
+                    match self.attention {
+                        Some((keys, handler)) if keys.contains(&byte) => attention = Some(handler),
+                        _ => {
+                            if self.rx.push(byte).is_err() {
+                                self.dropped += 1;
+                            }
+                        }
+                    }
+                    continue;
+                }
+
+                if line_status & LSR_TX_EMPTY != 0 {
+                    for _ in 0..TX_FIFO_SIZE {
+                        match self.tx.pop() {
+                            Some(byte) => self.write(REG_DATA, byte),
+                            None => break,
+                        }
+                    }
+                    if self.tx.is_empty() {
+                        self.set_interrupt_enable(self.interrupt_enable & !IER_TX_EMPTY);
+                    }
+                }
+
+                // Reading the modem status acknowledges the modem status interrupt.
+                self.modem_status = self.read(REG_MODEM_STATUS);
+            }
+        }
+
+        attention
+    }
+}
+
+lazy_static! {
+    static ref PORTS: [Mutex<Option<Uart>>; 4] =
+        ComPort::ALL.map(|port| Mutex::new(Uart::probe(port)));
+}
+
+fn with_uart<F, R>(port: ComPort, f: F) -> Result<R, UartError>
+where
+    F: FnOnce(&mut Uart) -> R,
+{
+    // The lock of a port is also acquired by its interrupt handler.
+    instructions::interrupts::without_interrupts(|| {
+        let mut uart = PORTS[port as usize].lock();
+        uart.as_mut().map(f).ok_or(UartError::NotPresent)
+    })
+}
+
+fn service_line(irq: u8) {
+    for port in ComPort::ALL.into_iter().filter(|port| port.irq() == irq) {
+        let attention = PORTS[port as usize].lock().as_mut().and_then(Uart::service);
+
+        // The handler is invoked without holding the lock, so that it can use the port.
+        if let Some(handler) = attention {
+            handler();
+        }
+    }
+}
+
+fn on_irq_3() {
+    service_line(3);
+}
+
+fn on_irq_4() {
+    service_line(4);
+}
+
+/// Checks whether a UART is attached to the port.
+pub fn is_present(port: ComPort) -> bool {
+    with_uart(port, |_| ()).is_ok()
+}
+
+pub fn config(port: ComPort) -> Result<LineConfig, UartError> {
+    with_uart(port, |uart| uart.config)
+}
+
+/// Changes the baud rate, the character format and the parity of the serial line.
+///
+/// The baud rate must evenly divide the clock frequency of 115200 Hz.
+pub fn configure(port: ComPort, config: LineConfig) -> Result<(), UartError> {
+    with_uart(port, |uart| {
+        uart.flush_polled();
+        unsafe { uart.apply(config) }
+    })?
+}
+
+/// Queues the data for transmission.
+///
+/// The data is transmitted by polling the transmitter instead, if the interrupts are disabled or the port is not
+/// serviced by its interrupt handler.
+pub fn write(port: ComPort, data: &[u8]) -> Result<(), UartError> {
+    let interrupts_enabled = instructions::interrupts::are_enabled();
+
+    with_uart(port, |uart| {
+        if !interrupts_enabled || !uart.irq_driven {
+            uart.flush_polled();
+            data.iter().for_each(|byte| uart.send_polled(*byte));
+            return;
+        }
+
+        for byte in data {
+            // Make room for the data by transmitting the buffered data, if necessary.
+            while uart.tx.push(*byte).is_err() {
+                if let Some(byte) = uart.tx.pop() {
+                    uart.send_polled(byte);
+                }
+            }
+        }
+
+        // The UART raises an interrupt as soon as the transmitter is empty.
+        uart.set_interrupt_enable(uart.interrupt_enable | IER_TX_EMPTY);
+    })
+}
+
+/// Returns the next received byte, if any, without blocking.
+pub fn read_byte(port: ComPort) -> Result<Option<u8>, UartError> {
+    with_uart(port, Uart::try_receive)
+}
+
+/// Reads the received data into the buffer without blocking, and returns the number of bytes read.
+pub fn read(port: ComPort, buf: &mut [u8]) -> Result<usize, UartError> {
+    with_uart(port, |uart| {
+        let mut len = 0;
+        while len < buf.len() {
+            match uart.try_receive() {
+                Some(byte) => buf[len] = byte,
+                None => break,
+            }
+            len += 1;
+        }
+        len
+    })
+}
+
+/// Blocks until a byte is received.
+///
+/// This works even if the interrupts are disabled, e.g. in exception handlers.
+pub fn receive(port: ComPort) -> Result<u8, UartError> {
+    loop {
+        if let Some(byte) = read_byte(port)? {
+            return Ok(byte);
+        }
+        core::hint::spin_loop();
+    }
+}
+
+pub fn modem_status(port: ComPort) -> Result<ModemStatus, UartError> {
+    with_uart(port, |uart| {
+        uart.modem_status = unsafe { uart.read(REG_MODEM_STATUS) };
+        ModemStatus::from(uart.modem_status)
+    })
+}
+
+/// Sets the Data Terminal Ready (DTR) and Request To Send (RTS) lines.
+pub fn set_modem_control(port: ComPort, dtr: bool, rts: bool) -> Result<(), UartError> {
+    with_uart(port, |uart| {
+        let mut modem_control = MCR_OUT_2;
+        if dtr {
+            modem_control |= MCR_DTR;
+        }
+        if rts {
+            modem_control |= MCR_RTS;
+        }
+        unsafe { uart.write(REG_MODEM_CONTROL, modem_control) };
+    })
+}
+
+/// Sets the bytes which, instead of being buffered, trigger the handler when received (e.g. a magic key).
+///
+/// The handler is invoked from the interrupt handler of the port.
+pub fn set_attention(port: ComPort, keys: &'static [u8], handler: fn()) -> Result<(), UartError> {
+    with_uart(port, |uart| uart.attention = Some((keys, handler)))
+}
+
+/// Returns the number of received bytes which were dropped due to a full buffer.
+pub fn dropped(port: ComPort) -> Result<usize, UartError> {
+    with_uart(port, |uart| uart.dropped)
+}
+
+/// Writer for formatted output to a serial port.
+pub struct Writer(pub ComPort);
+
+impl Write for Writer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write(self.0, s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+pub(crate) fn init() -> Result<(), ()> {
+    let lines: [(u8, irq::IrqHandler); 2] = [(3, on_irq_3), (4, on_irq_4)];
+    for (irq, handler) in lines {
+        let ports = ComPort::ALL
+            .into_iter()
+            .filter(|port| port.irq() == irq && is_present(*port));
+        let mut serviced = false;
+        for port in ports {
+            with_uart(port, |uart| uart.irq_driven = true).map_err(|_| ())?;
+            serviced = true;
+        }
+
+        if serviced {
+            irq::register(irq, handler).map_err(|_| ())?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,385 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::fmt;
+use core::fmt::{Arguments, Write};
+use core::ptr;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use multiboot2::FramebufferType;
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::Port;
+use x86_64::PhysAddr;
+
+use crate::aux::log;
+use crate::kernel::sync::Mutex;
+use crate::kernel::video;
+
+use super::elf;
+use super::paging;
+
+/// The physical address of the text buffer of the color text modes.
+const BUFFER_ADDR: u64 = 0xB8000;
+
+pub const BUFFER_WIDTH: usize = 80;
+pub const BUFFER_HEIGHT: usize = 25;
+
+const TAB_WIDTH: usize = 8;
+
+/// The ports of the CRT Controller (CRTC), whose registers are selected through the address port.
+const CRTC_ADDRESS_PORT: u16 = 0x3D4;
+const CRTC_DATA_PORT: u16 = 0x3D5;
+
+const CRTC_CURSOR_START: u8 = 0x0A;
+const CRTC_CURSOR_END: u8 = 0x0B;
+const CRTC_CURSOR_LOCATION_HIGH: u8 = 0x0E;
+const CRTC_CURSOR_LOCATION_LOW: u8 = 0x0F;
+
+/// The bit of the cursor start register which turns the cursor off.
+const CURSOR_DISABLE: u8 = 1 << 5;
+
+/// The scanlines of a character cell between which the cursor is drawn, i.e., an underline.
+const CURSOR_SCANLINES: (u8, u8) = (14, 15);
+
+/// The bit of a color which selects its bright variant.
+const INTENSITY: u8 = 0x8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum VgaColor {
+    Black = 0,
+    Blue = 1,
+    Green = 2,
+    Cyan = 3,
+    Red = 4,
+    Magenta = 5,
+    Brown = 6,
+    LightGray = 7,
+    DarkGray = 8,
+    LightBlue = 9,
+    LightGreen = 10,
+    LightCyan = 11,
+    LightRed = 12,
+    Pink = 13,
+    Yellow = 14,
+    White = 15,
+}
+
+impl VgaColor {
+    /// The colors in the order of their ANSI codes, as they're arranged differently in the VGA palette.
+    const ANSI: [VgaColor; 8] = [
+        VgaColor::Black,
+        VgaColor::Red,
+        VgaColor::Green,
+        VgaColor::Brown,
+        VgaColor::Blue,
+        VgaColor::Magenta,
+        VgaColor::Cyan,
+        VgaColor::LightGray,
+    ];
+
+    /// Returns the 4-bit value of an ANSI color, whose bright variant is set through the intensity bit.
+    fn from_ansi(code: u16, bright: bool) -> u8 {
+        let color = Self::ANSI[code as usize % Self::ANSI.len()] as u8;
+        if bright {
+            color | INTENSITY
+        } else {
+            color
+        }
+    }
+}
+
+/// The attribute byte of a character cell, made of a foreground and a background color.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct ColorCode(u8);
+
+impl ColorCode {
+    /// NOTE: The bright background colors make the characters blink instead, which is the default of the hardware.
+    pub const fn new(foreground: VgaColor, background: VgaColor) -> Self {
+        Self((background as u8) << 4 | (foreground as u8))
+    }
+}
+
+impl Default for ColorCode {
+    fn default() -> Self {
+        Self::new(VgaColor::LightGray, VgaColor::Black)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+struct ScreenChar {
+    character: u8,
+    color: ColorCode,
+}
+
+/// The state of the parser of the ANSI escape sequences, of which only SGR is understood.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Escape {
+    None,
+    Start,
+    /// Within a control sequence, with the parameter which is being read.
+    Csi(u16),
+}
+
+/// VGA Text Writer
+///
+/// Writes to the text buffer of the 80x25 color text mode, which the firmware or the bootloader leaves the display in
+/// unless a graphics mode has been set. It merely depends on the buffer being mapped, which makes it suitable as the
+/// console of last resort.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/Printing_To_Screen
+pub struct VgaTextWriter {
+    buffer: usize,
+    column: usize,
+    row: usize,
+    color: ColorCode,
+    escape: Escape,
+    bold: bool,
+}
+
+impl VgaTextWriter {
+    /// Creates a writer over the text buffer, which is accessed through the higher half mapping.
+    pub fn new() -> Option<Self> {
+        let buffer = paging::phys_to_virt(PhysAddr::new(BUFFER_ADDR))?;
+
+        Some(Self {
+            buffer: buffer.as_u64() as usize,
+            column: 0,
+            row: 0,
+            color: ColorCode::default(),
+            escape: Escape::None,
+            bold: false,
+        })
+    }
+
+    pub fn set_color(&mut self, color: ColorCode) {
+        self.color = color;
+    }
+
+    fn cell(&self, column: usize, row: usize) -> *mut ScreenChar {
+        (self.buffer as *mut ScreenChar).wrapping_add(row * BUFFER_WIDTH + column)
+    }
+
+    fn write_cell(&self, column: usize, row: usize, character: u8) {
+        let cell = ScreenChar {
+            character,
+            color: self.color,
+        };
+        unsafe { ptr::write_volatile(self.cell(column, row), cell) };
+    }
+
+    /// Clears the screen with the current color and moves the cursor to the top-left corner.
+    pub fn clear(&mut self) {
+        for row in 0..BUFFER_HEIGHT {
+            self.clear_row(row);
+        }
+        self.column = 0;
+        self.row = 0;
+        self.update_cursor();
+    }
+
+    fn clear_row(&self, row: usize) {
+        for column in 0..BUFFER_WIDTH {
+            self.write_cell(column, row, b' ');
+        }
+    }
+
+    fn new_line(&mut self) {
+        self.column = 0;
+        if self.row + 1 < BUFFER_HEIGHT {
+            self.row += 1;
+            return;
+        }
+
+        for row in 1..BUFFER_HEIGHT {
+            for column in 0..BUFFER_WIDTH {
+                unsafe {
+                    let cell = ptr::read_volatile(self.cell(column, row));
+                    ptr::write_volatile(self.cell(column, row - 1), cell);
+                }
+            }
+        }
+        self.clear_row(BUFFER_HEIGHT - 1);
+    }
+
+    /// Writes a character of code page 437, interpreting the control characters.
+    pub fn write_byte(&mut self, byte: u8) {
+        match byte {
+            b'\n' => self.new_line(),
+            b'\r' => self.column = 0,
+            b'\t' => {
+                self.column = ((self.column / TAB_WIDTH + 1) * TAB_WIDTH).min(BUFFER_WIDTH - 1)
+            }
+            0x08 => self.column = self.column.saturating_sub(1),
+            _ => {
+                // The line is wrapped lazily, so that the last column can be written without scrolling.
+                if self.column >= BUFFER_WIDTH {
+                    self.new_line();
+                }
+                self.write_cell(self.column, self.row, byte);
+                self.column += 1;
+            }
+        }
+    }
+
+    /// Selects the graphic rendition of an SGR parameter, which is the only escape sequence emitted by the logger.
+    fn select_graphic_rendition(&mut self, param: u16) {
+        let ColorCode(color) = self.color;
+        let (foreground, background) = (color & 0xF, color >> 4);
+        let foreground = match param {
+            0 => {
+                self.bold = false;
+                self.color = ColorCode::default();
+                return;
+            }
+            1 => {
+                self.bold = true;
+                foreground | INTENSITY
+            }
+            30..=37 => VgaColor::from_ansi(param - 30, self.bold),
+            39 => VgaColor::LightGray as u8,
+            90..=97 => VgaColor::from_ansi(param - 90, true),
+            _ => foreground,
+        };
+        self.color = ColorCode(background << 4 | foreground);
+    }
+
+    fn write_char(&mut self, c: char) {
+        self.escape = match (self.escape, c) {
+            (Escape::None, '\x1b') => Escape::Start,
+            (Escape::None, '\n' | '\r' | '\t' | '\x08') => {
+                self.write_byte(c as u8);
+                Escape::None
+            }
+            (Escape::None, _) if c.is_control() => Escape::None,
+            (Escape::None, _) => {
+                self.write_byte(video::to_cp437(c).unwrap_or(video::CP437_REPLACEMENT));
+                Escape::None
+            }
+            (Escape::Start, '[') => Escape::Csi(0),
+            (Escape::Start, _) => Escape::None,
+            (Escape::Csi(param), '0'..='9') => Escape::Csi(
+                param
+                    .saturating_mul(10)
+                    .saturating_add(c as u16 - '0' as u16),
+            ),
+            (Escape::Csi(param), ';') => {
+                self.select_graphic_rendition(param);
+                Escape::Csi(0)
+            }
+            (Escape::Csi(param), 'm') => {
+                self.select_graphic_rendition(param);
+                Escape::None
+            }
+            // Any other control sequence is discarded.
+            (Escape::Csi(_), '\x40'..='\x7E') => Escape::None,
+            (Escape::Csi(param), _) => Escape::Csi(param),
+        };
+    }
+
+    /// Moves the hardware cursor to the position at which the next character will be written.
+    pub fn update_cursor(&self) {
+        let position = (self.row * BUFFER_WIDTH + self.column.min(BUFFER_WIDTH - 1)) as u16;
+        write_crtc(CRTC_CURSOR_LOCATION_HIGH, (position >> 8) as u8);
+        write_crtc(CRTC_CURSOR_LOCATION_LOW, position as u8);
+    }
+
+    pub fn enable_cursor(&self) {
+        let (start, end) = CURSOR_SCANLINES;
+        write_crtc(
+            CRTC_CURSOR_START,
+            (read_crtc(CRTC_CURSOR_START) & 0xC0) | start,
+        );
+        write_crtc(CRTC_CURSOR_END, (read_crtc(CRTC_CURSOR_END) & 0xE0) | end);
+    }
+
+    pub fn disable_cursor(&self) {
+        write_crtc(CRTC_CURSOR_START, CURSOR_DISABLE);
+    }
+}
+
+impl Write for VgaTextWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        s.chars().for_each(|c| self.write_char(c));
+        self.update_cursor();
+
+        Ok(())
+    }
+}
+
+fn read_crtc(register: u8) -> u8 {
+    unsafe {
+        Port::<u8>::new(CRTC_ADDRESS_PORT).write(register);
+        Port::<u8>::new(CRTC_DATA_PORT).read()
+    }
+}
+
+fn write_crtc(register: u8, value: u8) {
+    unsafe {
+        Port::<u8>::new(CRTC_ADDRESS_PORT).write(register);
+        Port::<u8>::new(CRTC_DATA_PORT).write(value);
+    }
+}
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+static WRITER: Mutex<Option<VgaTextWriter>> = Mutex::new(None);
+
+/// Checks whether the text mode console is in use, i.e., the display has been left in text mode.
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// Writes formatted text to the screen, if the display is in text mode.
+pub fn print(args: Arguments) {
+    interrupts::without_interrupts(|| {
+        if let Some(writer) = WRITER.lock().as_mut() {
+            let _ = writer.write_fmt(args);
+        }
+    });
+}
+
+/// Sets up the text mode console, unless the bootloader has reported a graphics mode.
+pub(crate) fn init() -> Result<(), ()> {
+    // Without a framebuffer tag, the display is assumed to be in the text mode set up by the firmware.
+    let text_mode = match elf::multiboot_info().framebuffer_tag() {
+        Some(Ok(tag)) => {
+            matches!(tag.buffer_type, FramebufferType::Text) && tag.address == BUFFER_ADDR
+        }
+        Some(Err(_)) => false,
+        None => true,
+    };
+    if !text_mode {
+        return Ok(());
+    }
+
+    let mut writer = VgaTextWriter::new().ok_or(())?;
+    writer.clear();
+    writer.enable_cursor();
+
+    *WRITER.lock() = Some(writer);
+    ACTIVE.store(true, Ordering::Relaxed);
+
+    log::register_sink(print)
+}
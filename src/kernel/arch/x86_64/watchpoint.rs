@@ -0,0 +1,180 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use x86_64::registers::debug::{
+    BreakpointCondition, BreakpointSize, DebugAddressRegister, DebugAddressRegisterNumber, Dr0,
+    Dr1, Dr2, Dr3, Dr6Flags, Dr7, Dr7Flags,
+};
+use x86_64::VirtAddr;
+
+use crate::kernel::sync::Mutex;
+
+/// The number of debug address registers (DR0-DR3).
+pub const MAX_WATCHPOINTS: usize = 4;
+
+static WATCHPOINTS: Mutex<[Option<Watchpoint>; MAX_WATCHPOINTS]> =
+    Mutex::new([None; MAX_WATCHPOINTS]);
+
+/// The kind of access that triggers a watchpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchpointKind {
+    Execute,
+    Write,
+    ReadWrite,
+}
+
+impl WatchpointKind {
+    fn condition(self) -> BreakpointCondition {
+        match self {
+            WatchpointKind::Execute => BreakpointCondition::InstructionExecution,
+            WatchpointKind::Write => BreakpointCondition::DataWrites,
+            WatchpointKind::ReadWrite => BreakpointCondition::DataReadsWrites,
+        }
+    }
+}
+
+/// The action taken once a watchpoint is hit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchpointAction {
+    /// Log the hit and continue the execution.
+    Continue,
+    /// Log the hit and hand control over to the debugger.
+    Break,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum WatchpointError {
+    /// All debug address registers are in use.
+    NoFreeSlot,
+    /// The length is not supported by the processor, or the access is not an instruction fetch of length 1.
+    InvalidLength,
+    /// The address is not aligned to the length of the watched region.
+    Misaligned,
+}
+
+/// Hardware Watchpoint
+///
+/// A watchpoint is backed by one of the debug address registers (DR0-DR3) and configured through the debug control
+/// register (DR7). The processor raises a debug exception (#DB) whenever the watched region is accessed, and reports
+/// the watchpoints that were hit in the debug status register (DR6).
+///
+/// OS Dev Wiki: https://wiki.osdev.org/CPU_Registers_x86#Debug_Registers
+#[derive(Clone, Copy, Debug)]
+pub struct Watchpoint {
+    pub addr: VirtAddr,
+    pub kind: WatchpointKind,
+    pub len: usize,
+    pub action: WatchpointAction,
+}
+
+fn register_number(slot: usize) -> DebugAddressRegisterNumber {
+    DebugAddressRegisterNumber::new(slot as u8).expect("invalid debug address register")
+}
+
+fn write_address(n: DebugAddressRegisterNumber, addr: u64) {
+    match n {
+        DebugAddressRegisterNumber::Dr0 => Dr0::write(addr),
+        DebugAddressRegisterNumber::Dr1 => Dr1::write(addr),
+        DebugAddressRegisterNumber::Dr2 => Dr2::write(addr),
+        DebugAddressRegisterNumber::Dr3 => Dr3::write(addr),
+    }
+}
+
+/// Sets up a watchpoint in a free debug address register and returns its slot.
+///
+/// Instruction breakpoints must have a length of 1, while data watchpoints may watch 1, 2, 4 or 8 bytes at an
+/// address aligned to the length.
+pub fn set(
+    addr: VirtAddr,
+    kind: WatchpointKind,
+    len: usize,
+    action: WatchpointAction,
+) -> Result<usize, WatchpointError> {
+    let size = match (kind, BreakpointSize::new(len)) {
+        (WatchpointKind::Execute, _) if len != 1 => return Err(WatchpointError::InvalidLength),
+        (_, Some(size)) => size,
+        (_, None) => return Err(WatchpointError::InvalidLength),
+    };
+    if addr.as_u64() % len as u64 != 0 {
+        return Err(WatchpointError::Misaligned);
+    }
+
+    let mut watchpoints = WATCHPOINTS.lock();
+    let slot = watchpoints
+        .iter()
+        .position(|watchpoint| watchpoint.is_none())
+        .ok_or(WatchpointError::NoFreeSlot)?;
+
+    let n = register_number(slot);
+    write_address(n, addr.as_u64());
+
+    let mut dr7 = Dr7::read();
+    dr7.set_condition(n, kind.condition());
+    dr7.set_size(n, size);
+    dr7.insert_flags(
+        Dr7Flags::global_breakpoint_enable(n) | Dr7Flags::GLOBAL_EXACT_BREAKPOINT_ENABLE,
+    );
+    Dr7::write(dr7);
+
+    watchpoints[slot] = Some(Watchpoint {
+        addr,
+        kind,
+        len,
+        action,
+    });
+
+    Ok(slot)
+}
+
+/// Disables the watchpoint in the given slot.
+pub fn clear(slot: usize) -> Option<Watchpoint> {
+    let watchpoint = WATCHPOINTS.lock().get_mut(slot)?.take()?;
+
+    let n = register_number(slot);
+    let mut dr7 = Dr7::read();
+    dr7.remove_flags(Dr7Flags::global_breakpoint_enable(n));
+    Dr7::write(dr7);
+    write_address(n, 0);
+
+    Some(watchpoint)
+}
+
+/// Returns the slot of the watchpoint matching the given parameters.
+pub fn find(addr: VirtAddr, kind: WatchpointKind) -> Option<usize> {
+    WATCHPOINTS
+        .lock()
+        .iter()
+        .position(|watchpoint| matches!(watchpoint, Some(w) if w.addr == addr && w.kind == kind))
+}
+
+/// Returns the watchpoints reported as hit in the given debug status.
+pub fn hits(status: Dr6Flags) -> impl Iterator<Item = (usize, Watchpoint)> {
+    let watchpoints = *WATCHPOINTS.lock();
+
+    (0..MAX_WATCHPOINTS).filter_map(move |slot| {
+        // The status bits may also be set for conditions of disabled debug address registers.
+        let watchpoint = watchpoints[slot]?;
+        status
+            .contains(Dr6Flags::trap(register_number(slot)))
+            .then_some((slot, watchpoint))
+    })
+}
@@ -0,0 +1,685 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::hint;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::task::Poll;
+
+use spin::Once;
+
+use crate::kernel::arch;
+use crate::kernel::arch::PhysAddr;
+use crate::kernel::block;
+use crate::kernel::block::{BlockDevice, BlockError, BlockInfo, Operation, Request, RequestId};
+use crate::kernel::dma;
+use crate::kernel::dma::DmaBuffer;
+use crate::kernel::pci;
+use crate::kernel::pci::{Bar, PciDevice, PciDriver, PciMatch};
+use crate::kernel::sync::Mutex;
+
+use super::{Identify, DEVICE_LBA, STATUS_BSY, STATUS_DRQ};
+use super::{
+    COMMAND_FLUSH_CACHE_EXT, COMMAND_IDENTIFY, COMMAND_READ_DMA_EXT, COMMAND_WRITE_DMA_EXT,
+};
+use super::{COMMAND_READ_FPDMA_QUEUED, COMMAND_WRITE_FPDMA_QUEUED};
+
+/// The maximum number of disks, which are named in turn.
+const MAX_DISKS: usize = 8;
+const DISK_NAMES: [&str; MAX_DISKS] = ["sda", "sdb", "sdc", "sdd", "sde", "sdf", "sdg", "sdh"];
+
+const MAX_PORTS: usize = 32;
+const MAX_COMMAND_SLOTS: usize = 32;
+/// The maximum number of entries of the PRDT of a command, i.e. of segments of a buffer.
+const MAX_PRDT_ENTRIES: usize = 16;
+/// The maximum number of bytes of an entry of the PRDT.
+const MAX_PRDT_BYTES: usize = 4 * 1024 * 1024;
+/// The maximum number of sectors of a command, whose count is 16 bits wide.
+const MAX_SECTORS_PER_COMMAND: u64 = 0xFFFF;
+
+// The registers of the HBA.
+const HBA_CAP: usize = 0x00;
+const HBA_GHC: usize = 0x04;
+const HBA_PI: usize = 0x0C;
+const HBA_VS: usize = 0x10;
+const HBA_CAP2: usize = 0x24;
+const HBA_BOHC: usize = 0x28;
+
+const CAP_S64A: u32 = 1 << 31;
+const CAP_SNCQ: u32 = 1 << 30;
+const CAP_NCS_SHIFT: u32 = 8;
+const CAP_NCS_MASK: u32 = 0x1F;
+const GHC_AE: u32 = 1 << 31;
+const GHC_IE: u32 = 1 << 1;
+const CAP2_BOH: u32 = 1 << 0;
+const BOHC_BOS: u32 = 1 << 0;
+const BOHC_OOS: u32 = 1 << 1;
+
+// The registers of a port, relative to its base.
+const PORTS_OFFSET: usize = 0x100;
+const PORT_SIZE: usize = 0x80;
+const PORT_CLB: usize = 0x00;
+const PORT_CLBU: usize = 0x04;
+const PORT_FB: usize = 0x08;
+const PORT_FBU: usize = 0x0C;
+const PORT_IS: usize = 0x10;
+const PORT_IE: usize = 0x14;
+const PORT_CMD: usize = 0x18;
+const PORT_TFD: usize = 0x20;
+const PORT_SIG: usize = 0x24;
+const PORT_SSTS: usize = 0x28;
+const PORT_SERR: usize = 0x30;
+const PORT_SACT: usize = 0x34;
+const PORT_CI: usize = 0x38;
+
+const CMD_ST: u32 = 1 << 0;
+const CMD_FRE: u32 = 1 << 4;
+const CMD_FR: u32 = 1 << 14;
+const CMD_CR: u32 = 1 << 15;
+/// The task file error, which stops the processing of the commands.
+const IS_TFES: u32 = 1 << 30;
+const SSTS_DET_PRESENT: u32 = 3;
+const SSTS_IPM_ACTIVE: u32 = 1;
+const SIGNATURE_ATA: u32 = 0x0000_0101;
+
+// The memory of a port: the command list, the received FISes and the command tables, which are aligned as required.
+const COMMAND_LIST_OFFSET: usize = 0;
+const COMMAND_HEADER_SIZE: usize = 32;
+const RECEIVED_FIS_OFFSET: usize = COMMAND_HEADER_SIZE * MAX_COMMAND_SLOTS;
+const RECEIVED_FIS_SIZE: usize = 256;
+const COMMAND_TABLES_OFFSET: usize = RECEIVED_FIS_OFFSET + RECEIVED_FIS_SIZE;
+const COMMAND_TABLE_PRDT: usize = 0x80;
+const PRDT_ENTRY_SIZE: usize = 16;
+const COMMAND_TABLE_SIZE: usize = COMMAND_TABLE_PRDT + MAX_PRDT_ENTRIES * PRDT_ENTRY_SIZE;
+const PORT_MEMORY_SIZE: usize = COMMAND_TABLES_OFFSET + MAX_COMMAND_SLOTS * COMMAND_TABLE_SIZE;
+
+/// The D2H register FIS within the received FISes, which holds the status of the last command.
+const RECEIVED_D2H_FIS: usize = 0x40;
+
+const FIS_TYPE_REG_H2D: u8 = 0x27;
+const FIS_H2D_COMMAND: u8 = 1 << 7;
+/// The length of the H2D register FIS in double words.
+const FIS_H2D_LENGTH: u32 = 5;
+const HEADER_WRITE: u32 = 1 << 6;
+const PRDT_INTERRUPT: u32 = 1 << 31;
+
+/// The number of times a register is polled before the port is given up on.
+const TIMEOUT: usize = 10_000_000;
+
+/// The size of the sectors of the disks, which are addressed by LBA48.
+const SECTOR_SIZE: usize = 512;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Slot {
+    Free,
+    /// The command has been issued, either queued through NCQ or on its own.
+    Pending {
+        queued: bool,
+    },
+    Done(Result<(), BlockError>),
+}
+
+/// A command to be issued to a port.
+#[derive(Clone, Copy, Debug)]
+struct Command {
+    command: u8,
+    lba: u64,
+    count: u16,
+    write: bool,
+    queued: bool,
+}
+
+/// A port of the HBA, which is linked to a SATA device.
+struct Port {
+    regs: usize,
+    memory: DmaBuffer,
+    slot_count: usize,
+    slots: [Slot; MAX_COMMAND_SLOTS],
+}
+
+impl Port {
+    fn read(&self, register: usize) -> u32 {
+        unsafe { ptr::read_volatile((self.regs + register) as *const u32) }
+    }
+
+    fn write(&self, register: usize, value: u32) {
+        unsafe { ptr::write_volatile((self.regs + register) as *mut u32, value) };
+    }
+
+    fn wait_clear(&self, register: usize, mask: u32) -> Result<(), BlockError> {
+        for _ in 0..TIMEOUT {
+            if self.read(register) & mask == 0 {
+                return Ok(());
+            }
+            hint::spin_loop();
+        }
+
+        Err(BlockError::Io)
+    }
+
+    /// Stops the processing of the command list and the receiving of FISes.
+    fn stop(&self) -> Result<(), BlockError> {
+        self.write(PORT_CMD, self.read(PORT_CMD) & !CMD_ST);
+        self.wait_clear(PORT_CMD, CMD_CR)?;
+        self.write(PORT_CMD, self.read(PORT_CMD) & !CMD_FRE);
+        self.wait_clear(PORT_CMD, CMD_FR)
+    }
+
+    fn start(&self) -> Result<(), BlockError> {
+        self.write(PORT_CMD, self.read(PORT_CMD) | CMD_FRE);
+        self.wait_clear(PORT_TFD, (STATUS_BSY | STATUS_DRQ) as u32)?;
+        self.write(PORT_CMD, self.read(PORT_CMD) | CMD_ST);
+
+        Ok(())
+    }
+
+    /// Hands the memory of the port to the HBA, with the interrupts disabled as the port is polled.
+    fn setup(&self) -> Result<(), BlockError> {
+        self.stop()?;
+
+        let base = self.memory.phys_addr();
+        let command_list = base + COMMAND_LIST_OFFSET as u64;
+        let received_fis = base + RECEIVED_FIS_OFFSET as u64;
+        self.write(PORT_CLB, command_list as u32);
+        self.write(PORT_CLBU, (command_list >> 32) as u32);
+        self.write(PORT_FB, received_fis as u32);
+        self.write(PORT_FBU, (received_fis >> 32) as u32);
+
+        for slot in 0..self.slot_count {
+            let table = base + (COMMAND_TABLES_OFFSET + slot * COMMAND_TABLE_SIZE) as u64;
+            let header = self.header(slot);
+            unsafe {
+                ptr::write_volatile(header.add(2), table as u32);
+                ptr::write_volatile(header.add(3), (table >> 32) as u32);
+            }
+        }
+
+        self.write(PORT_IE, 0);
+        self.write(PORT_SERR, u32::MAX);
+        self.write(PORT_IS, u32::MAX);
+
+        self.start()
+    }
+
+    fn header(&self, slot: usize) -> *mut u32 {
+        unsafe {
+            self.memory
+                .as_ptr::<u8>()
+                .add(COMMAND_LIST_OFFSET + slot * COMMAND_HEADER_SIZE) as *mut u32
+        }
+    }
+
+    fn table(&self, slot: usize) -> *mut u8 {
+        unsafe {
+            self.memory
+                .as_ptr::<u8>()
+                .add(COMMAND_TABLES_OFFSET + slot * COMMAND_TABLE_SIZE)
+        }
+    }
+
+    /// Issues a command with a buffer given as its physical segments, and returns the slot which tracks it.
+    ///
+    /// The queued commands can't be mixed with the others, so a command waits until those of the other kind have
+    /// completed.
+    fn issue(
+        &mut self,
+        command: Command,
+        segments: &[(u64, usize)],
+        depth: usize,
+    ) -> Result<usize, BlockError> {
+        self.reap();
+
+        let pending = |slot: &Slot| matches!(slot, Slot::Pending { .. });
+        let conflicts = self.slots.iter().any(|slot| match *slot {
+            Slot::Pending { queued } => !command.queued || !queued,
+            _ => false,
+        });
+        if conflicts || self.slots.iter().filter(|slot| pending(slot)).count() >= depth {
+            return Err(BlockError::Busy);
+        }
+        let slot = self.slots[..self.slot_count]
+            .iter()
+            .position(|slot| *slot == Slot::Free)
+            .ok_or(BlockError::Busy)?;
+
+        let table = self.table(slot);
+        unsafe { ptr::write_bytes(table, 0, COMMAND_TABLE_SIZE) };
+
+        // Host to Device Register FIS
+        let mut fis = [0u8; 20];
+        fis[0] = FIS_TYPE_REG_H2D;
+        fis[1] = FIS_H2D_COMMAND;
+        fis[2] = command.command;
+        fis[4..7].copy_from_slice(&command.lba.to_le_bytes()[..3]);
+        fis[7] = DEVICE_LBA;
+        fis[8..11].copy_from_slice(&command.lba.to_le_bytes()[3..6]);
+        let count = command.count.to_le_bytes();
+        match command.queued {
+            // The count moves to the features, while the count holds the tag.
+            true => {
+                fis[3] = count[0];
+                fis[11] = count[1];
+                fis[12] = (slot as u8) << 3;
+            }
+            false => fis[12..14].copy_from_slice(&count),
+        }
+        unsafe { ptr::copy_nonoverlapping(fis.as_ptr(), table, fis.len()) };
+
+        for (i, (addr, len)) in segments.iter().enumerate() {
+            let entry = unsafe { table.add(COMMAND_TABLE_PRDT + i * PRDT_ENTRY_SIZE) as *mut u32 };
+            let last = if i + 1 == segments.len() {
+                PRDT_INTERRUPT
+            } else {
+                0
+            };
+            unsafe {
+                ptr::write_volatile(entry, *addr as u32);
+                ptr::write_volatile(entry.add(1), (*addr >> 32) as u32);
+                ptr::write_volatile(entry.add(3), (*len as u32 - 1) | last);
+            }
+        }
+
+        let mut flags = FIS_H2D_LENGTH | (segments.len() as u32) << 16;
+        if command.write {
+            flags |= HEADER_WRITE;
+        }
+        let header = self.header(slot);
+        unsafe {
+            ptr::write_volatile(header, flags);
+            ptr::write_volatile(header.add(1), 0);
+        }
+
+        if command.queued {
+            self.write(PORT_SACT, 1 << slot);
+        }
+        self.write(PORT_CI, 1 << slot);
+        self.slots[slot] = Slot::Pending {
+            queued: command.queued,
+        };
+
+        Ok(slot)
+    }
+
+    /// Completes the commands which the device has finished, or fails them all after an error.
+    fn reap(&mut self) {
+        let interrupts = self.read(PORT_IS);
+        self.write(PORT_IS, interrupts);
+
+        if interrupts & IS_TFES != 0 {
+            let fis = unsafe {
+                self.memory
+                    .as_ptr::<u8>()
+                    .add(RECEIVED_FIS_OFFSET + RECEIVED_D2H_FIS)
+            };
+            let (status, error) = unsafe {
+                (
+                    ptr::read_volatile(fis.add(2)),
+                    ptr::read_volatile(fis.add(3)),
+                )
+            };
+            log::warn!(
+                "AHCI: task file error (status {:#X}, error {:#X})",
+                status,
+                error
+            );
+
+            for slot in self.slots.iter_mut() {
+                if let Slot::Pending { .. } = slot {
+                    *slot = Slot::Done(Err(BlockError::Io));
+                }
+            }
+            // The port must be restarted to process commands again.
+            self.write(PORT_SERR, u32::MAX);
+            if self.stop().and_then(|()| self.start()).is_err() {
+                log::warn!("AHCI: failed to restart the port");
+            }
+            return;
+        }
+
+        let issued = self.read(PORT_CI);
+        let active = self.read(PORT_SACT);
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            let busy = match *slot {
+                Slot::Pending { queued: true } => active & (1 << index) != 0,
+                Slot::Pending { queued: false } => issued & (1 << index) != 0,
+                _ => continue,
+            };
+            if !busy {
+                *slot = Slot::Done(Ok(()));
+            }
+        }
+    }
+
+    fn poll(&mut self, slot: usize) -> Poll<Result<(), BlockError>> {
+        self.reap();
+        match self.slots.get(slot) {
+            Some(Slot::Done(result)) => {
+                let result = *result;
+                self.slots[slot] = Slot::Free;
+                Poll::Ready(result)
+            }
+            Some(Slot::Pending { .. }) => Poll::Pending,
+            _ => Poll::Ready(Err(BlockError::UnknownRequest)),
+        }
+    }
+
+    /// Issues a command and waits for it, which is only used while the port is being probed.
+    fn execute(&mut self, command: Command, segments: &[(u64, usize)]) -> Result<(), BlockError> {
+        let slot = self.issue(command, segments, 1)?;
+        for _ in 0..TIMEOUT {
+            if let Poll::Ready(result) = self.poll(slot) {
+                return result;
+            }
+            hint::spin_loop();
+        }
+
+        // Stopping the port clears its issued commands, which aborts the command so that its slot can be reused. The
+        // command is left pending if the port doesn't stop, as the HBA may still be working on it.
+        log::warn!("AHCI: command {:#X} timed out", command.command);
+        if self.stop().is_ok() {
+            self.slots[slot] = Slot::Free;
+            if self.start().is_err() {
+                log::warn!("AHCI: failed to restart the port");
+            }
+        }
+
+        Err(BlockError::Io)
+    }
+}
+
+/// Advanced Host Controller Interface (AHCI)
+///
+/// A SATA disk attached to a port of an AHCI controller, which transfers the data itself from a list of commands in
+/// memory. The reads and writes are queued through Native Command Queuing (NCQ) if both the controller and the disk
+/// support it, so that the disk can reorder them.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/AHCI
+pub struct AhciDisk {
+    name: &'static str,
+    port: Mutex<Port>,
+    identify: Identify,
+    info: BlockInfo,
+    /// The number of commands which can be in flight through NCQ, or 1 without it.
+    queue_depth: usize,
+}
+
+impl AhciDisk {
+    pub fn identify(&self) -> &Identify {
+        &self.identify
+    }
+
+    /// Splits the buffer of a request into the entries of a PRDT.
+    fn segments(
+        request: &Request,
+        segments: &mut [(u64, usize); MAX_PRDT_ENTRIES],
+    ) -> Result<usize, BlockError> {
+        let mut count = 0;
+        for segment in dma::segments(request.buf, request.len) {
+            let (mut addr, mut len) = segment.ok_or(BlockError::InvalidBuffer)?;
+            // The buffers must be aligned to words.
+            if addr % 2 != 0 {
+                return Err(BlockError::InvalidBuffer);
+            }
+            while len > 0 {
+                let part = len.min(MAX_PRDT_BYTES);
+                *segments.get_mut(count).ok_or(BlockError::InvalidBuffer)? = (addr, part);
+                count += 1;
+                addr += part as u64;
+                len -= part;
+            }
+        }
+
+        Ok(count)
+    }
+}
+
+impl BlockDevice for AhciDisk {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn info(&self) -> BlockInfo {
+        self.info
+    }
+
+    unsafe fn submit(&self, request: Request) -> Result<RequestId, BlockError> {
+        request.check(&self.info)?;
+
+        let mut segments = [(0, 0); MAX_PRDT_ENTRIES];
+        let (command, count) = match request.op {
+            Operation::Flush => (
+                Command {
+                    command: COMMAND_FLUSH_CACHE_EXT,
+                    lba: 0,
+                    count: 0,
+                    write: false,
+                    queued: false,
+                },
+                0,
+            ),
+            op => {
+                let sectors = request.block_count(&self.info);
+                if sectors > MAX_SECTORS_PER_COMMAND {
+                    return Err(BlockError::InvalidBuffer);
+                }
+
+                let write = op == Operation::Write;
+                let queued = self.queue_depth > 1;
+                let command = match (queued, write) {
+                    (true, true) => COMMAND_WRITE_FPDMA_QUEUED,
+                    (true, false) => COMMAND_READ_FPDMA_QUEUED,
+                    (false, true) => COMMAND_WRITE_DMA_EXT,
+                    (false, false) => COMMAND_READ_DMA_EXT,
+                };
+                let command = Command {
+                    command,
+                    lba: request.block,
+                    count: sectors as u16,
+                    write,
+                    queued,
+                };
+                (command, Self::segments(&request, &mut segments)?)
+            }
+        };
+
+        let slot = arch::without_interrupts(|| {
+            self.port
+                .lock()
+                .issue(command, &segments[..count], self.queue_depth)
+        })?;
+
+        Ok(slot as RequestId)
+    }
+
+    fn poll(&self, id: RequestId) -> Poll<Result<(), BlockError>> {
+        arch::without_interrupts(|| self.port.lock().poll(id as usize))
+    }
+}
+
+static DISKS: [Once<AhciDisk>; MAX_DISKS] = [const { Once::new() }; MAX_DISKS];
+static DISK_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+static DRIVER: PciDriver = PciDriver {
+    name: "ahci",
+    matches: &[PciMatch::class(0x01, 0x06).with_prog_if(0x01)],
+    probe,
+};
+
+/// Takes the ownership of the HBA from the firmware, if the firmware supports the handoff.
+fn take_ownership(hba: usize) {
+    let read = |register: usize| unsafe { ptr::read_volatile((hba + register) as *const u32) };
+    let write = |register: usize, value| unsafe {
+        ptr::write_volatile((hba + register) as *mut u32, value)
+    };
+
+    if read(HBA_CAP2) & CAP2_BOH == 0 {
+        return;
+    }
+    write(HBA_BOHC, read(HBA_BOHC) | BOHC_OOS);
+    for _ in 0..TIMEOUT {
+        if read(HBA_BOHC) & BOHC_BOS == 0 {
+            return;
+        }
+        hint::spin_loop();
+    }
+    log::warn!("AHCI: firmware didn't release the controller");
+}
+
+/// Sets up a port with a device attached, and identifies the device.
+fn probe_port(
+    regs: usize,
+    slot_count: usize,
+    ncq: bool,
+) -> Result<Option<(Port, Identify, usize)>, BlockError> {
+    let status = unsafe { ptr::read_volatile((regs + PORT_SSTS) as *const u32) };
+    let signature = unsafe { ptr::read_volatile((regs + PORT_SIG) as *const u32) };
+    if status & 0xF != SSTS_DET_PRESENT
+        || (status >> 8) & 0xF != SSTS_IPM_ACTIVE
+        || signature != SIGNATURE_ATA
+    {
+        return Ok(None);
+    }
+
+    let memory = DmaBuffer::allocate(PORT_MEMORY_SIZE + SECTOR_SIZE).ok_or(BlockError::NoMemory)?;
+    let mut port = Port {
+        regs,
+        memory,
+        slot_count,
+        slots: [Slot::Free; MAX_COMMAND_SLOTS],
+    };
+    port.setup()?;
+
+    // The data of IDENTIFY DEVICE is received right after the memory of the port.
+    let buf_addr = port.memory.phys_addr() + PORT_MEMORY_SIZE as u64;
+    let identify = Command {
+        command: COMMAND_IDENTIFY,
+        lba: 0,
+        count: 0,
+        write: false,
+        queued: false,
+    };
+    port.execute(identify, &[(buf_addr, SECTOR_SIZE)])?;
+
+    let mut words = [0; 256];
+    let data = unsafe { port.memory.as_ptr::<u8>().add(PORT_MEMORY_SIZE) as *const u16 };
+    for (i, word) in words.iter_mut().enumerate() {
+        *word = unsafe { ptr::read_volatile(data.add(i)) };
+    }
+    let identify = Identify::parse(&words);
+
+    let depth = match (ncq, identify.queue_depth) {
+        (true, Some(depth)) => (depth as usize).min(slot_count),
+        _ => 1,
+    };
+
+    Ok(Some((port, identify, depth)))
+}
+
+fn probe(pci: &PciDevice) -> Result<(), ()> {
+    let Some(Bar::Memory { addr, size, .. }) = pci.bars[5] else {
+        return Err(());
+    };
+    let hba = arch::paging::map_mmio(PhysAddr::new(addr), size as usize).ok_or(())?;
+    let hba = hba.as_u64() as usize;
+    pci.enable(true);
+
+    let read = |register: usize| unsafe { ptr::read_volatile((hba + register) as *const u32) };
+    let write = |register: usize, value| unsafe {
+        ptr::write_volatile((hba + register) as *mut u32, value)
+    };
+
+    take_ownership(hba);
+    write(HBA_GHC, (read(HBA_GHC) | GHC_AE) & !GHC_IE);
+
+    let capabilities = read(HBA_CAP);
+    let slot_count = ((capabilities >> CAP_NCS_SHIFT) & CAP_NCS_MASK) as usize + 1;
+    let ncq = capabilities & CAP_SNCQ != 0;
+    let version = read(HBA_VS);
+    log::info!(
+        "AHCI {}.{}: {} command slots{}{}",
+        version >> 16,
+        (version >> 8) & 0xFF,
+        slot_count,
+        if ncq { ", NCQ" } else { "" },
+        if capabilities & CAP_S64A != 0 {
+            ", 64-bit"
+        } else {
+            ""
+        }
+    );
+
+    let implemented = read(HBA_PI);
+    for index in (0..MAX_PORTS).filter(|index| implemented & (1 << index) != 0) {
+        let regs = hba + PORTS_OFFSET + index * PORT_SIZE;
+        let (port, identify, queue_depth) = match probe_port(regs, slot_count, ncq) {
+            Ok(Some(found)) => found,
+            Ok(None) => continue,
+            Err(error) => {
+                log::warn!("AHCI: failed to set up port {}: {:?}", index, error);
+                continue;
+            }
+        };
+        if identify.sector_size != SECTOR_SIZE {
+            log::warn!(
+                "AHCI: {} has unsupported sectors of {} bytes",
+                identify.model(),
+                identify.sector_size
+            );
+            continue;
+        }
+
+        let number = DISK_COUNT.fetch_add(1, Ordering::Relaxed);
+        let Some(slot) = DISKS.get(number) else {
+            log::warn!("AHCI: too many disks");
+            break;
+        };
+        log::info!(
+            "{}: {} ({}) on port {}, queue depth {}",
+            DISK_NAMES[number],
+            identify.model(),
+            identify.serial(),
+            index,
+            queue_depth
+        );
+
+        let disk = slot.call_once(|| AhciDisk {
+            name: DISK_NAMES[number],
+            port: Mutex::new(port),
+            identify,
+            info: BlockInfo {
+                block_size: SECTOR_SIZE,
+                block_count: identify.sector_count,
+                read_only: false,
+            },
+            queue_depth,
+        });
+        if block::register(disk).is_err() {
+            log::warn!("AHCI: failed to register {}", disk.name);
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn init() -> Result<(), ()> {
+    pci::register_driver(&DRIVER).map_err(|_| ())
+}
@@ -0,0 +1,446 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::slice;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::task::Poll;
+
+use spin::Once;
+
+use crate::kernel::block;
+use crate::kernel::block::{BlockDevice, BlockError, BlockInfo, Operation, Request, RequestId};
+use crate::kernel::pci;
+use crate::kernel::pci::{Bar, PciDevice, PciDriver, PciMatch};
+use crate::kernel::port;
+use crate::kernel::sync::Mutex;
+
+use super::{Identify, DEVICE_LBA, SECTOR_SIZE};
+use super::{COMMAND_FLUSH_CACHE, COMMAND_FLUSH_CACHE_EXT, COMMAND_IDENTIFY};
+use super::{
+    COMMAND_READ_SECTORS, COMMAND_READ_SECTORS_EXT, COMMAND_WRITE_SECTORS,
+    COMMAND_WRITE_SECTORS_EXT,
+};
+use super::{STATUS_BSY, STATUS_DF, STATUS_DRQ, STATUS_ERR};
+
+/// The maximum number of drives, i.e. the master and the slave of both channels of a controller.
+const MAX_DRIVES: usize = 4;
+const DRIVE_NAMES: [&str; MAX_DRIVES] = ["hda", "hdb", "hdc", "hdd"];
+
+/// The number of completed requests kept until they're polled.
+const MAX_COMPLETIONS: usize = 8;
+
+// The ports of the channels in compatibility mode.
+const PRIMARY_PORTS: (u16, u16) = (0x1F0, 0x3F6);
+const SECONDARY_PORTS: (u16, u16) = (0x170, 0x376);
+
+// The bits of the programming interface which tell that a channel is in native mode.
+const PROG_IF_PRIMARY_NATIVE: u8 = 1 << 0;
+const PROG_IF_SECONDARY_NATIVE: u8 = 1 << 2;
+
+/// The control register lies at an offset into the BAR of a channel in native mode.
+const NATIVE_CONTROL_OFFSET: u16 = 2;
+
+// The registers of a channel, relative to its command block.
+const REG_DATA: u16 = 0;
+const REG_SECTOR_COUNT: u16 = 2;
+const REG_LBA_LOW: u16 = 3;
+const REG_LBA_MID: u16 = 4;
+const REG_LBA_HIGH: u16 = 5;
+const REG_DEVICE: u16 = 6;
+const REG_STATUS: u16 = 7;
+const REG_COMMAND: u16 = 7;
+
+/// Disables the interrupts of the channel, as the drives are polled (nIEN).
+const CONTROL_INTERRUPT_DISABLE: u8 = 1 << 1;
+/// The bits of the device register which are always set.
+const DEVICE_ALWAYS_SET: u8 = 0xA0;
+/// The value of the status register on a channel without drives.
+const FLOATING_BUS: u8 = 0xFF;
+
+/// The maximum number of sectors per command with LBA28, where a count of 0 means 256.
+const LBA28_MAX_SECTORS: u64 = 256;
+const LBA28_MAX_SECTOR: u64 = 1 << 28;
+
+/// The number of times the status is polled before a command is given up on.
+const TIMEOUT: usize = 10_000_000;
+
+// The signatures of the devices which aren't ATA devices, as left in the LBA registers.
+const SIGNATURE_ATAPI: (u8, u8) = (0x14, 0xEB);
+const SIGNATURE_SATA: (u8, u8) = (0x3C, 0xC3);
+
+/// A channel of an IDE controller, which is shared by a master and a slave drive.
+#[derive(Debug)]
+struct Channel {
+    base: u16,
+    control: u16,
+}
+
+impl Channel {
+    fn read(&self, register: u16) -> u8 {
+        unsafe { port::read_u8(self.base + register) }
+    }
+
+    fn write(&self, register: u16, value: u8) {
+        unsafe { port::write_u8(self.base + register, value) };
+    }
+
+    /// Waits about 400 ns, which the drives need to update their status, by reading the alternate status.
+    fn delay(&self) {
+        for _ in 0..4 {
+            unsafe { port::read_u8(self.control) };
+        }
+    }
+
+    fn select(&self, drive: u8, lba_high: u8) {
+        self.write(
+            REG_DEVICE,
+            DEVICE_ALWAYS_SET | DEVICE_LBA | drive << 4 | lba_high,
+        );
+        self.delay();
+    }
+
+    /// Waits until the drive isn't busy, and returns its status.
+    fn wait_ready(&self) -> Result<u8, BlockError> {
+        for _ in 0..TIMEOUT {
+            let status = self.read(REG_STATUS);
+            if status & STATUS_BSY == 0 {
+                return Ok(status);
+            }
+        }
+
+        Err(BlockError::Io)
+    }
+
+    /// Waits until the drive is ready to transfer a sector.
+    fn wait_data(&self) -> Result<(), BlockError> {
+        let status = self.wait_ready()?;
+        match status {
+            _ if status & (STATUS_ERR | STATUS_DF) != 0 => Err(BlockError::Io),
+            _ if status & STATUS_DRQ != 0 => Ok(()),
+            _ => Err(BlockError::Io),
+        }
+    }
+
+    fn identify(&self, drive: u8) -> Option<Identify> {
+        self.select(drive, 0);
+        for register in [REG_SECTOR_COUNT, REG_LBA_LOW, REG_LBA_MID, REG_LBA_HIGH] {
+            self.write(register, 0);
+        }
+        self.write(REG_COMMAND, COMMAND_IDENTIFY);
+        self.delay();
+
+        // A missing drive leaves the status at zero.
+        if self.read(REG_STATUS) == 0 {
+            return None;
+        }
+        self.wait_ready().ok()?;
+
+        // The packet devices abort the command, and leave their signature instead.
+        let signature = (self.read(REG_LBA_MID), self.read(REG_LBA_HIGH));
+        if signature == SIGNATURE_ATAPI || signature == SIGNATURE_SATA {
+            return None;
+        }
+        self.wait_data().ok()?;
+
+        let mut words = [0; 256];
+        for word in words.iter_mut() {
+            *word = unsafe { port::read_u16(self.base + REG_DATA) };
+        }
+
+        Some(Identify::parse(&words))
+    }
+
+    /// Sets up the registers for a command with LBA48, which takes the upper bytes first.
+    fn setup_lba48(&self, drive: u8, lba: u64, count: u16) {
+        self.select(drive, 0);
+        self.write(REG_SECTOR_COUNT, (count >> 8) as u8);
+        self.write(REG_LBA_LOW, (lba >> 24) as u8);
+        self.write(REG_LBA_MID, (lba >> 32) as u8);
+        self.write(REG_LBA_HIGH, (lba >> 40) as u8);
+        self.write(REG_SECTOR_COUNT, count as u8);
+        self.write(REG_LBA_LOW, lba as u8);
+        self.write(REG_LBA_MID, (lba >> 8) as u8);
+        self.write(REG_LBA_HIGH, (lba >> 16) as u8);
+    }
+
+    fn setup_lba28(&self, drive: u8, lba: u64, count: u16) {
+        self.select(drive, (lba >> 24) as u8 & 0x0F);
+        self.write(REG_SECTOR_COUNT, count as u8);
+        self.write(REG_LBA_LOW, lba as u8);
+        self.write(REG_LBA_MID, (lba >> 8) as u8);
+        self.write(REG_LBA_HIGH, (lba >> 16) as u8);
+    }
+
+    /// Transfers sectors between a drive and a buffer through PIO, one command of at most 256 sectors at a time.
+    fn transfer(
+        &self,
+        drive: u8,
+        lba48: bool,
+        op: Operation,
+        lba: u64,
+        buf: &mut [u8],
+    ) -> Result<(), BlockError> {
+        let mut lba = lba;
+        for chunk in buf.chunks_mut(LBA28_MAX_SECTORS as usize * SECTOR_SIZE) {
+            let count = (chunk.len() / SECTOR_SIZE) as u16;
+            let command = match (lba48, op) {
+                (true, Operation::Write) => COMMAND_WRITE_SECTORS_EXT,
+                (true, _) => COMMAND_READ_SECTORS_EXT,
+                (false, Operation::Write) => COMMAND_WRITE_SECTORS,
+                (false, _) => COMMAND_READ_SECTORS,
+            };
+            match lba48 {
+                true => self.setup_lba48(drive, lba, count),
+                false => self.setup_lba28(drive, lba, count),
+            }
+            self.write(REG_COMMAND, command);
+
+            for sector in chunk.chunks_mut(SECTOR_SIZE) {
+                self.delay();
+                self.wait_data()?;
+                for pair in sector.chunks_mut(2) {
+                    match op {
+                        Operation::Write => {
+                            let word = u16::from_le_bytes([pair[0], pair[1]]);
+                            unsafe { port::write_u16(self.base + REG_DATA, word) };
+                        }
+                        _ => {
+                            let word = unsafe { port::read_u16(self.base + REG_DATA) };
+                            pair.copy_from_slice(&word.to_le_bytes());
+                        }
+                    }
+                }
+            }
+
+            // The last sector of a write has been taken once the drive is no longer busy.
+            let status = self.wait_ready()?;
+            if status & (STATUS_ERR | STATUS_DF) != 0 {
+                return Err(BlockError::Io);
+            }
+            lba += count as u64;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&self, drive: u8, lba48: bool) -> Result<(), BlockError> {
+        self.select(drive, 0);
+        self.write(
+            REG_COMMAND,
+            match lba48 {
+                true => COMMAND_FLUSH_CACHE_EXT,
+                false => COMMAND_FLUSH_CACHE,
+            },
+        );
+        self.delay();
+
+        match self.wait_ready()? & (STATUS_ERR | STATUS_DF) {
+            0 => Ok(()),
+            _ => Err(BlockError::Io),
+        }
+    }
+}
+
+/// ATA PIO Mode
+///
+/// A drive attached to an IDE controller, whose sectors are transferred by the processor through the data register
+/// of its channel. The requests are carried out as soon as they're submitted, so they've always completed when
+/// they're polled.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/ATA_PIO_Mode
+pub struct IdeDrive {
+    name: &'static str,
+    channel: &'static Mutex<Channel>,
+    drive: u8,
+    identify: Identify,
+    info: BlockInfo,
+    completions: Mutex<[Option<Result<(), BlockError>>; MAX_COMPLETIONS]>,
+}
+
+impl IdeDrive {
+    pub fn identify(&self) -> &Identify {
+        &self.identify
+    }
+
+    fn execute(&self, request: &Request) -> Result<(), BlockError> {
+        let channel = self.channel.lock();
+        match request.op {
+            Operation::Flush => channel.flush(self.drive, self.identify.lba48),
+            op => {
+                let buf = unsafe { slice::from_raw_parts_mut(request.buf, request.len) };
+                let lba48 = self.identify.lba48
+                    || request.block + request.block_count(&self.info) > LBA28_MAX_SECTOR;
+                channel.transfer(self.drive, lba48, op, request.block, buf)
+            }
+        }
+    }
+}
+
+impl BlockDevice for IdeDrive {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn info(&self) -> BlockInfo {
+        self.info
+    }
+
+    unsafe fn submit(&self, request: Request) -> Result<RequestId, BlockError> {
+        request.check(&self.info)?;
+
+        let mut completions = self.completions.lock();
+        let slot = completions
+            .iter()
+            .position(Option::is_none)
+            .ok_or(BlockError::Busy)?;
+        completions[slot] = Some(self.execute(&request));
+
+        Ok(slot as RequestId)
+    }
+
+    fn poll(&self, id: RequestId) -> Poll<Result<(), BlockError>> {
+        let result = self
+            .completions
+            .lock()
+            .get_mut(id as usize)
+            .and_then(Option::take);
+
+        Poll::Ready(result.unwrap_or(Err(BlockError::UnknownRequest)))
+    }
+}
+
+static CHANNELS: [Once<Mutex<Channel>>; 2] = [const { Once::new() }; 2];
+static DRIVES: [Once<IdeDrive>; MAX_DRIVES] = [const { Once::new() }; MAX_DRIVES];
+static DRIVE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+static DRIVER: PciDriver = PciDriver {
+    name: "ata-pio",
+    matches: &[PciMatch::class(0x01, 0x01)],
+    probe,
+};
+
+/// Returns the ports of a channel, which are either fixed or given by the BARs in native mode.
+fn channel_ports(
+    pci: &PciDevice,
+    native: bool,
+    bar: usize,
+    compat: (u16, u16),
+) -> Option<(u16, u16)> {
+    if !native {
+        return Some(compat);
+    }
+
+    match (pci.bars[bar], pci.bars[bar + 1]) {
+        (Some(Bar::Io { port: base, .. }), Some(Bar::Io { port: control, .. })) => Some((
+            u16::try_from(base).ok()?,
+            u16::try_from(control).ok()? + NATIVE_CONTROL_OFFSET,
+        )),
+        _ => None,
+    }
+}
+
+fn probe(pci: &PciDevice) -> Result<(), ()> {
+    // The channels in compatibility mode use fixed ports, so only one controller can be driven.
+    if CHANNELS.iter().any(Once::is_completed) {
+        return Err(());
+    }
+    pci.enable(false);
+
+    let channels = [
+        channel_ports(
+            pci,
+            pci.prog_if & PROG_IF_PRIMARY_NATIVE != 0,
+            0,
+            PRIMARY_PORTS,
+        ),
+        channel_ports(
+            pci,
+            pci.prog_if & PROG_IF_SECONDARY_NATIVE != 0,
+            2,
+            SECONDARY_PORTS,
+        ),
+    ];
+
+    for (index, ports) in channels.into_iter().enumerate() {
+        let Some((base, control)) = ports else {
+            continue;
+        };
+        let channel = CHANNELS[index].call_once(|| Mutex::new(Channel { base, control }));
+
+        let guard = channel.lock();
+        if guard.read(REG_STATUS) == FLOATING_BUS {
+            continue;
+        }
+        unsafe { port::write_u8(control, CONTROL_INTERRUPT_DISABLE) };
+
+        for drive in 0..2 {
+            let Some(identify) = guard.identify(drive) else {
+                continue;
+            };
+            if identify.sector_size != SECTOR_SIZE {
+                log::warn!(
+                    "ATA: {} has unsupported sectors of {} bytes",
+                    identify.model(),
+                    identify.sector_size
+                );
+                continue;
+            }
+
+            let number = DRIVE_COUNT.fetch_add(1, Ordering::Relaxed);
+            let Some(slot) = DRIVES.get(number) else {
+                break;
+            };
+            log::info!(
+                "{}: {} ({}), {}",
+                DRIVE_NAMES[number],
+                identify.model(),
+                identify.serial(),
+                if identify.lba48 { "LBA48" } else { "LBA28" }
+            );
+            slot.call_once(|| IdeDrive {
+                name: DRIVE_NAMES[number],
+                channel,
+                drive,
+                identify,
+                info: BlockInfo {
+                    block_size: SECTOR_SIZE,
+                    block_count: identify.sector_count,
+                    read_only: false,
+                },
+                completions: Mutex::new([None; MAX_COMPLETIONS]),
+            });
+        }
+    }
+
+    // The drives are registered once the channels are unlocked, as the partition tables are read from them.
+    for drive in DRIVES.iter().filter_map(Once::get) {
+        if block::register(drive).is_err() {
+            log::warn!("ATA: failed to register {}", drive.name);
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn init() -> Result<(), ()> {
+    pci::register_driver(&DRIVER).map_err(|_| ())
+}
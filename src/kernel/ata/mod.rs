@@ -0,0 +1,143 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::str;
+
+pub mod ahci;
+pub mod ide;
+
+// The commands of the ATA command set.
+const COMMAND_READ_SECTORS: u8 = 0x20;
+const COMMAND_READ_SECTORS_EXT: u8 = 0x24;
+const COMMAND_READ_DMA_EXT: u8 = 0x25;
+const COMMAND_WRITE_SECTORS: u8 = 0x30;
+const COMMAND_WRITE_SECTORS_EXT: u8 = 0x34;
+const COMMAND_WRITE_DMA_EXT: u8 = 0x35;
+const COMMAND_READ_FPDMA_QUEUED: u8 = 0x60;
+const COMMAND_WRITE_FPDMA_QUEUED: u8 = 0x61;
+const COMMAND_FLUSH_CACHE: u8 = 0xE7;
+const COMMAND_FLUSH_CACHE_EXT: u8 = 0xEA;
+const COMMAND_IDENTIFY: u8 = 0xEC;
+
+// The bits of the status register.
+const STATUS_ERR: u8 = 1 << 0;
+const STATUS_DRQ: u8 = 1 << 3;
+const STATUS_DF: u8 = 1 << 5;
+const STATUS_BSY: u8 = 1 << 7;
+
+/// The bit of the device register which selects the addressing by LBA.
+const DEVICE_LBA: u8 = 1 << 6;
+
+/// The size of the sectors, unless the device reports a larger logical sector.
+const SECTOR_SIZE: usize = 512;
+
+// The words of the data returned by IDENTIFY DEVICE.
+const IDENTIFY_SERIAL: usize = 10;
+const IDENTIFY_MODEL: usize = 27;
+const IDENTIFY_LBA28_SECTORS: usize = 60;
+const IDENTIFY_QUEUE_DEPTH: usize = 75;
+const IDENTIFY_SATA_CAPABILITIES: usize = 76;
+const IDENTIFY_COMMAND_SETS: usize = 83;
+const IDENTIFY_LBA48_SECTORS: usize = 100;
+const IDENTIFY_SECTOR_SIZE: usize = 106;
+const IDENTIFY_LOGICAL_SECTOR_SIZE: usize = 117;
+
+const COMMAND_SETS_LBA48: u16 = 1 << 10;
+const SATA_CAPABILITIES_NCQ: u16 = 1 << 8;
+// The sector size word is valid if its top bits are `01`, and tells whether the logical sectors are larger.
+const SECTOR_SIZE_VALID_MASK: u16 = 0b11 << 14;
+const SECTOR_SIZE_VALID: u16 = 0b01 << 14;
+const SECTOR_SIZE_LARGE_LOGICAL: u16 = 1 << 12;
+
+/// The data returned by the IDENTIFY DEVICE command, which describes an ATA device.
+#[derive(Clone, Copy, Debug)]
+pub struct Identify {
+    model: [u8; 40],
+    serial: [u8; 20],
+    pub lba48: bool,
+    pub sector_count: u64,
+    pub sector_size: usize,
+    /// The depth of the queue of Native Command Queuing (NCQ), if the device supports it.
+    pub queue_depth: Option<u8>,
+}
+
+impl Identify {
+    pub fn parse(words: &[u16; 256]) -> Self {
+        // The strings hold two characters per word, with the first one in the upper byte.
+        let string = |buf: &mut [u8], start: usize| {
+            for (i, pair) in buf.chunks_mut(2).enumerate() {
+                pair.copy_from_slice(&words[start + i].to_be_bytes());
+            }
+        };
+        let mut model = [0; 40];
+        string(&mut model, IDENTIFY_MODEL);
+        let mut serial = [0; 20];
+        string(&mut serial, IDENTIFY_SERIAL);
+
+        let lba48 = words[IDENTIFY_COMMAND_SETS] & COMMAND_SETS_LBA48 != 0;
+        let sector_count = match lba48 {
+            true => (0..4).fold(0, |count, i| {
+                count | (words[IDENTIFY_LBA48_SECTORS + i] as u64) << (16 * i)
+            }),
+            false => {
+                (words[IDENTIFY_LBA28_SECTORS + 1] as u64) << 16
+                    | words[IDENTIFY_LBA28_SECTORS] as u64
+            }
+        };
+
+        let sector_info = words[IDENTIFY_SECTOR_SIZE];
+        let sector_size = if sector_info & SECTOR_SIZE_VALID_MASK == SECTOR_SIZE_VALID
+            && sector_info & SECTOR_SIZE_LARGE_LOGICAL != 0
+        {
+            let size_in_words = (words[IDENTIFY_LOGICAL_SECTOR_SIZE + 1] as usize) << 16
+                | words[IDENTIFY_LOGICAL_SECTOR_SIZE] as usize;
+            size_in_words * 2
+        } else {
+            SECTOR_SIZE
+        };
+
+        let queue_depth = (words[IDENTIFY_SATA_CAPABILITIES] & SATA_CAPABILITIES_NCQ != 0)
+            .then(|| (words[IDENTIFY_QUEUE_DEPTH] & 0x1F) as u8 + 1);
+
+        Self {
+            model,
+            serial,
+            lba48,
+            sector_count,
+            sector_size,
+            queue_depth,
+        }
+    }
+
+    pub fn model(&self) -> &str {
+        str::from_utf8(&self.model).unwrap_or_default().trim()
+    }
+
+    pub fn serial(&self) -> &str {
+        str::from_utf8(&self.serial).unwrap_or_default().trim()
+    }
+}
+
+pub(crate) fn init() -> Result<(), ()> {
+    ide::init()?;
+    ahci::init()
+}
@@ -0,0 +1,469 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::hint;
+use core::ptr;
+use core::slice;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::kernel::cmdline;
+use crate::kernel::cmdline::{Arg, Param, ParamError};
+use crate::kernel::dma::DmaBuffer;
+use crate::kernel::sync;
+use crate::kernel::sync::{Mutex, MutexGuard};
+
+use super::queue::{RequestQueue, MAX_REQUESTS};
+use super::{BlockDevice, BlockError, Request};
+
+/// The size of the buffers, which is the largest block size that is cached.
+pub const BUFFER_SIZE: usize = 4096;
+
+/// The maximum number of buffers, whose state is kept in a fixed-size array.
+const MAX_BUFFERS: usize = 1024;
+const DEFAULT_BUFFERS: usize = 256;
+
+/// `blockcache=<size>` sets the memory used by the buffer cache, e.g., `blockcache=2M`.
+static BLOCKCACHE: Param = Param::new("blockcache", set_size);
+
+static BUFFER_COUNT: AtomicUsize = AtomicUsize::new(DEFAULT_BUFFERS);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The number of buffers.
+    pub capacity: usize,
+    /// The number of buffers holding a block.
+    pub used: usize,
+    /// The number of buffers which haven't been written back yet.
+    pub dirty: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub write_backs: u64,
+}
+
+#[derive(Clone, Copy)]
+struct Entry {
+    device: Option<&'static dyn BlockDevice>,
+    block: u64,
+    dirty: bool,
+    /// The block is being read into the buffer or written back from it, with the cache unlocked.
+    busy: bool,
+    /// The value of the clock when the buffer was last used, which finds the least recently used one.
+    last_used: u64,
+}
+
+impl Entry {
+    const EMPTY: Entry = Entry {
+        device: None,
+        block: 0,
+        dirty: false,
+        busy: false,
+        last_used: 0,
+    };
+
+    fn holds(&self, device: &dyn BlockDevice, block: u64) -> bool {
+        self.block == block && self.belongs_to(device)
+    }
+
+    fn belongs_to(&self, device: &dyn BlockDevice) -> bool {
+        self.device
+            .is_some_and(|cached| same_device(cached, device))
+    }
+}
+
+fn same_device(a: &dyn BlockDevice, b: &dyn BlockDevice) -> bool {
+    ptr::addr_eq(a as *const dyn BlockDevice, b as *const dyn BlockDevice)
+}
+
+struct Cache {
+    memory: Option<DmaBuffer>,
+    entries: [Entry; MAX_BUFFERS],
+    count: usize,
+    clock: u64,
+    stats: CacheStats,
+}
+
+impl Cache {
+    const fn new() -> Self {
+        Self {
+            memory: None,
+            entries: [Entry::EMPTY; MAX_BUFFERS],
+            count: 0,
+            clock: 0,
+            stats: CacheStats {
+                capacity: 0,
+                used: 0,
+                dirty: 0,
+                hits: 0,
+                misses: 0,
+                write_backs: 0,
+            },
+        }
+    }
+
+    fn buffer(&mut self, index: usize, block_size: usize) -> &mut [u8] {
+        let memory = self
+            .memory
+            .as_mut()
+            .expect("buffer cache is not initialized");
+        &mut memory.as_mut_slice()[index * BUFFER_SIZE..index * BUFFER_SIZE + block_size]
+    }
+
+    /// Returns the buffer of a busy entry, which is accessed with the cache unlocked.
+    ///
+    /// SAFETY: The caller must be the one which has made the entry busy.
+    unsafe fn busy_buffer(&self, index: usize, block_size: usize) -> &'static mut [u8] {
+        let memory = self
+            .memory
+            .as_ref()
+            .expect("buffer cache is not initialized");
+        slice::from_raw_parts_mut(memory.as_ptr::<u8>().add(index * BUFFER_SIZE), block_size)
+    }
+
+    /// Finds the buffer which holds a block, or reserves the least recently used one on a miss.
+    fn lookup(
+        &mut self,
+        device: &'static dyn BlockDevice,
+        block: u64,
+    ) -> Result<Lookup, BlockError> {
+        if device.info().block_size > BUFFER_SIZE {
+            return Err(BlockError::Unsupported);
+        }
+        if self.count == 0 {
+            return Err(BlockError::NoMemory);
+        }
+        self.clock += 1;
+
+        let entries = &mut self.entries[..self.count];
+        if let Some(index) = entries.iter().position(|entry| entry.holds(device, block)) {
+            if entries[index].busy {
+                return Ok(Lookup::Busy);
+            }
+            entries[index].last_used = self.clock;
+            self.stats.hits += 1;
+            return Ok(Lookup::Hit(index));
+        }
+
+        let Some(index) = entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| !entry.busy)
+            .min_by_key(|(_, entry)| (entry.device.is_some(), entry.last_used))
+            .map(|(index, _)| index)
+        else {
+            return Ok(Lookup::Busy);
+        };
+
+        // A dirty buffer keeps its block while it's written back, so that the block isn't read in the meantime.
+        let entry = &mut entries[index];
+        if entry.dirty {
+            entry.busy = true;
+            return Ok(Lookup::WriteBack(index, *entry));
+        }
+
+        self.stats.misses += 1;
+        *entry = Entry {
+            device: Some(device),
+            block,
+            dirty: false,
+            busy: true,
+            last_used: self.clock,
+        };
+
+        Ok(Lookup::Read(index))
+    }
+    fn invalidate(&mut self, device: &dyn BlockDevice) {
+        self.entries[..self.count]
+            .iter_mut()
+            .filter(|entry| entry.belongs_to(device))
+            .for_each(|entry| *entry = Entry::EMPTY);
+    }
+}
+
+/// What has been found out about the buffer of a block.
+enum Lookup {
+    /// The buffer holds the block.
+    Hit(usize),
+    /// The buffer has been reserved for the block, which is to be read into it.
+    Read(usize),
+    /// The buffer to be reused has to be written back first.
+    WriteBack(usize, Entry),
+    /// The buffer holding the block, or every one which could be reused, is busy.
+    Busy,
+}
+
+static CACHE: Mutex<Cache> = Mutex::new(Cache::new());
+
+/// Waits for another thread to finish the I/O of a busy buffer.
+///
+/// The thread spins rather than sleeps, as it may hold locks of its own, e.g. those of a filesystem. The thread doing
+/// the I/O isn't preempted until it's done, so it can't be kept from finishing by the spinning one.
+fn wait_busy() {
+    hint::spin_loop();
+}
+
+/// Returns the locked cache along with the buffer which holds a block, reading the block into the least recently used
+/// buffer on a miss.
+///
+/// The cache is unlocked during the I/O, while the buffer is marked as busy and the thread can't be preempted.
+fn get(
+    device: &'static dyn BlockDevice,
+    block: u64,
+) -> Result<(MutexGuard<'static, Cache>, usize), BlockError> {
+    let block_size = device.info().block_size;
+    loop {
+        let mut cache = CACHE.lock();
+        match cache.lookup(device, block)? {
+            Lookup::Hit(index) => return Ok((cache, index)),
+            Lookup::Busy => {
+                drop(cache);
+                wait_busy();
+            }
+            Lookup::WriteBack(index, entry) => {
+                let device = entry.device.expect("a dirty buffer holds no block");
+                let block_size = device.info().block_size;
+                let buffer = unsafe { cache.busy_buffer(index, block_size) };
+                let _preempt = sync::disable_preemption();
+                drop(cache);
+
+                let result = device.write_blocks(entry.block, buffer);
+                let mut cache = CACHE.lock();
+                cache.entries[index].busy = false;
+                result?;
+                cache.entries[index].dirty = false;
+                cache.stats.write_backs += 1;
+            }
+            Lookup::Read(index) => {
+                let buffer = unsafe { cache.busy_buffer(index, block_size) };
+                let _preempt = sync::disable_preemption();
+                drop(cache);
+
+                let result = device.read_blocks(block, buffer);
+                let mut cache = CACHE.lock();
+                match result {
+                    Ok(()) => {
+                        cache.entries[index].busy = false;
+                        return Ok((cache, index));
+                    }
+                    Err(error) => {
+                        cache.entries[index] = Entry::EMPTY;
+                        return Err(error);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn set_size(arg: &Arg) -> Result<(), ParamError> {
+    let count = arg.parse_size()? as usize / BUFFER_SIZE;
+    if count == 0 || count > MAX_BUFFERS {
+        return Err(ParamError::InvalidValue);
+    }
+    BUFFER_COUNT.store(count, Ordering::Relaxed);
+
+    Ok(())
+}
+
+/// Passes the cached contents of a block to the closure.
+///
+/// NOTE: The cache is locked while the closure runs, so it must not access the cache itself.
+pub fn read<F, R>(device: &'static dyn BlockDevice, block: u64, f: F) -> Result<R, BlockError>
+where
+    F: FnOnce(&[u8]) -> R,
+{
+    let (mut cache, index) = get(device, block)?;
+
+    Ok(f(cache.buffer(index, device.info().block_size)))
+}
+
+/// Lets the closure modify the cached contents of a block, which are written back later (write-back).
+///
+/// NOTE: The cache is locked while the closure runs, so it must not access the cache itself.
+pub fn write<F, R>(device: &'static dyn BlockDevice, block: u64, f: F) -> Result<R, BlockError>
+where
+    F: FnOnce(&mut [u8]) -> R,
+{
+    if device.info().read_only {
+        return Err(BlockError::ReadOnly);
+    }
+
+    let (mut cache, index) = get(device, block)?;
+    cache.entries[index].dirty = true;
+
+    Ok(f(cache.buffer(index, device.info().block_size)))
+}
+
+/// Reads a range of bytes of a device through the cache, which doesn't have to be aligned to the blocks.
+pub fn read_bytes(
+    device: &'static dyn BlockDevice,
+    offset: u64,
+    buf: &mut [u8],
+) -> Result<(), BlockError> {
+    let block_size = device.info().block_size as u64;
+    let mut done = 0;
+    while done < buf.len() {
+        let pos = offset + done as u64;
+        let start = (pos % block_size) as usize;
+        let len = (block_size as usize - start).min(buf.len() - done);
+        read(device, pos / block_size, |block| {
+            buf[done..done + len].copy_from_slice(&block[start..start + len]);
+        })?;
+        done += len;
+    }
+
+    Ok(())
+}
+
+/// Writes a range of bytes of a device through the cache, which doesn't have to be aligned to the blocks.
+pub fn write_bytes(
+    device: &'static dyn BlockDevice,
+    offset: u64,
+    buf: &[u8],
+) -> Result<(), BlockError> {
+    let block_size = device.info().block_size as u64;
+    let mut done = 0;
+    while done < buf.len() {
+        let pos = offset + done as u64;
+        let start = (pos % block_size) as usize;
+        let len = (block_size as usize - start).min(buf.len() - done);
+        write(device, pos / block_size, |block| {
+            block[start..start + len].copy_from_slice(&buf[done..done + len]);
+        })?;
+        done += len;
+    }
+
+    Ok(())
+}
+
+/// Writes back the dirty blocks of a device, and makes them persistent.
+///
+/// The buffers are written back in batches through a request queue, during which they're busy, the cache is unlocked
+/// and the thread can't be preempted.
+pub fn sync(device: &'static dyn BlockDevice) -> Result<(), BlockError> {
+    let block_size = device.info().block_size;
+    let mut queue = RequestQueue::new(device);
+    let mut batch = [0; MAX_REQUESTS];
+
+    loop {
+        let mut count = 0;
+        let mut pending = false;
+        {
+            let mut cache = CACHE.lock();
+            for index in 0..cache.count {
+                let entry = &mut cache.entries[index];
+                if !entry.belongs_to(device) {
+                    continue;
+                }
+                // A busy buffer may be being written back, which has to be over before the device is flushed.
+                if entry.busy {
+                    pending = true;
+                } else if entry.dirty && !queue.is_full() {
+                    entry.busy = true;
+                    let block = entry.block;
+                    let buffer = unsafe { cache.busy_buffer(index, block_size) };
+                    let _ = unsafe { queue.push(Request::write(block, buffer)) };
+                    batch[count] = index;
+                    count += 1;
+                }
+            }
+        }
+
+        if count == 0 {
+            match pending {
+                true => wait_busy(),
+                false => break,
+            }
+            continue;
+        }
+
+        let preempt = sync::disable_preemption();
+        let result = queue.run();
+        let mut cache = CACHE.lock();
+        drop(preempt);
+        for index in &batch[..count] {
+            let entry = &mut cache.entries[*index];
+            entry.busy = false;
+            entry.dirty &= result.is_err();
+        }
+        result?;
+        cache.stats.write_backs += count as u64;
+    }
+
+    device.flush()
+}
+
+/// Writes back the dirty blocks of every device.
+pub fn sync_all() -> Result<(), BlockError> {
+    super::devices().try_for_each(sync)
+}
+
+/// Drops the cached blocks of a device without writing them back, e.g. once it has been removed.
+///
+/// Waits for the busy buffers of the device, which would otherwise be filled after they have been dropped.
+pub fn invalidate(device: &dyn BlockDevice) {
+    loop {
+        let mut cache = CACHE.lock();
+        let count = cache.count;
+        if !cache.entries[..count]
+            .iter()
+            .any(|entry| entry.busy && entry.belongs_to(device))
+        {
+            cache.invalidate(device);
+            return;
+        }
+        drop(cache);
+        wait_busy();
+    }
+}
+
+pub fn stats() -> CacheStats {
+    let cache = CACHE.lock();
+    let entries = &cache.entries[..cache.count];
+
+    CacheStats {
+        capacity: cache.count,
+        used: entries
+            .iter()
+            .filter(|entry| entry.device.is_some())
+            .count(),
+        dirty: entries.iter().filter(|entry| entry.dirty).count(),
+        ..cache.stats
+    }
+}
+
+/// Buffer Cache
+///
+/// Keeps recently used blocks of the block devices in memory, and holds back the writes to them until they're
+/// evicted or synced.
+pub(crate) fn init() -> Result<(), ()> {
+    cmdline::register(&BLOCKCACHE).map_err(|_| ())?;
+    let count = BUFFER_COUNT.load(Ordering::Relaxed);
+
+    let memory = DmaBuffer::allocate(count * BUFFER_SIZE).ok_or(())?;
+    let mut cache = CACHE.lock();
+    cache.memory = Some(memory);
+    cache.count = count;
+
+    log::info!("buffer cache of {} KiB", count * BUFFER_SIZE / 1024);
+
+    Ok(())
+}
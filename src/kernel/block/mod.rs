@@ -0,0 +1,265 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::hint;
+use core::task::Poll;
+
+use crate::kernel::sync::RwLock;
+
+pub mod cache;
+pub mod partition;
+pub mod queue;
+
+/// The maximum number of block devices, including the partitions.
+const MAX_DEVICES: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockError {
+    /// The request reaches beyond the last block of the device.
+    OutOfRange,
+    /// The buffer isn't a whole number of blocks, or can't be passed to the device.
+    InvalidBuffer,
+    ReadOnly,
+    /// The device can't take any more requests until some of those in flight have completed.
+    Busy,
+    /// The request is unknown, e.g. because its completion has already been polled.
+    UnknownRequest,
+    Unsupported,
+    NoMemory,
+    /// The device has failed to carry out the request.
+    Io,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegisterError {
+    /// A device with the same name has already been registered.
+    Duplicate,
+    NoFreeSlot,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockInfo {
+    /// The size of the blocks in bytes, which is the unit of the requests.
+    pub block_size: usize,
+    pub block_count: u64,
+    pub read_only: bool,
+}
+
+impl BlockInfo {
+    pub fn size(&self) -> u64 {
+        self.block_count * self.block_size as u64
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operation {
+    Read,
+    Write,
+    /// Makes the blocks written so far persistent, e.g. by writing back the volatile cache of the device.
+    Flush,
+}
+
+/// A request to transfer a range of blocks between a device and a buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Request {
+    pub op: Operation,
+    pub block: u64,
+    pub buf: *mut u8,
+    /// The length of the buffer in bytes, which is a whole number of blocks.
+    pub len: usize,
+}
+
+impl Request {
+    pub fn read(block: u64, buf: &mut [u8]) -> Self {
+        Self {
+            op: Operation::Read,
+            block,
+            buf: buf.as_mut_ptr(),
+            len: buf.len(),
+        }
+    }
+
+    pub fn write(block: u64, buf: &[u8]) -> Self {
+        Self {
+            op: Operation::Write,
+            block,
+            buf: buf.as_ptr() as *mut u8,
+            len: buf.len(),
+        }
+    }
+
+    pub fn flush() -> Self {
+        Self {
+            op: Operation::Flush,
+            block: 0,
+            buf: core::ptr::null_mut(),
+            len: 0,
+        }
+    }
+
+    /// Returns the number of blocks transferred by the request.
+    pub fn block_count(&self, info: &BlockInfo) -> u64 {
+        (self.len / info.block_size) as u64
+    }
+
+    /// Checks whether the request can be carried out by a device.
+    pub fn check(&self, info: &BlockInfo) -> Result<(), BlockError> {
+        if self.op == Operation::Flush {
+            return Ok(());
+        }
+        if self.op == Operation::Write && info.read_only {
+            return Err(BlockError::ReadOnly);
+        }
+        if self.len == 0 || self.len % info.block_size != 0 || self.buf.is_null() {
+            return Err(BlockError::InvalidBuffer);
+        }
+
+        let end = self.block.checked_add(self.block_count(info));
+        match end {
+            Some(end) if end <= info.block_count => Ok(()),
+            _ => Err(BlockError::OutOfRange),
+        }
+    }
+}
+
+/// The identifier of a request which has been submitted to a device, until its completion is polled.
+pub type RequestId = u32;
+
+/// A device which stores data in blocks of a fixed size, such as a disk or a partition of it.
+///
+/// The requests are asynchronous: they're submitted to the device, and then polled until they complete. The blocking
+/// methods are built on top of them.
+pub trait BlockDevice: Sync {
+    fn name(&self) -> &str;
+
+    fn info(&self) -> BlockInfo;
+
+    /// Submits a request to the device, which carries it out in the background.
+    ///
+    /// # Safety
+    ///
+    /// The buffer of the request must stay valid, and must not be accessed, until the request has completed.
+    unsafe fn submit(&self, request: Request) -> Result<RequestId, BlockError>;
+
+    /// Checks whether a request has completed, which forgets it once it's ready.
+    fn poll(&self, id: RequestId) -> Poll<Result<(), BlockError>>;
+
+    /// Waits for a request to complete.
+    fn wait(&self, id: RequestId) -> Result<(), BlockError> {
+        loop {
+            match self.poll(id) {
+                Poll::Ready(result) => return result,
+                Poll::Pending => hint::spin_loop(),
+            }
+        }
+    }
+
+    /// Carries out a request, which is retried for as long as the device is busy.
+    ///
+    /// # Safety
+    ///
+    /// The buffer of the request must be valid.
+    unsafe fn execute(&self, request: Request) -> Result<(), BlockError> {
+        let id = loop {
+            match self.submit(request) {
+                Err(BlockError::Busy) => hint::spin_loop(),
+                result => break result?,
+            }
+        };
+
+        self.wait(id)
+    }
+
+    fn read_blocks(&self, block: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        unsafe { self.execute(Request::read(block, buf)) }
+    }
+
+    fn write_blocks(&self, block: u64, buf: &[u8]) -> Result<(), BlockError> {
+        unsafe { self.execute(Request::write(block, buf)) }
+    }
+
+    fn flush(&self) -> Result<(), BlockError> {
+        unsafe { self.execute(Request::flush()) }
+    }
+}
+
+static DEVICES: RwLock<[Option<&'static dyn BlockDevice>; MAX_DEVICES]> =
+    RwLock::new([None; MAX_DEVICES]);
+
+/// Makes a device available under its name.
+fn add(device: &'static dyn BlockDevice) -> Result<(), RegisterError> {
+    let mut devices = DEVICES.write();
+    if devices
+        .iter()
+        .flatten()
+        .any(|registered| registered.name() == device.name())
+    {
+        return Err(RegisterError::Duplicate);
+    }
+    let slot = devices
+        .iter_mut()
+        .find(|slot| slot.is_none())
+        .ok_or(RegisterError::NoFreeSlot)?;
+    *slot = Some(device);
+
+    let info = device.info();
+    log::info!(
+        "block device {}: {} blocks of {} bytes ({} MiB){}",
+        device.name(),
+        info.block_count,
+        info.block_size,
+        info.size() / (1024 * 1024),
+        if info.read_only { ", read-only" } else { "" }
+    );
+
+    Ok(())
+}
+
+/// Makes a device available under its name, along with the partitions found on it.
+pub fn register(device: &'static dyn BlockDevice) -> Result<(), RegisterError> {
+    add(device)?;
+
+    match partition::scan(device) {
+        Ok(0) => {}
+        Ok(count) => log::info!("{}: {} partition(s)", device.name(), count),
+        Err(error) => log::warn!(
+            "{}: failed to read the partition table: {:?}",
+            device.name(),
+            error
+        ),
+    }
+
+    Ok(())
+}
+
+/// Returns the registered devices, in the order of their registration.
+pub fn devices() -> impl Iterator<Item = &'static dyn BlockDevice> {
+    (0..MAX_DEVICES).map_while(|index| DEVICES.read()[index])
+}
+
+pub fn find(name: &str) -> Option<&'static dyn BlockDevice> {
+    devices().find(|device| device.name() == name)
+}
+
+pub(crate) fn init() -> Result<(), ()> {
+    cache::init()
+}
@@ -0,0 +1,403 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::fmt::Write;
+use core::str;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::task::Poll;
+
+use spin::Once;
+
+use super::{BlockDevice, BlockError, BlockInfo, Request, RequestId};
+
+/// The maximum number of partitions over all the devices.
+const MAX_PARTITIONS: usize = 32;
+
+/// The largest block which is read while scanning, as the buffer is on the stack.
+const MAX_BLOCK_SIZE: usize = 4096;
+
+const MBR_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+const MBR_SIGNATURE_OFFSET: usize = 510;
+const MBR_ENTRIES_OFFSET: usize = 446;
+const MBR_ENTRY_SIZE: usize = 16;
+const MBR_PRIMARY_COUNT: usize = 4;
+// The status of a partition, which tells an MBR apart from e.g. the boot sector of a FAT volume.
+const MBR_STATUS_INACTIVE: u8 = 0x00;
+const MBR_STATUS_ACTIVE: u8 = 0x80;
+
+// The types of the partitions which aren't data partitions.
+const MBR_TYPE_EMPTY: u8 = 0x00;
+const MBR_TYPE_EXTENDED_CHS: u8 = 0x05;
+const MBR_TYPE_EXTENDED_LBA: u8 = 0x0F;
+const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xEE;
+
+/// The maximum number of logical partitions followed in an extended partition, which guards against a loop.
+const MAX_LOGICAL_PARTITIONS: usize = 16;
+
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+const GPT_HEADER_BLOCK: u64 = 1;
+const GPT_ENTRIES_LBA: usize = 72;
+const GPT_ENTRY_COUNT: usize = 80;
+const GPT_ENTRY_SIZE: usize = 84;
+const GPT_MIN_ENTRY_SIZE: usize = 128;
+/// The maximum number of entries read, as most disks use far fewer than the 128 entries reserved.
+const GPT_MAX_ENTRIES: u32 = 128;
+
+/// The type of a partition, as stored in its table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PartitionKind {
+    Mbr(u8),
+    /// The type GUID, in the mixed-endian byte order of the table.
+    Gpt([u8; 16]),
+}
+
+/// A range of blocks of a device, which is a block device of its own.
+pub struct Partition {
+    name: [u8; 16],
+    name_len: usize,
+    parent: &'static dyn BlockDevice,
+    /// The number of the partition, starting at 1, which follows the convention of Linux for logical partitions.
+    number: usize,
+    start: u64,
+    info: BlockInfo,
+    kind: PartitionKind,
+}
+
+impl Partition {
+    pub fn parent(&self) -> &'static dyn BlockDevice {
+        self.parent
+    }
+
+    pub fn number(&self) -> usize {
+        self.number
+    }
+
+    /// Returns the first block of the partition on its parent.
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    pub fn kind(&self) -> PartitionKind {
+        self.kind
+    }
+}
+
+impl BlockDevice for Partition {
+    fn name(&self) -> &str {
+        str::from_utf8(&self.name[..self.name_len]).unwrap_or_default()
+    }
+
+    fn info(&self) -> BlockInfo {
+        self.info
+    }
+
+    unsafe fn submit(&self, request: Request) -> Result<RequestId, BlockError> {
+        request.check(&self.info)?;
+        self.parent.submit(Request {
+            block: request.block + self.start,
+            ..request
+        })
+    }
+
+    fn poll(&self, id: RequestId) -> Poll<Result<(), BlockError>> {
+        self.parent.poll(id)
+    }
+}
+
+/// A buffer into which the name of a partition is formatted.
+struct NameWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Write for NameWriter<'_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let end = self.len + s.len();
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(core::fmt::Error)?
+            .copy_from_slice(s.as_bytes());
+        self.len = end;
+
+        Ok(())
+    }
+}
+
+static PARTITIONS: [Once<Partition>; MAX_PARTITIONS] = [const { Once::new() }; MAX_PARTITIONS];
+static PARTITION_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Registers a partition of a device as a block device, named after the device and the number of the partition.
+fn add(
+    parent: &'static dyn BlockDevice,
+    number: usize,
+    start: u64,
+    count: u64,
+    kind: PartitionKind,
+) -> Result<(), BlockError> {
+    let parent_info = parent.info();
+    let end = start.checked_add(count).ok_or(BlockError::OutOfRange)?;
+    if count == 0 || end > parent_info.block_count {
+        log::warn!(
+            "{}: partition {} lies beyond the end of the device",
+            parent.name(),
+            number
+        );
+        return Err(BlockError::OutOfRange);
+    }
+
+    let mut name = [0; 16];
+    let mut writer = NameWriter {
+        buf: &mut name,
+        len: 0,
+    };
+    // A name ending in a digit is separated from the number, e.g. `nvme0n1p1`.
+    let separator = match parent.name().ends_with(|c: char| c.is_ascii_digit()) {
+        true => "p",
+        false => "",
+    };
+    write!(writer, "{}{}{}", parent.name(), separator, number)
+        .map_err(|_| BlockError::Unsupported)?;
+    let name_len = writer.len;
+
+    let index = PARTITION_COUNT.fetch_add(1, Ordering::Relaxed);
+    let slot = PARTITIONS.get(index).ok_or(BlockError::NoMemory)?;
+    let partition = slot.call_once(|| Partition {
+        name,
+        name_len,
+        parent,
+        number,
+        start,
+        info: BlockInfo {
+            block_count: count,
+            ..parent_info
+        },
+        kind,
+    });
+
+    super::add(partition).map_err(|_| BlockError::NoMemory)
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(buf: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
+
+/// A partition table entry of an MBR or an extended boot record.
+#[derive(Clone, Copy, Debug)]
+struct MbrEntry {
+    status: u8,
+    kind: u8,
+    start: u64,
+    count: u64,
+}
+
+impl MbrEntry {
+    fn parse(block: &[u8], index: usize) -> Self {
+        let entry = &block[MBR_ENTRIES_OFFSET + index * MBR_ENTRY_SIZE..][..MBR_ENTRY_SIZE];
+        Self {
+            status: entry[0],
+            kind: entry[4],
+            start: read_u32(entry, 8) as u64,
+            count: read_u32(entry, 12) as u64,
+        }
+    }
+
+    fn is_extended(&self) -> bool {
+        matches!(self.kind, MBR_TYPE_EXTENDED_CHS | MBR_TYPE_EXTENDED_LBA)
+    }
+}
+
+/// Master Boot Record (MBR)
+///
+/// The four primary partitions, one of which may be an extended partition that holds a linked list of extended boot
+/// records (EBR), each describing a logical partition numbered from 5.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/Partition_Table
+fn scan_mbr(device: &'static dyn BlockDevice, mbr: &[u8]) -> Result<usize, BlockError> {
+    let mut found = 0;
+    for index in 0..MBR_PRIMARY_COUNT {
+        let entry = MbrEntry::parse(mbr, index);
+        match entry.kind {
+            MBR_TYPE_EMPTY => {}
+            _ if entry.is_extended() => found += scan_extended(device, entry.start)?,
+            kind => {
+                if add(
+                    device,
+                    index + 1,
+                    entry.start,
+                    entry.count,
+                    PartitionKind::Mbr(kind),
+                )
+                .is_ok()
+                {
+                    found += 1;
+                }
+            }
+        }
+    }
+
+    Ok(found)
+}
+
+fn scan_extended(
+    device: &'static dyn BlockDevice,
+    extended_start: u64,
+) -> Result<usize, BlockError> {
+    let block_size = device.info().block_size;
+    let mut buf = [0; MAX_BLOCK_SIZE];
+    let ebr = &mut buf[..block_size];
+
+    // The logical partitions are relative to their EBR, while the links are relative to the extended partition.
+    let mut found = 0;
+    let mut ebr_start = extended_start;
+    for number in 5..5 + MAX_LOGICAL_PARTITIONS {
+        device.read_blocks(ebr_start, ebr)?;
+        if ebr[MBR_SIGNATURE_OFFSET..MBR_SIGNATURE_OFFSET + 2] != MBR_SIGNATURE {
+            break;
+        }
+
+        let entry = MbrEntry::parse(ebr, 0);
+        if entry.kind != MBR_TYPE_EMPTY {
+            let start = ebr_start + entry.start;
+            if add(
+                device,
+                number,
+                start,
+                entry.count,
+                PartitionKind::Mbr(entry.kind),
+            )
+            .is_ok()
+            {
+                found += 1;
+            }
+        }
+
+        let link = MbrEntry::parse(ebr, 1);
+        if !link.is_extended() || link.start == 0 {
+            break;
+        }
+        ebr_start = extended_start + link.start;
+    }
+
+    Ok(found)
+}
+
+/// GUID Partition Table (GPT)
+///
+/// The header in the second block locates an array of entries, of which those with a type GUID other than zero are
+/// partitions.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/GPT
+fn scan_gpt(device: &'static dyn BlockDevice) -> Result<usize, BlockError> {
+    let block_size = device.info().block_size;
+    let mut buf = [0; MAX_BLOCK_SIZE];
+    let block = &mut buf[..block_size];
+
+    device.read_blocks(GPT_HEADER_BLOCK, block)?;
+    if &block[..GPT_SIGNATURE.len()] != GPT_SIGNATURE {
+        log::warn!("{}: protective MBR without a GPT header", device.name());
+        return Ok(0);
+    }
+
+    let entries_lba = read_u64(block, GPT_ENTRIES_LBA);
+    let entry_count = read_u32(block, GPT_ENTRY_COUNT).min(GPT_MAX_ENTRIES) as usize;
+    let entry_size = read_u32(block, GPT_ENTRY_SIZE) as usize;
+    if entry_size < GPT_MIN_ENTRY_SIZE || entry_size > block_size || block_size % entry_size != 0 {
+        return Err(BlockError::Unsupported);
+    }
+
+    let entries_per_block = block_size / entry_size;
+    let mut found = 0;
+    let mut current = None;
+    for index in 0..entry_count {
+        let lba = entries_lba + (index / entries_per_block) as u64;
+        if current != Some(lba) {
+            device.read_blocks(lba, block)?;
+            current = Some(lba);
+        }
+
+        let entry = &block[(index % entries_per_block) * entry_size..][..entry_size];
+        let kind: [u8; 16] = entry[..16].try_into().unwrap();
+        if kind == [0; 16] {
+            continue;
+        }
+
+        // The last block is inclusive.
+        let start = read_u64(entry, 32);
+        let end = read_u64(entry, 40);
+        let count = match end.checked_add(1) {
+            Some(last) if end >= start => last - start,
+            _ => {
+                log::warn!(
+                    "{}: partition {} has an invalid range",
+                    device.name(),
+                    index + 1
+                );
+                continue;
+            }
+        };
+        if add(device, index + 1, start, count, PartitionKind::Gpt(kind)).is_ok() {
+            found += 1;
+        }
+    }
+
+    Ok(found)
+}
+
+/// Reads the partition table of a device, and registers each of its partitions as a block device.
+///
+/// Returns the number of partitions found, which is zero for a device without a partition table.
+pub fn scan(device: &'static dyn BlockDevice) -> Result<usize, BlockError> {
+    let block_size = device.info().block_size;
+    if !(MBR_SIGNATURE_OFFSET + MBR_SIGNATURE.len()..=MAX_BLOCK_SIZE).contains(&block_size) {
+        return Err(BlockError::Unsupported);
+    }
+
+    let mut buf = [0; MAX_BLOCK_SIZE];
+    let mbr = &mut buf[..block_size];
+    device.read_blocks(0, mbr)?;
+    if mbr[MBR_SIGNATURE_OFFSET..MBR_SIGNATURE_OFFSET + 2] != MBR_SIGNATURE {
+        return Ok(0);
+    }
+
+    let mut entries = (0..MBR_PRIMARY_COUNT).map(|index| MbrEntry::parse(mbr, index));
+    if !entries
+        .clone()
+        .all(|entry| matches!(entry.status, MBR_STATUS_INACTIVE | MBR_STATUS_ACTIVE))
+    {
+        return Ok(0);
+    }
+
+    let is_gpt = entries.any(|entry| entry.kind == MBR_TYPE_GPT_PROTECTIVE);
+    match is_gpt {
+        true => scan_gpt(device),
+        false => scan_mbr(device, mbr),
+    }
+}
+
+/// Returns the registered partitions.
+pub fn partitions() -> impl Iterator<Item = &'static Partition> {
+    PARTITIONS.iter().filter_map(Once::get)
+}
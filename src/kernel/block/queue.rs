@@ -0,0 +1,216 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::hint;
+
+use super::{BlockDevice, BlockError, Operation, Request};
+
+/// The maximum number of requests queued before they're run.
+pub const MAX_REQUESTS: usize = 32;
+
+/// The maximum length of a request which results from merging others.
+const MAX_MERGED_LEN: usize = 64 * 1024;
+
+/// Request Queue
+///
+/// Collects the requests to a device, which are reordered and merged when they're run: the requests between two
+/// flushes are sorted by an elevator which sweeps up from where the previous run has stopped (C-LOOK), and those
+/// transferring adjacent blocks from and to adjacent memory are merged into one. A flush waits for all the requests
+/// queued before it, and is only then submitted on its own.
+pub struct RequestQueue {
+    device: &'static dyn BlockDevice,
+    requests: [Request; MAX_REQUESTS],
+    len: usize,
+    /// The block following the last one which has been submitted.
+    position: u64,
+}
+
+impl RequestQueue {
+    pub fn new(device: &'static dyn BlockDevice) -> Self {
+        Self {
+            device,
+            requests: [Request::flush(); MAX_REQUESTS],
+            len: 0,
+            position: 0,
+        }
+    }
+
+    pub fn device(&self) -> &'static dyn BlockDevice {
+        self.device
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == MAX_REQUESTS
+    }
+
+    /// Queues a request, or hands it back if the queue is full.
+    ///
+    /// # Safety
+    ///
+    /// The buffer of the request must stay valid, and must not be accessed, until the queue has been run.
+    pub unsafe fn push(&mut self, request: Request) -> Result<(), Request> {
+        if self.is_full() {
+            return Err(request);
+        }
+
+        self.requests[self.len] = request;
+        self.len += 1;
+
+        Ok(())
+    }
+
+    /// Carries out the queued requests, and returns the first error of any of them.
+    pub fn run(&mut self) -> Result<(), BlockError> {
+        let mut result = Ok(());
+        let mut start = 0;
+        while start < self.len {
+            let end = (start..self.len)
+                .find(|&i| self.requests[i].op == Operation::Flush)
+                .unwrap_or(self.len);
+
+            let count = self.schedule(start, end);
+            let batch_result = self.dispatch(start, start + count);
+            result = result.and(batch_result);
+
+            if end < self.len {
+                result = result.and(unsafe { self.device.execute(self.requests[end]) });
+            }
+            start = end + 1;
+        }
+        self.len = 0;
+
+        result
+    }
+
+    /// Sorts the requests of a batch in the order of the elevator, and merges them in place.
+    ///
+    /// Returns the number of requests left at the start of the batch.
+    fn schedule(&mut self, start: usize, end: usize) -> usize {
+        let position = self.position;
+        let batch = &mut self.requests[start..end];
+
+        // The blocks behind the elevator are visited on the next sweep. The sort is stable, and never moves a request
+        // past another one it conflicts with, which keeps the overlapping requests in their order.
+        let block_size = self.device.info().block_size;
+        let key = |request: &Request| (request.block < position, request.block);
+        for i in 1..batch.len() {
+            let mut j = i;
+            while j > 0
+                && key(&batch[j - 1]) > key(&batch[j])
+                && !conflicts(&batch[j - 1], &batch[j], block_size)
+            {
+                batch.swap(j - 1, j);
+                j -= 1;
+            }
+        }
+
+        let mut count = 0;
+        for i in 0..batch.len() {
+            let request = batch[i];
+            if count > 0 && can_merge(&batch[count - 1], &request, block_size) {
+                batch[count - 1].len += request.len;
+            } else {
+                batch[count] = request;
+                count += 1;
+            }
+        }
+
+        count
+    }
+
+    /// Submits the requests, while keeping as many of them in flight as the device takes.
+    ///
+    /// The device may complete the requests in flight in any order, hence a request conflicting with one of them is
+    /// held back until the latter has completed.
+    fn dispatch(&mut self, start: usize, end: usize) -> Result<(), BlockError> {
+        let block_size = self.device.info().block_size;
+        let mut in_flight = [(0, Request::flush()); MAX_REQUESTS];
+        let mut oldest = 0;
+        let mut count = 0;
+        let mut result = Ok(());
+
+        for request in &self.requests[start..end] {
+            while in_flight[oldest..count]
+                .iter()
+                .any(|(_, other)| conflicts(other, request, block_size))
+            {
+                result = result.and(self.device.wait(in_flight[oldest].0));
+                oldest += 1;
+            }
+
+            loop {
+                match unsafe { self.device.submit(*request) } {
+                    Ok(id) => {
+                        in_flight[count] = (id, *request);
+                        count += 1;
+                        break;
+                    }
+                    // The device takes more requests once the oldest one has completed.
+                    Err(BlockError::Busy) if oldest < count => {
+                        result = result.and(self.device.wait(in_flight[oldest].0));
+                        oldest += 1;
+                    }
+                    Err(BlockError::Busy) => hint::spin_loop(),
+                    Err(error) => {
+                        result = result.and(Err(error));
+                        break;
+                    }
+                }
+            }
+
+            let blocks = (request.len / block_size) as u64;
+            self.position = request.block + blocks;
+        }
+
+        for (id, _) in &in_flight[oldest..count] {
+            result = result.and(self.device.wait(*id));
+        }
+
+        result
+    }
+}
+
+/// Checks whether a request continues another one, both on the device and in the memory.
+fn can_merge(previous: &Request, next: &Request, block_size: usize) -> bool {
+    let blocks = (previous.len / block_size) as u64;
+
+    previous.op == next.op
+        && previous.block + blocks == next.block
+        && previous.buf.wrapping_add(previous.len) == next.buf
+        && previous.len + next.len <= MAX_MERGED_LEN
+}
+
+/// Checks whether two requests transfer some of the same blocks, with at least one of them writing, in which case they
+/// must be carried out in the order they were queued.
+fn conflicts(a: &Request, b: &Request, block_size: usize) -> bool {
+    let end = |request: &Request| request.block + (request.len / block_size) as u64;
+
+    (a.op == Operation::Write || b.op == Operation::Write) && a.block < end(b) && b.block < end(a)
+}
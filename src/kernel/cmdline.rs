@@ -0,0 +1,247 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::str::FromStr;
+
+use spin::Once;
+
+use crate::kernel::sync::Mutex;
+
+/// The maximum number of parameters which can be registered by the subsystems.
+const MAX_PARAMS: usize = 32;
+
+/// An argument on the kernel command line, which is either a `key=value` pair or a flag without a value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Arg {
+    pub key: &'static str,
+    pub value: Option<&'static str>,
+}
+
+impl Arg {
+    fn from_token(token: &'static str) -> Self {
+        match token.split_once('=') {
+            Some((key, value)) => Self {
+                key,
+                value: Some(unquote(value)),
+            },
+            None => Self {
+                key: token,
+                value: None,
+            },
+        }
+    }
+
+    /// Parses the value of the argument.
+    pub fn parse<T: FromStr>(&self) -> Result<T, ParamError> {
+        self.value
+            .ok_or(ParamError::MissingValue)?
+            .parse()
+            .map_err(|_| ParamError::InvalidValue)
+    }
+
+    /// Parses the value of the argument as a number of bytes, which may be followed by a binary unit, e.g., `512M`.
+    pub fn parse_size(&self) -> Result<u64, ParamError> {
+        let value = self.value.ok_or(ParamError::MissingValue)?;
+        let (digits, shift) = match value.as_bytes().last() {
+            Some(b'K' | b'k') => (&value[..value.len() - 1], 10),
+            Some(b'M' | b'm') => (&value[..value.len() - 1], 20),
+            Some(b'G' | b'g') => (&value[..value.len() - 1], 30),
+            _ => (value, 0),
+        };
+
+        digits
+            .parse::<u64>()
+            .ok()
+            .and_then(|size| size.checked_mul(1 << shift))
+            .ok_or(ParamError::InvalidValue)
+    }
+
+    /// Checks that the argument is a flag, i.e., it has no value.
+    pub fn flag(&self) -> Result<(), ParamError> {
+        match self.value {
+            Some(_) => Err(ParamError::UnexpectedValue),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParamError {
+    /// The parameter requires a value, but it was given as a flag.
+    MissingValue,
+    /// The parameter is a flag, but it was given a value.
+    UnexpectedValue,
+    InvalidValue,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegisterError {
+    /// A parameter with the same name has already been registered.
+    Duplicate,
+    NoFreeSlot,
+}
+
+/// The handler of a parameter, which is called once for every occurrence of the parameter on the command line.
+pub type ParamHandler = fn(&Arg) -> Result<(), ParamError>;
+
+/// A parameter which a subsystem accepts on the kernel command line.
+pub struct Param {
+    pub name: &'static str,
+    pub handler: ParamHandler,
+}
+
+impl Param {
+    pub const fn new(name: &'static str, handler: ParamHandler) -> Self {
+        Self { name, handler }
+    }
+}
+
+/// Iterator over the arguments of a command line.
+///
+/// Arguments are separated by whitespace, and a value may be put in double quotes in order to contain whitespace,
+/// e.g., `key="some value"`.
+#[derive(Clone)]
+pub struct Args {
+    rest: &'static str,
+}
+
+impl Args {
+    pub fn new(cmdline: &'static str) -> Self {
+        Self { rest: cmdline }
+    }
+}
+
+impl Iterator for Args {
+    type Item = Arg;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = self.rest.trim_start();
+        if rest.is_empty() {
+            self.rest = rest;
+            return None;
+        }
+
+        let mut quoted = false;
+        let end = rest
+            .char_indices()
+            .find(|&(_, c)| {
+                if c == '"' {
+                    quoted = !quoted;
+                }
+                !quoted && c.is_whitespace()
+            })
+            .map_or(rest.len(), |(i, _)| i);
+
+        let (token, rest) = rest.split_at(end);
+        self.rest = rest;
+
+        Some(Arg::from_token(token))
+    }
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+static CMDLINE: Once<&'static str> = Once::new();
+
+static PARAMS: Mutex<[Option<&'static Param>; MAX_PARAMS]> = Mutex::new([None; MAX_PARAMS]);
+
+/// Returns the command line passed to the kernel, which is empty until it has been retrieved from the bootloader.
+pub fn cmdline() -> &'static str {
+    CMDLINE.get().copied().unwrap_or_default()
+}
+
+/// Returns an iterator over the arguments on the kernel command line.
+pub fn args() -> Args {
+    Args::new(cmdline())
+}
+
+fn apply(param: &Param) {
+    for arg in args().filter(|arg| arg.key == param.name) {
+        if let Err(error) = (param.handler)(&arg) {
+            log::warn!("ignoring kernel parameter `{}`: {:?}", param.name, error);
+        }
+    }
+}
+
+/// Registers a parameter on the kernel command line.
+///
+/// The handler is called right away if the command line is already available, or else as soon as it is.
+pub fn register(param: &'static Param) -> Result<(), RegisterError> {
+    {
+        let mut params = PARAMS.lock();
+        if params
+            .iter()
+            .flatten()
+            .any(|other| other.name == param.name)
+        {
+            return Err(RegisterError::Duplicate);
+        }
+
+        let slot = params
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(RegisterError::NoFreeSlot)?;
+        *slot = Some(param);
+    }
+
+    // The handler is called without holding the lock, so that it is free to register further parameters.
+    if CMDLINE.is_completed() {
+        apply(param);
+    }
+
+    Ok(())
+}
+
+fn is_registered(name: &str) -> bool {
+    PARAMS
+        .lock()
+        .iter()
+        .flatten()
+        .any(|param| param.name == name)
+}
+
+/// Stores the command line and passes the arguments to the handlers of the parameters registered so far.
+pub(crate) fn init(cmdline: &'static str) -> Result<(), ()> {
+    if CMDLINE.is_completed() {
+        return Err(());
+    }
+    CMDLINE.call_once(|| cmdline);
+
+    log::info!("kernel command line: {}", cmdline);
+
+    let params = *PARAMS.lock();
+    params.into_iter().flatten().for_each(apply);
+
+    Ok(())
+}
+
+/// Reports the arguments on the command line which no subsystem has registered a parameter for.
+pub fn report_unknown() {
+    args()
+        .filter(|arg| !is_registered(arg.key))
+        .for_each(|arg| log::warn!("unknown kernel parameter `{}`", arg.key));
+}
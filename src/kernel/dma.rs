@@ -0,0 +1,140 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::ptr;
+use core::slice;
+
+use crate::kernel::arch;
+use crate::kernel::arch::frame::FRAME_SIZE;
+use crate::kernel::arch::{PhysAddr, VirtAddr};
+
+/// A physically contiguous buffer in which devices can access the memory directly (DMA).
+///
+/// The buffer is made of whole frames below 4 GiB, which are zeroed when allocated. It isn't freed when dropped,
+/// as it may still be in use by a device, hence it must be freed explicitly.
+#[derive(Debug)]
+pub struct DmaBuffer {
+    phys_addr: PhysAddr,
+    virt_addr: VirtAddr,
+    len: usize,
+}
+
+// The buffer is owned like a `Box`, and only accessed through its owner.
+unsafe impl Send for DmaBuffer {}
+unsafe impl Sync for DmaBuffer {}
+
+impl DmaBuffer {
+    pub fn allocate(len: usize) -> Option<Self> {
+        let frames = len.max(1).div_ceil(FRAME_SIZE);
+        let phys_addr = arch::frame::allocate(frames)?;
+        let Some(virt_addr) = arch::paging::phys_range_to_virt(phys_addr, frames * FRAME_SIZE)
+        else {
+            arch::frame::deallocate(phys_addr, frames);
+            return None;
+        };
+
+        unsafe { ptr::write_bytes(virt_addr.as_mut_ptr::<u8>(), 0, frames * FRAME_SIZE) };
+
+        Some(Self {
+            phys_addr,
+            virt_addr,
+            len: frames * FRAME_SIZE,
+        })
+    }
+
+    pub fn free(self) {
+        arch::frame::deallocate(self.phys_addr, self.len / FRAME_SIZE);
+    }
+
+    pub fn phys_addr(&self) -> u64 {
+        self.phys_addr.as_u64()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn as_ptr<T>(&self) -> *mut T {
+        self.virt_addr.as_mut_ptr::<T>()
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.as_ptr::<u8>(), self.len) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.as_ptr::<u8>(), self.len) }
+    }
+}
+
+/// Returns the physical address to which a virtual address is mapped.
+pub fn virt_to_phys(addr: usize) -> Option<u64> {
+    let addr = VirtAddr::try_new(addr as u64).ok()?;
+    arch::paging::translate(addr).map(|addr| addr.as_u64())
+}
+
+/// Iterator over the physically contiguous parts of a buffer in the virtual memory.
+///
+/// It yields the physical address and the length of each part, so that a device can be given the buffer as a list
+/// of segments (scatter-gather), e.g. when it's on the stack.
+#[derive(Clone, Copy, Debug)]
+pub struct Segments {
+    addr: usize,
+    remaining: usize,
+}
+
+impl Iterator for Segments {
+    type Item = Option<(u64, usize)>;
+
+    /// Yields `None` for a part which isn't mapped, after which the iteration ends.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let Some(start) = virt_to_phys(self.addr) else {
+            self.remaining = 0;
+            return Some(None);
+        };
+
+        // The part extends over the following pages for as long as they're mapped right after each other.
+        let mut len = (FRAME_SIZE - self.addr % FRAME_SIZE).min(self.remaining);
+        while len < self.remaining && virt_to_phys(self.addr + len) == Some(start + len as u64) {
+            len = (len + FRAME_SIZE).min(self.remaining);
+        }
+
+        self.addr += len;
+        self.remaining -= len;
+        Some(Some((start, len)))
+    }
+}
+
+pub fn segments(ptr: *const u8, len: usize) -> Segments {
+    Segments {
+        addr: ptr as usize,
+        remaining: len,
+    }
+}
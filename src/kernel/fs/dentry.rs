@@ -0,0 +1,108 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::kernel::sync::Mutex;
+
+use super::InodeId;
+
+/// The number of entries kept in the cache.
+const CACHE_SIZE: usize = 64;
+/// The maximum length of a name which is cached, as longer names are rare enough to be looked up every time.
+const MAX_CACHED_NAME_LEN: usize = 32;
+
+#[derive(Clone, Copy)]
+struct Dentry {
+    mount: usize,
+    parent: InodeId,
+    name: [u8; MAX_CACHED_NAME_LEN],
+    name_len: u8,
+    inode: InodeId,
+}
+
+impl Dentry {
+    fn matches(&self, mount: usize, parent: InodeId, name: &str) -> bool {
+        self.mount == mount
+            && self.parent == parent
+            && &self.name[..self.name_len as usize] == name.as_bytes()
+    }
+}
+
+/// Directory Entry Cache
+///
+/// Remembers the results of recent lookups, so walking the same paths again doesn't reach the filesystems. The cache
+/// is direct-mapped, so every entry has a single slot, chosen by hashing its directory and name.
+static CACHE: Mutex<[Option<Dentry>; CACHE_SIZE]> = Mutex::new([None; CACHE_SIZE]);
+
+/// Hashes a directory entry with FNV-1a.
+fn slot(mount: usize, parent: InodeId, name: &str) -> usize {
+    let hash = (mount as u64)
+        .to_le_bytes()
+        .iter()
+        .chain(parent.to_le_bytes().iter())
+        .chain(name.as_bytes())
+        .fold(0xCBF2_9CE4_8422_2325u64, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x100_0000_01B3)
+        });
+
+    hash as usize % CACHE_SIZE
+}
+
+pub(super) fn lookup(mount: usize, parent: InodeId, name: &str) -> Option<InodeId> {
+    CACHE.lock()[slot(mount, parent, name)]
+        .filter(|dentry| dentry.matches(mount, parent, name))
+        .map(|dentry| dentry.inode)
+}
+
+pub(super) fn insert(mount: usize, parent: InodeId, name: &str, inode: InodeId) {
+    if name.len() > MAX_CACHED_NAME_LEN {
+        return;
+    }
+
+    let mut dentry = Dentry {
+        mount,
+        parent,
+        name: [0; MAX_CACHED_NAME_LEN],
+        name_len: name.len() as u8,
+        inode,
+    };
+    dentry.name[..name.len()].copy_from_slice(name.as_bytes());
+
+    CACHE.lock()[slot(mount, parent, name)] = Some(dentry);
+}
+
+/// Forgets an entry which has been removed from its directory.
+pub(super) fn invalidate(mount: usize, parent: InodeId, name: &str) {
+    let mut cache = CACHE.lock();
+    let entry = &mut cache[slot(mount, parent, name)];
+    if entry.is_some_and(|dentry| dentry.matches(mount, parent, name)) {
+        *entry = None;
+    }
+}
+
+/// Forgets every entry of a filesystem which is being unmounted.
+pub(super) fn invalidate_mount(mount: usize) {
+    for slot in CACHE.lock().iter_mut() {
+        if slot.is_some_and(|dentry| dentry.mount == mount) {
+            *slot = None;
+        }
+    }
+}
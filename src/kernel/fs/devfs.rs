@@ -0,0 +1,305 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::iter;
+use core::ptr;
+
+use crate::kernel::block::{self, cache, BlockDevice};
+use crate::kernel::input::keyboard::KeyState;
+use crate::kernel::input::{self, InputEvent};
+use crate::kernel::uart::{self, ComPort};
+use crate::kernel::video::framebuffer::{self, Framebuffer};
+
+use super::{DirEntry, FileSystem, FileType, FsError, InodeId, Metadata};
+
+const ROOT: InodeId = 0;
+
+/// The classes of the devices, which make up the upper half of the numbers of their inodes.
+const CLASS_SERIAL: u64 = 1;
+const CLASS_BLOCK: u64 = 2;
+const CLASS_FRAMEBUFFER: u64 = 3;
+const CLASS_INPUT: u64 = 4;
+
+const SERIAL_NAMES: [&str; 4] = ["ttyS0", "ttyS1", "ttyS2", "ttyS3"];
+
+/// The size of the records in which the input events are read.
+const INPUT_EVENT_SIZE: usize = 16;
+const INPUT_EVENT_KEY: u8 = 1;
+const INPUT_EVENT_MOUSE: u8 = 2;
+
+const DEVICE_MODE: u16 = 0o660;
+
+/// A device, which is listed as a node of the filesystem while it's present.
+#[derive(Clone, Copy)]
+enum Device {
+    /// A serial port, which is read without blocking.
+    Serial(ComPort),
+    /// A block device or a partition, along with its position among the registered devices.
+    Block(usize, &'static dyn BlockDevice),
+    /// The framebuffer, whose memory is read and written as is.
+    Framebuffer(&'static Framebuffer),
+    /// The queue of the input events, which are read as records of 16 bytes in little-endian order.
+    ///
+    /// A key event is made of its type (1), its state (1 if pressed, 0 if released), the key code (`u16`), the
+    /// modifiers (`u16`), 2 bytes of padding, and the character (`u32`, 0 if none). A mouse event is made of its type
+    /// (2), the buttons being held down, the buttons which changed, the motion of the wheel (`i8`), and the
+    /// horizontal and vertical motion (`i16` each).
+    Input,
+}
+
+impl Device {
+    /// Lists the devices which are present, serial ports first.
+    fn all() -> impl Iterator<Item = Device> {
+        let serial = ComPort::ALL
+            .into_iter()
+            .filter(|port| uart::is_present(*port))
+            .map(Device::Serial);
+        let block = block::devices()
+            .enumerate()
+            .map(|(index, device)| Device::Block(index, device));
+
+        serial
+            .chain(block)
+            .chain(framebuffer::get().map(Device::Framebuffer))
+            .chain(iter::once(Device::Input))
+    }
+
+    fn find(inode: InodeId) -> Result<Device, FsError> {
+        Device::all()
+            .find(|device| device.inode() == inode)
+            .ok_or(FsError::NotFound)
+    }
+
+    fn inode(&self) -> InodeId {
+        let (class, index) = match self {
+            Device::Serial(port) => (CLASS_SERIAL, *port as u64),
+            Device::Block(index, _) => (CLASS_BLOCK, *index as u64),
+            Device::Framebuffer(_) => (CLASS_FRAMEBUFFER, 0),
+            Device::Input => (CLASS_INPUT, 0),
+        };
+
+        class << 32 | index
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Device::Serial(port) => SERIAL_NAMES[*port as usize],
+            Device::Block(_, device) => device.name(),
+            Device::Framebuffer(_) => "fb0",
+            Device::Input => "input",
+        }
+    }
+
+    fn file_type(&self) -> FileType {
+        match self {
+            Device::Block(..) => FileType::BlockDevice,
+            _ => FileType::CharDevice,
+        }
+    }
+
+    /// Returns the size of a device which is addressed by offsets, or 0 for a stream.
+    fn size(&self) -> u64 {
+        match self {
+            Device::Block(_, device) => device.info().size(),
+            Device::Framebuffer(framebuffer) => framebuffer.size() as u64,
+            _ => 0,
+        }
+    }
+
+    fn read(&self, offset: u64, buf: &mut [u8]) -> Result<usize, FsError> {
+        match self {
+            Device::Serial(port) => uart::read(*port, buf).map_err(|_| FsError::NotFound),
+            Device::Block(_, device) => {
+                let len = self.clamp(offset, buf.len());
+                cache::read_bytes(*device, offset, &mut buf[..len])?;
+                Ok(len)
+            }
+            Device::Framebuffer(framebuffer) => {
+                let len = self.clamp(offset, buf.len());
+                unsafe {
+                    let src = (framebuffer.base + offset as usize) as *const u8;
+                    ptr::copy_nonoverlapping(src, buf.as_mut_ptr(), len);
+                }
+                Ok(len)
+            }
+            Device::Input => {
+                let mut len = 0;
+                while let Some(record) = buf.get_mut(len..len + INPUT_EVENT_SIZE) {
+                    let Some(event) = input::poll() else {
+                        break;
+                    };
+                    encode_event(event, record);
+                    len += INPUT_EVENT_SIZE;
+                }
+                Ok(len)
+            }
+        }
+    }
+
+    fn write(&self, offset: u64, buf: &[u8]) -> Result<usize, FsError> {
+        if offset >= self.size() && matches!(self, Device::Block(..) | Device::Framebuffer(_)) {
+            return Err(FsError::NoSpace);
+        }
+
+        match self {
+            Device::Serial(port) => uart::write(*port, buf)
+                .map(|_| buf.len())
+                .map_err(|_| FsError::NotFound),
+            Device::Block(_, device) => {
+                if device.info().read_only {
+                    return Err(FsError::ReadOnly);
+                }
+                let len = self.clamp(offset, buf.len());
+                cache::write_bytes(*device, offset, &buf[..len])?;
+                Ok(len)
+            }
+            Device::Framebuffer(framebuffer) => {
+                let len = self.clamp(offset, buf.len());
+                unsafe {
+                    let dst = (framebuffer.base + offset as usize) as *mut u8;
+                    ptr::copy_nonoverlapping(buf.as_ptr(), dst, len);
+                }
+                Ok(len)
+            }
+            Device::Input => Err(FsError::ReadOnly),
+        }
+    }
+
+    /// Limits the length of a transfer at an offset to the size of the device.
+    fn clamp(&self, offset: u64, len: usize) -> usize {
+        self.size().saturating_sub(offset).min(len as u64) as usize
+    }
+}
+
+fn encode_event(event: InputEvent, record: &mut [u8]) {
+    record.fill(0);
+    match event {
+        InputEvent::Key(event) => {
+            record[0] = INPUT_EVENT_KEY;
+            record[1] = (event.state == KeyState::Pressed) as u8;
+            record[2..4].copy_from_slice(&(event.key as u16).to_le_bytes());
+            record[4..6].copy_from_slice(&event.modifiers.bits().to_le_bytes());
+            record[8..12].copy_from_slice(&(event.char.map_or(0, u32::from)).to_le_bytes());
+        }
+        InputEvent::Mouse(event) => {
+            record[0] = INPUT_EVENT_MOUSE;
+            record[1] = event.buttons.bits();
+            record[2] = event.changed.bits();
+            record[3] = event.wheel as u8;
+            record[4..6].copy_from_slice(&event.dx.to_le_bytes());
+            record[6..8].copy_from_slice(&event.dy.to_le_bytes());
+        }
+    }
+}
+
+/// Device Filesystem (devfs)
+///
+/// Lists the devices known to the kernel as nodes of a single directory, e.g., `ttyS0` for the first serial port,
+/// `vda1` for the first partition of a virtio disk, `fb0` for the framebuffer and `input` for the input events. The
+/// nodes aren't stored anywhere, but reflect the devices as they're registered, and their reads and writes are passed
+/// on to the drivers.
+pub struct Devfs;
+
+static DEVFS: Devfs = Devfs;
+
+impl FileSystem for Devfs {
+    fn name(&self) -> &str {
+        "devfs"
+    }
+
+    fn root(&self) -> InodeId {
+        ROOT
+    }
+
+    fn lookup(&self, dir: InodeId, name: &str) -> Result<InodeId, FsError> {
+        if dir != ROOT {
+            return Device::find(dir).and(Err(FsError::NotADirectory));
+        }
+
+        Device::all()
+            .find(|device| device.name() == name)
+            .map(|device| device.inode())
+            .ok_or(FsError::NotFound)
+    }
+
+    fn metadata(&self, inode: InodeId) -> Result<Metadata, FsError> {
+        if inode == ROOT {
+            return Ok(Metadata {
+                links: 2,
+                ..Metadata::new(ROOT, FileType::Directory, 0, 0o755)
+            });
+        }
+
+        let device = Device::find(inode)?;
+        Ok(Metadata::new(
+            inode,
+            device.file_type(),
+            device.size(),
+            DEVICE_MODE,
+        ))
+    }
+
+    fn read(&self, inode: InodeId, offset: u64, buf: &mut [u8]) -> Result<usize, FsError> {
+        match inode {
+            ROOT => Err(FsError::IsADirectory),
+            inode => Device::find(inode)?.read(offset, buf),
+        }
+    }
+
+    fn write(&self, inode: InodeId, offset: u64, buf: &[u8]) -> Result<usize, FsError> {
+        match inode {
+            ROOT => Err(FsError::IsADirectory),
+            inode => Device::find(inode)?.write(offset, buf),
+        }
+    }
+
+    /// Leaves the devices alone, as they can't be resized, so they can still be opened for writing with `TRUNCATE`.
+    fn truncate(&self, inode: InodeId, _size: u64) -> Result<(), FsError> {
+        match inode {
+            ROOT => Err(FsError::IsADirectory),
+            inode => Device::find(inode).map(|_| ()),
+        }
+    }
+
+    fn read_dir(&self, dir: InodeId, position: u64) -> Result<Option<(DirEntry, u64)>, FsError> {
+        if dir != ROOT {
+            return Device::find(dir).and(Err(FsError::NotADirectory));
+        }
+
+        // The position is the number of the entry, which may shift as the devices come and go.
+        let Some(device) = Device::all().nth(position as usize) else {
+            return Ok(None);
+        };
+        let entry = DirEntry::new(device.inode(), device.file_type(), device.name())?;
+
+        Ok(Some((entry, position + 1)))
+    }
+
+    fn sync(&self) -> Result<(), FsError> {
+        Ok(cache::sync_all()?)
+    }
+}
+
+/// Mounts the devices at `/dev`.
+pub(super) fn init() -> Result<(), ()> {
+    super::mount("/dev", &DEVFS).map_err(|_| ())
+}
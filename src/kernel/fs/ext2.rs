@@ -0,0 +1,504 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use spin::Once;
+
+use crate::kernel::block::{cache, BlockDevice};
+
+use super::{DirEntry, FileSystem, FileType, FsError, InodeId, Metadata, MAX_NAME_LEN};
+
+/// The maximum number of ext2 volumes which can be in use at the same time.
+const MAX_VOLUMES: usize = 8;
+
+/// The superblock is always 1024 bytes into the volume, regardless of the block size.
+const SUPERBLOCK_OFFSET: u64 = 1024;
+const SUPERBLOCK_SIZE: usize = 1024;
+const MAGIC: u16 = 0xEF53;
+
+const GROUP_DESCRIPTOR_SIZE: u64 = 32;
+/// The size of an inode in revision 0, which later revisions may enlarge.
+const GOOD_OLD_INODE_SIZE: u16 = 128;
+const ROOT_INODE: InodeId = 2;
+
+/// The number of block pointers in an inode, of which the last three are indirect.
+const DIRECT_BLOCKS: u64 = 12;
+const SINGLY_INDIRECT: usize = 12;
+const DOUBLY_INDIRECT: usize = 13;
+const TRIPLY_INDIRECT: usize = 14;
+
+/// The length of the target of a symbolic link which is stored in the block pointers instead of a block.
+const FAST_SYMLINK_MAX_LEN: u64 = 60;
+
+/// The directory entries carry the type of the inode.
+const INCOMPAT_FILETYPE: u32 = 0x0002;
+/// The filesystem has to be recovered from its journal before it's consistent.
+const INCOMPAT_RECOVER: u32 = 0x0004;
+/// The groups are packed into flexible groups, which only affects where their metadata is placed.
+const INCOMPAT_FLEX_BG: u32 = 0x0200;
+const SUPPORTED_INCOMPAT: u32 = INCOMPAT_FILETYPE | INCOMPAT_FLEX_BG;
+
+/// The size of regular files has 64 bits, the upper half of which is stored in place of the directory ACL.
+const RO_COMPAT_LARGE_FILE: u32 = 0x0002;
+
+const TYPE_MASK: u16 = 0xF000;
+const TYPE_FIFO: u16 = 0x1000;
+const TYPE_CHAR: u16 = 0x2000;
+const TYPE_DIRECTORY: u16 = 0x4000;
+const TYPE_BLOCK: u16 = 0x6000;
+const TYPE_REGULAR: u16 = 0x8000;
+const TYPE_SYMLINK: u16 = 0xA000;
+const TYPE_SOCKET: u16 = 0xC000;
+
+/// The size of the fixed part of a directory entry, which is followed by the name.
+const DIR_ENTRY_HEADER_SIZE: usize = 8;
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(buf[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+/// The part of an inode which is used, which is the whole inode of revision 0.
+struct Inode {
+    mode: u16,
+    uid: u32,
+    gid: u32,
+    size: u64,
+    mtime: u32,
+    links: u16,
+    /// The number of 512-byte sectors allocated to the inode.
+    sectors: u32,
+    blocks: [u32; 15],
+    /// The raw block pointers, which hold the target of a fast symbolic link.
+    raw_blocks: [u8; 60],
+}
+
+impl Inode {
+    fn file_type(&self) -> Result<FileType, FsError> {
+        match self.mode & TYPE_MASK {
+            TYPE_REGULAR => Ok(FileType::Regular),
+            TYPE_DIRECTORY => Ok(FileType::Directory),
+            TYPE_SYMLINK => Ok(FileType::Symlink),
+            TYPE_CHAR => Ok(FileType::CharDevice),
+            TYPE_BLOCK => Ok(FileType::BlockDevice),
+            TYPE_FIFO => Ok(FileType::Fifo),
+            TYPE_SOCKET => Ok(FileType::Socket),
+            _ => Err(FsError::Corrupted),
+        }
+    }
+
+    /// Checks whether the inode is a symbolic link whose target is stored in the block pointers.
+    fn is_fast_symlink(&self) -> bool {
+        self.mode & TYPE_MASK == TYPE_SYMLINK
+            && self.size < FAST_SYMLINK_MAX_LEN
+            && self.sectors == 0
+    }
+}
+
+/// A used directory entry, whose name is read into a separate buffer.
+struct RawEntry {
+    inode: InodeId,
+    /// The type of the inode, if the entries carry it.
+    file_type: Option<FileType>,
+    name_len: usize,
+    /// The offset of the following entry.
+    next: u64,
+}
+
+/// Second Extended Filesystem (ext2)
+///
+/// The volume is divided into groups of blocks, each with its own bitmaps and table of inodes, which are described
+/// by the table of group descriptors following the superblock. The data of an inode is located through 12 direct
+/// block pointers, followed by a singly, a doubly and a triply indirect one. The filesystem is mounted read-only.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/Ext2
+pub struct Ext2Fs {
+    device: &'static dyn BlockDevice,
+    block_size: u64,
+    inode_count: u32,
+    inodes_per_group: u32,
+    inode_size: u64,
+    group_count: u32,
+    /// The position of the table of group descriptors.
+    descriptors_offset: u64,
+    incompat: u32,
+    ro_compat: u32,
+}
+
+static VOLUMES: [Once<Ext2Fs>; MAX_VOLUMES] = [const { Once::new() }; MAX_VOLUMES];
+static VOLUME_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+impl Ext2Fs {
+    fn parse(device: &'static dyn BlockDevice) -> Result<Self, FsError> {
+        let mut superblock = [0; SUPERBLOCK_SIZE];
+        cache::read_bytes(device, SUPERBLOCK_OFFSET, &mut superblock)?;
+        if read_u16(&superblock, 56) != MAGIC {
+            return Err(FsError::Unsupported);
+        }
+
+        let inode_count = read_u32(&superblock, 0);
+        let block_count = read_u32(&superblock, 4);
+        let first_data_block = read_u32(&superblock, 20) as u64;
+        let log_block_size = read_u32(&superblock, 24);
+        let blocks_per_group = read_u32(&superblock, 32);
+        let inodes_per_group = read_u32(&superblock, 40);
+        let revision = read_u32(&superblock, 76);
+
+        let (inode_size, incompat, ro_compat) = match revision {
+            0 => (GOOD_OLD_INODE_SIZE, 0, 0),
+            _ => (
+                read_u16(&superblock, 88),
+                read_u32(&superblock, 96),
+                read_u32(&superblock, 100),
+            ),
+        };
+
+        if log_block_size > 6
+            || blocks_per_group == 0
+            || inodes_per_group == 0
+            || first_data_block >= block_count as u64
+            || inode_size < GOOD_OLD_INODE_SIZE
+            || !inode_size.is_power_of_two()
+        {
+            return Err(FsError::Corrupted);
+        }
+
+        let unsupported = incompat & !SUPPORTED_INCOMPAT;
+        if unsupported != 0 {
+            let reason = match unsupported & INCOMPAT_RECOVER {
+                0 => "unsupported",
+                _ => "needing recovery from a journal",
+            };
+            log::warn!(
+                "{}: ext2 with {} features {:#x}",
+                device.name(),
+                reason,
+                unsupported
+            );
+            return Err(FsError::Unsupported);
+        }
+
+        let block_size = 1024 << log_block_size;
+        let group_count =
+            (block_count as u64 - first_data_block).div_ceil(blocks_per_group as u64) as u32;
+
+        Ok(Self {
+            device,
+            block_size,
+            inode_count,
+            inodes_per_group,
+            inode_size: inode_size as u64,
+            group_count,
+            descriptors_offset: (first_data_block + 1) * block_size,
+            incompat,
+            ro_compat,
+        })
+    }
+
+    pub fn device(&self) -> &'static dyn BlockDevice {
+        self.device
+    }
+
+    pub fn block_size(&self) -> u64 {
+        self.block_size
+    }
+
+    fn read_u32(&self, offset: u64) -> Result<u32, FsError> {
+        let mut buf = [0; 4];
+        cache::read_bytes(self.device, offset, &mut buf)?;
+
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn inode(&self, inode: InodeId) -> Result<Inode, FsError> {
+        if inode == 0 || inode > self.inode_count as u64 {
+            return Err(FsError::NotFound);
+        }
+
+        let group = (inode - 1) / self.inodes_per_group as u64;
+        let index = (inode - 1) % self.inodes_per_group as u64;
+        if group >= self.group_count as u64 {
+            return Err(FsError::Corrupted);
+        }
+        let table =
+            self.read_u32(self.descriptors_offset + group * GROUP_DESCRIPTOR_SIZE + 8)? as u64;
+
+        let mut raw = [0; GOOD_OLD_INODE_SIZE as usize];
+        cache::read_bytes(
+            self.device,
+            table * self.block_size + index * self.inode_size,
+            &mut raw,
+        )?;
+
+        let mode = read_u16(&raw, 0);
+        let mut size = read_u32(&raw, 4) as u64;
+        if mode & TYPE_MASK == TYPE_REGULAR && self.ro_compat & RO_COMPAT_LARGE_FILE != 0 {
+            size |= (read_u32(&raw, 108) as u64) << 32;
+        }
+
+        let mut blocks = [0; 15];
+        for (i, block) in blocks.iter_mut().enumerate() {
+            *block = read_u32(&raw, 40 + 4 * i);
+        }
+
+        Ok(Inode {
+            mode,
+            uid: read_u16(&raw, 2) as u32 | (read_u16(&raw, 120) as u32) << 16,
+            gid: read_u16(&raw, 24) as u32 | (read_u16(&raw, 122) as u32) << 16,
+            size,
+            mtime: read_u32(&raw, 16),
+            links: read_u16(&raw, 26),
+            sectors: read_u32(&raw, 28),
+            blocks,
+            raw_blocks: raw[40..100].try_into().unwrap(),
+        })
+    }
+
+    /// Finds the block holding a block of an inode, which is 0 for a hole in a sparse file.
+    fn map_block(&self, inode: &Inode, block: u64) -> Result<u64, FsError> {
+        let pointers = self.block_size / 4;
+        if block < DIRECT_BLOCKS {
+            return Ok(inode.blocks[block as usize] as u64);
+        }
+
+        // The pointers are followed from the top level, along the digits of the block in base `pointers`.
+        let mut rest = block - DIRECT_BLOCKS;
+        let (root, levels) = if rest < pointers {
+            (SINGLY_INDIRECT, 1)
+        } else if rest - pointers < pointers * pointers {
+            rest -= pointers;
+            (DOUBLY_INDIRECT, 2)
+        } else if rest - pointers - pointers * pointers < pointers * pointers * pointers {
+            rest -= pointers + pointers * pointers;
+            (TRIPLY_INDIRECT, 3)
+        } else {
+            return Err(FsError::InvalidOffset);
+        };
+
+        let mut current = inode.blocks[root] as u64;
+        for level in (0..levels).rev() {
+            if current == 0 {
+                return Ok(0);
+            }
+            let index = (rest / pointers.pow(level)) % pointers;
+            current = self.read_u32(current * self.block_size + index * 4)? as u64;
+        }
+
+        Ok(current)
+    }
+
+    /// Reads the data of an inode from an offset, and returns the number of bytes read.
+    fn read_data(&self, inode: &Inode, offset: u64, buf: &mut [u8]) -> Result<usize, FsError> {
+        if offset >= inode.size {
+            return Ok(0);
+        }
+        let len = buf.len().min((inode.size - offset) as usize);
+
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done as u64;
+            let start = pos % self.block_size;
+            let chunk = ((self.block_size - start) as usize).min(len - done);
+            let target = &mut buf[done..done + chunk];
+
+            match self.map_block(inode, pos / self.block_size)? {
+                0 => target.fill(0),
+                block => cache::read_bytes(self.device, block * self.block_size + start, target)?,
+            }
+            done += chunk;
+        }
+
+        Ok(len)
+    }
+
+    /// Reads the directory entry at an offset of a directory, and returns it along with the offset of the next one.
+    ///
+    /// The entries which are unused, as well as `.` and `..`, are skipped.
+    fn dir_entry(
+        &self,
+        dir: &Inode,
+        mut offset: u64,
+        name_buf: &mut [u8; MAX_NAME_LEN],
+    ) -> Result<Option<RawEntry>, FsError> {
+        while offset < dir.size {
+            let mut header = [0; DIR_ENTRY_HEADER_SIZE];
+            if self.read_data(dir, offset, &mut header)? < DIR_ENTRY_HEADER_SIZE {
+                return Err(FsError::Corrupted);
+            }
+
+            let inode = read_u32(&header, 0) as InodeId;
+            let record_len = read_u16(&header, 4) as u64;
+            // Without the file types, the length of the name has 16 bits.
+            let (name_len, file_type) = match self.incompat & INCOMPAT_FILETYPE {
+                0 => (read_u16(&header, 6) as usize, None),
+                _ => (header[6] as usize, Some(header[7])),
+            };
+            if record_len < DIR_ENTRY_HEADER_SIZE as u64 || name_len > MAX_NAME_LEN {
+                return Err(FsError::Corrupted);
+            }
+
+            let name = &mut name_buf[..name_len];
+            self.read_data(dir, offset + DIR_ENTRY_HEADER_SIZE as u64, name)?;
+            let next = offset + record_len;
+            if inode == 0 || name == b"." || name == b".." {
+                offset = next;
+                continue;
+            }
+
+            let file_type = file_type.and_then(|file_type| match file_type {
+                1 => Some(FileType::Regular),
+                2 => Some(FileType::Directory),
+                3 => Some(FileType::CharDevice),
+                4 => Some(FileType::BlockDevice),
+                5 => Some(FileType::Fifo),
+                6 => Some(FileType::Socket),
+                7 => Some(FileType::Symlink),
+                _ => None,
+            });
+
+            return Ok(Some(RawEntry {
+                inode,
+                file_type,
+                name_len,
+                next,
+            }));
+        }
+
+        Ok(None)
+    }
+
+    fn dir(&self, inode: InodeId) -> Result<Inode, FsError> {
+        let dir = self.inode(inode)?;
+        match dir.mode & TYPE_MASK {
+            TYPE_DIRECTORY => Ok(dir),
+            _ => Err(FsError::NotADirectory),
+        }
+    }
+}
+
+impl FileSystem for Ext2Fs {
+    fn name(&self) -> &str {
+        "ext2"
+    }
+
+    fn root(&self) -> InodeId {
+        ROOT_INODE
+    }
+
+    fn lookup(&self, dir: InodeId, name: &str) -> Result<InodeId, FsError> {
+        let dir = self.dir(dir)?;
+        let mut name_buf = [0; MAX_NAME_LEN];
+        let mut offset = 0;
+        while let Some(entry) = self.dir_entry(&dir, offset, &mut name_buf)? {
+            if &name_buf[..entry.name_len] == name.as_bytes() {
+                return Ok(entry.inode);
+            }
+            offset = entry.next;
+        }
+
+        Err(FsError::NotFound)
+    }
+
+    fn metadata(&self, inode: InodeId) -> Result<Metadata, FsError> {
+        let node = self.inode(inode)?;
+
+        Ok(Metadata {
+            links: node.links as u32,
+            uid: node.uid,
+            gid: node.gid,
+            mtime: node.mtime as u64,
+            ..Metadata::new(inode, node.file_type()?, node.size, node.mode & 0o7777)
+        })
+    }
+
+    fn read(&self, inode: InodeId, offset: u64, buf: &mut [u8]) -> Result<usize, FsError> {
+        let node = self.inode(inode)?;
+        match node.file_type()? {
+            FileType::Directory => Err(FsError::IsADirectory),
+            _ => self.read_data(&node, offset, buf),
+        }
+    }
+
+    fn read_dir(&self, dir: InodeId, position: u64) -> Result<Option<(DirEntry, u64)>, FsError> {
+        let dir = self.dir(dir)?;
+        let mut name_buf = [0; MAX_NAME_LEN];
+        let Some(entry) = self.dir_entry(&dir, position, &mut name_buf)? else {
+            return Ok(None);
+        };
+
+        // Without the file types in the entries, the inode itself tells it.
+        let file_type = match entry.file_type {
+            Some(file_type) => file_type,
+            None => self.inode(entry.inode)?.file_type()?,
+        };
+        let name =
+            core::str::from_utf8(&name_buf[..entry.name_len]).map_err(|_| FsError::Corrupted)?;
+
+        Ok(Some((
+            DirEntry::new(entry.inode, file_type, name)?,
+            entry.next,
+        )))
+    }
+
+    fn read_link(&self, inode: InodeId, buf: &mut [u8]) -> Result<usize, FsError> {
+        let node = self.inode(inode)?;
+        if node.file_type()? != FileType::Symlink {
+            return Err(FsError::InvalidPath);
+        }
+
+        let len = node.size as usize;
+        let target = buf.get_mut(..len).ok_or(FsError::NameTooLong)?;
+        match node.is_fast_symlink() {
+            true => target.copy_from_slice(&node.raw_blocks[..len]),
+            false => {
+                self.read_data(&node, 0, target)?;
+            }
+        }
+
+        Ok(len)
+    }
+}
+
+/// Detects an ext2 volume on a block device, and returns it for mounting.
+///
+/// This fails with `Unsupported` if the device doesn't hold an ext2 volume, or if it uses features which aren't
+/// supported, such as extents.
+pub fn probe(device: &'static dyn BlockDevice) -> Result<&'static Ext2Fs, FsError> {
+    let fs = Ext2Fs::parse(device)?;
+
+    let index = VOLUME_COUNT.fetch_add(1, Ordering::Relaxed);
+    let slot = VOLUMES.get(index).ok_or(FsError::NoSpace)?;
+    let fs = slot.call_once(|| fs);
+
+    log::info!(
+        "{}: ext2 volume with {} groups of blocks of {} bytes",
+        device.name(),
+        fs.group_count,
+        fs.block_size
+    );
+
+    Ok(fs)
+}
@@ -0,0 +1,450 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::char;
+use core::str;
+
+use super::super::{FileType, FsError, MAX_NAME_LEN};
+
+/// The size of a directory entry.
+pub const ENTRY_SIZE: usize = 32;
+
+pub const ATTR_READ_ONLY: u8 = 0x01;
+pub const ATTR_HIDDEN: u8 = 0x02;
+pub const ATTR_SYSTEM: u8 = 0x04;
+pub const ATTR_VOLUME_ID: u8 = 0x08;
+pub const ATTR_DIRECTORY: u8 = 0x10;
+pub const ATTR_ARCHIVE: u8 = 0x20;
+/// The combination of attributes which marks an entry holding a part of a long name.
+pub const ATTR_LONG_NAME: u8 = ATTR_READ_ONLY | ATTR_HIDDEN | ATTR_SYSTEM | ATTR_VOLUME_ID;
+
+/// The first byte of an entry which has been deleted.
+pub const ENTRY_FREE: u8 = 0xE5;
+/// The first byte of the entry after the last one in use.
+pub const ENTRY_END: u8 = 0x00;
+/// The first byte of a short name beginning with 0xE5, which would mark the entry as free otherwise.
+const ENTRY_KANJI: u8 = 0x05;
+
+/// The flags of the reserved byte which mark the base and the extension of a short name as lowercase.
+const NTRES_LOWER_BASE: u8 = 0x08;
+const NTRES_LOWER_EXT: u8 = 0x10;
+
+/// The flag of the sequence number which marks the last entry of a long name, which comes first.
+const LFN_LAST: u8 = 0x40;
+const LFN_CHARS_PER_ENTRY: usize = 13;
+/// The offsets of the UCS-2 characters within an entry of a long name.
+const LFN_CHAR_OFFSETS: [usize; LFN_CHARS_PER_ENTRY] =
+    [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+/// The maximum number of characters of a long name.
+const LFN_MAX_CHARS: usize = 255;
+pub const LFN_MAX_ENTRIES: usize = LFN_MAX_CHARS.div_ceil(LFN_CHARS_PER_ENTRY);
+
+/// The date written into new entries, which is the epoch of FAT (1980-01-01), as there's no clock to read the time
+/// from.
+const DEFAULT_DATE: u16 = (1 << 5) | 1;
+
+/// The characters which may appear in a short name besides the uppercase letters and the digits.
+const SHORT_NAME_SPECIAL: &[u8] = b"$%'-_@~`!(){}^#&";
+/// The characters which may not appear in a long name besides the control characters.
+const LONG_NAME_INVALID: &[char] = &['"', '*', '/', ':', '<', '>', '?', '\\', '|'];
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(buf[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+/// An entry of a directory with a short (8.3) name, which holds the attributes of a file or a directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ShortEntry(pub [u8; ENTRY_SIZE]);
+
+impl ShortEntry {
+    pub fn new(name: [u8; 11], attributes: u8, cluster: u32) -> Self {
+        let mut entry = ShortEntry([0; ENTRY_SIZE]);
+        entry.0[..11].copy_from_slice(&name);
+        entry.0[11] = attributes;
+        // The dates of creation, last access and last modification.
+        for offset in [16, 18, 24] {
+            entry.0[offset..offset + 2].copy_from_slice(&DEFAULT_DATE.to_le_bytes());
+        }
+        entry.set_cluster(cluster);
+
+        entry
+    }
+
+    pub fn name(&self) -> [u8; 11] {
+        self.0[..11].try_into().unwrap()
+    }
+
+    pub fn attributes(&self) -> u8 {
+        self.0[11]
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.attributes() & ATTR_DIRECTORY != 0
+    }
+
+    pub fn file_type(&self) -> FileType {
+        match self.is_dir() {
+            true => FileType::Directory,
+            false => FileType::Regular,
+        }
+    }
+
+    /// Checks whether the entry is `.` or `..`.
+    pub fn is_dot(&self) -> bool {
+        self.0[0] == b'.'
+    }
+
+    pub fn cluster(&self) -> u32 {
+        ((read_u16(&self.0, 20) as u32) << 16) | read_u16(&self.0, 26) as u32
+    }
+
+    pub fn set_cluster(&mut self, cluster: u32) {
+        self.0[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+        self.0[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+    }
+
+    pub fn size(&self) -> u32 {
+        read_u32(&self.0, 28)
+    }
+
+    pub fn set_size(&mut self, size: u32) {
+        self.0[28..32].copy_from_slice(&size.to_le_bytes());
+    }
+
+    /// Returns the time of the last modification in seconds since the Unix epoch.
+    pub fn mtime(&self) -> u64 {
+        let time = read_u16(&self.0, 22) as i64;
+        let date = read_u16(&self.0, 24) as i64;
+        let (year, month, day) = (1980 + (date >> 9), (date >> 5) & 0xF, date & 0x1F);
+        if !(1..=12).contains(&month) || day == 0 {
+            return 0;
+        }
+
+        // The days since the Unix epoch, counted in years which begin in March, so the leap day comes last.
+        let year = if month <= 2 { year - 1 } else { year };
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146097 + day_of_era - 719468;
+
+        let seconds = (time >> 11) * 3600 + ((time >> 5) & 0x3F) * 60 + (time & 0x1F) * 2;
+        (days * 86400 + seconds).max(0) as u64
+    }
+
+    /// Returns the permission bits, which lack the write permissions for a read-only entry.
+    pub fn mode(&self) -> u16 {
+        let mode = if self.is_dir() { 0o755 } else { 0o644 };
+        match self.attributes() & ATTR_READ_ONLY != 0 {
+            true => mode & !0o222,
+            false => mode,
+        }
+    }
+
+    /// Formats the short name as `NAME.EXT`, honoring the flags which mark its parts as lowercase.
+    pub fn display_name<'a>(&self, buf: &'a mut [u8; 12]) -> &'a str {
+        let flags = self.0[12];
+        let mut len = 0;
+        let mut push = |byte: u8, lower: bool| {
+            let byte = match byte {
+                _ if !byte.is_ascii() => b'?',
+                _ if lower => byte.to_ascii_lowercase(),
+                _ => byte,
+            };
+            buf[len] = byte;
+            len += 1;
+        };
+
+        let name = self.name();
+        let base = name[..8].trim_ascii_end();
+        let ext = name[8..].trim_ascii_end();
+        for (i, byte) in base.iter().enumerate() {
+            let byte = if i == 0 && *byte == ENTRY_KANJI {
+                ENTRY_FREE
+            } else {
+                *byte
+            };
+            push(byte, flags & NTRES_LOWER_BASE != 0);
+        }
+        if !ext.is_empty() {
+            push(b'.', false);
+            for byte in ext {
+                push(*byte, flags & NTRES_LOWER_EXT != 0);
+            }
+        }
+
+        str::from_utf8(&buf[..len]).unwrap_or_default()
+    }
+}
+
+/// Computes the checksum of a short name, which the entries of the long name preceding it carry.
+pub fn checksum(name: &[u8; 11]) -> u8 {
+    name.iter()
+        .fold(0u8, |sum, byte| sum.rotate_right(1).wrapping_add(*byte))
+}
+
+/// Collects the parts of a long name, which are stored in reverse order before the short entry they belong to.
+pub struct LongName {
+    chars: [u16; LFN_MAX_ENTRIES * LFN_CHARS_PER_ENTRY],
+    checksum: u8,
+    /// The sequence number of the entry which was seen last, which counts down to 1.
+    sequence: u8,
+    /// The index of the first entry of the long name in its directory.
+    pub start: u32,
+}
+
+impl LongName {
+    pub const fn new() -> Self {
+        Self {
+            chars: [0; LFN_MAX_ENTRIES * LFN_CHARS_PER_ENTRY],
+            checksum: 0,
+            sequence: 0,
+            start: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.sequence = 0;
+    }
+
+    /// Adds an entry of a long name, which is dropped if it's out of sequence.
+    pub fn push(&mut self, index: u32, entry: &[u8; ENTRY_SIZE]) {
+        let sequence = entry[0] & !LFN_LAST;
+        let is_last = entry[0] & LFN_LAST != 0;
+        if sequence == 0 || sequence as usize > LFN_MAX_ENTRIES {
+            self.sequence = 0;
+            return;
+        }
+
+        if is_last {
+            self.checksum = entry[13];
+            self.start = index;
+            self.chars.fill(0);
+        } else if self.sequence != sequence + 1 || self.checksum != entry[13] {
+            self.sequence = 0;
+            return;
+        }
+        self.sequence = sequence;
+
+        let start = (sequence as usize - 1) * LFN_CHARS_PER_ENTRY;
+        for (i, offset) in LFN_CHAR_OFFSETS.iter().enumerate() {
+            self.chars[start + i] = read_u16(entry, *offset);
+        }
+    }
+
+    /// Checks whether the long name is complete, and belongs to the short entry which follows it.
+    pub fn belongs_to(&self, short: &ShortEntry) -> bool {
+        self.sequence == 1 && self.checksum == checksum(&short.name())
+    }
+
+    /// Decodes the long name into UTF-8 if it belongs to a short entry.
+    pub fn decode<'a>(
+        &self,
+        short: &ShortEntry,
+        buf: &'a mut [u8; MAX_NAME_LEN],
+    ) -> Option<&'a str> {
+        if !self.belongs_to(short) {
+            return None;
+        }
+
+        let len = self
+            .chars
+            .iter()
+            .position(|c| *c == 0x0000 || *c == 0xFFFF)
+            .unwrap_or(self.chars.len());
+        let mut written = 0;
+        for c in char::decode_utf16(self.chars[..len].iter().copied()) {
+            let c = c.unwrap_or(char::REPLACEMENT_CHARACTER);
+            let target = buf.get_mut(written..written + c.len_utf8())?;
+            c.encode_utf8(target);
+            written += c.len_utf8();
+        }
+
+        str::from_utf8(&buf[..written]).ok()
+    }
+}
+
+/// Builds the entries of a long name for a short name, in the order they're stored in.
+pub fn long_name_entries(
+    name: &str,
+    short_name: &[u8; 11],
+    entries: &mut [[u8; ENTRY_SIZE]; LFN_MAX_ENTRIES],
+) -> usize {
+    let mut chars = [0xFFFF; LFN_MAX_ENTRIES * LFN_CHARS_PER_ENTRY];
+    let mut len = 0;
+    for c in name.encode_utf16() {
+        chars[len] = c;
+        len += 1;
+    }
+    // The name is terminated by a NUL character, unless it fills its last entry.
+    if len % LFN_CHARS_PER_ENTRY != 0 {
+        chars[len] = 0x0000;
+    }
+
+    let count = len.div_ceil(LFN_CHARS_PER_ENTRY);
+    let checksum = checksum(short_name);
+    for (i, entry) in entries[..count].iter_mut().enumerate() {
+        let sequence = count - i;
+        *entry = [0; ENTRY_SIZE];
+        entry[0] = sequence as u8 | if i == 0 { LFN_LAST } else { 0 };
+        entry[11] = ATTR_LONG_NAME;
+        entry[13] = checksum;
+
+        let start = (sequence - 1) * LFN_CHARS_PER_ENTRY;
+        for (j, offset) in LFN_CHAR_OFFSETS.iter().enumerate() {
+            entry[*offset..*offset + 2].copy_from_slice(&chars[start + j].to_le_bytes());
+        }
+    }
+
+    count
+}
+
+/// Checks whether a name can be stored as a long name.
+pub fn validate(name: &str) -> Result<(), FsError> {
+    if name.encode_utf16().count() > LFN_MAX_CHARS {
+        return Err(FsError::NameTooLong);
+    }
+    if name
+        .chars()
+        .any(|c| c.is_control() || LONG_NAME_INVALID.contains(&c))
+        || name.ends_with(['.', ' '])
+    {
+        return Err(FsError::InvalidPath);
+    }
+
+    Ok(())
+}
+
+fn is_short_char(byte: u8) -> bool {
+    byte.is_ascii_uppercase() || byte.is_ascii_digit() || SHORT_NAME_SPECIAL.contains(&byte)
+}
+
+/// Converts a name into a short name if it's one already, possibly in lowercase, and returns it along with the flags
+/// of the reserved byte.
+pub fn exact_short_name(name: &str) -> Option<([u8; 11], u8)> {
+    let (base, ext) = name.rsplit_once('.').unwrap_or((name, ""));
+    if base.is_empty() || base.len() > 8 || ext.len() > 3 || (ext.is_empty() && name.ends_with('.'))
+    {
+        return None;
+    }
+
+    let mut short = [b' '; 11];
+    let mut flags = 0;
+    for (part, range, lower_flag) in [
+        (base, 0..8, NTRES_LOWER_BASE),
+        (ext, 8..11, NTRES_LOWER_EXT),
+    ] {
+        let lower = part.bytes().any(|byte| byte.is_ascii_lowercase());
+        let upper = part.bytes().any(|byte| byte.is_ascii_uppercase());
+        if lower && upper {
+            return None;
+        }
+        if lower {
+            flags |= lower_flag;
+        }
+
+        for (target, byte) in short[range].iter_mut().zip(part.bytes()) {
+            *target = byte.to_ascii_uppercase();
+            if !is_short_char(*target) {
+                return None;
+            }
+        }
+    }
+    if short[0] == ENTRY_FREE {
+        short[0] = ENTRY_KANJI;
+    }
+
+    Some((short, flags))
+}
+
+/// Derives a short name from a long name, which is made unique by a numeric tail, e.g. `LONGNA~1.TXT`.
+pub fn generated_short_name(name: &str, number: u32) -> [u8; 11] {
+    let name = name.trim_start_matches(['.', ' ']);
+    let (base, ext) = match name.rsplit_once('.') {
+        Some((base, ext)) if !base.is_empty() => (base, ext),
+        _ => (name, ""),
+    };
+    let convert = |byte: u8| {
+        let byte = byte.to_ascii_uppercase();
+        if is_short_char(byte) {
+            byte
+        } else {
+            b'_'
+        }
+    };
+
+    let mut short = [b' '; 11];
+    for (target, byte) in short[8..]
+        .iter_mut()
+        .zip(ext.bytes().filter(|byte| *byte != b' '))
+    {
+        *target = convert(byte);
+    }
+
+    // The tail is formatted backwards, beginning with the last digit.
+    let mut tail = [0; 8];
+    let mut tail_len = 0;
+    let mut n = number;
+    while n > 0 {
+        tail[tail_len] = b'0' + (n % 10) as u8;
+        tail_len += 1;
+        n /= 10;
+    }
+    tail[tail_len] = b'~';
+    tail_len += 1;
+
+    let mut len = 0;
+    for byte in base
+        .bytes()
+        .filter(|byte| *byte != b' ' && *byte != b'.')
+        .take(8 - tail_len)
+    {
+        short[len] = convert(byte);
+        len += 1;
+    }
+    for byte in tail[..tail_len].iter().rev() {
+        short[len] = *byte;
+        len += 1;
+    }
+    if short[0] == ENTRY_FREE {
+        short[0] = ENTRY_KANJI;
+    }
+
+    short
+}
+
+/// Returns the entries `.` and `..` of a new directory.
+pub fn dot_entries(cluster: u32, parent_cluster: u32) -> [ShortEntry; 2] {
+    let mut dot = [b' '; 11];
+    dot[0] = b'.';
+    let mut dot_dot = dot;
+    dot_dot[1] = b'.';
+
+    [
+        ShortEntry::new(dot, ATTR_DIRECTORY, cluster),
+        ShortEntry::new(dot_dot, ATTR_DIRECTORY, parent_cluster),
+    ]
+}
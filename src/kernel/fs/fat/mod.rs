@@ -0,0 +1,989 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use spin::Once;
+
+use crate::kernel::block::{cache, BlockDevice};
+use crate::kernel::sync::Mutex;
+
+use self::dir::{LongName, ShortEntry, ENTRY_END, ENTRY_FREE, ENTRY_SIZE, LFN_MAX_ENTRIES};
+use super::{DirEntry, FileSystem, FileType, FsError, InodeId, Metadata, MAX_NAME_LEN};
+
+mod dir;
+
+/// The maximum number of FAT volumes which can be in use at the same time.
+const MAX_VOLUMES: usize = 8;
+
+const BOOT_SECTOR_SIZE: usize = 512;
+const BOOT_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+const BOOT_SIGNATURE_OFFSET: usize = 510;
+
+/// The largest number of clusters of a FAT12 volume, and of a FAT16 volume.
+const FAT12_MAX_CLUSTERS: u32 = 4084;
+const FAT16_MAX_CLUSTERS: u32 = 65524;
+
+/// The number of the first cluster of the data region, as the first two entries of the FAT are reserved.
+const FIRST_CLUSTER: u32 = 2;
+/// The bits of a FAT32 entry which hold the number of a cluster, as the others are reserved.
+const FAT32_CLUSTER_MASK: u32 = 0x0FFF_FFFF;
+
+const FSINFO_LEAD_SIGNATURE: u32 = 0x4161_5252;
+const FSINFO_STRUCT_SIGNATURE: u32 = 0x6141_7272;
+const FSINFO_STRUCT_SIGNATURE_OFFSET: u64 = 484;
+const FSINFO_FREE_COUNT_OFFSET: u64 = 488;
+const FSINFO_NEXT_FREE_OFFSET: u64 = 492;
+/// The value of a field of the FSInfo structure which isn't known.
+const FSINFO_UNKNOWN: u32 = 0xFFFF_FFFF;
+
+/// The inode number of the root directory. The other inodes are numbered by the position of their short entry on the
+/// device, which is never at the very beginning, as that's where the boot sector is.
+const ROOT_INODE: InodeId = 0;
+
+/// The largest suffix of a generated short name, e.g. `LONGNA~1`.
+const MAX_SHORT_NAME_SUFFIX: u32 = 999_999;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(buf[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+/// The location of the entries of a directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Dir {
+    /// The root directory of FAT12 and FAT16, which is a fixed area before the data region.
+    FixedRoot,
+    /// A directory stored in a chain of clusters, which begins with the given one.
+    Chain(u32),
+}
+
+/// A cursor over the slots of a directory, each of which holds an entry.
+struct Slots<'a> {
+    fs: &'a FatFs,
+    dir: Dir,
+    index: u32,
+    /// The current cluster of a directory stored in clusters, and its position in the chain.
+    cluster: u32,
+    position: u32,
+}
+
+impl<'a> Slots<'a> {
+    /// Creates a cursor positioned at a slot, whose cluster is reached once it's read.
+    fn new(fs: &'a FatFs, dir: Dir, index: u32) -> Self {
+        let cluster = match dir {
+            Dir::FixedRoot => 0,
+            Dir::Chain(first) => first,
+        };
+
+        Self {
+            fs,
+            dir,
+            index,
+            cluster,
+            position: 0,
+        }
+    }
+
+    /// Returns the index and the position on the device of the current slot, and moves to the next one.
+    fn next(&mut self) -> Result<Option<(u32, u64)>, FsError> {
+        let index = self.index;
+        let offset = match self.dir {
+            Dir::FixedRoot if index >= self.fs.root_entries => return Ok(None),
+            Dir::FixedRoot => self.fs.root_offset + index as u64 * ENTRY_SIZE as u64,
+            Dir::Chain(_) => {
+                let per_cluster = self.fs.entries_per_cluster();
+                while self.position < index / per_cluster {
+                    match self.fs.next_cluster(self.cluster)? {
+                        Some(next) => self.cluster = next,
+                        None => return Ok(None),
+                    }
+                    self.position += 1;
+                }
+                self.fs.cluster_offset(self.cluster)
+                    + (index % per_cluster) as u64 * ENTRY_SIZE as u64
+            }
+        };
+        self.index += 1;
+
+        Ok(Some((index, offset)))
+    }
+
+    /// Returns the next entry in use, along with its long name, while skipping `.` and `..`.
+    fn next_entry(&mut self, long_name: &mut LongName) -> Result<Option<Found>, FsError> {
+        long_name.reset();
+        while let Some((index, offset)) = self.next()? {
+            let raw = self.fs.read_entry(offset)?;
+            match raw[0] {
+                ENTRY_END => return Ok(None),
+                ENTRY_FREE => long_name.reset(),
+                _ if raw[11] & dir::ATTR_LONG_NAME == dir::ATTR_LONG_NAME => {
+                    long_name.push(index, &raw)
+                }
+                _ if raw[11] & dir::ATTR_VOLUME_ID != 0 || ShortEntry(raw).is_dot() => {
+                    long_name.reset()
+                }
+                _ => {
+                    let entry = ShortEntry(raw);
+                    let start = match long_name.belongs_to(&entry) {
+                        true => long_name.start,
+                        false => index,
+                    };
+
+                    return Ok(Some(Found {
+                        entry,
+                        offset,
+                        start,
+                        index,
+                    }));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// An entry in use of a directory.
+struct Found {
+    entry: ShortEntry,
+    /// The position of the short entry on the device, which is the inode number.
+    offset: u64,
+    /// The index of the first slot of the entry, which is the first entry of its long name if it has one.
+    start: u32,
+    /// The index of the slot of the short entry.
+    index: u32,
+}
+
+struct State {
+    /// The cluster at which the search for free clusters begins.
+    next_free: u32,
+    /// Whether the FSInfo structure has to be updated.
+    fsinfo_dirty: bool,
+}
+
+/// File Allocation Table (FAT)
+///
+/// A FAT12, FAT16 or FAT32 volume, whose data region is divided into clusters. The table maps each cluster to the
+/// next one of the file it belongs to, and the directories are files of 32-byte entries, which may be preceded by
+/// entries holding a long name (VFAT).
+///
+/// OS Dev Wiki: https://wiki.osdev.org/FAT
+pub struct FatFs {
+    device: &'static dyn BlockDevice,
+    fat_type: FatType,
+    cluster_size: u32,
+    /// The position of the first copy of the table, and the size of each copy.
+    fat_offset: u64,
+    fat_size: u64,
+    fat_count: u32,
+    /// The fixed root directory of FAT12 and FAT16.
+    root_offset: u64,
+    root_entries: u32,
+    /// The first cluster of the root directory of FAT32.
+    root_cluster: u32,
+    data_offset: u64,
+    cluster_count: u32,
+    fsinfo_offset: Option<u64>,
+    // The changes are serialized, as they involve several entries of the table and the directories.
+    state: Mutex<State>,
+}
+
+static VOLUMES: [Once<FatFs>; MAX_VOLUMES] = [const { Once::new() }; MAX_VOLUMES];
+static VOLUME_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+impl FatFs {
+    /// Parses the BIOS parameter block (BPB) in the boot sector.
+    fn parse(device: &'static dyn BlockDevice) -> Result<Self, FsError> {
+        let mut boot = [0; BOOT_SECTOR_SIZE];
+        cache::read_bytes(device, 0, &mut boot)?;
+        if boot[BOOT_SIGNATURE_OFFSET..BOOT_SIGNATURE_OFFSET + 2] != BOOT_SIGNATURE {
+            return Err(FsError::Unsupported);
+        }
+
+        let bytes_per_sector = read_u16(&boot, 11) as u64;
+        let sectors_per_cluster = boot[13] as u64;
+        let reserved_sectors = read_u16(&boot, 14) as u64;
+        let fat_count = boot[16] as u32;
+        let root_entries = read_u16(&boot, 17) as u32;
+        let total_sectors = match read_u16(&boot, 19) {
+            0 => read_u32(&boot, 32) as u64,
+            count => count as u64,
+        };
+        let fat_sectors = match read_u16(&boot, 22) {
+            0 => read_u32(&boot, 36) as u64,
+            count => count as u64,
+        };
+
+        let valid = matches!(bytes_per_sector, 512 | 1024 | 2048 | 4096)
+            && sectors_per_cluster.is_power_of_two()
+            && reserved_sectors != 0
+            && fat_count != 0
+            && fat_sectors != 0;
+        if !valid {
+            return Err(FsError::Unsupported);
+        }
+
+        let root_sectors = (root_entries as u64 * ENTRY_SIZE as u64).div_ceil(bytes_per_sector);
+        let data_sector = reserved_sectors + fat_count as u64 * fat_sectors + root_sectors;
+        let cluster_count = (total_sectors
+            .checked_sub(data_sector)
+            .ok_or(FsError::Corrupted)?
+            / sectors_per_cluster)
+            .min(FAT32_CLUSTER_MASK as u64 - 16) as u32;
+
+        // The type is determined by the number of clusters alone.
+        let fat_type = if cluster_count <= FAT12_MAX_CLUSTERS {
+            FatType::Fat12
+        } else if cluster_count <= FAT16_MAX_CLUSTERS {
+            FatType::Fat16
+        } else {
+            FatType::Fat32
+        };
+
+        let mut fs = Self {
+            device,
+            fat_type,
+            cluster_size: (bytes_per_sector * sectors_per_cluster) as u32,
+            fat_offset: reserved_sectors * bytes_per_sector,
+            fat_size: fat_sectors * bytes_per_sector,
+            fat_count,
+            root_offset: (reserved_sectors + fat_count as u64 * fat_sectors) * bytes_per_sector,
+            root_entries,
+            root_cluster: 0,
+            data_offset: data_sector * bytes_per_sector,
+            cluster_count,
+            fsinfo_offset: None,
+            state: Mutex::new(State {
+                next_free: FIRST_CLUSTER,
+                fsinfo_dirty: false,
+            }),
+        };
+
+        if fat_type == FatType::Fat32 {
+            fs.root_cluster = read_u32(&boot, 44) & FAT32_CLUSTER_MASK;
+            if !fs.is_valid_cluster(fs.root_cluster) {
+                return Err(FsError::Corrupted);
+            }
+
+            let fsinfo_offset = read_u16(&boot, 48) as u64 * bytes_per_sector;
+            if fsinfo_offset != 0 && fs.read_u32(fsinfo_offset)? == FSINFO_LEAD_SIGNATURE {
+                fs.fsinfo_offset = Some(fsinfo_offset);
+                let next_free = fs.read_u32(fsinfo_offset + FSINFO_NEXT_FREE_OFFSET)?;
+                if fs.is_valid_cluster(next_free) {
+                    fs.state.get_mut().next_free = next_free;
+                }
+            }
+        } else if root_entries == 0 {
+            return Err(FsError::Corrupted);
+        }
+
+        Ok(fs)
+    }
+
+    pub fn fat_type(&self) -> FatType {
+        self.fat_type
+    }
+
+    pub fn device(&self) -> &'static dyn BlockDevice {
+        self.device
+    }
+
+    fn read_u32(&self, offset: u64) -> Result<u32, FsError> {
+        let mut buf = [0; 4];
+        cache::read_bytes(self.device, offset, &mut buf)?;
+
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn write_u32(&self, offset: u64, value: u32) -> Result<(), FsError> {
+        Ok(cache::write_bytes(
+            self.device,
+            offset,
+            &value.to_le_bytes(),
+        )?)
+    }
+
+    fn check_writable(&self) -> Result<(), FsError> {
+        match self.device.info().read_only {
+            true => Err(FsError::ReadOnly),
+            false => Ok(()),
+        }
+    }
+
+    fn entries_per_cluster(&self) -> u32 {
+        self.cluster_size / ENTRY_SIZE as u32
+    }
+
+    fn is_valid_cluster(&self, cluster: u32) -> bool {
+        (FIRST_CLUSTER..FIRST_CLUSTER + self.cluster_count).contains(&cluster)
+    }
+
+    fn cluster_offset(&self, cluster: u32) -> u64 {
+        self.data_offset + (cluster - FIRST_CLUSTER) as u64 * self.cluster_size as u64
+    }
+
+    /// Returns the entry of a cluster in the table.
+    fn fat_entry(&self, cluster: u32) -> Result<u32, FsError> {
+        let mut buf = [0; 4];
+        let value = match self.fat_type {
+            // The entries are 12 bits long, so two of them share three bytes.
+            FatType::Fat12 => {
+                let offset = cluster as u64 + cluster as u64 / 2;
+                cache::read_bytes(self.device, self.fat_offset + offset, &mut buf[..2])?;
+                let value = u16::from_le_bytes([buf[0], buf[1]]) as u32;
+                match cluster % 2 {
+                    0 => value & 0xFFF,
+                    _ => value >> 4,
+                }
+            }
+            FatType::Fat16 => {
+                let offset = cluster as u64 * 2;
+                cache::read_bytes(self.device, self.fat_offset + offset, &mut buf[..2])?;
+                u16::from_le_bytes([buf[0], buf[1]]) as u32
+            }
+            FatType::Fat32 => {
+                self.read_u32(self.fat_offset + cluster as u64 * 4)? & FAT32_CLUSTER_MASK
+            }
+        };
+
+        Ok(value)
+    }
+
+    /// Sets the entry of a cluster in every copy of the table.
+    fn set_fat_entry(&self, cluster: u32, value: u32) -> Result<(), FsError> {
+        for copy in 0..self.fat_count as u64 {
+            let fat_offset = self.fat_offset + copy * self.fat_size;
+            match self.fat_type {
+                FatType::Fat12 => {
+                    let offset = fat_offset + cluster as u64 + cluster as u64 / 2;
+                    let mut buf = [0; 2];
+                    cache::read_bytes(self.device, offset, &mut buf)?;
+                    let old = u16::from_le_bytes(buf);
+                    let new = match cluster % 2 {
+                        0 => (old & 0xF000) | (value as u16 & 0xFFF),
+                        _ => (old & 0x000F) | ((value as u16) << 4),
+                    };
+                    cache::write_bytes(self.device, offset, &new.to_le_bytes())?;
+                }
+                FatType::Fat16 => {
+                    let offset = fat_offset + cluster as u64 * 2;
+                    cache::write_bytes(self.device, offset, &(value as u16).to_le_bytes())?;
+                }
+                FatType::Fat32 => {
+                    // The reserved bits are kept as they are.
+                    let offset = fat_offset + cluster as u64 * 4;
+                    let old = self.read_u32(offset)?;
+                    self.write_u32(
+                        offset,
+                        (old & !FAT32_CLUSTER_MASK) | (value & FAT32_CLUSTER_MASK),
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the value which marks the end of a chain.
+    fn end_of_chain(&self) -> u32 {
+        match self.fat_type {
+            FatType::Fat12 => 0xFFF,
+            FatType::Fat16 => 0xFFFF,
+            FatType::Fat32 => FAT32_CLUSTER_MASK,
+        }
+    }
+
+    /// Returns the cluster which follows another one in its chain, or `None` at the end of the chain.
+    fn next_cluster(&self, cluster: u32) -> Result<Option<u32>, FsError> {
+        let value = self.fat_entry(cluster)?;
+        if value >= self.end_of_chain() - 7 {
+            return Ok(None);
+        }
+
+        match self.is_valid_cluster(value) {
+            true => Ok(Some(value)),
+            false => Err(FsError::Corrupted),
+        }
+    }
+
+    /// Finds a free cluster, and appends it to a chain, or starts a new chain with it.
+    fn allocate_cluster(&self, state: &mut State, previous: Option<u32>) -> Result<u32, FsError> {
+        let start = state.next_free.max(FIRST_CLUSTER);
+        for i in 0..self.cluster_count {
+            let cluster = FIRST_CLUSTER + (start - FIRST_CLUSTER + i) % self.cluster_count;
+            if self.fat_entry(cluster)? != 0 {
+                continue;
+            }
+
+            self.set_fat_entry(cluster, self.end_of_chain())?;
+            if let Some(previous) = previous {
+                self.set_fat_entry(previous, cluster)?;
+            }
+            state.next_free = cluster + 1;
+            state.fsinfo_dirty = true;
+
+            return Ok(cluster);
+        }
+
+        Err(FsError::NoSpace)
+    }
+
+    /// Releases every cluster of a chain.
+    fn free_chain(&self, state: &mut State, first: u32) -> Result<(), FsError> {
+        let mut cluster = Some(first);
+        // The length of the chain is bounded, so a loop in a corrupted table ends as well.
+        for _ in 0..self.cluster_count {
+            let Some(current) = cluster else {
+                break;
+            };
+            cluster = self.next_cluster(current)?;
+            self.set_fat_entry(current, 0)?;
+        }
+        state.next_free = state.next_free.min(first);
+        state.fsinfo_dirty = true;
+
+        Ok(())
+    }
+
+    fn zero_cluster(&self, cluster: u32) -> Result<(), FsError> {
+        let zeros = [0; BOOT_SECTOR_SIZE];
+        let offset = self.cluster_offset(cluster);
+        for chunk in (0..self.cluster_size as u64).step_by(zeros.len()) {
+            cache::write_bytes(self.device, offset + chunk, &zeros)?;
+        }
+
+        Ok(())
+    }
+
+    fn read_entry(&self, offset: u64) -> Result<[u8; ENTRY_SIZE], FsError> {
+        let mut raw = [0; ENTRY_SIZE];
+        cache::read_bytes(self.device, offset, &mut raw)?;
+
+        Ok(raw)
+    }
+
+    fn write_entry(&self, offset: u64, raw: &[u8; ENTRY_SIZE]) -> Result<(), FsError> {
+        Ok(cache::write_bytes(self.device, offset, raw)?)
+    }
+
+    /// Returns the short entry of an inode other than the root directory.
+    fn entry(&self, inode: InodeId) -> Result<ShortEntry, FsError> {
+        if inode == ROOT_INODE || inode % ENTRY_SIZE as u64 != 0 {
+            return Err(FsError::NotFound);
+        }
+
+        let raw = self.read_entry(inode)?;
+        if matches!(raw[0], ENTRY_END | ENTRY_FREE) {
+            return Err(FsError::NotFound);
+        }
+
+        Ok(ShortEntry(raw))
+    }
+
+    /// Returns the location of the entries of a directory.
+    fn dir(&self, inode: InodeId) -> Result<Dir, FsError> {
+        if inode == ROOT_INODE {
+            return Ok(match self.fat_type {
+                FatType::Fat32 => Dir::Chain(self.root_cluster),
+                _ => Dir::FixedRoot,
+            });
+        }
+
+        let entry = self.entry(inode)?;
+        if !entry.is_dir() {
+            return Err(FsError::NotADirectory);
+        }
+        match self.is_valid_cluster(entry.cluster()) {
+            true => Ok(Dir::Chain(entry.cluster())),
+            false => Err(FsError::Corrupted),
+        }
+    }
+
+    /// Finds an entry of a directory by its long or short name, which are compared regardless of their case.
+    fn find(&self, dir: Dir, name: &str) -> Result<Found, FsError> {
+        let mut slots = Slots::new(self, dir, 0);
+        let mut long_name = LongName::new();
+        while let Some(found) = slots.next_entry(&mut long_name)? {
+            let mut long_buf = [0; MAX_NAME_LEN];
+            let mut short_buf = [0; 12];
+            let matches = long_name
+                .decode(&found.entry, &mut long_buf)
+                .is_some_and(|long| long.eq_ignore_ascii_case(name))
+                || found
+                    .entry
+                    .display_name(&mut short_buf)
+                    .eq_ignore_ascii_case(name);
+            if matches {
+                return Ok(found);
+            }
+        }
+
+        Err(FsError::NotFound)
+    }
+
+    /// Checks whether a short name is taken in a directory.
+    fn short_name_exists(&self, dir: Dir, name: &[u8; 11]) -> Result<bool, FsError> {
+        let mut slots = Slots::new(self, dir, 0);
+        let mut long_name = LongName::new();
+        while let Some(found) = slots.next_entry(&mut long_name)? {
+            if found.entry.name() == *name {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Finds consecutive free slots in a directory, and grows it if there aren't enough of them.
+    fn free_slots(&self, state: &mut State, dir: Dir, count: u32) -> Result<u32, FsError> {
+        loop {
+            let mut slots = Slots::new(self, dir, 0);
+            let mut run = 0;
+            let mut last_cluster = None;
+            while let Some((index, offset)) = slots.next()? {
+                last_cluster = Some(slots.cluster);
+                match self.read_entry(offset)?[0] {
+                    ENTRY_FREE | ENTRY_END => run += 1,
+                    _ => run = 0,
+                }
+                if run == count {
+                    return Ok(index + 1 - count);
+                }
+            }
+
+            // Only the directories stored in clusters can grow.
+            let (Dir::Chain(_), Some(last)) = (dir, last_cluster) else {
+                return Err(FsError::NoSpace);
+            };
+            let cluster = self.allocate_cluster(state, Some(last))?;
+            self.zero_cluster(cluster)?;
+        }
+    }
+
+    /// Writes zeros, or data if given, into a file, and allocates the clusters needed along the way.
+    fn write_range(
+        &self,
+        state: &mut State,
+        entry: &mut ShortEntry,
+        offset: u64,
+        len: usize,
+        data: Option<&[u8]>,
+    ) -> Result<(), FsError> {
+        let cluster_size = self.cluster_size as u64;
+        let mut cluster = match entry.cluster() {
+            0 => {
+                let cluster = self.allocate_cluster(state, None)?;
+                entry.set_cluster(cluster);
+                cluster
+            }
+            cluster => cluster,
+        };
+        for _ in 0..offset / cluster_size {
+            cluster = match self.next_cluster(cluster)? {
+                Some(next) => next,
+                None => self.allocate_cluster(state, Some(cluster))?,
+            };
+        }
+
+        let zeros = [0; BOOT_SECTOR_SIZE];
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done as u64;
+            let start = pos % cluster_size;
+            if start == 0 && done != 0 {
+                cluster = match self.next_cluster(cluster)? {
+                    Some(next) => next,
+                    None => self.allocate_cluster(state, Some(cluster))?,
+                };
+            }
+
+            let chunk = ((cluster_size - start) as usize).min(len - done);
+            let chunk = match data {
+                Some(data) => {
+                    cache::write_bytes(
+                        self.device,
+                        self.cluster_offset(cluster) + start,
+                        &data[done..done + chunk],
+                    )?;
+                    chunk
+                }
+                None => {
+                    let chunk = chunk.min(zeros.len());
+                    cache::write_bytes(
+                        self.device,
+                        self.cluster_offset(cluster) + start,
+                        &zeros[..chunk],
+                    )?;
+                    chunk
+                }
+            };
+            done += chunk;
+        }
+
+        Ok(())
+    }
+
+    /// Writes back the FSInfo structure of FAT32, whose count of free clusters is dropped rather than maintained.
+    fn write_fsinfo(&self, state: &mut State) -> Result<(), FsError> {
+        let Some(offset) = self.fsinfo_offset.filter(|_| state.fsinfo_dirty) else {
+            return Ok(());
+        };
+        if self.read_u32(offset + FSINFO_STRUCT_SIGNATURE_OFFSET)? == FSINFO_STRUCT_SIGNATURE {
+            self.write_u32(offset + FSINFO_FREE_COUNT_OFFSET, FSINFO_UNKNOWN)?;
+            self.write_u32(offset + FSINFO_NEXT_FREE_OFFSET, state.next_free)?;
+        }
+        state.fsinfo_dirty = false;
+
+        Ok(())
+    }
+}
+
+impl FileSystem for FatFs {
+    fn name(&self) -> &str {
+        match self.fat_type {
+            FatType::Fat12 => "fat12",
+            FatType::Fat16 => "fat16",
+            FatType::Fat32 => "fat32",
+        }
+    }
+
+    fn root(&self) -> InodeId {
+        ROOT_INODE
+    }
+
+    fn lookup(&self, dir: InodeId, name: &str) -> Result<InodeId, FsError> {
+        Ok(self.find(self.dir(dir)?, name)?.offset)
+    }
+
+    fn metadata(&self, inode: InodeId) -> Result<Metadata, FsError> {
+        if inode == ROOT_INODE {
+            return Ok(Metadata::new(inode, FileType::Directory, 0, 0o755));
+        }
+
+        let entry = self.entry(inode)?;
+        let size = if entry.is_dir() {
+            0
+        } else {
+            entry.size() as u64
+        };
+
+        Ok(Metadata {
+            mtime: entry.mtime(),
+            ..Metadata::new(inode, entry.file_type(), size, entry.mode())
+        })
+    }
+
+    fn read(&self, inode: InodeId, offset: u64, buf: &mut [u8]) -> Result<usize, FsError> {
+        let entry = self.entry(inode)?;
+        if entry.is_dir() {
+            return Err(FsError::IsADirectory);
+        }
+
+        let size = entry.size() as u64;
+        if offset >= size || entry.cluster() == 0 {
+            return Ok(0);
+        }
+        let len = buf.len().min((size - offset) as usize);
+
+        let cluster_size = self.cluster_size as u64;
+        let mut cluster = entry.cluster();
+        for _ in 0..offset / cluster_size {
+            cluster = self.next_cluster(cluster)?.ok_or(FsError::Corrupted)?;
+        }
+
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done as u64;
+            let start = pos % cluster_size;
+            if start == 0 && done != 0 {
+                cluster = self.next_cluster(cluster)?.ok_or(FsError::Corrupted)?;
+            }
+
+            let chunk = ((cluster_size - start) as usize).min(len - done);
+            cache::read_bytes(
+                self.device,
+                self.cluster_offset(cluster) + start,
+                &mut buf[done..done + chunk],
+            )?;
+            done += chunk;
+        }
+
+        Ok(len)
+    }
+
+    fn write(&self, inode: InodeId, offset: u64, buf: &[u8]) -> Result<usize, FsError> {
+        self.check_writable()?;
+        let mut state = self.state.lock();
+        let mut entry = self.entry(inode)?;
+        if entry.is_dir() {
+            return Err(FsError::IsADirectory);
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let size = entry.size() as u64;
+        let end = offset + buf.len() as u64;
+        if end > u32::MAX as u64 {
+            return Err(FsError::NoSpace);
+        }
+
+        // A gap between the end of the file and the offset reads as zeros.
+        let result = if offset > size {
+            self.write_range(&mut state, &mut entry, size, (offset - size) as usize, None)
+        } else {
+            Ok(())
+        }
+        .and_then(|_| self.write_range(&mut state, &mut entry, offset, buf.len(), Some(buf)));
+
+        // The entry is written even if the write failed halfway, so the clusters allocated aren't lost.
+        if result.is_ok() {
+            entry.set_size(size.max(end) as u32);
+        }
+        self.write_entry(inode, &entry.0)?;
+        result?;
+
+        Ok(buf.len())
+    }
+
+    fn truncate(&self, inode: InodeId, size: u64) -> Result<(), FsError> {
+        self.check_writable()?;
+        let mut state = self.state.lock();
+        let mut entry = self.entry(inode)?;
+        if entry.is_dir() {
+            return Err(FsError::IsADirectory);
+        }
+        if size > u32::MAX as u64 {
+            return Err(FsError::NoSpace);
+        }
+
+        let old_size = entry.size() as u64;
+        if size > old_size {
+            let result = self.write_range(
+                &mut state,
+                &mut entry,
+                old_size,
+                (size - old_size) as usize,
+                None,
+            );
+            if result.is_ok() {
+                entry.set_size(size as u32);
+            }
+            self.write_entry(inode, &entry.0)?;
+            return result;
+        }
+
+        let keep = size.div_ceil(self.cluster_size as u64);
+        if entry.cluster() != 0 {
+            if keep == 0 {
+                self.free_chain(&mut state, entry.cluster())?;
+                entry.set_cluster(0);
+            } else {
+                let mut last = entry.cluster();
+                for _ in 1..keep {
+                    last = self.next_cluster(last)?.ok_or(FsError::Corrupted)?;
+                }
+                if let Some(rest) = self.next_cluster(last)? {
+                    self.set_fat_entry(last, self.end_of_chain())?;
+                    self.free_chain(&mut state, rest)?;
+                }
+            }
+        }
+        entry.set_size(size as u32);
+
+        self.write_entry(inode, &entry.0)
+    }
+
+    fn read_dir(&self, dir: InodeId, position: u64) -> Result<Option<(DirEntry, u64)>, FsError> {
+        let dir = self.dir(dir)?;
+        let position = u32::try_from(position).map_err(|_| FsError::InvalidOffset)?;
+
+        let mut slots = Slots::new(self, dir, position);
+        let mut long_name = LongName::new();
+        let Some(found) = slots.next_entry(&mut long_name)? else {
+            return Ok(None);
+        };
+
+        let mut long_buf = [0; MAX_NAME_LEN];
+        let mut short_buf = [0; 12];
+        let name = match long_name.decode(&found.entry, &mut long_buf) {
+            Some(name) => name,
+            None => found.entry.display_name(&mut short_buf),
+        };
+        let entry = DirEntry::new(found.offset, found.entry.file_type(), name)?;
+
+        Ok(Some((entry, found.index as u64 + 1)))
+    }
+
+    fn create(
+        &self,
+        dir: InodeId,
+        name: &str,
+        file_type: FileType,
+        mode: u16,
+    ) -> Result<InodeId, FsError> {
+        self.check_writable()?;
+        if !matches!(file_type, FileType::Regular | FileType::Directory) {
+            return Err(FsError::Unsupported);
+        }
+        dir::validate(name)?;
+
+        let mut state = self.state.lock();
+        let parent = self.dir(dir)?;
+        // The entry `..` refers to the root directory by cluster 0, even on FAT32.
+        let parent_cluster = match parent {
+            Dir::Chain(cluster) if dir != ROOT_INODE => cluster,
+            _ => 0,
+        };
+        match self.find(parent, name) {
+            Ok(_) => return Err(FsError::AlreadyExists),
+            Err(FsError::NotFound) => {}
+            Err(error) => return Err(error),
+        }
+
+        // A long name is only needed if the name doesn't fit into a short one.
+        let mut long_entries = [[0; ENTRY_SIZE]; LFN_MAX_ENTRIES];
+        let (short_name, flags, long_count) = match dir::exact_short_name(name) {
+            Some((short_name, flags)) => (short_name, flags, 0),
+            None => {
+                let mut number = 1;
+                let short_name = loop {
+                    let short_name = dir::generated_short_name(name, number);
+                    if !self.short_name_exists(parent, &short_name)? {
+                        break short_name;
+                    }
+                    if number == MAX_SHORT_NAME_SUFFIX {
+                        return Err(FsError::AlreadyExists);
+                    }
+                    number += 1;
+                };
+                let count = dir::long_name_entries(name, &short_name, &mut long_entries);
+                (short_name, 0, count)
+            }
+        };
+
+        let start = self.free_slots(&mut state, parent, long_count as u32 + 1)?;
+
+        let cluster = match file_type {
+            FileType::Directory => {
+                let cluster = self.allocate_cluster(&mut state, None)?;
+                self.zero_cluster(cluster)?;
+                let offset = self.cluster_offset(cluster);
+                for (i, dot) in dir::dot_entries(cluster, parent_cluster).iter().enumerate() {
+                    self.write_entry(offset + (i * ENTRY_SIZE) as u64, &dot.0)?;
+                }
+                cluster
+            }
+            _ => 0,
+        };
+
+        let mut attributes = match file_type {
+            FileType::Directory => dir::ATTR_DIRECTORY,
+            _ => dir::ATTR_ARCHIVE,
+        };
+        if mode & 0o222 == 0 {
+            attributes |= dir::ATTR_READ_ONLY;
+        }
+        let mut entry = ShortEntry::new(short_name, attributes, cluster);
+        entry.0[12] = flags;
+
+        let mut slots = Slots::new(self, parent, start);
+        for raw in &long_entries[..long_count] {
+            let (_, offset) = slots.next()?.ok_or(FsError::Corrupted)?;
+            self.write_entry(offset, raw)?;
+        }
+        let (_, offset) = slots.next()?.ok_or(FsError::Corrupted)?;
+        self.write_entry(offset, &entry.0)?;
+
+        Ok(offset)
+    }
+
+    fn remove(&self, dir: InodeId, name: &str) -> Result<(), FsError> {
+        self.check_writable()?;
+        let mut state = self.state.lock();
+        let dir = self.dir(dir)?;
+        let found = self.find(dir, name)?;
+
+        if found.entry.is_dir() {
+            let mut slots = Slots::new(self, self.dir(found.offset)?, 0);
+            if slots.next_entry(&mut LongName::new())?.is_some() {
+                return Err(FsError::NotEmpty);
+            }
+        }
+
+        let mut slots = Slots::new(self, dir, found.start);
+        for _ in found.start..=found.index {
+            let (_, offset) = slots.next()?.ok_or(FsError::Corrupted)?;
+            cache::write_bytes(self.device, offset, &[ENTRY_FREE])?;
+        }
+
+        if found.entry.cluster() != 0 {
+            self.free_chain(&mut state, found.entry.cluster())?;
+        }
+
+        Ok(())
+    }
+
+    fn symlink(&self, _dir: InodeId, _name: &str, _target: &str) -> Result<InodeId, FsError> {
+        Err(FsError::Unsupported)
+    }
+
+    fn sync(&self) -> Result<(), FsError> {
+        if !self.device.info().read_only {
+            self.write_fsinfo(&mut self.state.lock())?;
+        }
+
+        Ok(cache::sync(self.device)?)
+    }
+}
+
+/// Detects a FAT volume on a block device, and returns it for mounting.
+///
+/// This fails with `Unsupported` if the device doesn't hold a FAT volume.
+pub fn probe(device: &'static dyn BlockDevice) -> Result<&'static FatFs, FsError> {
+    let fs = FatFs::parse(device)?;
+
+    let index = VOLUME_COUNT.fetch_add(1, Ordering::Relaxed);
+    let slot = VOLUMES.get(index).ok_or(FsError::NoSpace)?;
+    let fs = slot.call_once(|| fs);
+
+    log::info!(
+        "{}: {:?} volume with {} clusters of {} bytes",
+        device.name(),
+        fs.fat_type,
+        fs.cluster_count,
+        fs.cluster_size
+    );
+
+    Ok(fs)
+}
@@ -0,0 +1,306 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::kernel::sync::Mutex;
+
+use super::path::{self, Vnode};
+use super::{dentry, DirEntry, FileType, FsError, Metadata};
+
+/// The maximum number of files which can be open at the same time.
+const MAX_OPEN_FILES: usize = 64;
+
+/// The mode of a file created by `open`.
+const DEFAULT_MODE: u16 = 0o644;
+
+/// The access to an open file, and how it's opened.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OpenFlags(u32);
+
+impl OpenFlags {
+    pub const READ: OpenFlags = OpenFlags(1 << 0);
+    pub const WRITE: OpenFlags = OpenFlags(1 << 1);
+    /// Every write goes to the end of the file.
+    pub const APPEND: OpenFlags = OpenFlags(1 << 2);
+    /// The file is created if it doesn't exist.
+    pub const CREATE: OpenFlags = OpenFlags(1 << 3);
+    /// Opening fails with `AlreadyExists` if the file exists, along with `CREATE`.
+    pub const EXCLUSIVE: OpenFlags = OpenFlags(1 << 4);
+    /// The file is emptied, along with `WRITE`.
+    pub const TRUNCATE: OpenFlags = OpenFlags(1 << 5);
+    /// A symbolic link at the end of the path is opened itself instead of its target.
+    pub const NO_FOLLOW: OpenFlags = OpenFlags(1 << 6);
+
+    pub const fn empty() -> Self {
+        OpenFlags(0)
+    }
+
+    pub const fn bits(&self) -> u32 {
+        self.0
+    }
+
+    pub const fn contains(&self, other: OpenFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns the flags combined with others, e.g. `OpenFlags::READ.with(OpenFlags::WRITE)`.
+    pub const fn with(self, other: OpenFlags) -> Self {
+        OpenFlags(self.0 | other.0)
+    }
+
+    pub fn insert(&mut self, other: OpenFlags) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: OpenFlags) {
+        self.0 &= !other.0;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SeekFrom {
+    Start(u64),
+    End(i64),
+    Current(i64),
+}
+
+#[derive(Clone, Copy)]
+struct OpenFile {
+    vnode: Vnode,
+    flags: OpenFlags,
+    /// The position in a regular file, or the position of the next entry of a directory.
+    offset: u64,
+}
+
+static OPEN_FILES: Mutex<[Option<OpenFile>; MAX_OPEN_FILES]> = Mutex::new([None; MAX_OPEN_FILES]);
+
+/// A handle to an open file, which is closed when it's dropped.
+#[derive(Debug)]
+pub struct File(usize);
+
+impl File {
+    pub fn open(path: &str, flags: OpenFlags) -> Result<Self, FsError> {
+        let vnode = match path::resolve(path, !flags.contains(OpenFlags::NO_FOLLOW)) {
+            Ok(_) if flags.contains(OpenFlags::CREATE.with(OpenFlags::EXCLUSIVE)) => {
+                return Err(FsError::AlreadyExists);
+            }
+            Ok(vnode) => vnode,
+            Err(FsError::NotFound) if flags.contains(OpenFlags::CREATE) => {
+                let (parent, name) = path::resolve_parent(path)?;
+                let inode =
+                    parent
+                        .fs()?
+                        .create(parent.inode, name, FileType::Regular, DEFAULT_MODE)?;
+                dentry::insert(parent.mount, parent.inode, name, inode);
+                Vnode::new(parent.mount, inode)
+            }
+            Err(error) => return Err(error),
+        };
+
+        let metadata = vnode.metadata()?;
+        let writes = flags.contains(OpenFlags::WRITE) || flags.contains(OpenFlags::APPEND);
+        if metadata.is_dir() && writes {
+            return Err(FsError::IsADirectory);
+        }
+        if flags.contains(OpenFlags::WRITE.with(OpenFlags::TRUNCATE)) && metadata.size != 0 {
+            vnode.fs()?.truncate(vnode.inode, 0)?;
+        }
+
+        let mut open_files = OPEN_FILES.lock();
+        let (index, slot) = open_files
+            .iter_mut()
+            .enumerate()
+            .find(|(_, slot)| slot.is_none())
+            .ok_or(FsError::TooManyOpenFiles)?;
+        *slot = Some(OpenFile {
+            vnode,
+            flags,
+            offset: 0,
+        });
+
+        Ok(File(index))
+    }
+
+    /// Opens a file for reading.
+    pub fn read_only(path: &str) -> Result<Self, FsError> {
+        Self::open(path, OpenFlags::READ)
+    }
+
+    /// Opens a file for writing, which is created if it doesn't exist, and emptied otherwise.
+    pub fn create(path: &str) -> Result<Self, FsError> {
+        Self::open(
+            path,
+            OpenFlags::WRITE
+                .with(OpenFlags::CREATE)
+                .with(OpenFlags::TRUNCATE),
+        )
+    }
+
+    fn state(&self) -> OpenFile {
+        OPEN_FILES.lock()[self.0].expect("file handle refers to a closed file")
+    }
+
+    fn set_offset(&mut self, offset: u64) {
+        if let Some(file) = OPEN_FILES.lock()[self.0].as_mut() {
+            file.offset = offset;
+        }
+    }
+
+    pub fn metadata(&self) -> Result<Metadata, FsError> {
+        self.state().vnode.metadata()
+    }
+
+    /// Reads from the current position, and returns the number of bytes read, which is 0 at the end of the file.
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, FsError> {
+        let file = self.state();
+        if !file.flags.contains(OpenFlags::READ) {
+            return Err(FsError::BadHandle);
+        }
+        if file.vnode.metadata()?.is_dir() {
+            return Err(FsError::IsADirectory);
+        }
+
+        let read = file.vnode.fs()?.read(file.vnode.inode, file.offset, buf)?;
+        self.set_offset(file.offset + read as u64);
+
+        Ok(read)
+    }
+
+    /// Reads until the buffer is full, and returns the number of bytes read, which is less only at the end of the
+    /// file.
+    pub fn read_all(&mut self, buf: &mut [u8]) -> Result<usize, FsError> {
+        let mut total = 0;
+        while total < buf.len() {
+            match self.read(&mut buf[total..])? {
+                0 => break,
+                read => total += read,
+            }
+        }
+
+        Ok(total)
+    }
+
+    /// Writes at the current position, or at the end of the file if it's been opened with `APPEND`.
+    pub fn write(&mut self, buf: &[u8]) -> Result<usize, FsError> {
+        let file = self.state();
+        let append = file.flags.contains(OpenFlags::APPEND);
+        if !file.flags.contains(OpenFlags::WRITE) && !append {
+            return Err(FsError::BadHandle);
+        }
+
+        let offset = match append {
+            true => file.vnode.metadata()?.size,
+            false => file.offset,
+        };
+        let written = file.vnode.fs()?.write(file.vnode.inode, offset, buf)?;
+        self.set_offset(offset + written as u64);
+
+        Ok(written)
+    }
+
+    /// Moves the current position, which may go past the end of the file, and returns it.
+    pub fn seek(&mut self, pos: SeekFrom) -> Result<u64, FsError> {
+        let file = self.state();
+        let offset = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => file.vnode.metadata()?.size.checked_add_signed(delta),
+            SeekFrom::Current(delta) => file.offset.checked_add_signed(delta),
+        }
+        .ok_or(FsError::InvalidOffset)?;
+        self.set_offset(offset);
+
+        Ok(offset)
+    }
+
+    /// Sets the size of the file, which is filled with zeros if it grows.
+    pub fn set_len(&mut self, size: u64) -> Result<(), FsError> {
+        let file = self.state();
+        if !file.flags.contains(OpenFlags::WRITE) {
+            return Err(FsError::BadHandle);
+        }
+
+        file.vnode.fs()?.truncate(file.vnode.inode, size)
+    }
+
+    /// Returns the next entry of a directory, which is opened for reading.
+    fn next_entry(&mut self) -> Result<Option<DirEntry>, FsError> {
+        let file = self.state();
+        match file.vnode.fs()?.read_dir(file.vnode.inode, file.offset)? {
+            Some((entry, next)) => {
+                self.set_offset(next);
+                Ok(Some(entry))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        OPEN_FILES.lock()[self.0] = None;
+    }
+}
+
+/// An iterator over the entries of a directory, which doesn't include `.` and `..`.
+#[derive(Debug)]
+pub struct ReadDir(File);
+
+impl ReadDir {
+    pub fn open(path: &str) -> Result<Self, FsError> {
+        let dir = File::open(path, OpenFlags::READ)?;
+        if !dir.metadata()?.is_dir() {
+            return Err(FsError::NotADirectory);
+        }
+
+        Ok(ReadDir(dir))
+    }
+}
+
+impl Iterator for ReadDir {
+    type Item = Result<DirEntry, FsError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next_entry().transpose()
+    }
+}
+
+/// Returns an iterator over the entries of a directory.
+pub fn read_dir(path: &str) -> Result<ReadDir, FsError> {
+    ReadDir::open(path)
+}
+
+/// Checks whether an inode is open.
+pub(super) fn is_open(vnode: Vnode) -> bool {
+    OPEN_FILES
+        .lock()
+        .iter()
+        .flatten()
+        .any(|file| file.vnode == vnode)
+}
+
+/// Checks whether any file of a filesystem is open.
+pub(super) fn is_mount_in_use(mount: usize) -> bool {
+    OPEN_FILES
+        .lock()
+        .iter()
+        .flatten()
+        .any(|file| file.vnode.mount == mount)
+}
@@ -0,0 +1,552 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::str;
+
+use crate::kernel::arch;
+use crate::kernel::sync::RwLock;
+
+use super::{DirEntry, FileSystem, FileType, FsError, InodeId, Metadata, MAX_NAME_LEN};
+
+/// The maximum number of files and directories the filesystem can hold.
+const MAX_INODES: usize = 1024;
+
+/// The position returned by `read_dir` after the last entry of a directory.
+const END_OF_DIR: u64 = u64::MAX;
+
+const CPIO_NEWC_MAGIC: &[u8] = b"070701";
+const CPIO_CRC_MAGIC: &[u8] = b"070702";
+const CPIO_HEADER_SIZE: usize = 110;
+const CPIO_TRAILER: &str = "TRAILER!!!";
+
+const CPIO_TYPE_MASK: u64 = 0o170000;
+const CPIO_TYPE_SOCKET: u64 = 0o140000;
+const CPIO_TYPE_SYMLINK: u64 = 0o120000;
+const CPIO_TYPE_REGULAR: u64 = 0o100000;
+const CPIO_TYPE_BLOCK: u64 = 0o060000;
+const CPIO_TYPE_DIRECTORY: u64 = 0o040000;
+const CPIO_TYPE_CHAR: u64 = 0o020000;
+const CPIO_TYPE_FIFO: u64 = 0o010000;
+
+const TAR_BLOCK_SIZE: usize = 512;
+const TAR_MAGIC: &[u8] = b"ustar";
+
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+
+/// The directories which exist even if no archive has them, so other filesystems can be mounted on top of them.
+const MOUNT_POINTS: &[&str] = &["dev", "mnt", "proc", "tmp"];
+
+#[derive(Clone, Copy)]
+struct Inode {
+    file_type: FileType,
+    /// The name of the inode in its directory, which points into the archive, like the data.
+    name: &'static str,
+    parent: usize,
+    first_child: Option<usize>,
+    next_sibling: Option<usize>,
+    /// The contents of a regular file, or the target of a symbolic link.
+    data: &'static [u8],
+    mode: u16,
+    uid: u32,
+    gid: u32,
+    mtime: u64,
+    /// The archive and the inode number within it, by which the hard links of a cpio archive refer to their data.
+    link: Option<(usize, u64)>,
+}
+
+impl Inode {
+    /// The root directory, which doubles as the initial value of the unused inodes.
+    const ROOT: Inode = Inode {
+        file_type: FileType::Directory,
+        name: "",
+        parent: 0,
+        first_child: None,
+        next_sibling: None,
+        data: &[],
+        mode: 0o755,
+        uid: 0,
+        gid: 0,
+        mtime: 0,
+        link: None,
+    };
+}
+
+/// The attributes of an entry of an archive.
+struct Entry {
+    file_type: FileType,
+    data: &'static [u8],
+    mode: u16,
+    uid: u32,
+    gid: u32,
+    mtime: u64,
+}
+
+struct Tree {
+    inodes: [Inode; MAX_INODES],
+    len: usize,
+}
+
+impl Tree {
+    const fn new() -> Self {
+        Self {
+            inodes: [Inode::ROOT; MAX_INODES],
+            len: 1,
+        }
+    }
+
+    fn get(&self, inode: InodeId) -> Result<&Inode, FsError> {
+        self.inodes[..self.len]
+            .get(inode as usize)
+            .ok_or(FsError::NotFound)
+    }
+
+    fn children(&self, dir: usize) -> impl Iterator<Item = usize> + '_ {
+        let mut next = self.inodes[dir].first_child;
+        core::iter::from_fn(move || {
+            let child = next?;
+            next = self.inodes[child].next_sibling;
+            Some(child)
+        })
+    }
+
+    fn child(&self, dir: usize, name: &str) -> Option<usize> {
+        self.children(dir)
+            .find(|child| self.inodes[*child].name == name)
+    }
+
+    /// Appends an empty inode to a directory, after its other entries to keep the order of the archive.
+    fn add(&mut self, dir: usize, name: &'static str) -> Result<usize, FsError> {
+        if self.len == MAX_INODES {
+            return Err(FsError::NoSpace);
+        }
+        let index = self.len;
+        self.len += 1;
+
+        self.inodes[index] = Inode {
+            name,
+            parent: dir,
+            ..Inode::ROOT
+        };
+        match self.children(dir).last() {
+            Some(last) => self.inodes[last].next_sibling = Some(index),
+            None => self.inodes[dir].first_child = Some(index),
+        }
+
+        Ok(index)
+    }
+
+    /// Finds the inode at a path relative to the root.
+    fn find<'a>(&self, path: impl Iterator<Item = &'a str>) -> Option<usize> {
+        path.filter(|component| !matches!(*component, "" | "."))
+            .try_fold(0, |dir, name| self.child(dir, name))
+    }
+
+    /// Adds an entry at a path, creating the missing directories leading to it.
+    ///
+    /// An existing entry is replaced, unless it's a directory with entries of its own.
+    fn insert(
+        &mut self,
+        path: impl Iterator<Item = &'static str>,
+        entry: &Entry,
+    ) -> Result<usize, FsError> {
+        let mut index = 0;
+        for name in path.filter(|component| !matches!(*component, "" | ".")) {
+            if name == ".." {
+                return Err(FsError::InvalidPath);
+            }
+            if name.len() > MAX_NAME_LEN {
+                return Err(FsError::NameTooLong);
+            }
+            if self.inodes[index].file_type != FileType::Directory {
+                return Err(FsError::NotADirectory);
+            }
+
+            index = match self.child(index, name) {
+                Some(child) => child,
+                None => self.add(index, name)?,
+            };
+        }
+
+        let inode = &mut self.inodes[index];
+        if entry.file_type != FileType::Directory {
+            if index == 0 {
+                return Err(FsError::InvalidPath);
+            }
+            if inode.first_child.is_some() {
+                return Err(FsError::AlreadyExists);
+            }
+        }
+        inode.file_type = entry.file_type;
+        inode.data = entry.data;
+        inode.mode = entry.mode;
+        inode.uid = entry.uid;
+        inode.gid = entry.gid;
+        inode.mtime = entry.mtime;
+        inode.link = None;
+
+        Ok(index)
+    }
+}
+
+static TREE: RwLock<Tree> = RwLock::new(Tree::new());
+
+/// Initial RAM Filesystem
+///
+/// A read-only filesystem holding the contents of the archives loaded as modules by the bootloader, which can be
+/// `newc` cpio archives, as built by `cpio -H newc`, or ustar archives, as built by `tar --format=ustar`. The archives
+/// are indexed in place, so the files aren't copied, and a later archive overrides the files of an earlier one.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/Initrd
+pub struct Initramfs;
+
+static INITRAMFS: Initramfs = Initramfs;
+
+impl FileSystem for Initramfs {
+    fn name(&self) -> &str {
+        "initramfs"
+    }
+
+    fn root(&self) -> InodeId {
+        0
+    }
+
+    fn lookup(&self, dir: InodeId, name: &str) -> Result<InodeId, FsError> {
+        let tree = TREE.read();
+        if tree.get(dir)?.file_type != FileType::Directory {
+            return Err(FsError::NotADirectory);
+        }
+
+        tree.child(dir as usize, name)
+            .map(|child| child as InodeId)
+            .ok_or(FsError::NotFound)
+    }
+
+    fn metadata(&self, inode: InodeId) -> Result<Metadata, FsError> {
+        let tree = TREE.read();
+        let node = tree.get(inode)?;
+
+        Ok(Metadata {
+            links: if node.file_type == FileType::Directory {
+                2
+            } else {
+                1
+            },
+            uid: node.uid,
+            gid: node.gid,
+            mtime: node.mtime,
+            ..Metadata::new(inode, node.file_type, node.data.len() as u64, node.mode)
+        })
+    }
+
+    fn read(&self, inode: InodeId, offset: u64, buf: &mut [u8]) -> Result<usize, FsError> {
+        let data = match TREE.read().get(inode)? {
+            node if node.file_type == FileType::Directory => return Err(FsError::IsADirectory),
+            node => node.data,
+        };
+
+        let data = data.get(offset as usize..).unwrap_or_default();
+        let len = data.len().min(buf.len());
+        buf[..len].copy_from_slice(&data[..len]);
+
+        Ok(len)
+    }
+
+    fn read_dir(&self, dir: InodeId, position: u64) -> Result<Option<(DirEntry, u64)>, FsError> {
+        let tree = TREE.read();
+        let node = tree.get(dir)?;
+        if node.file_type != FileType::Directory {
+            return Err(FsError::NotADirectory);
+        }
+
+        // The position is the number of the next entry, as the root is never an entry itself.
+        let child = match position {
+            0 => node.first_child,
+            END_OF_DIR => None,
+            position => Some(position as usize)
+                .filter(|child| *child < tree.len && tree.inodes[*child].parent == dir as usize),
+        };
+        let Some(child) = child else {
+            return Ok(None);
+        };
+
+        let inode = &tree.inodes[child];
+        let entry = DirEntry::new(child as InodeId, inode.file_type, inode.name)?;
+        let next = inode.next_sibling.map_or(END_OF_DIR, |next| next as u64);
+
+        Ok(Some((entry, next)))
+    }
+
+    fn read_link(&self, inode: InodeId, buf: &mut [u8]) -> Result<usize, FsError> {
+        let tree = TREE.read();
+        let node = tree.get(inode)?;
+        if node.file_type != FileType::Symlink {
+            return Err(FsError::InvalidPath);
+        }
+
+        let target = buf.get_mut(..node.data.len()).ok_or(FsError::NameTooLong)?;
+        target.copy_from_slice(node.data);
+
+        Ok(node.data.len())
+    }
+}
+
+/// Parses a number in ASCII, which may be padded with spaces or NUL characters, as in the fields of a tar header.
+fn parse_number(field: &[u8], radix: u32) -> Result<u64, FsError> {
+    let digits = str::from_utf8(field)
+        .map_err(|_| FsError::Corrupted)?
+        .trim_matches(|c: char| c == ' ' || c == '\0');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+
+    u64::from_str_radix(digits, radix).map_err(|_| FsError::Corrupted)
+}
+
+/// Returns the string before the first NUL character of a field.
+fn parse_str(field: &'static [u8]) -> Result<&'static str, FsError> {
+    let len = field
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(field.len());
+    str::from_utf8(&field[..len]).map_err(|_| FsError::Corrupted)
+}
+
+/// Unpacks a `newc` cpio archive, and returns the number of entries.
+///
+/// Every field of a header is a number of 8 hexadecimal digits, and both the name following the header and the data
+/// are padded to a multiple of 4 bytes.
+///
+/// Spec: https://man.freebsd.org/cgi/man.cgi?query=cpio&sektion=5
+fn unpack_cpio(tree: &mut Tree, archive: usize, data: &'static [u8]) -> Result<usize, FsError> {
+    let mut offset = 0;
+    let mut count = 0;
+    loop {
+        let header = data
+            .get(offset..offset + CPIO_HEADER_SIZE)
+            .ok_or(FsError::Corrupted)?;
+        if !header.starts_with(CPIO_NEWC_MAGIC) && !header.starts_with(CPIO_CRC_MAGIC) {
+            return Err(FsError::Corrupted);
+        }
+        let field = |index: usize| parse_number(&header[6 + 8 * index..14 + 8 * index], 16);
+
+        let ino = field(0)?;
+        let mode = field(1)?;
+        let nlink = field(4)?;
+        let size = field(6)? as usize;
+        let name_size = field(11)? as usize;
+
+        let name_start = offset + CPIO_HEADER_SIZE;
+        let name = data
+            .get(name_start..name_start + name_size)
+            .ok_or(FsError::Corrupted)?;
+        let name = parse_str(name)?;
+        let data_start = (name_start + name_size).next_multiple_of(4);
+        let contents = data
+            .get(data_start..data_start + size)
+            .ok_or(FsError::Corrupted)?;
+        offset = (data_start + size).next_multiple_of(4);
+
+        if name == CPIO_TRAILER {
+            return Ok(count);
+        }
+
+        let file_type = match mode & CPIO_TYPE_MASK {
+            CPIO_TYPE_REGULAR => FileType::Regular,
+            CPIO_TYPE_DIRECTORY => FileType::Directory,
+            CPIO_TYPE_SYMLINK => FileType::Symlink,
+            CPIO_TYPE_CHAR => FileType::CharDevice,
+            CPIO_TYPE_BLOCK => FileType::BlockDevice,
+            CPIO_TYPE_FIFO => FileType::Fifo,
+            CPIO_TYPE_SOCKET => FileType::Socket,
+            _ => return Err(FsError::Corrupted),
+        };
+        let entry = Entry {
+            file_type,
+            data: contents,
+            mode: (mode & 0o7777) as u16,
+            uid: field(2)? as u32,
+            gid: field(3)? as u32,
+            mtime: field(5)?,
+        };
+
+        let index = match tree.insert(name.split('/'), &entry) {
+            Ok(index) => index,
+            Err(error) => {
+                log::warn!("initramfs: skipping {}: {:?}", name, error);
+                continue;
+            }
+        };
+        count += 1;
+
+        // Hard links share an inode number, and only the last of them carries the data.
+        if file_type == FileType::Regular && nlink > 1 {
+            if contents.is_empty() {
+                tree.inodes[index].link = Some((archive, ino));
+            } else {
+                for inode in tree.inodes[..tree.len].iter_mut() {
+                    if inode.link == Some((archive, ino)) {
+                        inode.data = contents;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Unpacks a ustar archive, and returns the number of entries.
+///
+/// Every entry is a header of 512 bytes followed by the data, which is padded to a multiple of 512 bytes. The
+/// archive ends with two blocks of zeros.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/USTAR
+fn unpack_tar(tree: &mut Tree, data: &'static [u8]) -> Result<usize, FsError> {
+    let mut offset = 0;
+    let mut count = 0;
+    while let Some(header) = data.get(offset..offset + TAR_BLOCK_SIZE) {
+        if header.iter().all(|byte| *byte == 0) {
+            break;
+        }
+
+        // The checksum is computed with its own field filled with spaces.
+        let checksum = parse_number(&header[148..156], 8)?;
+        let sum = header
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| if (148..156).contains(&i) { b' ' } else { *byte } as u64)
+            .sum::<u64>();
+        if checksum != sum {
+            return Err(FsError::Corrupted);
+        }
+
+        let name = parse_str(&header[0..100])?;
+        let prefix = match &header[257..262] == TAR_MAGIC {
+            true => parse_str(&header[345..500])?,
+            false => "",
+        };
+        let size = parse_number(&header[124..136], 8)? as usize;
+        let type_flag = header[156];
+        let link_name = parse_str(&header[157..257])?;
+
+        let data_start = offset + TAR_BLOCK_SIZE;
+        let contents = data
+            .get(data_start..data_start + size)
+            .ok_or(FsError::Corrupted)?;
+        offset = data_start + size.next_multiple_of(TAR_BLOCK_SIZE);
+
+        let (file_type, contents) = match type_flag {
+            b'0' | b'\0' | b'7' => (FileType::Regular, contents),
+            b'1' => match tree.find(link_name.split('/')) {
+                Some(target) => (tree.inodes[target].file_type, tree.inodes[target].data),
+                None => {
+                    log::warn!("initramfs: skipping {}: missing link target", name);
+                    continue;
+                }
+            },
+            b'2' => (FileType::Symlink, link_name.as_bytes()),
+            b'3' => (FileType::CharDevice, &[][..]),
+            b'4' => (FileType::BlockDevice, &[][..]),
+            b'5' => (FileType::Directory, &[][..]),
+            b'6' => (FileType::Fifo, &[][..]),
+            _ => {
+                log::warn!(
+                    "initramfs: skipping {}: unsupported type {:?}",
+                    name,
+                    type_flag as char
+                );
+                continue;
+            }
+        };
+        let entry = Entry {
+            file_type,
+            data: contents,
+            mode: (parse_number(&header[100..108], 8)? & 0o7777) as u16,
+            uid: parse_number(&header[108..116], 8)? as u32,
+            gid: parse_number(&header[116..124], 8)? as u32,
+            mtime: parse_number(&header[136..148], 8)?,
+        };
+
+        match tree.insert(prefix.split('/').chain(name.split('/')), &entry) {
+            Ok(_) => count += 1,
+            Err(error) => log::warn!("initramfs: skipping {}: {:?}", name, error),
+        }
+    }
+
+    Ok(count)
+}
+
+/// Unpacks the archives loaded by the bootloader, and mounts them at the root.
+pub(super) fn init() -> Result<(), ()> {
+    let mut tree = TREE.write();
+    let dir = Entry {
+        file_type: FileType::Directory,
+        data: &[],
+        mode: 0o755,
+        uid: 0,
+        gid: 0,
+        mtime: 0,
+    };
+    for path in MOUNT_POINTS {
+        tree.insert(path.split('/'), &dir).map_err(|_| ())?;
+    }
+
+    for (archive, module) in arch::modules().enumerate() {
+        let Some(data) = module.data() else {
+            log::warn!("initramfs: module {} is out of reach", module.cmdline);
+            continue;
+        };
+
+        let result = if data.starts_with(CPIO_NEWC_MAGIC) || data.starts_with(CPIO_CRC_MAGIC) {
+            unpack_cpio(&mut tree, archive, data)
+        } else if data.get(257..262) == Some(TAR_MAGIC) {
+            unpack_tar(&mut tree, data)
+        } else if data.starts_with(GZIP_MAGIC) {
+            log::warn!(
+                "initramfs: module {} is compressed, which isn't supported",
+                module.cmdline
+            );
+            continue;
+        } else {
+            log::warn!(
+                "initramfs: module {} isn't a cpio or tar archive",
+                module.cmdline
+            );
+            continue;
+        };
+
+        match result {
+            Ok(count) => log::info!(
+                "initramfs: {} entries from module {} ({} KiB)",
+                count,
+                module.cmdline,
+                data.len() / 1024
+            ),
+            Err(error) => log::warn!(
+                "initramfs: module {} is malformed: {:?}",
+                module.cmdline,
+                error
+            ),
+        }
+    }
+    drop(tree);
+
+    super::mount("/", &INITRAMFS).map_err(|_| ())
+}
@@ -0,0 +1,303 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::fmt;
+use core::str;
+
+use crate::kernel::block::{self, BlockDevice, BlockError};
+use crate::kernel::cmdline;
+use crate::kernel::cmdline::{Arg, Param, ParamError};
+
+pub use self::file::{read_dir, File, OpenFlags, ReadDir, SeekFrom};
+pub use self::mount::{mount, mounts, unmount, MountInfo};
+pub use self::path::{metadata, mkdir, read_link, remove, symlink, symlink_metadata};
+
+pub mod ext2;
+pub mod fat;
+
+mod dentry;
+mod devfs;
+mod file;
+mod initramfs;
+mod mount;
+mod path;
+mod procfs;
+mod tmpfs;
+
+/// `mount=<device>:<path>` mounts the filesystem on a block device at a directory, e.g., `mount=vdb:/mnt`.
+static MOUNT: Param = Param::new("mount", mount_device);
+
+/// The maximum length of a name in a directory.
+pub const MAX_NAME_LEN: usize = 255;
+/// The maximum length of a path.
+pub const MAX_PATH_LEN: usize = 256;
+
+/// The number of an inode, which is unique within its filesystem.
+pub type InodeId = u64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FsError {
+    NotFound,
+    NotADirectory,
+    IsADirectory,
+    AlreadyExists,
+    /// The directory can't be removed while it has entries.
+    NotEmpty,
+    ReadOnly,
+    InvalidPath,
+    NameTooLong,
+    /// A position before the start of a file has been sought.
+    InvalidOffset,
+    /// Too many symbolic links were followed while resolving a path, which is likely a loop.
+    TooManyLinks,
+    /// The path is a mount point, or the filesystem is still mounted.
+    Busy,
+    NoSpace,
+    TooManyOpenFiles,
+    /// The file has been opened without the access needed.
+    BadHandle,
+    /// The data of the filesystem is inconsistent.
+    Corrupted,
+    Unsupported,
+    Block(BlockError),
+}
+
+impl From<BlockError> for FsError {
+    fn from(error: BlockError) -> Self {
+        FsError::Block(error)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileType {
+    Regular,
+    Directory,
+    Symlink,
+    CharDevice,
+    BlockDevice,
+    Fifo,
+    Socket,
+}
+
+impl FileType {
+    /// Returns the character which stands for the type in a listing, as in the output of `ls -l`.
+    pub fn symbol(&self) -> char {
+        match self {
+            FileType::Regular => '-',
+            FileType::Directory => 'd',
+            FileType::Symlink => 'l',
+            FileType::CharDevice => 'c',
+            FileType::BlockDevice => 'b',
+            FileType::Fifo => 'p',
+            FileType::Socket => 's',
+        }
+    }
+}
+
+/// The attributes of an inode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Metadata {
+    pub inode: InodeId,
+    pub file_type: FileType,
+    pub size: u64,
+    /// The permission bits, e.g. `0o755`.
+    pub mode: u16,
+    pub links: u32,
+    pub uid: u32,
+    pub gid: u32,
+    /// The time of the last modification in seconds since the Unix epoch, or 0 if unknown.
+    pub mtime: u64,
+}
+
+impl Metadata {
+    pub fn new(inode: InodeId, file_type: FileType, size: u64, mode: u16) -> Self {
+        Self {
+            inode,
+            file_type,
+            size,
+            mode,
+            links: 1,
+            uid: 0,
+            gid: 0,
+            mtime: 0,
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_type == FileType::Directory
+    }
+}
+
+/// An entry of a directory.
+#[derive(Clone, Copy)]
+pub struct DirEntry {
+    pub inode: InodeId,
+    pub file_type: FileType,
+    name: [u8; MAX_NAME_LEN],
+    name_len: u8,
+}
+
+impl DirEntry {
+    pub fn new(inode: InodeId, file_type: FileType, name: &str) -> Result<Self, FsError> {
+        let mut entry = Self {
+            inode,
+            file_type,
+            name: [0; MAX_NAME_LEN],
+            name_len: 0,
+        };
+        entry
+            .name
+            .get_mut(..name.len())
+            .ok_or(FsError::NameTooLong)?
+            .copy_from_slice(name.as_bytes());
+        entry.name_len = name.len() as u8;
+
+        Ok(entry)
+    }
+
+    pub fn name(&self) -> &str {
+        str::from_utf8(&self.name[..self.name_len as usize]).unwrap_or_default()
+    }
+}
+
+impl fmt::Debug for DirEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DirEntry")
+            .field("inode", &self.inode)
+            .field("file_type", &self.file_type)
+            .field("name", &self.name())
+            .finish()
+    }
+}
+
+/// A filesystem, which the VFS reaches through the numbers of its inodes.
+///
+/// The methods changing the filesystem fail with `ReadOnly` unless they're implemented. The entries `.` and `..` are
+/// handled by the VFS, so the directories don't list them.
+pub trait FileSystem: Sync {
+    /// Returns the type of the filesystem, e.g. `tmpfs`.
+    fn name(&self) -> &str;
+
+    fn root(&self) -> InodeId;
+
+    /// Finds an entry of a directory.
+    fn lookup(&self, dir: InodeId, name: &str) -> Result<InodeId, FsError>;
+
+    fn metadata(&self, inode: InodeId) -> Result<Metadata, FsError>;
+
+    /// Reads from a file at an offset, and returns the number of bytes read, which is 0 at the end of the file.
+    fn read(&self, inode: InodeId, offset: u64, buf: &mut [u8]) -> Result<usize, FsError>;
+
+    fn write(&self, _inode: InodeId, _offset: u64, _buf: &[u8]) -> Result<usize, FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    /// Sets the size of a file, which is filled with zeros if it grows.
+    fn truncate(&self, _inode: InodeId, _size: u64) -> Result<(), FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    /// Returns the entry of a directory at a position, along with the position of the next one.
+    ///
+    /// The positions are opaque to the VFS, except that the first entry is at 0.
+    fn read_dir(&self, dir: InodeId, position: u64) -> Result<Option<(DirEntry, u64)>, FsError>;
+
+    /// Reads the target of a symbolic link, and returns its length.
+    fn read_link(&self, _inode: InodeId, _buf: &mut [u8]) -> Result<usize, FsError> {
+        Err(FsError::Unsupported)
+    }
+
+    /// Creates a regular file or a directory in a directory.
+    fn create(
+        &self,
+        _dir: InodeId,
+        _name: &str,
+        _file_type: FileType,
+        _mode: u16,
+    ) -> Result<InodeId, FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    fn symlink(&self, _dir: InodeId, _name: &str, _target: &str) -> Result<InodeId, FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    /// Removes an entry of a directory, which must be empty if it's a directory itself.
+    fn remove(&self, _dir: InodeId, _name: &str) -> Result<(), FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    /// Writes back the changes which haven't reached the storage yet.
+    fn sync(&self) -> Result<(), FsError> {
+        Ok(())
+    }
+}
+
+/// Writes back the changes of every mounted filesystem.
+pub fn sync() -> Result<(), FsError> {
+    mounts().try_for_each(|mount| mount.fs.sync())
+}
+
+/// Detects the filesystem on a block device, and returns it for mounting.
+///
+/// This fails with `Unsupported` if none of the filesystem drivers recognizes the device.
+pub fn probe(device: &'static dyn BlockDevice) -> Result<&'static dyn FileSystem, FsError> {
+    match fat::probe(device) {
+        Err(FsError::Unsupported) => {}
+        result => return result.map(|fs| fs as &'static dyn FileSystem),
+    }
+    match ext2::probe(device) {
+        Err(FsError::Unsupported) => {}
+        result => return result.map(|fs| fs as &'static dyn FileSystem),
+    }
+
+    Err(FsError::Unsupported)
+}
+
+fn mount_device(arg: &Arg) -> Result<(), ParamError> {
+    let value = arg.value.ok_or(ParamError::MissingValue)?;
+    let (name, path) = value.split_once(':').ok_or(ParamError::InvalidValue)?;
+    let Some(device) = block::find(name) else {
+        log::warn!("{}: no such block device to mount", name);
+        return Err(ParamError::InvalidValue);
+    };
+
+    match probe(device).and_then(|fs| mount(path, fs).map(|_| fs)) {
+        Ok(fs) => log::info!("{}: mounted {} at {}", name, fs.name(), path),
+        Err(error) => {
+            log::warn!("{}: failed to mount at {}: {:?}", name, path, error);
+            return Err(ParamError::InvalidValue);
+        }
+    }
+
+    Ok(())
+}
+
+/// Mounts the initial RAM filesystem as the root filesystem along with the pseudo-filesystems at `/dev`, `/proc` and
+/// `/tmp`, and then the block devices given on the command line.
+pub(crate) fn init() -> Result<(), ()> {
+    initramfs::init()?;
+    devfs::init()?;
+    procfs::init()?;
+    tmpfs::init()?;
+    cmdline::register(&MOUNT).map_err(|_| ())
+}
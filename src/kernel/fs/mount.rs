@@ -0,0 +1,196 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::str;
+
+use crate::kernel::sync::RwLock;
+
+use super::path::{self, Vnode};
+use super::{dentry, file, FileSystem, FsError, MAX_PATH_LEN};
+
+/// The maximum number of filesystems which can be mounted at the same time.
+pub(super) const MAX_MOUNTS: usize = 16;
+
+#[derive(Clone, Copy)]
+struct Mount {
+    path: [u8; MAX_PATH_LEN],
+    path_len: usize,
+    fs: &'static dyn FileSystem,
+    /// The directory covered by the filesystem, which is `None` for the root filesystem.
+    mountpoint: Option<Vnode>,
+}
+
+static MOUNTS: RwLock<[Option<Mount>; MAX_MOUNTS]> = RwLock::new([None; MAX_MOUNTS]);
+
+/// A filesystem mounted at a path.
+#[derive(Clone, Copy)]
+pub struct MountInfo {
+    path: [u8; MAX_PATH_LEN],
+    path_len: usize,
+    pub fs: &'static dyn FileSystem,
+}
+
+impl MountInfo {
+    pub fn path(&self) -> &str {
+        str::from_utf8(&self.path[..self.path_len]).unwrap_or_default()
+    }
+}
+
+/// Mounts a filesystem at a directory, which hides its entries until the filesystem is unmounted.
+///
+/// The first filesystem has to be mounted at `/`. Mounting on top of a mount point stacks the filesystems, so only
+/// the one mounted last is visible.
+pub fn mount(path: &str, fs: &'static dyn FileSystem) -> Result<(), FsError> {
+    let path = path::normalize(path)?;
+
+    let mountpoint = if MOUNTS.read().iter().all(Option::is_none) {
+        if path != "/" {
+            return Err(FsError::NotFound);
+        }
+        None
+    } else {
+        let vnode = path::resolve(path, true)?;
+        if !vnode.metadata()?.is_dir() {
+            return Err(FsError::NotADirectory);
+        }
+        Some(vnode)
+    };
+
+    let mut entry = Mount {
+        path: [0; MAX_PATH_LEN],
+        path_len: path.len(),
+        fs,
+        mountpoint,
+    };
+    entry.path[..path.len()].copy_from_slice(path.as_bytes());
+
+    let mut mounts = MOUNTS.write();
+    // Another filesystem might have been mounted at the same place in the meantime.
+    if mounts
+        .iter()
+        .flatten()
+        .any(|mount| mount.mountpoint == mountpoint)
+    {
+        return Err(FsError::Busy);
+    }
+    let slot = mounts
+        .iter_mut()
+        .find(|slot| slot.is_none())
+        .ok_or(FsError::NoSpace)?;
+    *slot = Some(entry);
+
+    Ok(())
+}
+
+/// Unmounts the filesystem mounted last at a path, after writing back its changes.
+///
+/// This fails with `Busy` while files of the filesystem are open, or other filesystems are mounted inside it.
+pub fn unmount(path: &str) -> Result<(), FsError> {
+    let vnode = path::resolve(path::normalize(path)?, true)?;
+    let fs = vnode.fs()?;
+    if vnode.inode != fs.root() {
+        return Err(FsError::InvalidPath);
+    }
+
+    let busy = MOUNTS.read().iter().flatten().any(|mount| {
+        mount
+            .mountpoint
+            .is_some_and(|mountpoint| mountpoint.mount == vnode.mount)
+    });
+    if busy || file::is_mount_in_use(vnode.mount) {
+        return Err(FsError::Busy);
+    }
+
+    fs.sync()?;
+
+    MOUNTS.write()[vnode.mount] = None;
+    dentry::invalidate_mount(vnode.mount);
+
+    Ok(())
+}
+
+/// Returns the mounted filesystems in the order they have been mounted.
+pub fn mounts() -> impl Iterator<Item = MountInfo> {
+    let mut infos = [None; MAX_MOUNTS];
+    for (info, mount) in infos.iter_mut().zip(MOUNTS.read().iter()) {
+        *info = mount.map(|mount| MountInfo {
+            path: mount.path,
+            path_len: mount.path_len,
+            fs: mount.fs,
+        });
+    }
+
+    infos.into_iter().flatten()
+}
+
+/// Returns the filesystem of a mount.
+pub(super) fn fs(mount: usize) -> Result<&'static dyn FileSystem, FsError> {
+    MOUNTS
+        .read()
+        .get(mount)
+        .copied()
+        .flatten()
+        .map(|mount| mount.fs)
+        .ok_or(FsError::NotFound)
+}
+
+/// Returns the root directory of the root filesystem.
+pub(super) fn root() -> Result<Vnode, FsError> {
+    let mounts = MOUNTS.read();
+    let (index, mount) = mounts
+        .iter()
+        .enumerate()
+        .find_map(|(index, mount)| {
+            mount
+                .filter(|mount| mount.mountpoint.is_none())
+                .map(|mount| (index, mount))
+        })
+        .ok_or(FsError::NotFound)?;
+
+    Ok(cross(&mounts, Vnode::new(index, mount.fs.root())))
+}
+
+/// Follows a directory to the root of the filesystem mounted on top of it, if any.
+pub(super) fn enter(vnode: Vnode) -> Vnode {
+    cross(&MOUNTS.read(), vnode)
+}
+
+/// Checks whether a filesystem is mounted on top of a directory.
+pub(super) fn is_mountpoint(vnode: Vnode) -> bool {
+    MOUNTS
+        .read()
+        .iter()
+        .flatten()
+        .any(|mount| mount.mountpoint == Some(vnode))
+}
+
+fn cross(mounts: &[Option<Mount>; MAX_MOUNTS], mut vnode: Vnode) -> Vnode {
+    while let Some((index, mount)) = mounts.iter().enumerate().find_map(|(index, mount)| {
+        mount
+            .filter(|mount| mount.mountpoint == Some(vnode))
+            .map(|mount| (index, mount))
+    }) {
+        vnode = Vnode::new(index, mount.fs.root());
+    }
+
+    vnode
+}
@@ -0,0 +1,257 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::str;
+
+use super::{
+    dentry, file, mount, FileSystem, FileType, FsError, InodeId, Metadata, MAX_NAME_LEN,
+    MAX_PATH_LEN,
+};
+
+/// The maximum number of symbolic links followed while resolving a path.
+const MAX_LINKS: usize = 8;
+/// The maximum number of directories a path can descend into.
+const MAX_DEPTH: usize = 64;
+
+/// An inode within one of the mounted filesystems.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct Vnode {
+    /// The index of the filesystem in the mount table.
+    pub mount: usize,
+    pub inode: InodeId,
+}
+
+impl Vnode {
+    pub fn new(mount: usize, inode: InodeId) -> Self {
+        Self { mount, inode }
+    }
+
+    pub fn fs(&self) -> Result<&'static dyn FileSystem, FsError> {
+        mount::fs(self.mount)
+    }
+
+    pub fn metadata(&self) -> Result<Metadata, FsError> {
+        self.fs()?.metadata(self.inode)
+    }
+
+    /// Finds an entry of the directory, and follows it to the root of a filesystem mounted on top of it.
+    fn lookup(&self, name: &str) -> Result<Vnode, FsError> {
+        let inode = match dentry::lookup(self.mount, self.inode, name) {
+            Some(inode) => inode,
+            None => {
+                let inode = self.fs()?.lookup(self.inode, name)?;
+                dentry::insert(self.mount, self.inode, name, inode);
+                inode
+            }
+        };
+
+        Ok(mount::enter(Vnode::new(self.mount, inode)))
+    }
+}
+
+/// The state of a path walk, which keeps the directories it passed through, so `..` can go back up across mount
+/// points and symbolic links.
+struct Walk {
+    stack: [Vnode; MAX_DEPTH],
+    depth: usize,
+    links: usize,
+}
+
+impl Walk {
+    fn new() -> Result<Self, FsError> {
+        let root = mount::root()?;
+
+        Ok(Self {
+            stack: [root; MAX_DEPTH],
+            depth: 1,
+            links: 0,
+        })
+    }
+
+    fn current(&self) -> Vnode {
+        self.stack[self.depth - 1]
+    }
+
+    fn push(&mut self, vnode: Vnode) -> Result<(), FsError> {
+        *self.stack.get_mut(self.depth).ok_or(FsError::NameTooLong)? = vnode;
+        self.depth += 1;
+
+        Ok(())
+    }
+
+    /// Walks a path relative to the current directory, unless it's absolute.
+    fn walk(&mut self, path: &str, follow: bool) -> Result<(), FsError> {
+        if path.starts_with('/') {
+            self.depth = 1;
+        }
+
+        let mut components = path
+            .split('/')
+            .filter(|component| !component.is_empty())
+            .peekable();
+        while let Some(component) = components.next() {
+            if !self.current().metadata()?.is_dir() {
+                return Err(FsError::NotADirectory);
+            }
+
+            match component {
+                "." => {}
+                ".." => self.depth = self.depth.saturating_sub(1).max(1),
+                name => {
+                    if name.len() > MAX_NAME_LEN {
+                        return Err(FsError::NameTooLong);
+                    }
+
+                    let vnode = self.current().lookup(name)?;
+                    let is_last = components.peek().is_none();
+                    if (follow || !is_last) && vnode.metadata()?.file_type == FileType::Symlink {
+                        self.follow(vnode)?;
+                    } else {
+                        self.push(vnode)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Continues the walk at the target of a symbolic link, which is relative to the directory containing it.
+    fn follow(&mut self, link: Vnode) -> Result<(), FsError> {
+        self.links += 1;
+        if self.links > MAX_LINKS {
+            return Err(FsError::TooManyLinks);
+        }
+
+        let mut buf = [0; MAX_PATH_LEN];
+        let len = link.fs()?.read_link(link.inode, &mut buf)?;
+        let target = buf
+            .get(..len)
+            .and_then(|target| str::from_utf8(target).ok())
+            .ok_or(FsError::Corrupted)?;
+
+        self.walk(target, true)
+    }
+}
+
+/// Checks that a path is absolute, and strips the slashes at its end.
+pub(super) fn normalize(path: &str) -> Result<&str, FsError> {
+    if !path.starts_with('/') {
+        return Err(FsError::InvalidPath);
+    }
+    if path.len() > MAX_PATH_LEN {
+        return Err(FsError::NameTooLong);
+    }
+
+    match path.trim_end_matches('/') {
+        "" => Ok("/"),
+        path => Ok(path),
+    }
+}
+
+/// Finds the inode at a path, following a symbolic link at its end only if asked to.
+pub(super) fn resolve(path: &str, follow: bool) -> Result<Vnode, FsError> {
+    let mut walk = Walk::new()?;
+    walk.walk(normalize(path)?, follow)?;
+
+    Ok(walk.current())
+}
+
+/// Finds the directory containing the last component of a path, and returns it along with that component.
+pub(super) fn resolve_parent(path: &str) -> Result<(Vnode, &str), FsError> {
+    let path = normalize(path)?;
+    let (parent, name) = path.rsplit_once('/').ok_or(FsError::InvalidPath)?;
+    if matches!(name, "" | "." | "..") {
+        return Err(FsError::InvalidPath);
+    }
+    if name.len() > MAX_NAME_LEN {
+        return Err(FsError::NameTooLong);
+    }
+
+    let parent = resolve(if parent.is_empty() { "/" } else { parent }, true)?;
+    if !parent.metadata()?.is_dir() {
+        return Err(FsError::NotADirectory);
+    }
+
+    Ok((parent, name))
+}
+
+/// Finds an entry of a directory without following it into a mounted filesystem.
+pub(super) fn lookup(dir: Vnode, name: &str) -> Result<Vnode, FsError> {
+    let inode = dir.fs()?.lookup(dir.inode, name)?;
+
+    Ok(Vnode::new(dir.mount, inode))
+}
+
+/// Returns the attributes of the inode at a path, following symbolic links.
+pub fn metadata(path: &str) -> Result<Metadata, FsError> {
+    resolve(path, true)?.metadata()
+}
+
+/// Returns the attributes of the inode at a path, without following a symbolic link at its end.
+pub fn symlink_metadata(path: &str) -> Result<Metadata, FsError> {
+    resolve(path, false)?.metadata()
+}
+
+/// Creates a directory.
+pub fn mkdir(path: &str, mode: u16) -> Result<(), FsError> {
+    let (parent, name) = resolve_parent(path)?;
+    parent
+        .fs()?
+        .create(parent.inode, name, FileType::Directory, mode)?;
+
+    Ok(())
+}
+
+/// Creates a symbolic link at a path, which points to a target.
+pub fn symlink(target: &str, path: &str) -> Result<(), FsError> {
+    if target.is_empty() || target.len() > MAX_PATH_LEN {
+        return Err(FsError::InvalidPath);
+    }
+
+    let (parent, name) = resolve_parent(path)?;
+    parent.fs()?.symlink(parent.inode, name, target)?;
+
+    Ok(())
+}
+
+/// Reads the target of a symbolic link, and returns its length.
+pub fn read_link(path: &str, buf: &mut [u8]) -> Result<usize, FsError> {
+    let vnode = resolve(path, false)?;
+    vnode.fs()?.read_link(vnode.inode, buf)
+}
+
+/// Removes a file, a symbolic link or an empty directory.
+///
+/// This fails with `Busy` while the inode is open, or a filesystem is mounted on top of it.
+pub fn remove(path: &str) -> Result<(), FsError> {
+    let (parent, name) = resolve_parent(path)?;
+    let vnode = lookup(parent, name)?;
+    if mount::is_mountpoint(vnode) || file::is_open(vnode) {
+        return Err(FsError::Busy);
+    }
+
+    parent.fs()?.remove(parent.inode, name)?;
+    dentry::invalidate(parent.mount, parent.inode, name);
+
+    Ok(())
+}
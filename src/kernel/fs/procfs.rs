@@ -0,0 +1,362 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::fmt;
+use core::fmt::Write;
+
+use crate::aux::log;
+use crate::kernel::arch::frame::FRAME_SIZE;
+use crate::kernel::arch::{self, acpi, irq, MemoryKind};
+use crate::kernel::block::cache;
+use crate::kernel::pci;
+use crate::kernel::thread;
+use crate::kernel::thread::Priority;
+use crate::kernel::timer;
+
+use super::{DirEntry, FileSystem, FileType, FsError, InodeId, Metadata};
+
+const ROOT: InodeId = 0;
+
+/// A function writing the contents of a file, which are generated anew each time it's read.
+type Generator = fn(&mut Window) -> fmt::Result;
+
+/// The files, whose inodes follow the root in this order.
+const FILES: &[(&str, Generator)] = &[
+    ("acpi", acpi_tables),
+    ("config", config),
+    ("interrupts", interrupts),
+    ("log", log_history),
+    ("memmap", memory_map),
+    ("meminfo", memory_info),
+    ("multiboot", multiboot),
+    ("pci", pci_devices),
+    ("sched", scheduler),
+    ("threads", threads),
+];
+
+/// Keeps the part of a text which falls into a window, i.e., the range of a file being read, and measures the whole.
+struct Window<'a> {
+    buf: &'a mut [u8],
+    offset: u64,
+    /// The length of the text so far.
+    len: u64,
+}
+
+impl<'a> Window<'a> {
+    fn new(offset: u64, buf: &'a mut [u8]) -> Self {
+        Self {
+            buf,
+            offset,
+            len: 0,
+        }
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        let start = self.len;
+        self.len += bytes.len() as u64;
+
+        let from = start.max(self.offset);
+        let to = self.len.min(self.offset + self.buf.len() as u64);
+        if from < to {
+            self.buf[(from - self.offset) as usize..(to - self.offset) as usize]
+                .copy_from_slice(&bytes[(from - start) as usize..(to - start) as usize]);
+        }
+    }
+
+    /// Returns the number of bytes which have been kept.
+    fn filled(&self) -> usize {
+        self.len
+            .saturating_sub(self.offset)
+            .min(self.buf.len() as u64) as usize
+    }
+}
+
+impl Write for Window<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+fn acpi_tables(out: &mut Window) -> fmt::Result {
+    match acpi::revision() {
+        Some((revision, oem_id)) => writeln!(out, "revision {} ({})", revision, oem_id)?,
+        None => return writeln!(out, "no ACPI tables"),
+    }
+
+    writeln!(
+        out,
+        "SIGNATURE  ADDRESS             LENGTH  REV  OEM     TABLE"
+    )?;
+    for table in acpi::tables() {
+        let (oem_id, oem_table_id) = table.oem();
+        writeln!(
+            out,
+            "{:<9}  {:#018x}  {:>6}  {:>3}  {:<6}  {}",
+            table.signature(),
+            table.phys_addr,
+            table.length(),
+            table.revision(),
+            oem_id,
+            oem_table_id
+        )?;
+    }
+
+    Ok(())
+}
+
+fn config(out: &mut Window) -> fmt::Result {
+    for (name, value) in arch::CONFIGURATION {
+        writeln!(out, "{}={}", name, value)?;
+    }
+
+    Ok(())
+}
+
+fn interrupts(out: &mut Window) -> fmt::Result {
+    writeln!(out, "IRQ  VECTOR       COUNT  TYPE")?;
+    for irq in 0..irq::IRQ_COUNT {
+        let count = irq::count(irq);
+        if count == 0 && !irq::is_registered(irq) {
+            continue;
+        }
+
+        let kind = match irq::is_legacy(irq) {
+            true => "legacy",
+            false => "allocated",
+        };
+        writeln!(
+            out,
+            "{:>3}  {:#6x}  {:>10}  {}",
+            irq,
+            irq::vector(irq),
+            count,
+            kind
+        )?;
+    }
+
+    Ok(())
+}
+
+fn log_history(out: &mut Window) -> fmt::Result {
+    log::with_history(|first, second| {
+        out.write_bytes(first);
+        out.write_bytes(second);
+    });
+
+    Ok(())
+}
+
+fn memory_map(out: &mut Window) -> fmt::Result {
+    for area in arch::memory_map() {
+        let kind = match area.kind {
+            MemoryKind::Available => "available",
+            MemoryKind::Reserved => "reserved",
+            MemoryKind::AcpiReclaimable => "ACPI reclaimable",
+            MemoryKind::AcpiNvs => "ACPI NVS",
+            MemoryKind::Defective => "defective",
+        };
+        writeln!(
+            out,
+            "{:#018x}-{:#018x}  {}",
+            area.start.as_u64(),
+            area.start.as_u64() + area.len,
+            kind
+        )?;
+    }
+
+    Ok(())
+}
+
+fn memory_info(out: &mut Window) -> fmt::Result {
+    let frames = arch::frame::stats();
+    let kib = |frames: usize| frames * FRAME_SIZE / 1024;
+    writeln!(out, "MemTotal:      {:>10} kB", kib(frames.total))?;
+    writeln!(out, "MemFree:       {:>10} kB", kib(frames.free))?;
+    writeln!(
+        out,
+        "MemUsed:       {:>10} kB",
+        kib(frames.total - frames.free)
+    )?;
+
+    let cache = cache::stats();
+    writeln!(out, "CacheBuffers:  {:>10}", cache.capacity)?;
+    writeln!(out, "CacheUsed:     {:>10}", cache.used)?;
+    writeln!(out, "CacheDirty:    {:>10}", cache.dirty)?;
+    writeln!(out, "CacheHits:     {:>10}", cache.hits)?;
+    writeln!(out, "CacheMisses:   {:>10}", cache.misses)?;
+    writeln!(out, "CacheWriteBacks: {:>8}", cache.write_backs)
+}
+
+fn multiboot(out: &mut Window) -> fmt::Result {
+    writeln!(out, "bootloader: {}", arch::boot_loader_name())?;
+    writeln!(out, "cmdline: {}", crate::kernel::cmdline::cmdline())?;
+    for (index, module) in arch::modules().enumerate() {
+        writeln!(
+            out,
+            "module {}: {:#x}-{:#x} ({} KiB) {}",
+            index,
+            module.start.as_u64(),
+            module.start.as_u64() + module.len as u64,
+            module.len / 1024,
+            module.cmdline
+        )?;
+    }
+
+    Ok(())
+}
+
+fn pci_devices(out: &mut Window) -> fmt::Result {
+    for (device, driver) in pci::devices() {
+        writeln!(
+            out,
+            "{}  {:04x}:{:04x}  {}  [{}]",
+            device.addr,
+            device.vendor_id,
+            device.device_id,
+            device.class_name(),
+            driver.unwrap_or("none")
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Converts ticks of the timer into milliseconds.
+fn millis(ticks: u64) -> u64 {
+    ticks * 1000 / timer::TICK_RATE
+}
+
+fn scheduler(out: &mut Window) -> fmt::Result {
+    let stats = thread::stats();
+    writeln!(out, "Uptime:        {:>10} ms", millis(timer::ticks()))?;
+    writeln!(out, "IdleTime:      {:>10} ms", millis(stats.idle_time))?;
+    writeln!(out, "TimeSlice:     {:>10} ms", millis(thread::TIME_SLICE))?;
+    writeln!(out, "Switches:      {:>10}", stats.switches)?;
+    writeln!(out, "Preemptions:   {:>10}", stats.preemptions)?;
+    for (priority, ready) in Priority::ALL.iter().zip(stats.ready) {
+        writeln!(out, "Ready {:<8} {:>10}", priority, ready)?;
+    }
+
+    Ok(())
+}
+
+fn threads(out: &mut Window) -> fmt::Result {
+    writeln!(out, "ID  PRIORITY  STATE        RUNTIME  SWITCHES  NAME")?;
+    for thread in thread::threads() {
+        writeln!(
+            out,
+            "{:>2}  {:<8}  {:<8}  {:>8} ms  {:>8}  {}",
+            thread.id,
+            thread.priority,
+            thread.state,
+            millis(thread.run_time),
+            thread.switches,
+            thread.name
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Process Filesystem (procfs)
+///
+/// A read-only filesystem of text files describing the state of the kernel, e.g., `meminfo` for the use of the
+/// physical memory or `interrupts` for the number of interrupts of each IRQ. The files are generated as they're read,
+/// so their contents are only consistent if each is read in one go.
+pub struct Procfs;
+
+static PROCFS: Procfs = Procfs;
+
+impl Procfs {
+    fn file(inode: InodeId) -> Result<Generator, FsError> {
+        let index = inode.checked_sub(1).ok_or(FsError::IsADirectory)?;
+        FILES
+            .get(index as usize)
+            .map(|(_, generate)| *generate)
+            .ok_or(FsError::NotFound)
+    }
+}
+
+impl FileSystem for Procfs {
+    fn name(&self) -> &str {
+        "procfs"
+    }
+
+    fn root(&self) -> InodeId {
+        ROOT
+    }
+
+    fn lookup(&self, dir: InodeId, name: &str) -> Result<InodeId, FsError> {
+        if dir != ROOT {
+            return Procfs::file(dir).and(Err(FsError::NotADirectory));
+        }
+
+        FILES
+            .iter()
+            .position(|(file, _)| *file == name)
+            .map(|index| index as InodeId + 1)
+            .ok_or(FsError::NotFound)
+    }
+
+    fn metadata(&self, inode: InodeId) -> Result<Metadata, FsError> {
+        if inode == ROOT {
+            return Ok(Metadata {
+                links: 2,
+                ..Metadata::new(ROOT, FileType::Directory, 0, 0o555)
+            });
+        }
+
+        // The size is that of the contents at this moment, as they're generated anyway.
+        let mut window = Window::new(0, &mut []);
+        Procfs::file(inode)?(&mut window).map_err(|_| FsError::Corrupted)?;
+
+        Ok(Metadata::new(inode, FileType::Regular, window.len, 0o444))
+    }
+
+    fn read(&self, inode: InodeId, offset: u64, buf: &mut [u8]) -> Result<usize, FsError> {
+        let generate = Procfs::file(inode)?;
+        let mut window = Window::new(offset, buf);
+        generate(&mut window).map_err(|_| FsError::Corrupted)?;
+
+        Ok(window.filled())
+    }
+
+    fn read_dir(&self, dir: InodeId, position: u64) -> Result<Option<(DirEntry, u64)>, FsError> {
+        if dir != ROOT {
+            return Procfs::file(dir).and(Err(FsError::NotADirectory));
+        }
+
+        // The position is the number of the entry.
+        let Some((name, _)) = FILES.get(position as usize) else {
+            return Ok(None);
+        };
+        let entry = DirEntry::new(position + 1, FileType::Regular, name)?;
+
+        Ok(Some((entry, position + 1)))
+    }
+}
+
+/// Mounts the information about the kernel at `/proc`.
+pub(super) fn init() -> Result<(), ()> {
+    super::mount("/proc", &PROCFS).map_err(|_| ())
+}
@@ -0,0 +1,482 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::ptr;
+use core::str;
+
+use crate::kernel::arch;
+use crate::kernel::arch::frame::FRAME_SIZE;
+use crate::kernel::arch::PhysAddr;
+use crate::kernel::sync::RwLock;
+
+use super::{DirEntry, FileSystem, FileType, FsError, InodeId, Metadata, MAX_NAME_LEN};
+
+/// The maximum number of files and directories the filesystem can hold.
+const MAX_INODES: usize = 256;
+
+/// The position returned by `read_dir` after the last entry of a directory.
+const END_OF_DIR: u64 = u64::MAX;
+
+/// The number of pages of a file, whose addresses fill a single page.
+const PAGES_PER_FILE: usize = FRAME_SIZE / 8;
+const MAX_FILE_SIZE: u64 = (PAGES_PER_FILE * FRAME_SIZE) as u64;
+
+#[derive(Clone, Copy)]
+struct Node {
+    /// The type of the inode, or `None` if the slot is free.
+    file_type: Option<FileType>,
+    name: [u8; MAX_NAME_LEN],
+    name_len: u8,
+    parent: usize,
+    first_child: Option<usize>,
+    next_sibling: Option<usize>,
+    /// The size of a regular file, or the length of the target of a symbolic link.
+    size: u64,
+    mode: u16,
+    /// The page holding the addresses of the pages of the data, which are allocated as they're written.
+    index: Option<PhysAddr>,
+}
+
+impl Node {
+    const FREE: Node = Node {
+        file_type: None,
+        name: [0; MAX_NAME_LEN],
+        name_len: 0,
+        parent: 0,
+        first_child: None,
+        next_sibling: None,
+        size: 0,
+        mode: 0,
+        index: None,
+    };
+
+    const ROOT: Node = Node {
+        file_type: Some(FileType::Directory),
+        mode: 0o1777,
+        ..Node::FREE
+    };
+
+    fn name(&self) -> &str {
+        str::from_utf8(&self.name[..self.name_len as usize]).unwrap_or_default()
+    }
+
+    fn is_dir(&self) -> bool {
+        self.file_type == Some(FileType::Directory)
+    }
+
+    /// Returns the address of a page of the data, if it has been written.
+    fn page(&self, page: usize) -> Option<PhysAddr> {
+        let index = self.index?;
+        let addr = unsafe { *page_ptr(index).cast::<u64>().add(page) };
+
+        (addr != 0).then(|| PhysAddr::new(addr))
+    }
+
+    /// Returns the address of a page of the data, which is allocated if it hasn't been written yet.
+    fn page_or_allocate(&mut self, page: usize) -> Result<PhysAddr, FsError> {
+        let index = match self.index {
+            Some(index) => index,
+            None => *self.index.insert(allocate_page()?),
+        };
+
+        let entry = unsafe { page_ptr(index).cast::<u64>().add(page) };
+        match unsafe { *entry } {
+            0 => {
+                let addr = allocate_page()?;
+                unsafe { *entry = addr.as_u64() };
+                Ok(addr)
+            }
+            addr => Ok(PhysAddr::new(addr)),
+        }
+    }
+
+    /// Frees the pages of the data from a page on, as well as the index if no page is left.
+    fn free_pages(&mut self, from: usize) {
+        let Some(index) = self.index else {
+            return;
+        };
+
+        let entries = page_ptr(index).cast::<u64>();
+        for page in from..PAGES_PER_FILE {
+            let entry = unsafe { entries.add(page) };
+            if unsafe { *entry } != 0 {
+                arch::frame::deallocate(PhysAddr::new(unsafe { *entry }), 1);
+                unsafe { *entry = 0 };
+            }
+        }
+
+        if from == 0 {
+            arch::frame::deallocate(index, 1);
+            self.index = None;
+        }
+    }
+
+    fn read(&self, offset: u64, buf: &mut [u8]) -> usize {
+        if offset >= self.size {
+            return 0;
+        }
+        let len = buf.len().min((self.size - offset) as usize);
+
+        let mut done = 0;
+        while done < len {
+            let pos = offset as usize + done;
+            let start = pos % FRAME_SIZE;
+            let chunk = (FRAME_SIZE - start).min(len - done);
+            let target = &mut buf[done..done + chunk];
+
+            match self.page(pos / FRAME_SIZE) {
+                Some(page) => unsafe {
+                    ptr::copy_nonoverlapping(page_ptr(page).add(start), target.as_mut_ptr(), chunk)
+                },
+                None => target.fill(0),
+            }
+            done += chunk;
+        }
+
+        len
+    }
+
+    fn write(&mut self, offset: u64, buf: &[u8]) -> Result<usize, FsError> {
+        offset
+            .checked_add(buf.len() as u64)
+            .filter(|end| *end <= MAX_FILE_SIZE)
+            .ok_or(FsError::NoSpace)?;
+
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = offset as usize + done;
+            let start = pos % FRAME_SIZE;
+            let chunk = (FRAME_SIZE - start).min(buf.len() - done);
+
+            let page = self.page_or_allocate(pos / FRAME_SIZE)?;
+            unsafe {
+                ptr::copy_nonoverlapping(buf[done..].as_ptr(), page_ptr(page).add(start), chunk)
+            };
+            done += chunk;
+            // The size follows the data written, so it stays consistent if the memory runs out.
+            self.size = self.size.max(pos as u64 + chunk as u64);
+        }
+
+        Ok(buf.len())
+    }
+
+    fn truncate(&mut self, size: u64) -> Result<(), FsError> {
+        if size > MAX_FILE_SIZE {
+            return Err(FsError::NoSpace);
+        }
+
+        if size < self.size {
+            self.free_pages((size as usize).div_ceil(FRAME_SIZE));
+
+            // The rest of the last page is cleared, as it would reappear if the file grows again.
+            let start = size as usize % FRAME_SIZE;
+            if let (Some(page), true) = (self.page(size as usize / FRAME_SIZE), start != 0) {
+                unsafe { ptr::write_bytes(page_ptr(page).add(start), 0, FRAME_SIZE - start) };
+            }
+        }
+        self.size = size;
+
+        Ok(())
+    }
+}
+
+/// Returns a pointer to a page, which is reachable through the direct mapping like every allocated frame.
+fn page_ptr(addr: PhysAddr) -> *mut u8 {
+    arch::paging::phys_to_virt(addr)
+        .expect("tmpfs page is outside of the direct mapping")
+        .as_mut_ptr()
+}
+
+fn allocate_page() -> Result<PhysAddr, FsError> {
+    let addr = arch::frame::allocate(1).ok_or(FsError::NoSpace)?;
+    unsafe { ptr::write_bytes(page_ptr(addr), 0, FRAME_SIZE) };
+
+    Ok(addr)
+}
+
+struct Table {
+    nodes: [Node; MAX_INODES],
+}
+
+impl Table {
+    const fn new() -> Self {
+        let mut nodes = [Node::FREE; MAX_INODES];
+        nodes[0] = Node::ROOT;
+
+        Self { nodes }
+    }
+
+    fn get(&self, inode: InodeId) -> Result<&Node, FsError> {
+        self.nodes
+            .get(inode as usize)
+            .filter(|node| node.file_type.is_some())
+            .ok_or(FsError::NotFound)
+    }
+
+    fn get_mut(&mut self, inode: InodeId) -> Result<&mut Node, FsError> {
+        self.nodes
+            .get_mut(inode as usize)
+            .filter(|node| node.file_type.is_some())
+            .ok_or(FsError::NotFound)
+    }
+
+    /// Returns a regular file, whose data can be changed.
+    fn file_mut(&mut self, inode: InodeId) -> Result<&mut Node, FsError> {
+        let node = self.get_mut(inode)?;
+        match node.file_type {
+            Some(FileType::Regular) => Ok(node),
+            Some(FileType::Directory) => Err(FsError::IsADirectory),
+            _ => Err(FsError::InvalidPath),
+        }
+    }
+
+    fn dir(&self, inode: InodeId) -> Result<&Node, FsError> {
+        match self.get(inode)? {
+            node if node.is_dir() => Ok(node),
+            _ => Err(FsError::NotADirectory),
+        }
+    }
+
+    fn children(&self, dir: usize) -> impl Iterator<Item = usize> + '_ {
+        let mut next = self.nodes[dir].first_child;
+        core::iter::from_fn(move || {
+            let child = next?;
+            next = self.nodes[child].next_sibling;
+            Some(child)
+        })
+    }
+
+    fn child(&self, dir: usize, name: &str) -> Option<usize> {
+        self.children(dir)
+            .find(|child| self.nodes[*child].name() == name)
+    }
+
+    /// Adds an empty inode to a directory, after its other entries.
+    fn add(
+        &mut self,
+        dir: InodeId,
+        name: &str,
+        file_type: FileType,
+        mode: u16,
+    ) -> Result<usize, FsError> {
+        self.dir(dir)?;
+        let dir = dir as usize;
+        if name.len() > MAX_NAME_LEN {
+            return Err(FsError::NameTooLong);
+        }
+        if self.child(dir, name).is_some() {
+            return Err(FsError::AlreadyExists);
+        }
+
+        let index = self
+            .nodes
+            .iter()
+            .position(|node| node.file_type.is_none())
+            .ok_or(FsError::NoSpace)?;
+
+        let node = &mut self.nodes[index];
+        *node = Node {
+            file_type: Some(file_type),
+            name_len: name.len() as u8,
+            parent: dir,
+            mode,
+            ..Node::FREE
+        };
+        node.name[..name.len()].copy_from_slice(name.as_bytes());
+
+        match self.children(dir).last() {
+            Some(last) => self.nodes[last].next_sibling = Some(index),
+            None => self.nodes[dir].first_child = Some(index),
+        }
+
+        Ok(index)
+    }
+
+    fn remove(&mut self, dir: InodeId, name: &str) -> Result<(), FsError> {
+        self.dir(dir)?;
+        let dir = dir as usize;
+        let index = self.child(dir, name).ok_or(FsError::NotFound)?;
+        if self.nodes[index].first_child.is_some() {
+            return Err(FsError::NotEmpty);
+        }
+
+        let next = self.nodes[index].next_sibling;
+        let previous = self
+            .children(dir)
+            .find(|child| self.nodes[*child].next_sibling == Some(index));
+        match previous {
+            Some(previous) => self.nodes[previous].next_sibling = next,
+            None => self.nodes[dir].first_child = next,
+        }
+
+        self.nodes[index].free_pages(0);
+        self.nodes[index] = Node::FREE;
+
+        Ok(())
+    }
+}
+
+/// Temporary Filesystem (tmpfs)
+///
+/// A writable filesystem which keeps everything in memory, and so loses its contents on reboot. The data of a file is
+/// stored in pages allocated as they're written, whose addresses are listed in a single page per file.
+pub struct Tmpfs {
+    table: RwLock<Table>,
+}
+
+impl Tmpfs {
+    pub const fn new() -> Self {
+        Self {
+            table: RwLock::new(Table::new()),
+        }
+    }
+}
+
+impl Default for Tmpfs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+static TMP: Tmpfs = Tmpfs::new();
+
+impl FileSystem for Tmpfs {
+    fn name(&self) -> &str {
+        "tmpfs"
+    }
+
+    fn root(&self) -> InodeId {
+        0
+    }
+
+    fn lookup(&self, dir: InodeId, name: &str) -> Result<InodeId, FsError> {
+        let table = self.table.read();
+        table.dir(dir)?;
+
+        table
+            .child(dir as usize, name)
+            .map(|child| child as InodeId)
+            .ok_or(FsError::NotFound)
+    }
+
+    fn metadata(&self, inode: InodeId) -> Result<Metadata, FsError> {
+        let table = self.table.read();
+        let node = table.get(inode)?;
+        let file_type = node.file_type.ok_or(FsError::NotFound)?;
+
+        Ok(Metadata {
+            links: if node.is_dir() { 2 } else { 1 },
+            ..Metadata::new(inode, file_type, node.size, node.mode)
+        })
+    }
+
+    fn read(&self, inode: InodeId, offset: u64, buf: &mut [u8]) -> Result<usize, FsError> {
+        match self.table.read().get(inode)? {
+            node if node.is_dir() => Err(FsError::IsADirectory),
+            node => Ok(node.read(offset, buf)),
+        }
+    }
+
+    fn write(&self, inode: InodeId, offset: u64, buf: &[u8]) -> Result<usize, FsError> {
+        self.table.write().file_mut(inode)?.write(offset, buf)
+    }
+
+    fn truncate(&self, inode: InodeId, size: u64) -> Result<(), FsError> {
+        self.table.write().file_mut(inode)?.truncate(size)
+    }
+
+    fn read_dir(&self, dir: InodeId, position: u64) -> Result<Option<(DirEntry, u64)>, FsError> {
+        let table = self.table.read();
+        let node = table.dir(dir)?;
+
+        // The position is the number of the next entry, as the root is never an entry itself.
+        let child = match position {
+            0 => node.first_child,
+            END_OF_DIR => None,
+            position => Some(position as usize).filter(|child| {
+                table
+                    .get(*child as InodeId)
+                    .is_ok_and(|child| child.parent == dir as usize)
+            }),
+        };
+        let Some(child) = child else {
+            return Ok(None);
+        };
+
+        let node = &table.nodes[child];
+        let file_type = node.file_type.ok_or(FsError::NotFound)?;
+        let entry = DirEntry::new(child as InodeId, file_type, node.name())?;
+        let next = node.next_sibling.map_or(END_OF_DIR, |next| next as u64);
+
+        Ok(Some((entry, next)))
+    }
+
+    fn read_link(&self, inode: InodeId, buf: &mut [u8]) -> Result<usize, FsError> {
+        let table = self.table.read();
+        let node = table.get(inode)?;
+        if node.file_type != Some(FileType::Symlink) {
+            return Err(FsError::InvalidPath);
+        }
+
+        let target = buf
+            .get_mut(..node.size as usize)
+            .ok_or(FsError::NameTooLong)?;
+        Ok(node.read(0, target))
+    }
+
+    fn create(
+        &self,
+        dir: InodeId,
+        name: &str,
+        file_type: FileType,
+        mode: u16,
+    ) -> Result<InodeId, FsError> {
+        if !matches!(file_type, FileType::Regular | FileType::Directory) {
+            return Err(FsError::Unsupported);
+        }
+
+        self.table
+            .write()
+            .add(dir, name, file_type, mode)
+            .map(|inode| inode as InodeId)
+    }
+
+    fn symlink(&self, dir: InodeId, name: &str, target: &str) -> Result<InodeId, FsError> {
+        let mut table = self.table.write();
+        let inode = table.add(dir, name, FileType::Symlink, 0o777)?;
+        if let Err(error) = table.nodes[inode].write(0, target.as_bytes()) {
+            table.remove(dir, name)?;
+            return Err(error);
+        }
+
+        Ok(inode as InodeId)
+    }
+
+    fn remove(&self, dir: InodeId, name: &str) -> Result<(), FsError> {
+        self.table.write().remove(dir, name)
+    }
+}
+
+/// Mounts an empty tmpfs at `/tmp`.
+pub(super) fn init() -> Result<(), ()> {
+    super::mount("/tmp", &TMP).map_err(|_| ())
+}
@@ -0,0 +1,654 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use super::keymap;
+
+/// The keys of a standard 105-key keyboard, independently of their layout.
+///
+/// The keys are named after their legend on a US keyboard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum KeyCode {
+    Escape,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    PrintScreen,
+    ScrollLock,
+    Pause,
+    Backtick,
+    Digit1,
+    Digit2,
+    Digit3,
+    Digit4,
+    Digit5,
+    Digit6,
+    Digit7,
+    Digit8,
+    Digit9,
+    Digit0,
+    Minus,
+    Equals,
+    Backspace,
+    Tab,
+    Q,
+    W,
+    E,
+    R,
+    T,
+    Y,
+    U,
+    I,
+    O,
+    P,
+    LeftBracket,
+    RightBracket,
+    Backslash,
+    CapsLock,
+    A,
+    S,
+    D,
+    F,
+    G,
+    H,
+    J,
+    K,
+    L,
+    Semicolon,
+    Quote,
+    Enter,
+    LeftShift,
+    /// The key between the left shift and Z on ISO keyboards.
+    NonUsBackslash,
+    Z,
+    X,
+    C,
+    V,
+    B,
+    N,
+    M,
+    Comma,
+    Period,
+    Slash,
+    RightShift,
+    LeftCtrl,
+    LeftMeta,
+    LeftAlt,
+    Space,
+    RightAlt,
+    RightMeta,
+    Menu,
+    RightCtrl,
+    Insert,
+    Home,
+    PageUp,
+    Delete,
+    End,
+    PageDown,
+    ArrowUp,
+    ArrowLeft,
+    ArrowDown,
+    ArrowRight,
+    NumLock,
+    KeypadSlash,
+    KeypadAsterisk,
+    KeypadMinus,
+    KeypadPlus,
+    KeypadEnter,
+    KeypadPeriod,
+    Keypad0,
+    Keypad1,
+    Keypad2,
+    Keypad3,
+    Keypad4,
+    Keypad5,
+    Keypad6,
+    Keypad7,
+    Keypad8,
+    Keypad9,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyState {
+    /// The key has been pressed, or is being held down and repeated by the keyboard.
+    Pressed,
+    Released,
+}
+
+/// The state of the modifier keys and of the lock keys.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Modifiers(u16);
+
+impl Modifiers {
+    pub const LEFT_SHIFT: Modifiers = Modifiers(1 << 0);
+    pub const RIGHT_SHIFT: Modifiers = Modifiers(1 << 1);
+    pub const LEFT_CTRL: Modifiers = Modifiers(1 << 2);
+    pub const RIGHT_CTRL: Modifiers = Modifiers(1 << 3);
+    pub const LEFT_ALT: Modifiers = Modifiers(1 << 4);
+    /// The right alt key, which acts as AltGr on many layouts.
+    pub const RIGHT_ALT: Modifiers = Modifiers(1 << 5);
+    pub const LEFT_META: Modifiers = Modifiers(1 << 6);
+    pub const RIGHT_META: Modifiers = Modifiers(1 << 7);
+    pub const CAPS_LOCK: Modifiers = Modifiers(1 << 8);
+    pub const NUM_LOCK: Modifiers = Modifiers(1 << 9);
+    pub const SCROLL_LOCK: Modifiers = Modifiers(1 << 10);
+
+    pub const fn empty() -> Self {
+        Modifiers(0)
+    }
+
+    pub const fn bits(&self) -> u16 {
+        self.0
+    }
+
+    pub const fn contains(&self, other: Modifiers) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    const fn intersects(&self, other: Modifiers) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    pub fn insert(&mut self, other: Modifiers) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: Modifiers) {
+        self.0 &= !other.0;
+    }
+
+    pub fn toggle(&mut self, other: Modifiers) {
+        self.0 ^= other.0;
+    }
+
+    pub fn shift(&self) -> bool {
+        self.intersects(Modifiers(Self::LEFT_SHIFT.0 | Self::RIGHT_SHIFT.0))
+    }
+
+    pub fn ctrl(&self) -> bool {
+        self.intersects(Modifiers(Self::LEFT_CTRL.0 | Self::RIGHT_CTRL.0))
+    }
+
+    pub fn alt(&self) -> bool {
+        self.contains(Self::LEFT_ALT)
+    }
+
+    pub fn altgr(&self) -> bool {
+        self.contains(Self::RIGHT_ALT)
+    }
+
+    pub fn meta(&self) -> bool {
+        self.intersects(Modifiers(Self::LEFT_META.0 | Self::RIGHT_META.0))
+    }
+
+    pub fn caps_lock(&self) -> bool {
+        self.contains(Self::CAPS_LOCK)
+    }
+
+    pub fn num_lock(&self) -> bool {
+        self.contains(Self::NUM_LOCK)
+    }
+
+    pub fn scroll_lock(&self) -> bool {
+        self.contains(Self::SCROLL_LOCK)
+    }
+
+    fn of_modifier_key(key: KeyCode) -> Option<Modifiers> {
+        let modifier = match key {
+            KeyCode::LeftShift => Self::LEFT_SHIFT,
+            KeyCode::RightShift => Self::RIGHT_SHIFT,
+            KeyCode::LeftCtrl => Self::LEFT_CTRL,
+            KeyCode::RightCtrl => Self::RIGHT_CTRL,
+            KeyCode::LeftAlt => Self::LEFT_ALT,
+            KeyCode::RightAlt => Self::RIGHT_ALT,
+            KeyCode::LeftMeta => Self::LEFT_META,
+            KeyCode::RightMeta => Self::RIGHT_META,
+            _ => return None,
+        };
+
+        Some(modifier)
+    }
+
+    fn of_lock_key(key: KeyCode) -> Option<Modifiers> {
+        let lock = match key {
+            KeyCode::CapsLock => Self::CAPS_LOCK,
+            KeyCode::NumLock => Self::NUM_LOCK,
+            KeyCode::ScrollLock => Self::SCROLL_LOCK,
+            _ => return None,
+        };
+
+        Some(lock)
+    }
+}
+
+/// A key which has been pressed or released, along with the character it produces in the active keymap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub key: KeyCode,
+    pub state: KeyState,
+    /// The state of the modifiers after the event.
+    pub modifiers: Modifiers,
+    pub char: Option<char>,
+}
+
+/// The scancode sets of the PS/2 keyboards.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/PS/2_Keyboard#Scan_Code_Sets
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScancodeSet {
+    /// The set of the original IBM PC (XT), into which the PS/2 controller translates by default.
+    Set1,
+    /// The set of the IBM PC AT, which every PS/2 keyboard supports.
+    Set2,
+}
+
+const PREFIX_EXTENDED: u8 = 0xE0;
+// Only used by the pause key, which sends a sequence when pressed, and nothing when released.
+const PREFIX_PAUSE: u8 = 0xE1;
+const SET_1_RELEASE: u8 = 0x80;
+const SET_2_RELEASE: u8 = 0xF0;
+
+/// The remaining number of bytes of the sequence of the pause key, after its prefix.
+const SET_1_PAUSE_LENGTH: u8 = 5;
+const SET_2_PAUSE_LENGTH: u8 = 7;
+
+fn set_1(code: u8) -> Option<KeyCode> {
+    let key = match code {
+        0x01 => KeyCode::Escape,
+        0x02 => KeyCode::Digit1,
+        0x03 => KeyCode::Digit2,
+        0x04 => KeyCode::Digit3,
+        0x05 => KeyCode::Digit4,
+        0x06 => KeyCode::Digit5,
+        0x07 => KeyCode::Digit6,
+        0x08 => KeyCode::Digit7,
+        0x09 => KeyCode::Digit8,
+        0x0A => KeyCode::Digit9,
+        0x0B => KeyCode::Digit0,
+        0x0C => KeyCode::Minus,
+        0x0D => KeyCode::Equals,
+        0x0E => KeyCode::Backspace,
+        0x0F => KeyCode::Tab,
+        0x10 => KeyCode::Q,
+        0x11 => KeyCode::W,
+        0x12 => KeyCode::E,
+        0x13 => KeyCode::R,
+        0x14 => KeyCode::T,
+        0x15 => KeyCode::Y,
+        0x16 => KeyCode::U,
+        0x17 => KeyCode::I,
+        0x18 => KeyCode::O,
+        0x19 => KeyCode::P,
+        0x1A => KeyCode::LeftBracket,
+        0x1B => KeyCode::RightBracket,
+        0x1C => KeyCode::Enter,
+        0x1D => KeyCode::LeftCtrl,
+        0x1E => KeyCode::A,
+        0x1F => KeyCode::S,
+        0x20 => KeyCode::D,
+        0x21 => KeyCode::F,
+        0x22 => KeyCode::G,
+        0x23 => KeyCode::H,
+        0x24 => KeyCode::J,
+        0x25 => KeyCode::K,
+        0x26 => KeyCode::L,
+        0x27 => KeyCode::Semicolon,
+        0x28 => KeyCode::Quote,
+        0x29 => KeyCode::Backtick,
+        0x2A => KeyCode::LeftShift,
+        0x2B => KeyCode::Backslash,
+        0x2C => KeyCode::Z,
+        0x2D => KeyCode::X,
+        0x2E => KeyCode::C,
+        0x2F => KeyCode::V,
+        0x30 => KeyCode::B,
+        0x31 => KeyCode::N,
+        0x32 => KeyCode::M,
+        0x33 => KeyCode::Comma,
+        0x34 => KeyCode::Period,
+        0x35 => KeyCode::Slash,
+        0x36 => KeyCode::RightShift,
+        0x37 => KeyCode::KeypadAsterisk,
+        0x38 => KeyCode::LeftAlt,
+        0x39 => KeyCode::Space,
+        0x3A => KeyCode::CapsLock,
+        0x3B => KeyCode::F1,
+        0x3C => KeyCode::F2,
+        0x3D => KeyCode::F3,
+        0x3E => KeyCode::F4,
+        0x3F => KeyCode::F5,
+        0x40 => KeyCode::F6,
+        0x41 => KeyCode::F7,
+        0x42 => KeyCode::F8,
+        0x43 => KeyCode::F9,
+        0x44 => KeyCode::F10,
+        0x45 => KeyCode::NumLock,
+        0x46 => KeyCode::ScrollLock,
+        0x47 => KeyCode::Keypad7,
+        0x48 => KeyCode::Keypad8,
+        0x49 => KeyCode::Keypad9,
+        0x4A => KeyCode::KeypadMinus,
+        0x4B => KeyCode::Keypad4,
+        0x4C => KeyCode::Keypad5,
+        0x4D => KeyCode::Keypad6,
+        0x4E => KeyCode::KeypadPlus,
+        0x4F => KeyCode::Keypad1,
+        0x50 => KeyCode::Keypad2,
+        0x51 => KeyCode::Keypad3,
+        0x52 => KeyCode::Keypad0,
+        0x53 => KeyCode::KeypadPeriod,
+        0x56 => KeyCode::NonUsBackslash,
+        0x57 => KeyCode::F11,
+        0x58 => KeyCode::F12,
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+fn set_1_extended(code: u8) -> Option<KeyCode> {
+    let key = match code {
+        0x1C => KeyCode::KeypadEnter,
+        0x1D => KeyCode::RightCtrl,
+        0x35 => KeyCode::KeypadSlash,
+        0x37 => KeyCode::PrintScreen,
+        0x38 => KeyCode::RightAlt,
+        0x47 => KeyCode::Home,
+        0x48 => KeyCode::ArrowUp,
+        0x49 => KeyCode::PageUp,
+        0x4B => KeyCode::ArrowLeft,
+        0x4D => KeyCode::ArrowRight,
+        0x4F => KeyCode::End,
+        0x50 => KeyCode::ArrowDown,
+        0x51 => KeyCode::PageDown,
+        0x52 => KeyCode::Insert,
+        0x53 => KeyCode::Delete,
+        0x5B => KeyCode::LeftMeta,
+        0x5C => KeyCode::RightMeta,
+        0x5D => KeyCode::Menu,
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+fn set_2(code: u8) -> Option<KeyCode> {
+    let key = match code {
+        0x01 => KeyCode::F9,
+        0x03 => KeyCode::F5,
+        0x04 => KeyCode::F3,
+        0x05 => KeyCode::F1,
+        0x06 => KeyCode::F2,
+        0x07 => KeyCode::F12,
+        0x09 => KeyCode::F10,
+        0x0A => KeyCode::F8,
+        0x0B => KeyCode::F6,
+        0x0C => KeyCode::F4,
+        0x0D => KeyCode::Tab,
+        0x0E => KeyCode::Backtick,
+        0x11 => KeyCode::LeftAlt,
+        0x12 => KeyCode::LeftShift,
+        0x14 => KeyCode::LeftCtrl,
+        0x15 => KeyCode::Q,
+        0x16 => KeyCode::Digit1,
+        0x1A => KeyCode::Z,
+        0x1B => KeyCode::S,
+        0x1C => KeyCode::A,
+        0x1D => KeyCode::W,
+        0x1E => KeyCode::Digit2,
+        0x21 => KeyCode::C,
+        0x22 => KeyCode::X,
+        0x23 => KeyCode::D,
+        0x24 => KeyCode::E,
+        0x25 => KeyCode::Digit4,
+        0x26 => KeyCode::Digit3,
+        0x29 => KeyCode::Space,
+        0x2A => KeyCode::V,
+        0x2B => KeyCode::F,
+        0x2C => KeyCode::T,
+        0x2D => KeyCode::R,
+        0x2E => KeyCode::Digit5,
+        0x31 => KeyCode::N,
+        0x32 => KeyCode::B,
+        0x33 => KeyCode::H,
+        0x34 => KeyCode::G,
+        0x35 => KeyCode::Y,
+        0x36 => KeyCode::Digit6,
+        0x3A => KeyCode::M,
+        0x3B => KeyCode::J,
+        0x3C => KeyCode::U,
+        0x3D => KeyCode::Digit7,
+        0x3E => KeyCode::Digit8,
+        0x41 => KeyCode::Comma,
+        0x42 => KeyCode::K,
+        0x43 => KeyCode::I,
+        0x44 => KeyCode::O,
+        0x45 => KeyCode::Digit0,
+        0x46 => KeyCode::Digit9,
+        0x49 => KeyCode::Period,
+        0x4A => KeyCode::Slash,
+        0x4B => KeyCode::L,
+        0x4C => KeyCode::Semicolon,
+        0x4D => KeyCode::P,
+        0x4E => KeyCode::Minus,
+        0x52 => KeyCode::Quote,
+        0x54 => KeyCode::LeftBracket,
+        0x55 => KeyCode::Equals,
+        0x58 => KeyCode::CapsLock,
+        0x59 => KeyCode::RightShift,
+        0x5A => KeyCode::Enter,
+        0x5B => KeyCode::RightBracket,
+        0x5D => KeyCode::Backslash,
+        0x61 => KeyCode::NonUsBackslash,
+        0x66 => KeyCode::Backspace,
+        0x69 => KeyCode::Keypad1,
+        0x6B => KeyCode::Keypad4,
+        0x6C => KeyCode::Keypad7,
+        0x70 => KeyCode::Keypad0,
+        0x71 => KeyCode::KeypadPeriod,
+        0x72 => KeyCode::Keypad2,
+        0x73 => KeyCode::Keypad5,
+        0x74 => KeyCode::Keypad6,
+        0x75 => KeyCode::Keypad8,
+        0x76 => KeyCode::Escape,
+        0x77 => KeyCode::NumLock,
+        0x78 => KeyCode::F11,
+        0x79 => KeyCode::KeypadPlus,
+        0x7A => KeyCode::Keypad3,
+        0x7B => KeyCode::KeypadMinus,
+        0x7C => KeyCode::KeypadAsterisk,
+        0x7D => KeyCode::Keypad9,
+        0x7E => KeyCode::ScrollLock,
+        0x83 => KeyCode::F7,
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+fn set_2_extended(code: u8) -> Option<KeyCode> {
+    let key = match code {
+        0x11 => KeyCode::RightAlt,
+        0x14 => KeyCode::RightCtrl,
+        0x1F => KeyCode::LeftMeta,
+        0x27 => KeyCode::RightMeta,
+        0x2F => KeyCode::Menu,
+        0x4A => KeyCode::KeypadSlash,
+        0x5A => KeyCode::KeypadEnter,
+        0x69 => KeyCode::End,
+        0x6B => KeyCode::ArrowLeft,
+        0x6C => KeyCode::Home,
+        0x70 => KeyCode::Insert,
+        0x71 => KeyCode::Delete,
+        0x72 => KeyCode::ArrowDown,
+        0x74 => KeyCode::ArrowRight,
+        0x75 => KeyCode::ArrowUp,
+        0x7A => KeyCode::PageDown,
+        0x7C => KeyCode::PrintScreen,
+        0x7D => KeyCode::PageUp,
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+/// Decoder of the scancodes sent by a keyboard, one byte at a time.
+#[derive(Clone, Copy, Debug)]
+pub struct Decoder {
+    set: ScancodeSet,
+    extended: bool,
+    release: bool,
+    // The number of bytes of the sequence of the pause key which remain to be skipped.
+    skip: u8,
+}
+
+impl Decoder {
+    pub const fn new(set: ScancodeSet) -> Self {
+        Self {
+            set,
+            extended: false,
+            release: false,
+            skip: 0,
+        }
+    }
+
+    pub fn set(&self) -> ScancodeSet {
+        self.set
+    }
+
+    pub fn decode(&mut self, byte: u8) -> Option<(KeyCode, KeyState)> {
+        if self.skip > 0 {
+            self.skip -= 1;
+            return None;
+        }
+
+        match (self.set, byte) {
+            (_, PREFIX_EXTENDED) => {
+                self.extended = true;
+                return None;
+            }
+            (ScancodeSet::Set1, PREFIX_PAUSE) => {
+                self.skip = SET_1_PAUSE_LENGTH;
+                return Some((KeyCode::Pause, KeyState::Pressed));
+            }
+            (ScancodeSet::Set2, PREFIX_PAUSE) => {
+                self.skip = SET_2_PAUSE_LENGTH;
+                return Some((KeyCode::Pause, KeyState::Pressed));
+            }
+            (ScancodeSet::Set2, SET_2_RELEASE) => {
+                self.release = true;
+                return None;
+            }
+            _ => {}
+        }
+
+        let extended = core::mem::take(&mut self.extended);
+        let (code, release) = match self.set {
+            ScancodeSet::Set1 => (byte & !SET_1_RELEASE, byte & SET_1_RELEASE != 0),
+            ScancodeSet::Set2 => (byte, core::mem::take(&mut self.release)),
+        };
+
+        // Some extended keys are wrapped in presses and releases of a fake shift key, for compatibility with the
+        // keyboards which lacked them.
+        let key = match (self.set, extended) {
+            (ScancodeSet::Set1, true) if matches!(code, 0x2A | 0x36) => return None,
+            (ScancodeSet::Set2, true) if matches!(code, 0x12 | 0x59) => return None,
+            (ScancodeSet::Set1, false) => set_1(code),
+            (ScancodeSet::Set1, true) => set_1_extended(code),
+            (ScancodeSet::Set2, false) => set_2(code),
+            (ScancodeSet::Set2, true) => set_2_extended(code),
+        }?;
+
+        let state = match release {
+            true => KeyState::Released,
+            false => KeyState::Pressed,
+        };
+        Some((key, state))
+    }
+}
+
+/// Keyboard
+///
+/// Turns the scancodes of a keyboard into key events, keeping track of the modifiers and translating the keys into
+/// characters through the active keymap.
+#[derive(Clone, Copy, Debug)]
+pub struct Keyboard {
+    decoder: Decoder,
+    modifiers: Modifiers,
+    // The lock keys which are being held down, which must not be toggled again by the repeated presses.
+    held_locks: Modifiers,
+}
+
+impl Keyboard {
+    pub const fn new(set: ScancodeSet) -> Self {
+        Self {
+            decoder: Decoder::new(set),
+            modifiers: Modifiers::empty(),
+            held_locks: Modifiers::empty(),
+        }
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        self.modifiers
+    }
+
+    pub fn process(&mut self, byte: u8) -> Option<KeyEvent> {
+        let (key, state) = self.decoder.decode(byte)?;
+
+        if let Some(modifier) = Modifiers::of_modifier_key(key) {
+            match state {
+                KeyState::Pressed => self.modifiers.insert(modifier),
+                KeyState::Released => self.modifiers.remove(modifier),
+            }
+        }
+        if let Some(lock) = Modifiers::of_lock_key(key) {
+            match state {
+                KeyState::Pressed if !self.held_locks.contains(lock) => {
+                    self.held_locks.insert(lock);
+                    self.modifiers.toggle(lock);
+                }
+                KeyState::Pressed => {}
+                KeyState::Released => self.held_locks.remove(lock),
+            }
+        }
+
+        let char = match state {
+            KeyState::Pressed => keymap::translate(key, self.modifiers),
+            KeyState::Released => None,
+        };
+
+        Some(KeyEvent {
+            key,
+            state,
+            modifiers: self.modifiers,
+            char,
+        })
+    }
+}
@@ -0,0 +1,267 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::kernel::arch;
+use crate::kernel::cmdline;
+use crate::kernel::cmdline::{Arg, Param, ParamError};
+use crate::kernel::sync::{Mutex, RwLock};
+
+use super::keyboard::{KeyCode, Modifiers};
+
+/// `keymap=<name>` selects the keymap which translates the keys into characters.
+static KEYMAP: Param = Param::new("keymap", request_keymap);
+
+/// The maximum number of keymaps which can be registered, including the built-in ones.
+const MAX_KEYMAPS: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeymapError {
+    /// No keymap with the name has been registered.
+    NotFound,
+    /// A keymap with the same name has already been registered.
+    Duplicate,
+    NoFreeSlot,
+}
+
+/// The characters produced by a key of a keymap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeymapEntry {
+    pub key: KeyCode,
+    pub normal: char,
+    pub shifted: char,
+    /// The character produced along with AltGr (the right alt key), if any.
+    pub altgr: Option<char>,
+}
+
+impl KeymapEntry {
+    pub const fn new(key: KeyCode, normal: char, shifted: char) -> Self {
+        Self {
+            key,
+            normal,
+            shifted,
+            altgr: None,
+        }
+    }
+
+    pub const fn with_altgr(self, altgr: char) -> Self {
+        Self {
+            altgr: Some(altgr),
+            ..self
+        }
+    }
+}
+
+/// Keymap
+///
+/// Maps the keys which produce printable characters to them, according to a keyboard layout. Caps lock acts as shift
+/// for the alphabetic characters. The keys whose meaning doesn't depend on the layout (e.g. enter or the keypad)
+/// are translated by every keymap alike, hence they're left out of the entries.
+///
+/// A keymap for another layout is defined as a static with its entries, and made available through [`register`].
+#[derive(Debug)]
+pub struct Keymap {
+    pub name: &'static str,
+    pub entries: &'static [KeymapEntry],
+}
+
+impl Keymap {
+    pub fn translate(&self, key: KeyCode, modifiers: Modifiers) -> Option<char> {
+        if let Some(c) = translate_common(key, modifiers) {
+            return Some(c);
+        }
+
+        let entry = self.entries.iter().find(|entry| entry.key == key)?;
+        let caps = modifiers.caps_lock() && entry.normal.is_alphabetic();
+        let c = if modifiers.altgr() {
+            entry.altgr?
+        } else if modifiers.shift() != caps {
+            entry.shifted
+        } else {
+            entry.normal
+        };
+
+        // The control characters are produced by holding ctrl along with the characters between `@` and `_`.
+        match modifiers.ctrl() {
+            true if c.is_ascii_alphabetic() || ('@'..='_').contains(&c) => {
+                Some((c.to_ascii_uppercase() as u8 & 0x1F) as char)
+            }
+            _ => Some(c),
+        }
+    }
+}
+
+fn translate_common(key: KeyCode, modifiers: Modifiers) -> Option<char> {
+    let c = match key {
+        KeyCode::Enter | KeyCode::KeypadEnter => '\n',
+        KeyCode::Backspace => '\x08',
+        KeyCode::Tab => '\t',
+        KeyCode::Escape => '\x1b',
+        KeyCode::KeypadSlash => '/',
+        KeyCode::KeypadAsterisk => '*',
+        KeyCode::KeypadMinus => '-',
+        KeyCode::KeypadPlus => '+',
+        // Without num lock, the rest of the keypad acts as the navigation keys.
+        _ if !modifiers.num_lock() => return None,
+        KeyCode::KeypadPeriod => '.',
+        KeyCode::Keypad0 => '0',
+        KeyCode::Keypad1 => '1',
+        KeyCode::Keypad2 => '2',
+        KeyCode::Keypad3 => '3',
+        KeyCode::Keypad4 => '4',
+        KeyCode::Keypad5 => '5',
+        KeyCode::Keypad6 => '6',
+        KeyCode::Keypad7 => '7',
+        KeyCode::Keypad8 => '8',
+        KeyCode::Keypad9 => '9',
+        _ => return None,
+    };
+
+    Some(c)
+}
+
+/// The keymap of the US keyboard layout.
+pub static US: Keymap = Keymap {
+    name: "us",
+    entries: &[
+        KeymapEntry::new(KeyCode::Backtick, '`', '~'),
+        KeymapEntry::new(KeyCode::Digit1, '1', '!'),
+        KeymapEntry::new(KeyCode::Digit2, '2', '@'),
+        KeymapEntry::new(KeyCode::Digit3, '3', '#'),
+        KeymapEntry::new(KeyCode::Digit4, '4', '$'),
+        KeymapEntry::new(KeyCode::Digit5, '5', '%'),
+        KeymapEntry::new(KeyCode::Digit6, '6', '^'),
+        KeymapEntry::new(KeyCode::Digit7, '7', '&'),
+        KeymapEntry::new(KeyCode::Digit8, '8', '*'),
+        KeymapEntry::new(KeyCode::Digit9, '9', '('),
+        KeymapEntry::new(KeyCode::Digit0, '0', ')'),
+        KeymapEntry::new(KeyCode::Minus, '-', '_'),
+        KeymapEntry::new(KeyCode::Equals, '=', '+'),
+        KeymapEntry::new(KeyCode::Q, 'q', 'Q'),
+        KeymapEntry::new(KeyCode::W, 'w', 'W'),
+        KeymapEntry::new(KeyCode::E, 'e', 'E'),
+        KeymapEntry::new(KeyCode::R, 'r', 'R'),
+        KeymapEntry::new(KeyCode::T, 't', 'T'),
+        KeymapEntry::new(KeyCode::Y, 'y', 'Y'),
+        KeymapEntry::new(KeyCode::U, 'u', 'U'),
+        KeymapEntry::new(KeyCode::I, 'i', 'I'),
+        KeymapEntry::new(KeyCode::O, 'o', 'O'),
+        KeymapEntry::new(KeyCode::P, 'p', 'P'),
+        KeymapEntry::new(KeyCode::LeftBracket, '[', '{'),
+        KeymapEntry::new(KeyCode::RightBracket, ']', '}'),
+        KeymapEntry::new(KeyCode::Backslash, '\\', '|'),
+        KeymapEntry::new(KeyCode::A, 'a', 'A'),
+        KeymapEntry::new(KeyCode::S, 's', 'S'),
+        KeymapEntry::new(KeyCode::D, 'd', 'D'),
+        KeymapEntry::new(KeyCode::F, 'f', 'F'),
+        KeymapEntry::new(KeyCode::G, 'g', 'G'),
+        KeymapEntry::new(KeyCode::H, 'h', 'H'),
+        KeymapEntry::new(KeyCode::J, 'j', 'J'),
+        KeymapEntry::new(KeyCode::K, 'k', 'K'),
+        KeymapEntry::new(KeyCode::L, 'l', 'L'),
+        KeymapEntry::new(KeyCode::Semicolon, ';', ':'),
+        KeymapEntry::new(KeyCode::Quote, '\'', '"'),
+        KeymapEntry::new(KeyCode::NonUsBackslash, '\\', '|'),
+        KeymapEntry::new(KeyCode::Z, 'z', 'Z'),
+        KeymapEntry::new(KeyCode::X, 'x', 'X'),
+        KeymapEntry::new(KeyCode::C, 'c', 'C'),
+        KeymapEntry::new(KeyCode::V, 'v', 'V'),
+        KeymapEntry::new(KeyCode::B, 'b', 'B'),
+        KeymapEntry::new(KeyCode::N, 'n', 'N'),
+        KeymapEntry::new(KeyCode::M, 'm', 'M'),
+        KeymapEntry::new(KeyCode::Comma, ',', '<'),
+        KeymapEntry::new(KeyCode::Period, '.', '>'),
+        KeymapEntry::new(KeyCode::Slash, '/', '?'),
+        KeymapEntry::new(KeyCode::Space, ' ', ' '),
+    ],
+};
+
+static KEYMAPS: Mutex<[Option<&'static Keymap>; MAX_KEYMAPS]> = Mutex::new([None; MAX_KEYMAPS]);
+
+// The active keymap is read by the interrupt handler of the keyboard.
+static ACTIVE: RwLock<&'static Keymap> = RwLock::new(&US);
+
+// The keymap requested on the command line, which may be registered later on.
+static REQUESTED: Mutex<Option<&'static str>> = Mutex::new(None);
+
+/// Translates a key into the character it produces in the active keymap, if any.
+pub fn translate(key: KeyCode, modifiers: Modifiers) -> Option<char> {
+    ACTIVE.read().translate(key, modifiers)
+}
+
+pub fn active() -> &'static Keymap {
+    *ACTIVE.read()
+}
+
+/// Makes a registered keymap the active one.
+pub fn select(name: &str) -> Result<(), KeymapError> {
+    let keymap = KEYMAPS
+        .lock()
+        .iter()
+        .flatten()
+        .find(|keymap| keymap.name == name)
+        .copied()
+        .ok_or(KeymapError::NotFound)?;
+
+    arch::without_interrupts(|| *ACTIVE.write() = keymap);
+
+    Ok(())
+}
+
+/// Makes a keymap available, and selects it if it has been requested on the command line.
+pub fn register(keymap: &'static Keymap) -> Result<(), KeymapError> {
+    {
+        let mut keymaps = KEYMAPS.lock();
+        if keymaps
+            .iter()
+            .flatten()
+            .any(|registered| registered.name == keymap.name)
+        {
+            return Err(KeymapError::Duplicate);
+        }
+        let slot = keymaps
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(KeymapError::NoFreeSlot)?;
+        *slot = Some(keymap);
+    }
+
+    if *REQUESTED.lock() == Some(keymap.name) {
+        select(keymap.name)?;
+    }
+
+    Ok(())
+}
+
+fn request_keymap(arg: &Arg) -> Result<(), ParamError> {
+    let name = arg.value.ok_or(ParamError::MissingValue)?;
+    *REQUESTED.lock() = Some(name);
+
+    // The keymap is selected as soon as it's registered, if it isn't yet.
+    let _ = select(name);
+
+    Ok(())
+}
+
+pub(crate) fn init() -> Result<(), ()> {
+    register(&US).map_err(|_| ())?;
+    cmdline::register(&KEYMAP).map_err(|_| ())
+}
@@ -0,0 +1,92 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::aux::ring_buffer::RingBuffer;
+use crate::kernel::arch;
+use crate::kernel::sync::Mutex;
+
+use self::keyboard::{KeyEvent, KeyState};
+use self::mouse::MouseEvent;
+
+pub mod keyboard;
+pub mod keymap;
+pub mod mouse;
+
+/// The maximum number of events which are queued until they are read.
+const QUEUE_SIZE: usize = 256;
+
+/// An event of an input device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputEvent {
+    Key(KeyEvent),
+    Mouse(MouseEvent),
+}
+
+// The events are queued by the interrupt handlers of the devices.
+static QUEUE: Mutex<RingBuffer<InputEvent, QUEUE_SIZE>> = Mutex::new(RingBuffer::new());
+
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+/// Queues an event of a device, dropping it if the queue is full.
+pub fn push(event: InputEvent) {
+    if arch::without_interrupts(|| QUEUE.lock().push(event)).is_err() {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Returns the oldest queued event, if any, without blocking.
+pub fn poll() -> Option<InputEvent> {
+    arch::without_interrupts(|| QUEUE.lock().pop())
+}
+
+/// Returns the oldest queued key event, discarding the events of the other devices before it.
+pub fn read_key() -> Option<KeyEvent> {
+    while let Some(event) = poll() {
+        if let InputEvent::Key(event) = event {
+            return Some(event);
+        }
+    }
+
+    None
+}
+
+/// Returns the character of the oldest queued key press which produces one, discarding the events before it.
+pub fn read_char() -> Option<char> {
+    while let Some(event) = read_key() {
+        if let (KeyState::Pressed, Some(c)) = (event.state, event.char) {
+            return Some(c);
+        }
+    }
+
+    None
+}
+
+/// Returns the number of events which were dropped due to a full queue.
+pub fn dropped() -> usize {
+    DROPPED.load(Ordering::Relaxed)
+}
+
+pub(crate) fn init() -> Result<(), ()> {
+    keymap::init()
+}
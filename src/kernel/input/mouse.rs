@@ -0,0 +1,206 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+/// The buttons of a mouse.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MouseButtons(u8);
+
+impl MouseButtons {
+    pub const LEFT: MouseButtons = MouseButtons(1 << 0);
+    pub const RIGHT: MouseButtons = MouseButtons(1 << 1);
+    pub const MIDDLE: MouseButtons = MouseButtons(1 << 2);
+    /// The fourth button, usually on the side of the mouse.
+    pub const BACK: MouseButtons = MouseButtons(1 << 3);
+    /// The fifth button, usually on the side of the mouse.
+    pub const FORWARD: MouseButtons = MouseButtons(1 << 4);
+
+    pub const fn empty() -> Self {
+        MouseButtons(0)
+    }
+
+    pub const fn bits(&self) -> u8 {
+        self.0
+    }
+
+    pub const fn contains(&self, other: MouseButtons) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns the buttons which are in only one of the sets, e.g. the buttons which changed between two states.
+    pub const fn difference(&self, other: MouseButtons) -> MouseButtons {
+        MouseButtons(self.0 ^ other.0)
+    }
+}
+
+/// The motion of a mouse and the state of its buttons, as reported by a single packet.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MouseEvent {
+    /// The horizontal motion, increasing to the right.
+    pub dx: i16,
+    /// The vertical motion, increasing downwards like the coordinates of the screen.
+    pub dy: i16,
+    /// The motion of the scroll wheel, increasing downwards (towards the user).
+    pub wheel: i8,
+    /// The buttons which are being held down.
+    pub buttons: MouseButtons,
+    /// The buttons which have been pressed or released since the previous event.
+    pub changed: MouseButtons,
+}
+
+impl MouseEvent {
+    pub fn is_motion(&self) -> bool {
+        self.dx != 0 || self.dy != 0 || self.wheel != 0
+    }
+
+    pub fn pressed(&self) -> MouseButtons {
+        MouseButtons(self.changed.0 & self.buttons.0)
+    }
+
+    pub fn released(&self) -> MouseButtons {
+        MouseButtons(self.changed.0 & !self.buttons.0)
+    }
+}
+
+/// The protocols of the PS/2 mice, which differ in the size of their packets.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/PS/2_Mouse
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MouseProtocol {
+    /// Three buttons without a wheel, in 3-byte packets.
+    Standard,
+    /// The IntelliMouse extension, which adds a scroll wheel in a fourth byte.
+    Wheel,
+    /// The IntelliMouse Explorer extension, which also adds the fourth and fifth buttons.
+    FiveButton,
+}
+
+impl MouseProtocol {
+    pub fn packet_size(self) -> usize {
+        match self {
+            MouseProtocol::Standard => 3,
+            MouseProtocol::Wheel | MouseProtocol::FiveButton => 4,
+        }
+    }
+}
+
+const PACKET_LEFT: u8 = 1 << 0;
+const PACKET_RIGHT: u8 = 1 << 1;
+const PACKET_MIDDLE: u8 = 1 << 2;
+// Always set in the first byte, which allows the decoder to resynchronize with the packets.
+const PACKET_ALWAYS_ONE: u8 = 1 << 3;
+const PACKET_X_SIGN: u8 = 1 << 4;
+const PACKET_Y_SIGN: u8 = 1 << 5;
+const PACKET_X_OVERFLOW: u8 = 1 << 6;
+const PACKET_Y_OVERFLOW: u8 = 1 << 7;
+// The fourth byte of the five-button protocol.
+const PACKET_BACK: u8 = 1 << 4;
+const PACKET_FORWARD: u8 = 1 << 5;
+
+/// Decoder of the packets sent by a mouse, one byte at a time.
+#[derive(Clone, Copy, Debug)]
+pub struct Decoder {
+    protocol: MouseProtocol,
+    packet: [u8; 4],
+    len: usize,
+    buttons: MouseButtons,
+}
+
+impl Decoder {
+    pub const fn new(protocol: MouseProtocol) -> Self {
+        Self {
+            protocol,
+            packet: [0; 4],
+            len: 0,
+            buttons: MouseButtons::empty(),
+        }
+    }
+
+    pub fn protocol(&self) -> MouseProtocol {
+        self.protocol
+    }
+
+    pub fn decode(&mut self, byte: u8) -> Option<MouseEvent> {
+        // A byte which can't start a packet means that the decoder has lost track of them.
+        if self.len == 0 && byte & PACKET_ALWAYS_ONE == 0 {
+            return None;
+        }
+
+        self.packet[self.len] = byte;
+        self.len += 1;
+        if self.len < self.protocol.packet_size() {
+            return None;
+        }
+        self.len = 0;
+
+        let [flags, x, y, extra] = self.packet;
+        let mut buttons = 0;
+        if flags & PACKET_LEFT != 0 {
+            buttons |= MouseButtons::LEFT.0;
+        }
+        if flags & PACKET_RIGHT != 0 {
+            buttons |= MouseButtons::RIGHT.0;
+        }
+        if flags & PACKET_MIDDLE != 0 {
+            buttons |= MouseButtons::MIDDLE.0;
+        }
+
+        // The motion is a 9-bit two's complement number, whose sign is in the first byte.
+        let motion = |value: u8, sign: u8, overflow: u8| match flags & overflow != 0 {
+            true => 0,
+            false if flags & sign != 0 => value as i16 - 0x100,
+            false => value as i16,
+        };
+        let dx = motion(x, PACKET_X_SIGN, PACKET_X_OVERFLOW);
+        let dy = -motion(y, PACKET_Y_SIGN, PACKET_Y_OVERFLOW);
+
+        let wheel = match self.protocol {
+            MouseProtocol::Standard => 0,
+            MouseProtocol::Wheel => extra as i8,
+            // The wheel is a 4-bit two's complement number, which is sign-extended.
+            MouseProtocol::FiveButton => ((extra << 4) as i8) >> 4,
+        };
+        if self.protocol == MouseProtocol::FiveButton {
+            if extra & PACKET_BACK != 0 {
+                buttons |= MouseButtons::BACK.0;
+            }
+            if extra & PACKET_FORWARD != 0 {
+                buttons |= MouseButtons::FORWARD.0;
+            }
+        }
+
+        let buttons = MouseButtons(buttons);
+        let changed = buttons.difference(self.buttons);
+        self.buttons = buttons;
+
+        Some(MouseEvent {
+            dx,
+            dy,
+            wheel,
+            buttons,
+            changed,
+        })
+    }
+}
@@ -22,10 +22,36 @@
 
 mod arch;
 
+pub mod ata;
+pub mod block;
+pub mod cmdline;
+pub mod dma;
+pub mod fs;
+pub mod input;
+pub mod nvme;
+pub mod pci;
 pub mod serial;
+pub mod sync;
+pub mod thread;
+pub mod video;
+pub mod virtio;
+
+#[cfg(target_arch = "x86_64")]
+pub use self::arch::{frame, irq, msi, port, timer, uart, vga, watchpoint};
 
 pub fn init(boot_info_addr: usize) {
     arch::init(boot_info_addr);
+    thread::init().expect("kernel failed to adopt the boot thread");
+    input::init().expect("kernel failed to initialize input");
+    block::init().expect("kernel failed to initialize the block layer");
+    virtio::init().expect("kernel failed to register virtio drivers");
+    ata::init().expect("kernel failed to register ATA drivers");
+    nvme::init().expect("kernel failed to register NVMe driver");
+    pci::init().expect("kernel failed to enumerate PCI devices");
+    fs::init().expect("kernel failed to mount the root filesystem");
+    video::init().expect("kernel failed to initialize video console");
+
+    cmdline::report_unknown();
 }
 
 pub fn hlt_loop() -> ! {
@@ -0,0 +1,659 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::fmt::Write;
+use core::hint;
+use core::ptr;
+use core::str;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::task::Poll;
+
+use spin::Once;
+
+use crate::kernel::arch;
+use crate::kernel::arch::PhysAddr;
+use crate::kernel::block;
+use crate::kernel::block::{BlockDevice, BlockError, BlockInfo, Operation, Request, RequestId};
+use crate::kernel::dma;
+use crate::kernel::dma::DmaBuffer;
+use crate::kernel::irq;
+use crate::kernel::msi;
+use crate::kernel::pci;
+use crate::kernel::pci::{Bar, PciDevice, PciDriver, PciMatch, COMMAND_BUS_MASTER};
+use crate::kernel::sync::Mutex;
+
+use self::queue::{Command, QueuePair, MAX_SLOTS, PRP_LIST_ENTRIES};
+
+pub mod queue;
+
+const MAX_CONTROLLERS: usize = 2;
+const MAX_NAMESPACES: usize = 8;
+/// The maximum number of I/O queue pairs per controller, one per processor.
+const MAX_IO_QUEUES: usize = 4;
+
+const ADMIN_QUEUE_SIZE: u16 = 32;
+const IO_QUEUE_SIZE: u16 = 64;
+
+/// The size of the pages of the controller, which is the minimum one that every controller supports.
+const PAGE_SIZE: usize = 4096;
+
+/// The number of times a register or a completion is polled before the controller is given up on.
+const TIMEOUT: usize = 10_000_000;
+
+// The registers of the controller.
+const REG_CAP: usize = 0x00;
+const REG_VS: usize = 0x08;
+const REG_CC: usize = 0x14;
+const REG_CSTS: usize = 0x1C;
+const REG_AQA: usize = 0x24;
+const REG_ASQ: usize = 0x28;
+const REG_ACQ: usize = 0x30;
+const REG_DOORBELLS: usize = 0x1000;
+
+const CAP_MQES_MASK: u64 = 0xFFFF;
+const CAP_DSTRD_SHIFT: u64 = 32;
+const CAP_DSTRD_MASK: u64 = 0xF;
+const CC_ENABLE: u32 = 1 << 0;
+/// The sizes of the entries of the I/O queues, as powers of two.
+const CC_IOSQES: u32 = 6 << 16;
+const CC_IOCQES: u32 = 4 << 20;
+const CSTS_READY: u32 = 1 << 0;
+const CSTS_FATAL: u32 = 1 << 1;
+
+// The opcodes of the admin commands.
+const ADMIN_CREATE_SQ: u32 = 0x01;
+const ADMIN_CREATE_CQ: u32 = 0x05;
+const ADMIN_IDENTIFY: u32 = 0x06;
+const ADMIN_SET_FEATURES: u32 = 0x09;
+
+// The opcodes of the I/O commands.
+const IO_FLUSH: u32 = 0x00;
+const IO_WRITE: u32 = 0x01;
+const IO_READ: u32 = 0x02;
+
+// The structures returned by IDENTIFY.
+const IDENTIFY_NAMESPACE: u32 = 0x00;
+const IDENTIFY_CONTROLLER: u32 = 0x01;
+const IDENTIFY_NAMESPACE_LIST: u32 = 0x02;
+
+const FEATURE_NUMBER_OF_QUEUES: u32 = 0x07;
+
+// The fields of the identify structures.
+const CONTROLLER_SERIAL: usize = 4;
+const CONTROLLER_MODEL: usize = 24;
+const CONTROLLER_MDTS: usize = 77;
+const NAMESPACE_SIZE: usize = 0;
+const NAMESPACE_FLBAS: usize = 26;
+const NAMESPACE_LBA_FORMATS: usize = 128;
+
+// The flags of the commands creating the I/O queues.
+const QUEUE_PHYSICALLY_CONTIGUOUS: u32 = 1 << 0;
+const QUEUE_INTERRUPTS_ENABLED: u32 = 1 << 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NvmeError {
+    /// The queue has no free slot.
+    Busy,
+    /// The controller has failed a command with the given status code.
+    Command(u16),
+    /// The buffer can't be described by the PRPs of a command.
+    InvalidBuffer,
+    UnknownCommand,
+    Timeout,
+    /// The controller has reported a fatal status.
+    Fatal,
+    NoMemory,
+}
+
+impl From<NvmeError> for BlockError {
+    fn from(error: NvmeError) -> Self {
+        match error {
+            NvmeError::Busy => BlockError::Busy,
+            NvmeError::InvalidBuffer => BlockError::InvalidBuffer,
+            NvmeError::UnknownCommand => BlockError::UnknownRequest,
+            NvmeError::NoMemory => BlockError::NoMemory,
+            _ => BlockError::Io,
+        }
+    }
+}
+
+fn read_u32(regs: usize, register: usize) -> u32 {
+    unsafe { ptr::read_volatile((regs + register) as *const u32) }
+}
+
+fn write_u32(regs: usize, register: usize, value: u32) {
+    unsafe { ptr::write_volatile((regs + register) as *mut u32, value) };
+}
+
+fn read_u64(regs: usize, register: usize) -> u64 {
+    (read_u32(regs, register + 4) as u64) << 32 | read_u32(regs, register) as u64
+}
+
+fn write_u64(regs: usize, register: usize, value: u64) {
+    write_u32(regs, register, value as u32);
+    write_u32(regs, register + 4, (value >> 32) as u32);
+}
+
+/// Builds a command from its opcode, the namespace and the command specific double words (CDW10 and up).
+fn command(opcode: u32, namespace: u32, dwords: &[u32]) -> Command {
+    let mut command = [0; 16];
+    command[0] = opcode;
+    command[1] = namespace;
+    command[10..10 + dwords.len()].copy_from_slice(dwords);
+    command
+}
+
+/// An NVMe controller with its I/O queue pairs, each of which belongs to a processor.
+struct Controller {
+    index: usize,
+    io_queues: [Mutex<Option<QueuePair>>; MAX_IO_QUEUES],
+    /// The IDs of the local APICs of the processors using the I/O queues.
+    cpus: [u8; MAX_IO_QUEUES],
+    queue_count: usize,
+    /// The maximum length of a transfer.
+    max_transfer: usize,
+    /// The admin queue, which is kept for the commands issued after the probing.
+    admin: Mutex<QueuePair>,
+}
+
+impl Controller {
+    /// Returns the I/O queue of the current processor, or the first one if it has none of its own.
+    fn current_queue(&self) -> usize {
+        let cpu = msi::current_cpu();
+        self.cpus[..self.queue_count]
+            .iter()
+            .position(|id| *id == cpu)
+            .unwrap_or(0)
+    }
+
+    fn reap(&self) {
+        for queue in self.io_queues.iter().take(self.queue_count) {
+            if let Some(queue) = queue.lock().as_mut() {
+                queue.reap();
+            }
+        }
+    }
+}
+
+/// A namespace of a controller, which is a block device of its own.
+pub struct Namespace {
+    name: [u8; 16],
+    name_len: usize,
+    controller: &'static Controller,
+    id: u32,
+    info: BlockInfo,
+}
+
+impl Namespace {
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Finds the physical pages of the buffer of a request, which must be aligned to double words.
+    fn pages(
+        request: &Request,
+        pages: &mut [u64; PRP_LIST_ENTRIES + 1],
+    ) -> Result<usize, BlockError> {
+        let start = request.buf as usize;
+        if start % 4 != 0 {
+            return Err(BlockError::InvalidBuffer);
+        }
+
+        let mut count = 0;
+        let mut addr = start;
+        while addr < start + request.len {
+            let page = dma::virt_to_phys(addr).ok_or(BlockError::InvalidBuffer)?;
+            *pages.get_mut(count).ok_or(BlockError::InvalidBuffer)? = page;
+            count += 1;
+            addr = (addr / PAGE_SIZE + 1) * PAGE_SIZE;
+        }
+
+        Ok(count)
+    }
+}
+
+impl BlockDevice for Namespace {
+    fn name(&self) -> &str {
+        str::from_utf8(&self.name[..self.name_len]).unwrap_or_default()
+    }
+
+    fn info(&self) -> BlockInfo {
+        self.info
+    }
+
+    unsafe fn submit(&self, request: Request) -> Result<RequestId, BlockError> {
+        request.check(&self.info)?;
+
+        let mut pages = [0; PRP_LIST_ENTRIES + 1];
+        let (command, count) = match request.op {
+            Operation::Flush => (command(IO_FLUSH, self.id, &[]), 0),
+            op => {
+                if request.len > self.controller.max_transfer {
+                    return Err(BlockError::InvalidBuffer);
+                }
+                let opcode = if op == Operation::Write {
+                    IO_WRITE
+                } else {
+                    IO_READ
+                };
+                let blocks = request.block_count(&self.info) as u32;
+                let dwords = [
+                    request.block as u32,
+                    (request.block >> 32) as u32,
+                    blocks - 1,
+                ];
+                (
+                    command(opcode, self.id, &dwords),
+                    Self::pages(&request, &mut pages)?,
+                )
+            }
+        };
+
+        let index = self.controller.current_queue();
+        let slot = arch::without_interrupts(|| {
+            let mut queue = self.controller.io_queues[index].lock();
+            let queue = queue.as_mut().ok_or(NvmeError::UnknownCommand)?;
+            queue.reap();
+            queue.submit(command, &pages[..count])
+        })?;
+
+        Ok((index * MAX_SLOTS + slot) as RequestId)
+    }
+
+    fn poll(&self, id: RequestId) -> Poll<Result<(), BlockError>> {
+        let (index, slot) = (id as usize / MAX_SLOTS, id as usize % MAX_SLOTS);
+        let Some(queue) = self.controller.io_queues.get(index) else {
+            return Poll::Ready(Err(BlockError::UnknownRequest));
+        };
+
+        arch::without_interrupts(|| match queue.lock().as_mut() {
+            Some(queue) => queue
+                .poll(slot)
+                .map(|result| result.map(|_| ()).map_err(BlockError::from)),
+            None => Poll::Ready(Err(BlockError::UnknownRequest)),
+        })
+    }
+}
+
+static CONTROLLERS: [Once<Controller>; MAX_CONTROLLERS] = [const { Once::new() }; MAX_CONTROLLERS];
+static CONTROLLER_COUNT: AtomicUsize = AtomicUsize::new(0);
+static NAMESPACES: [Once<Namespace>; MAX_NAMESPACES] = [const { Once::new() }; MAX_NAMESPACES];
+static NAMESPACE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+static DRIVER: PciDriver = PciDriver {
+    name: "nvme",
+    matches: &[PciMatch::class(0x01, 0x08).with_prog_if(0x02)],
+    probe,
+};
+
+/// Completes the commands of every I/O queue, as the vectors of the queues share the handler.
+fn on_interrupt() {
+    for controller in CONTROLLERS.iter().filter_map(Once::get) {
+        controller.reap();
+    }
+}
+
+fn wait_ready(regs: usize, ready: bool) -> Result<(), NvmeError> {
+    for _ in 0..TIMEOUT {
+        let status = read_u32(regs, REG_CSTS);
+        if status & CSTS_FATAL != 0 {
+            return Err(NvmeError::Fatal);
+        }
+        if (status & CSTS_READY != 0) == ready {
+            return Ok(());
+        }
+        hint::spin_loop();
+    }
+
+    Err(NvmeError::Timeout)
+}
+
+/// Resets the controller, and enables it with a new admin queue pair.
+fn enable(regs: usize, doorbell_stride: usize) -> Result<QueuePair, NvmeError> {
+    let config = read_u32(regs, REG_CC);
+    if config & CC_ENABLE != 0 {
+        write_u32(regs, REG_CC, config & !CC_ENABLE);
+    }
+    wait_ready(regs, false)?;
+
+    let admin = QueuePair::new(0, ADMIN_QUEUE_SIZE, regs, doorbell_stride, false)?;
+    let size = ADMIN_QUEUE_SIZE as u32 - 1;
+    write_u32(regs, REG_AQA, size << 16 | size);
+    write_u64(regs, REG_ASQ, admin.submission_addr());
+    write_u64(regs, REG_ACQ, admin.completion_addr());
+
+    write_u32(regs, REG_CC, CC_IOSQES | CC_IOCQES | CC_ENABLE);
+    if let Err(error) = wait_ready(regs, true) {
+        write_u32(regs, REG_CC, 0);
+        admin.free();
+        return Err(error);
+    }
+
+    Ok(admin)
+}
+
+/// Routes the completions of an I/O queue to a processor through MSI-X, if the controller has it.
+///
+/// Returns the IRQ which has been allocated, or `None` if the queue is to be polled.
+fn enable_interrupt(pci: &PciDevice, id: u16, cpu: u8) -> Option<u8> {
+    pci.msi_x()?;
+    msi::enable_msi_x(pci, id, cpu, on_interrupt)
+        .map_err(|error| log::warn!("NVMe: queue {} is polled: {:?}", id, error))
+        .ok()
+}
+
+/// Creates an I/O queue pair on the controller, whose completions are signaled if it has interrupts.
+fn create_io_queue(
+    admin: &mut QueuePair,
+    queue: &QueuePair,
+    interrupts: bool,
+) -> Result<(), NvmeError> {
+    let (id, size) = (queue.id(), queue.size());
+    let mut flags = QUEUE_PHYSICALLY_CONTIGUOUS;
+    if interrupts {
+        flags |= QUEUE_INTERRUPTS_ENABLED;
+    }
+
+    let queue_size = (size as u32 - 1) << 16 | id as u32;
+    let create_cq = command(ADMIN_CREATE_CQ, 0, &[queue_size, (id as u32) << 16 | flags]);
+    admin.execute(create_cq, &[queue.completion_addr()])?;
+
+    let create_sq = command(
+        ADMIN_CREATE_SQ,
+        0,
+        &[queue_size, (id as u32) << 16 | QUEUE_PHYSICALLY_CONTIGUOUS],
+    );
+    admin.execute(create_sq, &[queue.submission_addr()])?;
+
+    Ok(())
+}
+
+/// A controller which is being set up, along with the resources which are released if it fails to be.
+struct Setup {
+    regs: usize,
+    doorbell_stride: usize,
+    admin: QueuePair,
+    buf: DmaBuffer,
+    io_queues: [Mutex<Option<QueuePair>>; MAX_IO_QUEUES],
+    /// The IRQs of the I/O queues which have an MSI-X vector.
+    irqs: [Option<u8>; MAX_IO_QUEUES],
+}
+
+/// What has been found out about a controller while setting it up.
+struct Config {
+    cpus: [u8; MAX_IO_QUEUES],
+    queue_count: usize,
+    max_transfer: usize,
+}
+
+impl Setup {
+    /// Identifies the controller, and creates an I/O queue pair for each processor, as far as it allows.
+    fn run(&mut self, pci: &PciDevice, max_queue_size: u16) -> Result<Config, NvmeError> {
+        let regs = self.regs;
+        let admin = &mut self.admin;
+        admin.execute(
+            command(ADMIN_IDENTIFY, 0, &[IDENTIFY_CONTROLLER]),
+            &[self.buf.phys_addr()],
+        )?;
+        let data = self.buf.as_slice();
+        let model =
+            str::from_utf8(&data[CONTROLLER_MODEL..CONTROLLER_MODEL + 40]).unwrap_or_default();
+        let serial =
+            str::from_utf8(&data[CONTROLLER_SERIAL..CONTROLLER_SERIAL + 20]).unwrap_or_default();
+        // The maximum data transfer size is a power of two of the pages, or unlimited if it's zero.
+        let max_transfer = match data[CONTROLLER_MDTS] {
+            0 => usize::MAX,
+            mdts => PAGE_SIZE << mdts.min(20),
+        }
+        .min(PRP_LIST_ENTRIES * PAGE_SIZE);
+
+        let version = read_u32(regs, REG_VS);
+        log::info!(
+            "NVMe {}.{}: {} ({})",
+            version >> 16,
+            (version >> 8) & 0xFF,
+            model.trim(),
+            serial.trim()
+        );
+
+        // The controller tells how many queue pairs it has allocated of those requested, as numbers from zero.
+        let mut cpus = [0; MAX_IO_QUEUES];
+        let mut wanted = 0;
+        for (slot, cpu) in cpus.iter_mut().zip(msi::cpus()) {
+            *slot = cpu;
+            wanted += 1;
+        }
+        let wanted = wanted.max(1) as u32;
+        let allocated = admin.execute(
+            command(
+                ADMIN_SET_FEATURES,
+                0,
+                &[FEATURE_NUMBER_OF_QUEUES, (wanted - 1) << 16 | (wanted - 1)],
+            ),
+            &[],
+        )?;
+        let queue_count = (wanted as usize)
+            .min((allocated & 0xFFFF) as usize + 1)
+            .min((allocated >> 16) as usize + 1);
+
+        let size = IO_QUEUE_SIZE.min(max_queue_size);
+        for index in 0..queue_count {
+            let id = index as u16 + 1;
+            let pair = QueuePair::new(id, size, regs, self.doorbell_stride, true)?;
+            let mut queue = self.io_queues[index].lock();
+            let pair = queue.insert(pair);
+            self.irqs[index] = enable_interrupt(pci, id, cpus[index]);
+            create_io_queue(admin, pair, self.irqs[index].is_some())?;
+        }
+
+        Ok(Config {
+            cpus,
+            queue_count,
+            max_transfer,
+        })
+    }
+
+    /// Stops the controller, and frees its interrupts and its queues, which it no longer accesses.
+    fn release(self, pci: &PciDevice) {
+        if self.irqs.iter().any(Option::is_some) {
+            msi::disable(pci);
+        }
+        for irq in self.irqs.into_iter().flatten() {
+            let _ = irq::free(irq);
+        }
+
+        write_u32(self.regs, REG_CC, 0);
+        pci.set_command(pci.command() & !COMMAND_BUS_MASTER);
+
+        for queue in self.io_queues {
+            if let Some(pair) = queue.into_inner() {
+                pair.free();
+            }
+        }
+        self.admin.free();
+        self.buf.free();
+    }
+}
+
+/// Registers the active namespaces of a controller as block devices.
+fn add_namespaces(
+    controller: &'static Controller,
+    admin: &mut QueuePair,
+    buf: &mut DmaBuffer,
+) -> Result<(), NvmeError> {
+    let mut list = [0u32; PAGE_SIZE / 4];
+    admin.execute(
+        command(ADMIN_IDENTIFY, 0, &[IDENTIFY_NAMESPACE_LIST]),
+        &[buf.phys_addr()],
+    )?;
+    for (i, id) in list.iter_mut().enumerate() {
+        *id = u32::from_le_bytes(buf.as_slice()[4 * i..4 * i + 4].try_into().unwrap());
+    }
+
+    for id in list.into_iter().take_while(|id| *id != 0) {
+        admin.execute(
+            command(ADMIN_IDENTIFY, id, &[IDENTIFY_NAMESPACE]),
+            &[buf.phys_addr()],
+        )?;
+        let data = buf.as_slice();
+        let block_count =
+            u64::from_le_bytes(data[NAMESPACE_SIZE..NAMESPACE_SIZE + 8].try_into().unwrap());
+        let format = NAMESPACE_LBA_FORMATS + 4 * (data[NAMESPACE_FLBAS] & 0xF) as usize;
+        let metadata_size = u16::from_le_bytes([data[format], data[format + 1]]);
+        let block_size = 1usize << data[format + 2].min(31);
+
+        if metadata_size != 0 || !(512..=PAGE_SIZE).contains(&block_size) {
+            log::warn!("NVMe: namespace {} has an unsupported format", id);
+            continue;
+        }
+
+        let number = NAMESPACE_COUNT.fetch_add(1, Ordering::Relaxed);
+        let Some(slot) = NAMESPACES.get(number) else {
+            log::warn!("NVMe: too many namespaces");
+            break;
+        };
+
+        let mut name = [0; 16];
+        let mut writer = NameWriter {
+            buf: &mut name,
+            len: 0,
+        };
+        let _ = write!(writer, "nvme{}n{}", controller.index, id);
+        let name_len = writer.len;
+
+        let namespace = slot.call_once(|| Namespace {
+            name,
+            name_len,
+            controller,
+            id,
+            info: BlockInfo {
+                block_size,
+                block_count,
+                read_only: false,
+            },
+        });
+        if block::register(namespace).is_err() {
+            log::warn!("NVMe: failed to register namespace {}", id);
+        }
+    }
+
+    Ok(())
+}
+
+/// A buffer into which the name of a namespace is formatted.
+struct NameWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Write for NameWriter<'_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let end = self.len + s.len();
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(core::fmt::Error)?
+            .copy_from_slice(s.as_bytes());
+        self.len = end;
+
+        Ok(())
+    }
+}
+
+/// Non-Volatile Memory Express (NVMe)
+///
+/// The controller takes commands through pairs of submission and completion queues in memory: the admin queue pair
+/// sets up the controller, while every processor gets an I/O queue pair of its own for the reads and writes, whose
+/// completions are signaled through MSI-X.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/NVMe
+fn probe_controller(pci: &PciDevice) -> Result<(), NvmeError> {
+    let Some(Bar::Memory { addr, size, .. }) = pci.bars[0] else {
+        return Err(NvmeError::Fatal);
+    };
+    let regs =
+        arch::paging::map_mmio(PhysAddr::new(addr), size as usize).ok_or(NvmeError::Fatal)?;
+    let regs = regs.as_u64() as usize;
+    pci.enable(true);
+
+    let capabilities = read_u64(regs, REG_CAP);
+    let doorbell_stride = 4 << ((capabilities >> CAP_DSTRD_SHIFT) & CAP_DSTRD_MASK);
+    let max_queue_size = (capabilities & CAP_MQES_MASK) as u16 + 1;
+    let buf = DmaBuffer::allocate(PAGE_SIZE).ok_or(NvmeError::NoMemory)?;
+    let admin = match enable(regs, doorbell_stride) {
+        Ok(admin) => admin,
+        Err(error) => {
+            buf.free();
+            return Err(error);
+        }
+    };
+
+    let mut setup = Setup {
+        regs,
+        doorbell_stride,
+        admin,
+        buf,
+        io_queues: [const { Mutex::new(None) }; MAX_IO_QUEUES],
+        irqs: [None; MAX_IO_QUEUES],
+    };
+    let index = CONTROLLER_COUNT.fetch_add(1, Ordering::Relaxed);
+    let result = CONTROLLERS
+        .get(index)
+        .ok_or(NvmeError::NoMemory)
+        .and_then(|slot| Ok((slot, setup.run(pci, max_queue_size)?)));
+    let (slot, config) = match result {
+        Ok(result) => result,
+        Err(error) => {
+            setup.release(pci);
+            return Err(error);
+        }
+    };
+
+    let Setup {
+        admin,
+        mut buf,
+        io_queues,
+        ..
+    } = setup;
+    let controller = slot.call_once(|| Controller {
+        index,
+        io_queues,
+        cpus: config.cpus,
+        queue_count: config.queue_count,
+        max_transfer: config.max_transfer,
+        admin: Mutex::new(admin),
+    });
+    log::info!("nvme{}: {} I/O queue pair(s)", index, config.queue_count);
+
+    let result = add_namespaces(controller, &mut controller.admin.lock(), &mut buf);
+    buf.free();
+
+    result
+}
+
+fn probe(pci: &PciDevice) -> Result<(), ()> {
+    probe_controller(pci)
+        .map_err(|error| log::warn!("NVMe: failed to set up the controller: {:?}", error))
+}
+
+pub(crate) fn init() -> Result<(), ()> {
+    pci::register_driver(&DRIVER).map_err(|_| ())
+}
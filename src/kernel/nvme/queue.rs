@@ -0,0 +1,270 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::ptr;
+use core::sync::atomic::{fence, Ordering};
+use core::task::Poll;
+
+use crate::kernel::dma::DmaBuffer;
+
+use super::NvmeError;
+
+/// The maximum number of commands in flight per queue pair, whose identifiers are their slots.
+pub const MAX_SLOTS: usize = 32;
+/// The number of entries of the PRP list of each slot, which limits the pages of a transfer.
+pub const PRP_LIST_ENTRIES: usize = 64;
+
+const SUBMISSION_ENTRY_SIZE: usize = 64;
+const COMPLETION_ENTRY_SIZE: usize = 16;
+const PRP_LIST_SIZE: usize = PRP_LIST_ENTRIES * 8;
+
+// The fields of the entries, in double words for the submissions and in bytes for the completions.
+const SUBMISSION_PRP1: usize = 6;
+const SUBMISSION_PRP2: usize = 8;
+const COMPLETION_RESULT: usize = 0;
+const COMPLETION_COMMAND_ID: usize = 12;
+const COMPLETION_STATUS: usize = 14;
+
+/// The phase bit of a completion, which the controller inverts on every pass through the queue.
+const STATUS_PHASE: u16 = 1 << 0;
+
+/// A command as its 16 double words, whose identifier is filled in when it's submitted.
+pub type Command = [u32; 16];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Slot {
+    Free,
+    Pending,
+    /// The result in the first double word of the completion, or the status code.
+    Done(Result<u32, u16>),
+    /// Given up on by `execute`, which is freed once the late completion of the command is reaped, as its identifier
+    /// and PRP list remain in use by the controller until then.
+    TimedOut,
+}
+
+/// A submission queue along with the completion queue which it's paired with.
+///
+/// NVMe Spec: https://nvmexpress.org/specifications/ (section 3.3)
+pub struct QueuePair {
+    id: u16,
+    size: u16,
+    submissions: DmaBuffer,
+    completions: DmaBuffer,
+    prp_lists: Option<DmaBuffer>,
+    sq_tail: u16,
+    cq_head: u16,
+    phase: bool,
+    sq_doorbell: usize,
+    cq_doorbell: usize,
+    slots: [Slot; MAX_SLOTS],
+}
+
+impl QueuePair {
+    /// Allocates the queues, whose doorbells are found from the registers of the controller and their stride.
+    pub fn new(
+        id: u16,
+        size: u16,
+        regs: usize,
+        doorbell_stride: usize,
+        prp_lists: bool,
+    ) -> Result<Self, NvmeError> {
+        let submissions = DmaBuffer::allocate(size as usize * SUBMISSION_ENTRY_SIZE)
+            .ok_or(NvmeError::NoMemory)?;
+        let Some(completions) = DmaBuffer::allocate(size as usize * COMPLETION_ENTRY_SIZE) else {
+            submissions.free();
+            return Err(NvmeError::NoMemory);
+        };
+        let prp_lists = match prp_lists {
+            true => match DmaBuffer::allocate(MAX_SLOTS * PRP_LIST_SIZE) {
+                Some(buf) => Some(buf),
+                None => {
+                    submissions.free();
+                    completions.free();
+                    return Err(NvmeError::NoMemory);
+                }
+            },
+            false => None,
+        };
+
+        let doorbells = regs + super::REG_DOORBELLS;
+        Ok(Self {
+            id,
+            size,
+            submissions,
+            completions,
+            prp_lists,
+            sq_tail: 0,
+            cq_head: 0,
+            phase: true,
+            sq_doorbell: doorbells + (2 * id as usize) * doorbell_stride,
+            cq_doorbell: doorbells + (2 * id as usize + 1) * doorbell_stride,
+            slots: [Slot::Free; MAX_SLOTS],
+        })
+    }
+
+    /// Frees the queues, which the controller must no longer access.
+    pub fn free(self) {
+        self.submissions.free();
+        self.completions.free();
+        if let Some(prp_lists) = self.prp_lists {
+            prp_lists.free();
+        }
+    }
+
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    pub fn submission_addr(&self) -> u64 {
+        self.submissions.phys_addr()
+    }
+
+    pub fn completion_addr(&self) -> u64 {
+        self.completions.phys_addr()
+    }
+
+    /// Submits a command, whose data lies in the given pages, and returns the slot which tracks it.
+    ///
+    /// The first page may start at an offset, while the others are whole pages. More than two pages are described
+    /// by the PRP list of the slot.
+    pub fn submit(&mut self, mut command: Command, pages: &[u64]) -> Result<usize, NvmeError> {
+        // A queue is full when its tail would reach its head, hence one entry is left unused.
+        let slot_count = MAX_SLOTS.min(self.size as usize - 1);
+        let slot = self.slots[..slot_count]
+            .iter()
+            .position(|slot| *slot == Slot::Free)
+            .ok_or(NvmeError::Busy)?;
+
+        let (prp1, prp2) = match pages {
+            [] => (0, 0),
+            [first] => (*first, 0),
+            [first, second] => (*first, *second),
+            [first, rest @ ..] => {
+                let lists = self.prp_lists.as_ref().ok_or(NvmeError::InvalidBuffer)?;
+                if rest.len() > PRP_LIST_ENTRIES {
+                    return Err(NvmeError::InvalidBuffer);
+                }
+                let list = unsafe { lists.as_ptr::<u64>().add(slot * PRP_LIST_ENTRIES) };
+                for (i, page) in rest.iter().enumerate() {
+                    unsafe { ptr::write_volatile(list.add(i), *page) };
+                }
+                (*first, lists.phys_addr() + (slot * PRP_LIST_SIZE) as u64)
+            }
+        };
+
+        command[0] |= (slot as u32) << 16;
+        command[SUBMISSION_PRP1] = prp1 as u32;
+        command[SUBMISSION_PRP1 + 1] = (prp1 >> 32) as u32;
+        command[SUBMISSION_PRP2] = prp2 as u32;
+        command[SUBMISSION_PRP2 + 1] = (prp2 >> 32) as u32;
+
+        let entry = unsafe {
+            self.submissions
+                .as_ptr::<u32>()
+                .add(self.sq_tail as usize * SUBMISSION_ENTRY_SIZE / 4)
+        };
+        for (i, dword) in command.iter().enumerate() {
+            unsafe { ptr::write_volatile(entry.add(i), *dword) };
+        }
+        self.slots[slot] = Slot::Pending;
+
+        // The entry must be visible to the controller before the doorbell is rung.
+        fence(Ordering::Release);
+        self.sq_tail = (self.sq_tail + 1) % self.size;
+        unsafe { ptr::write_volatile(self.sq_doorbell as *mut u32, self.sq_tail as u32) };
+
+        Ok(slot)
+    }
+
+    /// Takes the completions which the controller has posted, i.e. those whose phase matches the current pass.
+    pub fn reap(&mut self) {
+        let mut reaped = false;
+        loop {
+            let entry = unsafe {
+                self.completions
+                    .as_ptr::<u8>()
+                    .add(self.cq_head as usize * COMPLETION_ENTRY_SIZE)
+            };
+            let status = unsafe { ptr::read_volatile(entry.add(COMPLETION_STATUS) as *const u16) };
+            if (status & STATUS_PHASE != 0) != self.phase {
+                break;
+            }
+            fence(Ordering::Acquire);
+
+            let id = unsafe { ptr::read_volatile(entry.add(COMPLETION_COMMAND_ID) as *const u16) }
+                as usize;
+            let result = unsafe { ptr::read_volatile(entry.add(COMPLETION_RESULT) as *const u32) };
+            match self.slots.get_mut(id) {
+                Some(slot @ Slot::Pending) => {
+                    *slot = Slot::Done(match status >> 1 {
+                        0 => Ok(result),
+                        code => Err(code),
+                    })
+                }
+                Some(slot @ Slot::TimedOut) => *slot = Slot::Free,
+                _ => {}
+            }
+
+            self.cq_head += 1;
+            if self.cq_head == self.size {
+                self.cq_head = 0;
+                self.phase = !self.phase;
+            }
+            reaped = true;
+        }
+
+        if reaped {
+            unsafe { ptr::write_volatile(self.cq_doorbell as *mut u32, self.cq_head as u32) };
+        }
+    }
+
+    pub fn poll(&mut self, slot: usize) -> Poll<Result<u32, NvmeError>> {
+        self.reap();
+        match self.slots.get(slot) {
+            Some(Slot::Done(result)) => {
+                let result = result.map_err(NvmeError::Command);
+                self.slots[slot] = Slot::Free;
+                Poll::Ready(result)
+            }
+            Some(Slot::Pending) => Poll::Pending,
+            _ => Poll::Ready(Err(NvmeError::UnknownCommand)),
+        }
+    }
+
+    /// Submits a command and waits for its completion.
+    pub fn execute(&mut self, command: Command, pages: &[u64]) -> Result<u32, NvmeError> {
+        let slot = self.submit(command, pages)?;
+        for _ in 0..super::TIMEOUT {
+            if let Poll::Ready(result) = self.poll(slot) {
+                return result;
+            }
+            core::hint::spin_loop();
+        }
+
+        self.slots[slot] = Slot::TimedOut;
+        Err(NvmeError::Timeout)
+    }
+}
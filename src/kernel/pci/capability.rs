@@ -0,0 +1,187 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use super::config::PciAddress;
+
+/// The offset of the pointer to the first capability, which is only valid if the status register says so.
+pub(super) const CAPABILITIES_POINTER: u16 = 0x34;
+
+const CAP_ID_POWER_MANAGEMENT: u8 = 0x01;
+const CAP_ID_MSI: u8 = 0x05;
+const CAP_ID_VENDOR_SPECIFIC: u8 = 0x09;
+const CAP_ID_PCI_EXPRESS: u8 = 0x10;
+const CAP_ID_MSI_X: u8 = 0x11;
+
+/// The maximum number of capabilities visited, which guards against a looping list.
+const MAX_CAPABILITIES: usize = 48;
+
+// The fields of the message control register of MSI.
+const MSI_64_BIT: u16 = 1 << 7;
+const MSI_PER_VECTOR_MASKING: u16 = 1 << 8;
+
+// The offset field of MSI-X holds the BAR in its lowest bits.
+const MSI_X_BAR_MASK: u32 = 0b111;
+
+/// Message Signaled Interrupts (MSI)
+///
+/// OS Dev Wiki: https://wiki.osdev.org/PCI#Message_Signaled_Interrupts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MsiCapability {
+    pub offset: u8,
+    /// Whether the message address is 64 bits wide.
+    pub is_64_bit: bool,
+    pub per_vector_masking: bool,
+    /// The number of vectors which the function can request, which is a power of two.
+    pub max_vectors: u8,
+}
+
+/// MSI-X, which places a table of vectors with their own addresses into the memory of a BAR.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MsiXCapability {
+    pub offset: u8,
+    pub table_size: u16,
+    pub table_bar: u8,
+    pub table_offset: u32,
+    /// The BAR of the Pending Bit Array (PBA).
+    pub pba_bar: u8,
+    pub pba_offset: u32,
+}
+
+/// The capability of the functions of PCI Express, which describes their role and their link.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PciExpressCapability {
+    pub offset: u8,
+    pub version: u8,
+    /// The type of device or port, e.g. 0 for an endpoint or 4 for a root port.
+    pub port_type: u8,
+    /// The generation of the current link speed (e.g. 3 for 8 GT/s).
+    pub link_speed: u8,
+    /// The number of lanes of the current link.
+    pub link_width: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Capability {
+    PowerManagement { offset: u8 },
+    Msi(MsiCapability),
+    MsiX(MsiXCapability),
+    PciExpress(PciExpressCapability),
+    VendorSpecific { offset: u8 },
+    Other { id: u8, offset: u8 },
+}
+
+impl Capability {
+    fn parse(addr: PciAddress, id: u8, offset: u8) -> Self {
+        let register = offset as u16;
+        match id {
+            CAP_ID_POWER_MANAGEMENT => Capability::PowerManagement { offset },
+            CAP_ID_MSI => {
+                let control = addr.read_u16(register + 2);
+                Capability::Msi(MsiCapability {
+                    offset,
+                    is_64_bit: control & MSI_64_BIT != 0,
+                    per_vector_masking: control & MSI_PER_VECTOR_MASKING != 0,
+                    max_vectors: 1 << ((control >> 1) & 0b111).min(5),
+                })
+            }
+            CAP_ID_MSI_X => {
+                let control = addr.read_u16(register + 2);
+                let table = addr.read_u32(register + 4);
+                let pba = addr.read_u32(register + 8);
+                Capability::MsiX(MsiXCapability {
+                    offset,
+                    table_size: (control & 0x7FF) + 1,
+                    table_bar: (table & MSI_X_BAR_MASK) as u8,
+                    table_offset: table & !MSI_X_BAR_MASK,
+                    pba_bar: (pba & MSI_X_BAR_MASK) as u8,
+                    pba_offset: pba & !MSI_X_BAR_MASK,
+                })
+            }
+            CAP_ID_PCI_EXPRESS => {
+                let capabilities = addr.read_u16(register + 2);
+                let link_status = addr.read_u16(register + 0x12);
+                Capability::PciExpress(PciExpressCapability {
+                    offset,
+                    version: (capabilities & 0xF) as u8,
+                    port_type: ((capabilities >> 4) & 0xF) as u8,
+                    link_speed: (link_status & 0xF) as u8,
+                    link_width: ((link_status >> 4) & 0x3F) as u8,
+                })
+            }
+            CAP_ID_VENDOR_SPECIFIC => Capability::VendorSpecific { offset },
+            _ => Capability::Other { id, offset },
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Capability::PowerManagement { .. } => "pm",
+            Capability::Msi(_) => "msi",
+            Capability::MsiX(_) => "msi-x",
+            Capability::PciExpress(_) => "pcie",
+            Capability::VendorSpecific { .. } => "vendor",
+            Capability::Other { .. } => "other",
+        }
+    }
+}
+
+/// Iterator over the linked list of capabilities of a function.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/PCI#Capabilities_List
+#[derive(Clone, Copy, Debug)]
+pub struct Capabilities {
+    addr: PciAddress,
+    next: u8,
+    remaining: usize,
+}
+
+impl Capabilities {
+    pub(super) fn new(addr: PciAddress, first: u8) -> Self {
+        Self {
+            addr,
+            next: first,
+            remaining: MAX_CAPABILITIES,
+        }
+    }
+
+    pub(super) fn empty(addr: PciAddress) -> Self {
+        Self::new(addr, 0)
+    }
+}
+
+impl Iterator for Capabilities {
+    type Item = Capability;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // The capabilities are aligned to 4 bytes, and follow the standard header of 64 bytes.
+        let offset = self.next & !0b11;
+        if offset < 0x40 || self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+
+        let header = self.addr.read_u16(offset as u16);
+        self.next = (header >> 8) as u8;
+
+        Some(Capability::parse(self.addr, header as u8, offset))
+    }
+}
@@ -0,0 +1,198 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::fmt;
+use core::ptr;
+
+use crate::kernel::sync::RwLock;
+
+/// The maximum number of memory-mapped areas of the configuration space, usually one per PCI segment.
+const MAX_ECAM_REGIONS: usize = 4;
+
+/// The size of the configuration space of a function through the legacy mechanism.
+pub const LEGACY_CONFIG_SIZE: u16 = 256;
+/// The size of the configuration space of a function through ECAM, which includes the extended capabilities.
+pub const EXTENDED_CONFIG_SIZE: u16 = 4096;
+
+/// The address of a function in the configuration space.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PciAddress {
+    pub segment: u16,
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl PciAddress {
+    pub const fn new(segment: u16, bus: u8, device: u8, function: u8) -> Self {
+        Self {
+            segment,
+            bus,
+            device,
+            function,
+        }
+    }
+
+    pub fn read_u32(&self, offset: u16) -> u32 {
+        read(*self, offset & !0b11)
+    }
+
+    pub fn read_u16(&self, offset: u16) -> u16 {
+        (self.read_u32(offset) >> ((offset & 0b10) * 8)) as u16
+    }
+
+    pub fn read_u8(&self, offset: u16) -> u8 {
+        (self.read_u32(offset) >> ((offset & 0b11) * 8)) as u8
+    }
+
+    pub fn write_u32(&self, offset: u16, value: u32) {
+        write(*self, offset & !0b11, value);
+    }
+
+    /// Writes a part of a register, by reading and writing it as a whole.
+    ///
+    /// NOTE: Some registers have bits which are cleared by writing ones to them (e.g. the status register).
+    pub fn write_u16(&self, offset: u16, value: u16) {
+        let shift = (offset & 0b10) * 8;
+        let register = self.read_u32(offset) & !(0xFFFF << shift);
+        self.write_u32(offset, register | (value as u32) << shift);
+    }
+
+    pub fn write_u8(&self, offset: u16, value: u8) {
+        let shift = (offset & 0b11) * 8;
+        let register = self.read_u32(offset) & !(0xFF << shift);
+        self.write_u32(offset, register | (value as u32) << shift);
+    }
+}
+
+impl fmt::Display for PciAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04x}:{:02x}:{:02x}.{}",
+            self.segment, self.bus, self.device, self.function
+        )
+    }
+}
+
+/// The legacy configuration mechanism, which is provided by the architecture (e.g. through I/O ports).
+///
+/// It only reaches the first 256 bytes of the configuration space of segment 0.
+#[derive(Clone, Copy, Debug)]
+pub struct LegacyAccess {
+    pub read: fn(PciAddress, u16) -> u32,
+    pub write: fn(PciAddress, u16, u32),
+}
+
+/// Enhanced Configuration Access Mechanism (ECAM)
+///
+/// The configuration space of every function of a range of buses is mapped into memory, with 4 KiB per function.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/PCI_Express
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EcamRegion {
+    /// The virtual address of the configuration space of the first bus.
+    pub base: usize,
+    pub segment: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+}
+
+impl EcamRegion {
+    fn covers(&self, addr: PciAddress) -> bool {
+        addr.segment == self.segment && (self.start_bus..=self.end_bus).contains(&addr.bus)
+    }
+
+    fn register(&self, addr: PciAddress, offset: u16) -> *mut u32 {
+        let bus = (addr.bus - self.start_bus) as usize;
+        let function = (bus << 20) | (addr.device as usize) << 15 | (addr.function as usize) << 12;
+        (self.base + function + offset as usize) as *mut u32
+    }
+}
+
+static LEGACY: RwLock<Option<LegacyAccess>> = RwLock::new(None);
+static ECAM: RwLock<[Option<EcamRegion>; MAX_ECAM_REGIONS]> = RwLock::new([None; MAX_ECAM_REGIONS]);
+
+fn find_ecam(addr: PciAddress) -> Option<EcamRegion> {
+    ECAM.read()
+        .iter()
+        .flatten()
+        .find(|region| region.covers(addr))
+        .copied()
+}
+
+/// Returns the size of the configuration space which is accessible for a function.
+pub fn config_size(addr: PciAddress) -> u16 {
+    match find_ecam(addr) {
+        Some(_) => EXTENDED_CONFIG_SIZE,
+        None => LEGACY_CONFIG_SIZE,
+    }
+}
+
+/// Reads a register of the configuration space, which reads as all ones if it's not accessible.
+fn read(addr: PciAddress, offset: u16) -> u32 {
+    if let Some(region) = find_ecam(addr) {
+        if offset < EXTENDED_CONFIG_SIZE {
+            return unsafe { ptr::read_volatile(region.register(addr, offset)) };
+        }
+    } else if let Some(legacy) = *LEGACY.read() {
+        if addr.segment == 0 && offset < LEGACY_CONFIG_SIZE {
+            return (legacy.read)(addr, offset);
+        }
+    }
+
+    u32::MAX
+}
+
+fn write(addr: PciAddress, offset: u16, value: u32) {
+    if let Some(region) = find_ecam(addr) {
+        if offset < EXTENDED_CONFIG_SIZE {
+            unsafe { ptr::write_volatile(region.register(addr, offset), value) };
+        }
+    } else if let Some(legacy) = *LEGACY.read() {
+        if addr.segment == 0 && offset < LEGACY_CONFIG_SIZE {
+            (legacy.write)(addr, offset, value);
+        }
+    }
+}
+
+/// Returns the memory-mapped areas of the configuration space.
+pub fn ecam_regions() -> impl Iterator<Item = EcamRegion> {
+    let regions = *ECAM.read();
+    regions.into_iter().flatten()
+}
+
+pub fn has_legacy_access() -> bool {
+    LEGACY.read().is_some()
+}
+
+pub(crate) fn register_legacy(access: LegacyAccess) {
+    *LEGACY.write() = Some(access);
+}
+
+pub(crate) fn register_ecam(region: EcamRegion) -> Result<(), ()> {
+    let mut regions = ECAM.write();
+    let slot = regions.iter_mut().find(|slot| slot.is_none()).ok_or(())?;
+    *slot = Some(region);
+
+    Ok(())
+}
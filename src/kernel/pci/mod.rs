@@ -0,0 +1,697 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::kernel::sync::{Mutex, RwLock};
+
+use self::capability::{Capabilities, Capability, CAPABILITIES_POINTER};
+use self::capability::{MsiCapability, MsiXCapability, PciExpressCapability};
+use self::config::PciAddress;
+
+pub mod capability;
+pub mod config;
+
+/// The maximum number of functions which are kept track of.
+const MAX_DEVICES: usize = 128;
+const MAX_DRIVERS: usize = 32;
+
+const DEVICES_PER_BUS: u8 = 32;
+const FUNCTIONS_PER_DEVICE: u8 = 8;
+
+const REG_VENDOR_ID: u16 = 0x00;
+const REG_DEVICE_ID: u16 = 0x02;
+const REG_COMMAND: u16 = 0x04;
+const REG_STATUS: u16 = 0x06;
+const REG_REVISION: u16 = 0x08;
+const REG_PROG_IF: u16 = 0x09;
+const REG_SUBCLASS: u16 = 0x0A;
+const REG_CLASS: u16 = 0x0B;
+const REG_HEADER_TYPE: u16 = 0x0E;
+const REG_BAR_0: u16 = 0x10;
+const REG_SECONDARY_BUS: u16 = 0x19;
+const REG_SUBORDINATE_BUS: u16 = 0x1A;
+const REG_SUBSYSTEM_VENDOR_ID: u16 = 0x2C;
+const REG_SUBSYSTEM_ID: u16 = 0x2E;
+const REG_INTERRUPT_LINE: u16 = 0x3C;
+const REG_INTERRUPT_PIN: u16 = 0x3D;
+
+/// The vendor ID read from a function which doesn't exist.
+const INVALID_VENDOR_ID: u16 = 0xFFFF;
+
+const HEADER_TYPE_MASK: u8 = 0x7F;
+const HEADER_MULTIFUNCTION: u8 = 1 << 7;
+const HEADER_GENERAL: u8 = 0x00;
+const HEADER_PCI_BRIDGE: u8 = 0x01;
+const HEADER_CARDBUS_BRIDGE: u8 = 0x02;
+
+pub const COMMAND_IO_SPACE: u16 = 1 << 0;
+pub const COMMAND_MEMORY_SPACE: u16 = 1 << 1;
+pub const COMMAND_BUS_MASTER: u16 = 1 << 2;
+pub const COMMAND_INTERRUPT_DISABLE: u16 = 1 << 10;
+
+const STATUS_CAPABILITIES: u16 = 1 << 4;
+
+const CLASS_BRIDGE: u8 = 0x06;
+const SUBCLASS_HOST_BRIDGE: u8 = 0x00;
+
+const BAR_IO: u32 = 1 << 0;
+const BAR_TYPE_MASK: u32 = 0b110;
+const BAR_TYPE_64_BIT: u32 = 0b100;
+const BAR_PREFETCHABLE: u32 = 1 << 3;
+const BAR_IO_MASK: u32 = !0b11;
+const BAR_MEMORY_MASK: u32 = !0b1111;
+
+/// A Base Address Register (BAR), which locates a range of memory or I/O ports decoded by a function.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bar {
+    Memory {
+        addr: u64,
+        size: u64,
+        prefetchable: bool,
+        is_64_bit: bool,
+    },
+    Io {
+        port: u32,
+        size: u32,
+    },
+}
+
+impl Bar {
+    pub fn addr(&self) -> u64 {
+        match *self {
+            Bar::Memory { addr, .. } => addr,
+            Bar::Io { port, .. } => port as u64,
+        }
+    }
+
+    pub fn size(&self) -> u64 {
+        match *self {
+            Bar::Memory { size, .. } => size,
+            Bar::Io { size, .. } => size as u64,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeaderKind {
+    General,
+    PciBridge {
+        secondary_bus: u8,
+        subordinate_bus: u8,
+    },
+    CardBusBridge,
+    Unknown(u8),
+}
+
+/// A function of a PCI device, as found during the enumeration.
+#[derive(Clone, Copy, Debug)]
+pub struct PciDevice {
+    pub addr: PciAddress,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    pub revision: u8,
+    pub subsystem_vendor_id: u16,
+    pub subsystem_id: u16,
+    pub header: HeaderKind,
+    pub bars: [Option<Bar>; 6],
+    /// The IRQ line to which the legacy interrupt pin is routed, as set up by the firmware.
+    pub interrupt_line: u8,
+    /// The legacy interrupt pin (1 to 4 for INTA# to INTD#), or 0 if there's none.
+    pub interrupt_pin: u8,
+}
+
+impl PciDevice {
+    fn probe(addr: PciAddress) -> Option<Self> {
+        let vendor_id = addr.read_u16(REG_VENDOR_ID);
+        if vendor_id == INVALID_VENDOR_ID {
+            return None;
+        }
+
+        let header = match addr.read_u8(REG_HEADER_TYPE) & HEADER_TYPE_MASK {
+            HEADER_GENERAL => HeaderKind::General,
+            HEADER_PCI_BRIDGE => HeaderKind::PciBridge {
+                secondary_bus: addr.read_u8(REG_SECONDARY_BUS),
+                subordinate_bus: addr.read_u8(REG_SUBORDINATE_BUS),
+            },
+            HEADER_CARDBUS_BRIDGE => HeaderKind::CardBusBridge,
+            kind => HeaderKind::Unknown(kind),
+        };
+        let (bar_count, has_subsystem) = match header {
+            HeaderKind::General => (6, true),
+            HeaderKind::PciBridge { .. } => (2, false),
+            _ => (0, false),
+        };
+
+        let mut device = Self {
+            addr,
+            vendor_id,
+            device_id: addr.read_u16(REG_DEVICE_ID),
+            class: addr.read_u8(REG_CLASS),
+            subclass: addr.read_u8(REG_SUBCLASS),
+            prog_if: addr.read_u8(REG_PROG_IF),
+            revision: addr.read_u8(REG_REVISION),
+            subsystem_vendor_id: 0,
+            subsystem_id: 0,
+            header,
+            bars: [None; 6],
+            interrupt_line: addr.read_u8(REG_INTERRUPT_LINE),
+            interrupt_pin: addr.read_u8(REG_INTERRUPT_PIN),
+        };
+        if has_subsystem {
+            device.subsystem_vendor_id = addr.read_u16(REG_SUBSYSTEM_VENDOR_ID);
+            device.subsystem_id = addr.read_u16(REG_SUBSYSTEM_ID);
+        }
+        device.decode_bars(bar_count);
+
+        Some(device)
+    }
+
+    /// Reads the BARs along with their sizes, which are found by writing all ones to them and reading back which
+    /// bits of the address are writable.
+    ///
+    /// OS Dev Wiki: https://wiki.osdev.org/PCI#Base_Address_Registers
+    fn decode_bars(&mut self, count: usize) {
+        let addr = self.addr;
+
+        // The function must not decode the addresses while they're being probed, except for the host bridges,
+        // through which the memory itself may be accessed.
+        let command = addr.read_u16(REG_COMMAND);
+        if (self.class, self.subclass) != (CLASS_BRIDGE, SUBCLASS_HOST_BRIDGE) {
+            let disabled = command & !(COMMAND_IO_SPACE | COMMAND_MEMORY_SPACE);
+            addr.write_u16(REG_COMMAND, disabled);
+        }
+
+        let probe = |index: usize| {
+            let register = REG_BAR_0 + 4 * index as u16;
+            let value = addr.read_u32(register);
+            addr.write_u32(register, u32::MAX);
+            let mask = addr.read_u32(register);
+            addr.write_u32(register, value);
+            (value, mask)
+        };
+
+        let mut index = 0;
+        while index < count {
+            let (value, mask) = probe(index);
+            let slot = index;
+            index += 1;
+
+            if value & BAR_IO != 0 {
+                let size = (!(mask & BAR_IO_MASK)).wrapping_add(1) & 0xFFFF;
+                if size != 0 {
+                    self.bars[slot] = Some(Bar::Io {
+                        port: value & BAR_IO_MASK,
+                        size,
+                    });
+                }
+                continue;
+            }
+
+            let is_64_bit = value & BAR_TYPE_MASK == BAR_TYPE_64_BIT && index < count;
+            let (mut bar_addr, mut bar_mask) = (
+                (value & BAR_MEMORY_MASK) as u64,
+                (mask & BAR_MEMORY_MASK) as u64,
+            );
+            if is_64_bit {
+                let (high_value, high_mask) = probe(index);
+                index += 1;
+                bar_addr |= (high_value as u64) << 32;
+                bar_mask |= (high_mask as u64) << 32;
+            } else {
+                bar_mask |= 0xFFFF_FFFF_0000_0000;
+            }
+
+            let size = (!bar_mask).wrapping_add(1);
+            if bar_mask != 0xFFFF_FFFF_0000_0000 && size != 0 {
+                self.bars[slot] = Some(Bar::Memory {
+                    addr: bar_addr,
+                    size,
+                    prefetchable: value & BAR_PREFETCHABLE != 0,
+                    is_64_bit,
+                });
+            }
+        }
+
+        addr.write_u16(REG_COMMAND, command);
+    }
+
+    pub fn is_multifunction(&self) -> bool {
+        self.addr.read_u8(REG_HEADER_TYPE) & HEADER_MULTIFUNCTION != 0
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        match self.addr.read_u16(REG_STATUS) & STATUS_CAPABILITIES != 0 {
+            true => Capabilities::new(self.addr, self.addr.read_u8(CAPABILITIES_POINTER)),
+            false => Capabilities::empty(self.addr),
+        }
+    }
+
+    pub fn msi(&self) -> Option<MsiCapability> {
+        self.capabilities().find_map(|capability| match capability {
+            Capability::Msi(msi) => Some(msi),
+            _ => None,
+        })
+    }
+
+    pub fn msi_x(&self) -> Option<MsiXCapability> {
+        self.capabilities().find_map(|capability| match capability {
+            Capability::MsiX(msi_x) => Some(msi_x),
+            _ => None,
+        })
+    }
+
+    pub fn pci_express(&self) -> Option<PciExpressCapability> {
+        self.capabilities().find_map(|capability| match capability {
+            Capability::PciExpress(pci_express) => Some(pci_express),
+            _ => None,
+        })
+    }
+
+    pub fn command(&self) -> u16 {
+        self.addr.read_u16(REG_COMMAND)
+    }
+
+    pub fn set_command(&self, command: u16) {
+        self.addr.write_u16(REG_COMMAND, command);
+    }
+
+    /// Enables the decoding of the BARs, and optionally the accesses of the function to the memory (DMA).
+    pub fn enable(&self, bus_master: bool) {
+        let mut command = self.command() | COMMAND_IO_SPACE | COMMAND_MEMORY_SPACE;
+        if bus_master {
+            command |= COMMAND_BUS_MASTER;
+        }
+        self.set_command(command);
+    }
+
+    /// Masks or unmasks the legacy interrupt of the function (INTx).
+    pub fn set_legacy_interrupt(&self, enabled: bool) {
+        match enabled {
+            true => self.set_command(self.command() & !COMMAND_INTERRUPT_DISABLE),
+            false => self.set_command(self.command() | COMMAND_INTERRUPT_DISABLE),
+        }
+    }
+
+    pub fn class_name(&self) -> &'static str {
+        class_name(self.class, self.subclass, self.prog_if)
+    }
+}
+
+/// Returns the name of a class of functions, as listed by the PCI-SIG.
+pub fn class_name(class: u8, subclass: u8, prog_if: u8) -> &'static str {
+    match (class, subclass, prog_if) {
+        (0x00, 0x01, _) => "VGA-compatible device",
+        (0x01, 0x00, _) => "SCSI controller",
+        (0x01, 0x01, _) => "IDE controller",
+        (0x01, 0x05, _) => "ATA controller",
+        (0x01, 0x06, 0x01) => "SATA controller (AHCI)",
+        (0x01, 0x06, _) => "SATA controller",
+        (0x01, 0x08, 0x02) => "NVMe controller",
+        (0x01, 0x08, _) => "non-volatile memory controller",
+        (0x01, _, _) => "mass storage controller",
+        (0x02, 0x00, _) => "Ethernet controller",
+        (0x02, _, _) => "network controller",
+        (0x03, 0x00, _) => "VGA-compatible controller",
+        (0x03, _, _) => "display controller",
+        (0x04, 0x01, _) => "audio device",
+        (0x04, 0x03, _) => "audio controller",
+        (0x04, _, _) => "multimedia controller",
+        (0x05, _, _) => "memory controller",
+        (0x06, 0x00, _) => "host bridge",
+        (0x06, 0x01, _) => "ISA bridge",
+        (0x06, 0x04, _) => "PCI-to-PCI bridge",
+        (0x06, 0x07, _) => "CardBus bridge",
+        (0x06, _, _) => "bridge",
+        (0x07, 0x00, _) => "serial controller",
+        (0x07, _, _) => "communication controller",
+        (0x08, 0x00, _) => "interrupt controller",
+        (0x08, _, _) => "system peripheral",
+        (0x09, _, _) => "input device controller",
+        (0x0C, 0x03, 0x00) => "USB controller (UHCI)",
+        (0x0C, 0x03, 0x10) => "USB controller (OHCI)",
+        (0x0C, 0x03, 0x20) => "USB controller (EHCI)",
+        (0x0C, 0x03, 0x30) => "USB controller (xHCI)",
+        (0x0C, 0x03, _) => "USB controller",
+        (0x0C, 0x05, _) => "SMBus controller",
+        (0x0C, _, _) => "serial bus controller",
+        (0x0D, _, _) => "wireless controller",
+        (0xFF, _, _) => "unassigned class",
+        _ => "unknown device",
+    }
+}
+
+/// Identifies the functions handled by a driver, where the fields left out match any function.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PciMatch {
+    pub vendor_id: Option<u16>,
+    pub device_id: Option<u16>,
+    pub class: Option<u8>,
+    pub subclass: Option<u8>,
+    pub prog_if: Option<u8>,
+}
+
+impl PciMatch {
+    pub const fn device(vendor_id: u16, device_id: u16) -> Self {
+        Self {
+            vendor_id: Some(vendor_id),
+            device_id: Some(device_id),
+            class: None,
+            subclass: None,
+            prog_if: None,
+        }
+    }
+
+    pub const fn vendor(vendor_id: u16) -> Self {
+        Self {
+            vendor_id: Some(vendor_id),
+            device_id: None,
+            class: None,
+            subclass: None,
+            prog_if: None,
+        }
+    }
+
+    pub const fn class(class: u8, subclass: u8) -> Self {
+        Self {
+            vendor_id: None,
+            device_id: None,
+            class: Some(class),
+            subclass: Some(subclass),
+            prog_if: None,
+        }
+    }
+
+    pub const fn with_prog_if(self, prog_if: u8) -> Self {
+        Self {
+            prog_if: Some(prog_if),
+            ..self
+        }
+    }
+
+    pub fn matches(&self, device: &PciDevice) -> bool {
+        self.vendor_id.is_none_or(|id| id == device.vendor_id)
+            && self.device_id.is_none_or(|id| id == device.device_id)
+            && self.class.is_none_or(|class| class == device.class)
+            && self
+                .subclass
+                .is_none_or(|subclass| subclass == device.subclass)
+            && self.prog_if.is_none_or(|prog_if| prog_if == device.prog_if)
+    }
+}
+
+/// A driver of PCI functions, which is bound to each function that it matches and successfully probes.
+#[derive(Debug)]
+pub struct PciDriver {
+    pub name: &'static str,
+    pub matches: &'static [PciMatch],
+    pub probe: fn(&PciDevice) -> Result<(), ()>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegisterError {
+    /// A driver with the same name has already been registered.
+    Duplicate,
+    NoFreeSlot,
+}
+
+#[derive(Clone, Copy)]
+struct Entry {
+    device: PciDevice,
+    driver: Option<&'static PciDriver>,
+}
+
+static DEVICES: RwLock<[Option<Entry>; MAX_DEVICES]> = RwLock::new([None; MAX_DEVICES]);
+static DRIVERS: Mutex<[Option<&'static PciDriver>; MAX_DRIVERS]> = Mutex::new([None; MAX_DRIVERS]);
+
+static ENUMERATED: AtomicBool = AtomicBool::new(false);
+
+/// Returns the functions found during the enumeration, along with the names of their drivers.
+pub fn devices() -> impl Iterator<Item = (PciDevice, Option<&'static str>)> {
+    (0..MAX_DEVICES).map_while(|index| {
+        let entry = DEVICES.read()[index]?;
+        Some((entry.device, entry.driver.map(|driver| driver.name)))
+    })
+}
+
+pub fn find<P>(mut predicate: P) -> Option<PciDevice>
+where
+    P: FnMut(&PciDevice) -> bool,
+{
+    devices()
+        .map(|(device, _)| device)
+        .find(|device| predicate(device))
+}
+
+/// Probes the driver for every function which it matches and which has no driver yet.
+fn bind(driver: &'static PciDriver) {
+    for index in 0..MAX_DEVICES {
+        let device = match DEVICES.read()[index] {
+            Some(entry) if entry.driver.is_none() => entry.device,
+            Some(_) => continue,
+            None => break,
+        };
+        if !driver.matches.iter().any(|id| id.matches(&device)) {
+            continue;
+        }
+
+        // The lock isn't held by the driver, so that it can look up other functions.
+        match (driver.probe)(&device) {
+            Ok(()) => {
+                if let Some(entry) = DEVICES.write()[index].as_mut() {
+                    entry.driver = Some(driver);
+                }
+                log::info!("PCI {}: bound to {}", device.addr, driver.name);
+            }
+            Err(()) => log::warn!("PCI {}: {} failed to probe", device.addr, driver.name),
+        }
+    }
+}
+
+/// Registers a driver, which is bound to the matching functions once they've been enumerated.
+pub fn register_driver(driver: &'static PciDriver) -> Result<(), RegisterError> {
+    {
+        let mut drivers = DRIVERS.lock();
+        if drivers
+            .iter()
+            .flatten()
+            .any(|registered| registered.name == driver.name)
+        {
+            return Err(RegisterError::Duplicate);
+        }
+        let slot = drivers
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(RegisterError::NoFreeSlot)?;
+        *slot = Some(driver);
+    }
+
+    if ENUMERATED.load(Ordering::Acquire) {
+        bind(driver);
+    }
+
+    Ok(())
+}
+
+/// Enumerates the functions of a segment recursively, following the bridges from the root buses.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/PCI#Recursive_Scan
+struct Scanner {
+    segment: u16,
+    visited: [u64; 4],
+    count: usize,
+}
+
+impl Scanner {
+    fn new(segment: u16) -> Self {
+        Self {
+            segment,
+            visited: [0; 4],
+            count: 0,
+        }
+    }
+
+    fn scan_bus(&mut self, bus: u8) {
+        let (word, bit) = (bus as usize / 64, 1 << (bus % 64));
+        if self.visited[word] & bit != 0 {
+            return;
+        }
+        self.visited[word] |= bit;
+
+        for device in 0..DEVICES_PER_BUS {
+            self.scan_device(bus, device);
+        }
+    }
+
+    fn scan_device(&mut self, bus: u8, device: u8) {
+        let Some(function) = PciDevice::probe(PciAddress::new(self.segment, bus, device, 0)) else {
+            return;
+        };
+        let multifunction = function.is_multifunction();
+        self.add(function);
+
+        if multifunction {
+            for function in 1..FUNCTIONS_PER_DEVICE {
+                if let Some(function) =
+                    PciDevice::probe(PciAddress::new(self.segment, bus, device, function))
+                {
+                    self.add(function);
+                }
+            }
+        }
+    }
+
+    fn add(&mut self, device: PciDevice) {
+        {
+            let mut devices = DEVICES.write();
+            match devices.iter_mut().find(|slot| slot.is_none()) {
+                Some(slot) => {
+                    *slot = Some(Entry {
+                        device,
+                        driver: None,
+                    })
+                }
+                None => {
+                    log::warn!("PCI {}: too many devices, ignored", device.addr);
+                    return;
+                }
+            }
+        }
+        self.count += 1;
+
+        if let HeaderKind::PciBridge { secondary_bus, .. } = device.header {
+            if secondary_bus > device.addr.bus {
+                self.scan_bus(secondary_bus);
+            }
+        }
+    }
+
+    /// Scans the root buses, of which there are several if the host bridge is a multifunction device.
+    fn scan(&mut self, start_bus: u8) {
+        let host = PciAddress::new(self.segment, start_bus, 0, 0);
+        match PciDevice::probe(host) {
+            Some(bridge) if bridge.is_multifunction() => {
+                for function in 0..FUNCTIONS_PER_DEVICE {
+                    let addr = PciAddress::new(self.segment, start_bus, 0, function);
+                    if addr.read_u16(REG_VENDOR_ID) != INVALID_VENDOR_ID {
+                        self.scan_bus(start_bus.saturating_add(function));
+                    }
+                }
+            }
+            _ => self.scan_bus(start_bus),
+        }
+    }
+}
+
+fn log_device(device: &PciDevice) {
+    log::info!(
+        "PCI {} [{:04x}:{:04x}] {} ({:02x}.{:02x}.{:02x}, rev {:02x})",
+        device.addr,
+        device.vendor_id,
+        device.device_id,
+        device.class_name(),
+        device.class,
+        device.subclass,
+        device.prog_if,
+        device.revision
+    );
+
+    for (index, bar) in device.bars.iter().enumerate() {
+        match bar {
+            Some(Bar::Memory {
+                addr,
+                size,
+                prefetchable,
+                is_64_bit,
+            }) => log::debug!(
+                "    BAR{}: memory at {:#X}, {} KiB{}{}",
+                index,
+                addr,
+                size.div_ceil(1024),
+                if *is_64_bit { ", 64-bit" } else { "" },
+                if *prefetchable { ", prefetchable" } else { "" }
+            ),
+            Some(Bar::Io { port, size }) => {
+                log::debug!("    BAR{}: I/O ports at {:#X}, {} bytes", index, port, size)
+            }
+            None => {}
+        }
+    }
+
+    for capability in device.capabilities() {
+        match capability {
+            Capability::Msi(msi) => log::debug!("    MSI: {} vectors", msi.max_vectors),
+            Capability::MsiX(msi_x) => log::debug!(
+                "    MSI-X: {} vectors, table in BAR{}",
+                msi_x.table_size,
+                msi_x.table_bar
+            ),
+            Capability::PciExpress(pcie) => log::debug!(
+                "    PCIe: version {}, port type {}, link gen {} x{}",
+                pcie.version,
+                pcie.port_type,
+                pcie.link_speed,
+                pcie.link_width
+            ),
+            _ => {}
+        }
+    }
+}
+
+/// Peripheral Component Interconnect (PCI)
+///
+/// Enumerates the functions of every segment which the configuration space can be accessed for, through either
+/// ECAM or the legacy mechanism, and binds the registered drivers to them.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/PCI
+pub(crate) fn init() -> Result<(), ()> {
+    let mut count = 0;
+    let mut scanned_segment_0 = false;
+    for region in config::ecam_regions() {
+        let mut scanner = Scanner::new(region.segment);
+        scanner.scan(region.start_bus);
+        count += scanner.count;
+        scanned_segment_0 |= region.segment == 0;
+    }
+    if !scanned_segment_0 && config::has_legacy_access() {
+        let mut scanner = Scanner::new(0);
+        scanner.scan(0);
+        count += scanner.count;
+    }
+
+    for (device, _) in devices() {
+        log_device(&device);
+    }
+    log::info!("PCI: {} functions found", count);
+
+    ENUMERATED.store(true, Ordering::Release);
+
+    let drivers = *DRIVERS.lock();
+    for driver in drivers.into_iter().flatten() {
+        bind(driver);
+    }
+
+    Ok(())
+}
@@ -29,6 +29,17 @@ pub fn _print(args: Arguments) {
     arch::serial::_print(args);
 }
 
+/// Reads the data received through the serial console into the buffer without blocking, and returns the number of
+/// bytes read.
+pub fn read(buf: &mut [u8]) -> usize {
+    arch::serial::read(buf)
+}
+
+/// Returns the next byte received through the serial console, if any, without blocking.
+pub fn read_byte() -> Option<u8> {
+    arch::serial::read_byte()
+}
+
 #[macro_export]
 macro_rules! serial_print {
     ($($arg:tt)*) => ($crate::kernel::serial::_print(format_args!($($arg)*)));
@@ -0,0 +1,184 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The number of locks held by the running thread, which isn't preempted unless it's zero.
+///
+/// The count belongs to the running thread, hence it's saved and restored along with the rest of its context when
+/// switching threads. The kernel only runs on the bootstrap processor, which has a single running thread.
+static PREEMPT_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns whether the running thread may be preempted, which it may not while it holds a lock.
+pub fn is_preemptible() -> bool {
+    PREEMPT_COUNT.load(Ordering::Relaxed) == 0
+}
+
+/// Returns the preemption count of the running thread, which is saved when switching away from it.
+pub(crate) fn preempt_count() -> usize {
+    PREEMPT_COUNT.load(Ordering::Relaxed)
+}
+
+/// Restores the preemption count of the thread which is being switched to.
+pub(crate) fn set_preempt_count(count: usize) {
+    PREEMPT_COUNT.store(count, Ordering::Relaxed);
+}
+
+/// Keeps the running thread from being preempted until the guard is dropped.
+pub fn disable_preemption() -> PreemptGuard {
+    PREEMPT_COUNT.fetch_add(1, Ordering::Acquire);
+    PreemptGuard(())
+}
+
+pub struct PreemptGuard(());
+
+impl Drop for PreemptGuard {
+    fn drop(&mut self) {
+        PREEMPT_COUNT.fetch_sub(1, Ordering::Release);
+    }
+}
+
+/// A spin lock which keeps the thread holding it from being preempted.
+///
+/// Were the thread preempted, the threads of a higher priority spinning on the lock would never let it run again to
+/// release the lock. A thread preempted while it holds none is rescheduled on the next interrupt instead.
+pub struct Mutex<T: ?Sized> {
+    inner: spin::Mutex<T>,
+}
+
+/// The lock is released before preemption is enabled again, as the fields are dropped in order.
+pub struct MutexGuard<'a, T: ?Sized + 'a> {
+    guard: spin::MutexGuard<'a, T>,
+    _preempt: PreemptGuard,
+}
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            inner: spin::Mutex::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        let preempt = disable_preemption();
+        MutexGuard {
+            guard: self.inner.lock(),
+            _preempt: preempt,
+        }
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        let preempt = disable_preemption();
+        self.inner.try_lock().map(|guard| MutexGuard {
+            guard,
+            _preempt: preempt,
+        })
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut()
+    }
+}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+/// A readers-writer spin lock which keeps the threads holding it from being preempted, as does `Mutex`.
+pub struct RwLock<T: ?Sized> {
+    inner: spin::RwLock<T>,
+}
+
+pub struct RwLockReadGuard<'a, T: ?Sized + 'a> {
+    guard: spin::RwLockReadGuard<'a, T>,
+    _preempt: PreemptGuard,
+}
+
+pub struct RwLockWriteGuard<'a, T: ?Sized + 'a> {
+    guard: spin::RwLockWriteGuard<'a, T>,
+    _preempt: PreemptGuard,
+}
+
+impl<T> RwLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            inner: spin::RwLock::new(value),
+        }
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        let preempt = disable_preemption();
+        RwLockReadGuard {
+            guard: self.inner.read(),
+            _preempt: preempt,
+        }
+    }
+
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        let preempt = disable_preemption();
+        RwLockWriteGuard {
+            guard: self.inner.write(),
+            _preempt: preempt,
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
@@ -0,0 +1,377 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::fmt;
+
+use crate::kernel::arch;
+use crate::kernel::arch::context;
+use crate::kernel::arch::context::Context;
+use crate::kernel::arch::stack::Stack;
+use crate::kernel::sync;
+use crate::kernel::sync::Mutex;
+use crate::kernel::timer;
+
+use self::sched::{Scheduler, Thread};
+
+mod sched;
+
+pub use self::sched::{SchedulerStats, TIME_SLICE};
+
+pub const MAX_THREADS: usize = 64;
+
+pub const PRIORITY_COUNT: usize = Priority::ALL.len();
+
+/// The function run by a thread, whose return value becomes the exit code of the thread.
+pub type ThreadEntry = fn(usize) -> usize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ThreadId(u64);
+
+impl ThreadId {
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for ThreadId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
+impl Priority {
+    pub const ALL: [Priority; 3] = [Priority::Low, Priority::Normal, Priority::High];
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+        };
+        f.pad(name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThreadState {
+    /// The thread is waiting for its turn on the processor.
+    Ready,
+    Running,
+    /// The thread is waiting for a deadline to pass.
+    Sleeping,
+    /// The thread is waiting for another thread to exit.
+    Blocked,
+    /// The thread has finished, but its exit code hasn't been collected by `join` yet.
+    Exited,
+}
+
+impl fmt::Display for ThreadState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ThreadState::Ready => "ready",
+            ThreadState::Running => "running",
+            ThreadState::Sleeping => "sleeping",
+            ThreadState::Blocked => "blocked",
+            ThreadState::Exited => "exited",
+        };
+        f.pad(name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThreadError {
+    /// Every slot of the thread table is in use.
+    TooManyThreads,
+    /// The memory for the stack of the thread couldn't be allocated.
+    OutOfMemory,
+    /// The thread does not exist, it has been joined already, or another thread is waiting to join it.
+    InvalidThread,
+    /// A thread can't wait for itself to exit.
+    Deadlock,
+}
+
+/// A snapshot of the state of a thread, e.g., for listing the threads.
+#[derive(Clone, Copy, Debug)]
+pub struct ThreadInfo {
+    pub id: ThreadId,
+    pub name: &'static str,
+    pub priority: Priority,
+    pub state: ThreadState,
+    /// The number of ticks during which the thread has been running.
+    pub run_time: u64,
+    /// The number of times the thread has been switched to.
+    pub switches: u64,
+}
+
+static SCHEDULER: Mutex<Scheduler> = Mutex::new(Scheduler::new());
+
+/// Switches between the contexts picked by the scheduler, if any, returning once the running thread is resumed.
+///
+/// The interrupts must be disabled by the caller, and the scheduler must have been unlocked, as the next thread is
+/// going to lock it as well. The contexts stay in place meanwhile, as the slots of the threads involved are only
+/// released by `join` once they have exited.
+fn switch(contexts: Option<(*mut Context, *const Context)>) {
+    if let Some((from, to)) = contexts {
+        unsafe { context::switch(from, to) };
+    }
+}
+
+/// Every thread begins here, on its own stack and with the interrupts disabled by the thread which switched to it.
+extern "C" fn trampoline(slot: usize) -> ! {
+    let (entry, arg) = SCHEDULER.lock().threads[slot]
+        .as_ref()
+        .and_then(|thread| thread.start)
+        .expect("a new thread has no entry point");
+
+    arch::enable_interrupts();
+
+    exit(entry(arg));
+}
+
+/// The idle thread halts the processor until there's something to do, which is when an interrupt handler has made
+/// another thread ready.
+fn idle(_: usize) -> usize {
+    loop {
+        arch::wait_for_interrupt();
+    }
+}
+
+fn create(
+    name: &'static str,
+    priority: Priority,
+    entry: ThreadEntry,
+    arg: usize,
+) -> Result<(usize, ThreadId), ThreadError> {
+    let stack = Stack::allocate().ok_or(ThreadError::OutOfMemory)?;
+
+    arch::without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+
+        // The slot is only known once the thread has been placed, hence the context is filled in afterwards.
+        let thread = Thread::new(name, priority, Context::default(), Some((entry, arg)));
+        let (slot, id) = scheduler
+            .insert(thread)
+            .ok_or(ThreadError::TooManyThreads)?;
+
+        let thread = scheduler.thread_mut(slot);
+        thread.context = Context::new(&stack, trampoline, slot);
+        thread.set_stack(stack);
+
+        Ok((slot, id))
+    })
+}
+
+/// Creates a thread of normal priority running `entry(arg)` on a stack of its own.
+pub fn spawn(name: &'static str, entry: ThreadEntry, arg: usize) -> Result<ThreadId, ThreadError> {
+    spawn_with_priority(name, Priority::Normal, entry, arg)
+}
+
+/// Creates a thread running `entry(arg)` on a stack of its own, which runs right away if it outranks the running
+/// thread.
+pub fn spawn_with_priority(
+    name: &'static str,
+    priority: Priority,
+    entry: ThreadEntry,
+    arg: usize,
+) -> Result<ThreadId, ThreadError> {
+    let (slot, id) = create(name, priority, entry, arg)?;
+
+    arch::without_interrupts(|| SCHEDULER.lock().make_ready(slot));
+    preempt();
+
+    Ok(id)
+}
+
+/// Accounts a tick of the timer to the running thread, waking up the sleeping threads which are due.
+///
+/// Called by the timer interrupt, whereas the switch to another thread is left to `preempt`.
+pub fn tick(now: u64) {
+    SCHEDULER.lock().tick(now);
+}
+
+/// Checks that the running thread holds no lock before it gives up the processor, as the threads spinning on the lock
+/// can't be preempted, and would never let it run again.
+fn check_may_block() {
+    assert!(
+        sync::is_preemptible(),
+        "thread gives up the processor while holding a lock"
+    );
+}
+
+/// Switches to another thread if the running one ought to be preempted, e.g., as its time slice has run out or a
+/// thread of a higher priority has become ready.
+///
+/// Called by the interrupt handlers once the interrupt has been acknowledged. A thread holding a lock is left running,
+/// until an interrupt finds it holding none.
+pub fn preempt() {
+    if !sync::is_preemptible() {
+        return;
+    }
+
+    arch::without_interrupts(|| {
+        let contexts = {
+            let mut scheduler = SCHEDULER.lock();
+            match scheduler.take_need_resched() {
+                true => scheduler.reschedule(true),
+                false => None,
+            }
+        };
+        switch(contexts);
+    });
+}
+
+/// Gives up the processor to the next thread ready to run at the priority of the running thread or above, if any.
+pub fn yield_now() {
+    check_may_block();
+    arch::without_interrupts(|| {
+        let contexts = SCHEDULER.lock().reschedule(false);
+        switch(contexts);
+    });
+}
+
+/// Suspends the running thread until the timer has reached the given tick.
+pub fn sleep_until(deadline: u64) {
+    check_may_block();
+    while timer::ticks() < deadline {
+        arch::without_interrupts(|| {
+            let contexts = {
+                let mut scheduler = SCHEDULER.lock();
+                let thread = scheduler.current_mut();
+                thread.state = ThreadState::Sleeping;
+                thread.deadline = deadline;
+                scheduler.reschedule(false)
+            };
+            switch(contexts);
+        });
+    }
+}
+
+/// Suspends the running thread for at least the given number of ticks.
+pub fn sleep(ticks: u64) {
+    sleep_until(timer::ticks() + ticks);
+}
+
+/// Finishes the running thread, leaving the exit code to be collected by `join`.
+pub fn exit(code: usize) -> ! {
+    arch::without_interrupts(|| {
+        let contexts = {
+            let mut scheduler = SCHEDULER.lock();
+            let thread = scheduler.current_mut();
+            thread.state = ThreadState::Exited;
+            thread.exit_code = code;
+
+            if let Some(joiner) = thread.joiner {
+                scheduler.make_ready(joiner);
+            }
+            scheduler.reschedule(false)
+        };
+        switch(contexts);
+    });
+
+    // There's no other thread left to run.
+    arch::hlt_loop();
+}
+
+/// Waits for a thread to exit and returns its exit code, releasing its slot and its stack.
+pub fn join(id: ThreadId) -> Result<usize, ThreadError> {
+    check_may_block();
+    loop {
+        let thread = arch::without_interrupts(|| {
+            let mut scheduler = SCHEDULER.lock();
+
+            let slot = scheduler.find(id).ok_or(ThreadError::InvalidThread)?;
+            let current = scheduler.current;
+            if slot == current {
+                return Err(ThreadError::Deadlock);
+            }
+
+            let thread = scheduler.thread_mut(slot);
+            if thread.state == ThreadState::Exited {
+                return Ok(scheduler.threads[slot].take());
+            }
+            if thread.joiner.is_some_and(|joiner| joiner != current) {
+                return Err(ThreadError::InvalidThread);
+            }
+            thread.joiner = Some(current);
+
+            scheduler.current_mut().state = ThreadState::Blocked;
+            let contexts = scheduler.reschedule(false);
+            drop(scheduler);
+            switch(contexts);
+
+            Ok(None)
+        })?;
+
+        // The stack is released outside of the scheduler, as unmapping it involves the frame allocator.
+        if let Some(thread) = thread {
+            return Ok(thread.exit_code);
+        }
+    }
+}
+
+/// Returns the ID of the running thread.
+pub fn current() -> ThreadId {
+    arch::without_interrupts(|| SCHEDULER.lock().current_mut().id)
+}
+
+/// Returns a snapshot of every thread, including the ones which have exited but haven't been joined yet.
+pub fn threads() -> impl Iterator<Item = ThreadInfo> {
+    arch::without_interrupts(|| SCHEDULER.lock().infos())
+        .into_iter()
+        .flatten()
+}
+
+pub fn stats() -> SchedulerStats {
+    arch::without_interrupts(|| SCHEDULER.lock().stats())
+}
+
+/// Adopts the thread of control which has booted the kernel as the first thread, and creates the idle thread.
+pub(crate) fn init() -> Result<(), ()> {
+    arch::without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        if scheduler.threads.iter().any(Option::is_some) {
+            return Err(());
+        }
+
+        let mut thread = Thread::new("main", Priority::Normal, Context::boot(), None);
+        thread.state = ThreadState::Running;
+        let (slot, _) = scheduler.insert(thread).ok_or(())?;
+        scheduler.current = slot;
+
+        Ok(())
+    })?;
+
+    // The idle thread is never queued, it's picked whenever the run queues are empty.
+    let (slot, _) = create("idle", Priority::Low, idle, 0).map_err(|_| ())?;
+    arch::without_interrupts(|| SCHEDULER.lock().set_idle(slot));
+
+    Ok(())
+}
@@ -0,0 +1,353 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::kernel::arch::context::Context;
+use crate::kernel::arch::stack::Stack;
+
+use super::{
+    Priority, ThreadEntry, ThreadId, ThreadInfo, ThreadState, MAX_THREADS, PRIORITY_COUNT,
+};
+
+/// The number of timer ticks a thread may run before giving way to the other ready threads of its priority.
+pub const TIME_SLICE: u64 = 10;
+
+pub(super) struct Thread {
+    pub(super) id: ThreadId,
+    pub(super) name: &'static str,
+    pub(super) priority: Priority,
+    pub(super) state: ThreadState,
+    pub(super) context: Context,
+    // The stack is released along with the slot. The thread running on the boot stack has no stack of its own.
+    stack: Option<Stack>,
+    // The entry point and its argument, which the thread running on the boot stack lacks as well.
+    pub(super) start: Option<(ThreadEntry, usize)>,
+    pub(super) exit_code: usize,
+    // The slot of the thread waiting in `join` for this one to exit.
+    pub(super) joiner: Option<usize>,
+    // The tick at which a sleeping thread is due to wake up.
+    pub(super) deadline: u64,
+    // The slot of the thread following this one in its run queue.
+    next: Option<usize>,
+    // The ticks left of the time slice of the running thread.
+    slice: u64,
+    run_time: u64,
+    switches: u64,
+}
+
+impl Thread {
+    pub(super) fn new(
+        name: &'static str,
+        priority: Priority,
+        context: Context,
+        start: Option<(ThreadEntry, usize)>,
+    ) -> Self {
+        Self {
+            id: ThreadId(0),
+            name,
+            priority,
+            state: ThreadState::Ready,
+            context,
+            stack: None,
+            start,
+            exit_code: 0,
+            joiner: None,
+            deadline: 0,
+            next: None,
+            slice: TIME_SLICE,
+            run_time: 0,
+            switches: 0,
+        }
+    }
+
+    pub(super) fn set_stack(&mut self, stack: Stack) {
+        self.stack = Some(stack);
+    }
+
+    fn info(&self) -> ThreadInfo {
+        ThreadInfo {
+            id: self.id,
+            name: self.name,
+            priority: self.priority,
+            state: self.state,
+            run_time: self.run_time,
+            switches: self.switches,
+        }
+    }
+}
+
+/// The counters kept by the scheduler since boot.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SchedulerStats {
+    /// The number of switches from one thread to another.
+    pub switches: u64,
+    /// The number of switches forced upon a running thread, as opposed to the ones made as it yields, sleeps, waits
+    /// or exits.
+    pub preemptions: u64,
+    /// The number of ticks spent in the idle thread.
+    pub idle_time: u64,
+    /// The number of threads waiting in the run queue of each priority.
+    pub ready: [usize; PRIORITY_COUNT],
+}
+
+/// A queue of ready threads, linked through their slots.
+#[derive(Clone, Copy, Debug, Default)]
+struct RunQueue {
+    head: Option<usize>,
+    tail: Option<usize>,
+    len: usize,
+}
+
+/// Scheduler
+///
+/// Keeps the table of threads and a run queue for each priority. The running thread is preempted as soon as a thread
+/// of a higher priority becomes ready, and gives way to the ready threads of its own priority once its time slice has
+/// run out. The lower priorities only run while the higher ones have nothing to do, and the idle thread runs while no
+/// thread is ready at all.
+///
+/// The scheduler is only accessed with the interrupts disabled, so that it isn't interleaved with the timer.
+pub(super) struct Scheduler {
+    pub(super) threads: [Option<Thread>; MAX_THREADS],
+    // The slot of the running thread.
+    pub(super) current: usize,
+    idle: Option<usize>,
+    queues: [RunQueue; PRIORITY_COUNT],
+    next_id: u64,
+    // Set when the running thread ought to be preempted at the next opportunity.
+    need_resched: bool,
+    stats: SchedulerStats,
+}
+
+impl Scheduler {
+    pub(super) const fn new() -> Self {
+        Self {
+            threads: [const { None }; MAX_THREADS],
+            current: 0,
+            idle: None,
+            queues: [RunQueue {
+                head: None,
+                tail: None,
+                len: 0,
+            }; PRIORITY_COUNT],
+            next_id: 0,
+            need_resched: false,
+            stats: SchedulerStats {
+                switches: 0,
+                preemptions: 0,
+                idle_time: 0,
+                ready: [0; PRIORITY_COUNT],
+            },
+        }
+    }
+
+    pub(super) fn thread_mut(&mut self, slot: usize) -> &mut Thread {
+        self.threads[slot]
+            .as_mut()
+            .expect("the slot of a scheduled thread is empty")
+    }
+
+    pub(super) fn current_mut(&mut self) -> &mut Thread {
+        self.thread_mut(self.current)
+    }
+
+    pub(super) fn find(&self, id: ThreadId) -> Option<usize> {
+        self.threads
+            .iter()
+            .position(|thread| thread.as_ref().is_some_and(|thread| thread.id == id))
+    }
+
+    /// Places a new thread into a free slot, assigning it an ID. The thread isn't ready to run until `make_ready`.
+    pub(super) fn insert(&mut self, mut thread: Thread) -> Option<(usize, ThreadId)> {
+        let slot = self.threads.iter().position(Option::is_none)?;
+
+        let id = ThreadId(self.next_id);
+        self.next_id += 1;
+
+        thread.id = id;
+        self.threads[slot] = Some(thread);
+
+        Some((slot, id))
+    }
+
+    /// Sets up the thread which runs while no other thread is ready.
+    pub(super) fn set_idle(&mut self, slot: usize) {
+        self.idle = Some(slot);
+    }
+
+    fn enqueue(&mut self, slot: usize) {
+        let priority = self.thread_mut(slot).priority as usize;
+        self.thread_mut(slot).next = None;
+
+        let queue = self.queues[priority];
+        match queue.tail {
+            Some(tail) => self.thread_mut(tail).next = Some(slot),
+            None => self.queues[priority].head = Some(slot),
+        }
+        self.queues[priority].tail = Some(slot);
+        self.queues[priority].len += 1;
+    }
+
+    /// Takes the first thread out of the run queue of the highest priority which isn't empty.
+    fn dequeue(&mut self) -> Option<usize> {
+        let priority = self.highest_ready()? as usize;
+
+        let slot = self.queues[priority].head?;
+        let next = self.thread_mut(slot).next.take();
+
+        let queue = &mut self.queues[priority];
+        queue.head = next;
+        if next.is_none() {
+            queue.tail = None;
+        }
+        queue.len -= 1;
+
+        Some(slot)
+    }
+
+    fn highest_ready(&self) -> Option<Priority> {
+        Priority::ALL
+            .into_iter()
+            .rev()
+            .find(|&priority| self.queues[priority as usize].len != 0)
+    }
+
+    /// Makes a thread ready to run, asking for the running thread to be preempted if it's outranked.
+    pub(super) fn make_ready(&mut self, slot: usize) {
+        let thread = self.thread_mut(slot);
+        thread.state = ThreadState::Ready;
+        let priority = thread.priority;
+        self.enqueue(slot);
+
+        let is_idle = self.idle == Some(self.current);
+        if let Some(current) = &self.threads[self.current] {
+            if is_idle || priority > current.priority {
+                self.need_resched = true;
+            }
+        }
+    }
+
+    /// Returns whether the running thread ought to be preempted, clearing the request.
+    pub(super) fn take_need_resched(&mut self) -> bool {
+        core::mem::take(&mut self.need_resched)
+    }
+
+    /// Accounts a tick of the timer to the running thread and wakes up the sleeping threads which are due.
+    pub(super) fn tick(&mut self, now: u64) {
+        let is_idle = self.idle == Some(self.current);
+        let Some(current) = self.threads[self.current].as_mut() else {
+            return;
+        };
+
+        current.run_time += 1;
+        if is_idle {
+            self.stats.idle_time += 1;
+        } else {
+            current.slice = current.slice.saturating_sub(1);
+            if current.slice == 0 {
+                self.need_resched = true;
+            }
+        }
+
+        for slot in 0..MAX_THREADS {
+            let due = self.threads[slot].as_ref().is_some_and(|thread| {
+                thread.state == ThreadState::Sleeping && thread.deadline <= now
+            });
+            if due {
+                self.make_ready(slot);
+            }
+        }
+    }
+
+    /// Picks the thread to run next and makes it the running one. A running thread carries on unless a thread of at
+    /// least its priority is ready, whereas one which has stopped running, e.g., as it sleeps, is always replaced.
+    ///
+    /// Returns the contexts to switch between, or `None` if the running thread carries on.
+    pub(super) fn reschedule(&mut self, preempted: bool) -> Option<(*mut Context, *const Context)> {
+        self.need_resched = false;
+
+        let current = self.current;
+        let is_idle = self.idle == Some(current);
+        let thread = self.thread_mut(current);
+        let (state, priority) = (thread.state, thread.priority);
+
+        if state == ThreadState::Running && !is_idle {
+            match self.highest_ready() {
+                Some(ready) if ready >= priority => {
+                    self.thread_mut(current).state = ThreadState::Ready;
+                    self.enqueue(current);
+                }
+                _ => {
+                    self.thread_mut(current).slice = TIME_SLICE;
+                    return None;
+                }
+            }
+        }
+
+        let next = match self.dequeue().or(self.idle) {
+            Some(next) if next != current => next,
+            // The thread has been woken up before it could be switched away from, or there's no other thread left.
+            _ => {
+                let thread = self.thread_mut(current);
+                if thread.state != ThreadState::Exited {
+                    thread.state = ThreadState::Running;
+                }
+                return None;
+            }
+        };
+
+        if preempted && state == ThreadState::Running && !is_idle {
+            self.stats.preemptions += 1;
+        }
+        self.stats.switches += 1;
+
+        let previous = self.thread_mut(current);
+        if previous.state == ThreadState::Running {
+            previous.state = ThreadState::Ready;
+        }
+        let from: *mut Context = &mut previous.context;
+
+        self.current = next;
+        let next = self.thread_mut(next);
+        next.state = ThreadState::Running;
+        next.slice = TIME_SLICE;
+        next.switches += 1;
+
+        Some((from, &next.context))
+    }
+
+    pub(super) fn infos(&self) -> [Option<ThreadInfo>; MAX_THREADS] {
+        let mut infos = [None; MAX_THREADS];
+        for (info, thread) in infos.iter_mut().zip(self.threads.iter()) {
+            *info = thread.as_ref().map(Thread::info);
+        }
+
+        infos
+    }
+
+    pub(super) fn stats(&self) -> SchedulerStats {
+        let mut stats = self.stats;
+        for (ready, queue) in stats.ready.iter_mut().zip(self.queues.iter()) {
+            *ready = queue.len;
+        }
+
+        stats
+    }
+}
@@ -0,0 +1,451 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::fmt;
+use core::fmt::{Arguments, Write};
+
+use crate::aux::log;
+use crate::kernel::arch;
+use crate::kernel::sync::Mutex;
+
+use super::font::Font;
+use super::framebuffer;
+use super::framebuffer::{Color, Framebuffer, FramebufferKind};
+use super::graphics;
+use super::{to_cp437, CP437_REPLACEMENT};
+
+const TAB_WIDTH: usize = 8;
+
+/// The maximum number of parameters of a control sequence, beyond which they are ignored.
+const MAX_PARAMS: usize = 8;
+
+/// The ANSI colors in the order of their SGR codes, followed by their bright variants.
+const PALETTE: [Color; 16] = [
+    Color::new(0x00, 0x00, 0x00),
+    Color::new(0xAA, 0x00, 0x00),
+    Color::new(0x00, 0xAA, 0x00),
+    Color::new(0xAA, 0x55, 0x00),
+    Color::new(0x00, 0x00, 0xAA),
+    Color::new(0xAA, 0x00, 0xAA),
+    Color::new(0x00, 0xAA, 0xAA),
+    Color::new(0xAA, 0xAA, 0xAA),
+    Color::new(0x55, 0x55, 0x55),
+    Color::new(0xFF, 0x55, 0x55),
+    Color::new(0x55, 0xFF, 0x55),
+    Color::new(0xFF, 0xFF, 0x55),
+    Color::new(0x55, 0x55, 0xFF),
+    Color::new(0xFF, 0x55, 0xFF),
+    Color::new(0x55, 0xFF, 0xFF),
+    Color::new(0xFF, 0xFF, 0xFF),
+];
+
+/// The text mode color indices of the ANSI colors, which are ordered differently.
+const TEXT_MODE_COLORS: [u8; 16] = [0, 4, 2, 6, 1, 5, 3, 7, 8, 12, 10, 14, 9, 13, 11, 15];
+
+const DEFAULT_FOREGROUND: usize = 7;
+const DEFAULT_BACKGROUND: usize = 0;
+
+/// The number of pixel lines of the cursor, which is drawn as an underline.
+const CURSOR_HEIGHT: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    /// Within a Control Sequence Introducer (CSI), i.e., `ESC [`.
+    Csi,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Attributes {
+    foreground: usize,
+    background: usize,
+    bold: bool,
+    inverse: bool,
+}
+
+impl Default for Attributes {
+    fn default() -> Self {
+        Self {
+            foreground: DEFAULT_FOREGROUND,
+            background: DEFAULT_BACKGROUND,
+            bold: false,
+            inverse: false,
+        }
+    }
+}
+
+impl Attributes {
+    /// Returns the palette indices of the foreground and the background colors.
+    fn colors(&self) -> (usize, usize) {
+        let foreground = match self.bold && self.foreground < 8 {
+            true => self.foreground + 8,
+            false => self.foreground,
+        };
+
+        match self.inverse {
+            true => (self.background, foreground),
+            false => (foreground, self.background),
+        }
+    }
+}
+
+/// Text Console
+///
+/// A terminal on top of a framebuffer, which renders text either through the glyphs of a font or, in text mode,
+/// through the character cells of the display. It understands the control characters and a subset of the ANSI escape
+/// sequences, i.e., cursor movement, erasure, and the colors and attributes of SGR.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/Terminals
+pub struct Console {
+    framebuffer: &'static Framebuffer,
+    font: &'static Font,
+    columns: usize,
+    rows: usize,
+    column: usize,
+    row: usize,
+    saved_position: (usize, usize),
+    attributes: Attributes,
+    cursor_visible: bool,
+    cursor_drawn: bool,
+    state: State,
+    params: [u16; MAX_PARAMS],
+    param_count: usize,
+    private: bool,
+}
+
+impl Console {
+    pub fn new(framebuffer: &'static Framebuffer, font: &'static Font) -> Self {
+        let (columns, rows) = match framebuffer.kind {
+            FramebufferKind::Rgb(_) => (
+                framebuffer.width / font.width(),
+                framebuffer.height / font.height(),
+            ),
+            FramebufferKind::Text => (framebuffer.width, framebuffer.height),
+        };
+
+        let console = Self {
+            framebuffer,
+            font,
+            columns,
+            rows,
+            column: 0,
+            row: 0,
+            saved_position: (0, 0),
+            attributes: Attributes::default(),
+            cursor_visible: true,
+            cursor_drawn: false,
+            state: State::Ground,
+            params: [0; MAX_PARAMS],
+            param_count: 0,
+            private: false,
+        };
+        console.erase(0, 0, columns, rows);
+
+        console
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn write_char(&mut self, c: char) {
+        match self.state {
+            State::Ground => self.control(c),
+            State::Escape => self.escape(c),
+            State::Csi => self.csi(c),
+        }
+    }
+
+    fn control(&mut self, c: char) {
+        match c {
+            '\x1b' => self.state = State::Escape,
+            '\n' => self.new_line(),
+            '\r' => self.column = 0,
+            '\t' => self.column = ((self.column / TAB_WIDTH + 1) * TAB_WIDTH).min(self.columns - 1),
+            '\x08' => self.column = self.column.saturating_sub(1),
+            _ if c.is_control() => {}
+            _ => self.put(c),
+        }
+    }
+
+    fn escape(&mut self, c: char) {
+        self.state = State::Ground;
+        match c {
+            '[' => {
+                self.params = [0; MAX_PARAMS];
+                self.param_count = 0;
+                self.private = false;
+                self.state = State::Csi;
+            }
+            '7' => self.saved_position = (self.column, self.row),
+            '8' => (self.column, self.row) = self.saved_position,
+            'c' => {
+                self.attributes = Attributes::default();
+                self.cursor_visible = true;
+                self.erase(0, 0, self.columns, self.rows);
+                (self.column, self.row) = (0, 0);
+            }
+            _ => {}
+        }
+    }
+
+    fn csi(&mut self, c: char) {
+        match c {
+            '0'..='9' => {
+                let digit = c as u16 - '0' as u16;
+                self.param_count = self.param_count.max(1);
+                if let Some(param) = self.params.get_mut(self.param_count - 1) {
+                    *param = param.saturating_mul(10).saturating_add(digit);
+                }
+            }
+            ';' => self.param_count = self.param_count.max(1) + 1,
+            '?' => self.private = true,
+            '\x40'..='\x7E' => {
+                self.state = State::Ground;
+                self.execute(c);
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns a parameter of the current control sequence, with zero or its absence meaning the default value.
+    fn param(&self, index: usize, default: usize) -> usize {
+        match self.params.get(index) {
+            Some(param) if index < self.param_count && *param != 0 => *param as usize,
+            _ => default,
+        }
+    }
+
+    fn execute(&mut self, command: char) {
+        let n = self.param(0, 1);
+        match command {
+            'A' => self.row = self.row.saturating_sub(n),
+            'B' => self.row = (self.row + n).min(self.rows - 1),
+            'C' => self.column = (self.column + n).min(self.columns - 1),
+            'D' => self.column = self.column.saturating_sub(n),
+            'E' => (self.column, self.row) = (0, (self.row + n).min(self.rows - 1)),
+            'F' => (self.column, self.row) = (0, self.row.saturating_sub(n)),
+            'G' => self.column = (n - 1).min(self.columns - 1),
+            'H' | 'f' => {
+                self.row = (n - 1).min(self.rows - 1);
+                self.column = (self.param(1, 1) - 1).min(self.columns - 1);
+            }
+            'J' => match self.param(0, 0) {
+                0 => {
+                    self.erase(self.column, self.row, self.columns, self.row + 1);
+                    self.erase(0, self.row + 1, self.columns, self.rows);
+                }
+                1 => {
+                    self.erase(0, 0, self.columns, self.row);
+                    self.erase(0, self.row, self.column + 1, self.row + 1);
+                }
+                _ => self.erase(0, 0, self.columns, self.rows),
+            },
+            'K' => match self.param(0, 0) {
+                0 => self.erase(self.column, self.row, self.columns, self.row + 1),
+                1 => self.erase(0, self.row, self.column + 1, self.row + 1),
+                _ => self.erase(0, self.row, self.columns, self.row + 1),
+            },
+            'm' => self.select_graphic_rendition(),
+            's' => self.saved_position = (self.column, self.row),
+            'u' => (self.column, self.row) = self.saved_position,
+            'h' | 'l' if self.private && self.param(0, 0) == 25 => {
+                self.cursor_visible = command == 'h'
+            }
+            _ => {}
+        }
+    }
+
+    fn select_graphic_rendition(&mut self) {
+        for index in 0..self.param_count.clamp(1, MAX_PARAMS) {
+            let attributes = &mut self.attributes;
+            match self.params[index] {
+                0 => *attributes = Attributes::default(),
+                1 => attributes.bold = true,
+                22 => attributes.bold = false,
+                7 => attributes.inverse = true,
+                27 => attributes.inverse = false,
+                code @ 30..=37 => attributes.foreground = (code - 30) as usize,
+                39 => attributes.foreground = DEFAULT_FOREGROUND,
+                code @ 40..=47 => attributes.background = (code - 40) as usize,
+                49 => attributes.background = DEFAULT_BACKGROUND,
+                code @ 90..=97 => attributes.foreground = (code - 90) as usize + 8,
+                code @ 100..=107 => attributes.background = (code - 100) as usize + 8,
+                // The parameters of the extended colors would be mistaken for attributes.
+                38 | 48 => break,
+                _ => {}
+            }
+        }
+    }
+
+    fn put(&mut self, c: char) {
+        // The line is wrapped lazily, so that the last column can be written without scrolling.
+        if self.column >= self.columns {
+            self.new_line();
+        }
+
+        let (foreground, background) = self.attributes.colors();
+        self.draw_cell(self.column, self.row, c, foreground, background);
+        self.column += 1;
+    }
+
+    fn new_line(&mut self) {
+        self.column = 0;
+        if self.row + 1 < self.rows {
+            self.row += 1;
+        } else {
+            self.scroll();
+        }
+    }
+
+    fn scroll(&mut self) {
+        let cell_height = match self.framebuffer.kind {
+            FramebufferKind::Rgb(_) => self.font.height(),
+            FramebufferKind::Text => 1,
+        };
+
+        self.framebuffer
+            .copy_lines(cell_height, 0, (self.rows - 1) * cell_height);
+        self.erase(0, self.rows - 1, self.columns, self.rows);
+    }
+
+    fn draw_cell(&self, column: usize, row: usize, c: char, foreground: usize, background: usize) {
+        match self.framebuffer.kind {
+            FramebufferKind::Rgb(format) => {
+                let foreground = format.encode(PALETTE[foreground]);
+                let background = format.encode(PALETTE[background]);
+
+                let glyph = self.font.glyph(c);
+                let (x, y) = (column * self.font.width(), row * self.font.height());
+                for dy in 0..self.font.height() {
+                    for dx in 0..self.font.width() {
+                        let value = match self.font.is_set(glyph, dx, dy) {
+                            true => foreground,
+                            false => background,
+                        };
+                        self.framebuffer.write_raw(x + dx, y + dy, value);
+                    }
+                }
+            }
+            FramebufferKind::Text => {
+                // The most significant bit of the background selects blinking rather than a bright color.
+                let attribute =
+                    (TEXT_MODE_COLORS[background] & 0x7) << 4 | TEXT_MODE_COLORS[foreground];
+                let value =
+                    (attribute as u32) << 8 | to_cp437(c).unwrap_or(CP437_REPLACEMENT) as u32;
+                self.framebuffer.write_raw(column, row, value);
+            }
+        }
+    }
+
+    /// Clears the cells within the given columns of the given rows, using the current background color.
+    fn erase(&self, column_start: usize, row_start: usize, column_end: usize, row_end: usize) {
+        let (_, background) = self.attributes.colors();
+        for row in row_start..row_end.min(self.rows) {
+            for column in column_start..column_end.min(self.columns) {
+                self.draw_cell(column, row, ' ', DEFAULT_FOREGROUND, background);
+            }
+        }
+    }
+
+    /// Inverts the cell under the cursor, which undoes itself when repeated.
+    fn toggle_cursor(&mut self) {
+        let column = self.column.min(self.columns - 1);
+        match self.framebuffer.kind {
+            FramebufferKind::Rgb(format) => {
+                let mask = format.encode(Color::new(0xFF, 0xFF, 0xFF));
+                let x = column * self.font.width();
+                let y = (self.row + 1) * self.font.height() - CURSOR_HEIGHT;
+                for y in y..y + CURSOR_HEIGHT {
+                    for x in x..x + self.font.width() {
+                        let value = self.framebuffer.read_raw(x, y);
+                        self.framebuffer.write_raw(x, y, value ^ mask);
+                    }
+                }
+            }
+            FramebufferKind::Text => {
+                // The intensity bits are left alone, so that the cell doesn't start blinking.
+                let value = self.framebuffer.read_raw(column, self.row);
+                self.framebuffer.write_raw(column, self.row, value ^ 0x7700);
+            }
+        }
+        self.cursor_drawn = !self.cursor_drawn;
+    }
+}
+
+impl Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.columns == 0 || self.rows == 0 {
+            return Ok(());
+        }
+
+        // The cursor is hidden while writing, as the cell beneath it is about to change.
+        if self.cursor_drawn {
+            self.toggle_cursor();
+        }
+        s.chars().for_each(|c| self.write_char(c));
+        if self.cursor_visible {
+            self.toggle_cursor();
+        }
+
+        Ok(())
+    }
+}
+
+static CONSOLE: Mutex<Option<Console>> = Mutex::new(None);
+
+/// Writes formatted text to the console, if it has been set up.
+pub fn print(args: Arguments) {
+    arch::without_interrupts(|| {
+        if let Some(console) = CONSOLE.lock().as_mut() {
+            let _ = console.write_fmt(args);
+        }
+    });
+}
+
+pub(crate) fn init() -> Result<(), ()> {
+    let framebuffer = match framebuffer::get() {
+        Some(framebuffer) => framebuffer,
+        None => return Ok(()),
+    };
+
+    // The logs would be drawn over the splash screen.
+    if graphics::is_splash_shown() {
+        return Ok(());
+    }
+
+    // The text mode is left to the console of the architecture, which also drives the hardware cursor.
+    #[cfg(target_arch = "x86_64")]
+    if framebuffer.kind == FramebufferKind::Text && arch::vga::is_active() {
+        return Ok(());
+    }
+
+    *CONSOLE.lock() = Some(Console::new(framebuffer, Font::builtin()));
+    log::register_sink(print)?;
+
+    Ok(())
+}
@@ -0,0 +1,294 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use lazy_static::lazy_static;
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_HEADER_SIZE: usize = 4;
+const PSF1_MODE_512: u8 = 0x01;
+const PSF1_MODE_HAS_TAB: u8 = 0x02;
+const PSF1_MODE_SEQ: u8 = 0x04;
+const PSF1_SEPARATOR: u16 = 0xFFFF;
+const PSF1_START_SEQ: u16 = 0xFFFE;
+
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xB5, 0x4A, 0x86];
+const PSF2_HEADER_SIZE: usize = 32;
+const PSF2_HAS_UNICODE_TABLE: u32 = 0x01;
+const PSF2_SEPARATOR: u8 = 0xFF;
+const PSF2_START_SEQ: u8 = 0xFE;
+
+/// The code point drawn for characters which are missing from a font.
+const REPLACEMENT_CHARACTER: char = '\u{FFFD}';
+
+/// The font built into the kernel, which has 8x16 glyphs for ASCII, as well as for the box drawing characters.
+static BUILTIN_FONT: &[u8] = include_bytes!("../../../static/fonts/default8x16.psf");
+
+lazy_static! {
+    static ref BUILTIN: Font = Font::parse(BUILTIN_FONT).expect("built-in font is malformed");
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FontError {
+    InvalidMagic,
+    /// The data ends before the glyphs described by the header.
+    Truncated,
+    UnsupportedVersion,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum UnicodeTable {
+    /// Code points as 16-bit little-endian integers.
+    Psf1(&'static [u8]),
+    /// Code points encoded in UTF-8.
+    Psf2(&'static [u8]),
+}
+
+impl UnicodeTable {
+    /// Finds the glyph of a character by scanning the table, which lists the code points of each glyph in turn.
+    fn find(&self, c: char) -> Option<usize> {
+        match *self {
+            UnicodeTable::Psf1(table) => {
+                let entries = (0..table.len() / 2)
+                    .map(|i| u16::from_le_bytes([table[2 * i], table[2 * i + 1]]));
+
+                let mut glyph = 0;
+                let mut in_sequence = false;
+                for entry in entries {
+                    match entry {
+                        PSF1_SEPARATOR => {
+                            glyph += 1;
+                            in_sequence = false;
+                        }
+                        PSF1_START_SEQ => in_sequence = true,
+                        _ if !in_sequence && entry as u32 == c as u32 => return Some(glyph),
+                        _ => {}
+                    }
+                }
+
+                None
+            }
+            UnicodeTable::Psf2(table) => {
+                let mut buf = [0; 4];
+                let encoded = c.encode_utf8(&mut buf).as_bytes();
+
+                // Sequences are skipped, since only single code points are looked up.
+                table
+                    .split(|byte| *byte == PSF2_SEPARATOR)
+                    .position(|entry| {
+                        let singles = entry
+                            .split(|byte| *byte == PSF2_START_SEQ)
+                            .next()
+                            .unwrap_or_default();
+                        singles
+                            .windows(encoded.len())
+                            .any(|window| window == encoded)
+                    })
+            }
+        }
+    }
+}
+
+/// PC Screen Font
+///
+/// A bitmap font in either version 1 or 2 of the PSF format, as used by the Linux console. Each glyph is a bitmap
+/// with one bit per pixel, in which every row is padded to a whole byte.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/PC_Screen_Font
+#[derive(Clone, Copy, Debug)]
+pub struct Font {
+    glyphs: &'static [u8],
+    glyph_count: usize,
+    bytes_per_glyph: usize,
+    width: usize,
+    height: usize,
+    unicode_table: Option<UnicodeTable>,
+    // The glyphs of ASCII characters are looked up beforehand, as they make up the vast majority of the text.
+    ascii: [Option<u16>; 128],
+    replacement: usize,
+}
+
+impl Font {
+    pub fn parse(data: &'static [u8]) -> Result<Self, FontError> {
+        if data.starts_with(&PSF1_MAGIC) {
+            Self::parse_psf1(data)
+        } else if data.starts_with(&PSF2_MAGIC) {
+            Self::parse_psf2(data)
+        } else {
+            Err(FontError::InvalidMagic)
+        }
+    }
+
+    fn parse_psf1(data: &'static [u8]) -> Result<Self, FontError> {
+        let header = data.get(..PSF1_HEADER_SIZE).ok_or(FontError::Truncated)?;
+        let mode = header[2];
+        let height = header[3] as usize;
+
+        let glyph_count = if mode & PSF1_MODE_512 != 0 { 512 } else { 256 };
+        let glyphs_end = PSF1_HEADER_SIZE + glyph_count * height;
+        let glyphs = data
+            .get(PSF1_HEADER_SIZE..glyphs_end)
+            .ok_or(FontError::Truncated)?;
+
+        let unicode_table = if mode & (PSF1_MODE_HAS_TAB | PSF1_MODE_SEQ) != 0 {
+            Some(UnicodeTable::Psf1(&data[glyphs_end..]))
+        } else {
+            None
+        };
+
+        Ok(Self::new(
+            glyphs,
+            glyph_count,
+            height,
+            8,
+            height,
+            unicode_table,
+        ))
+    }
+
+    fn parse_psf2(data: &'static [u8]) -> Result<Self, FontError> {
+        let header = data.get(..PSF2_HEADER_SIZE).ok_or(FontError::Truncated)?;
+        let field = |index: usize| {
+            let offset = 4 * index;
+            u32::from_le_bytes([
+                header[offset],
+                header[offset + 1],
+                header[offset + 2],
+                header[offset + 3],
+            ]) as usize
+        };
+
+        if field(1) != 0 {
+            return Err(FontError::UnsupportedVersion);
+        }
+        let header_size = field(2);
+        let flags = field(3) as u32;
+        let glyph_count = field(4);
+        let bytes_per_glyph = field(5);
+        let height = field(6);
+        let width = field(7);
+
+        if bytes_per_glyph < height * width.div_ceil(8) {
+            return Err(FontError::Truncated);
+        }
+
+        let glyphs_end = glyph_count
+            .checked_mul(bytes_per_glyph)
+            .and_then(|size| size.checked_add(header_size))
+            .ok_or(FontError::Truncated)?;
+        let glyphs = data
+            .get(header_size..glyphs_end)
+            .ok_or(FontError::Truncated)?;
+
+        let unicode_table = if flags & PSF2_HAS_UNICODE_TABLE != 0 {
+            Some(UnicodeTable::Psf2(&data[glyphs_end..]))
+        } else {
+            None
+        };
+
+        Ok(Self::new(
+            glyphs,
+            glyph_count,
+            bytes_per_glyph,
+            width,
+            height,
+            unicode_table,
+        ))
+    }
+
+    fn new(
+        glyphs: &'static [u8],
+        glyph_count: usize,
+        bytes_per_glyph: usize,
+        width: usize,
+        height: usize,
+        unicode_table: Option<UnicodeTable>,
+    ) -> Self {
+        let mut font = Self {
+            glyphs,
+            glyph_count,
+            bytes_per_glyph,
+            width,
+            height,
+            unicode_table,
+            ascii: [None; 128],
+            replacement: 0,
+        };
+
+        for c in 0..font.ascii.len() {
+            font.ascii[c] = font
+                .find(c as u8 as char)
+                .and_then(|index| u16::try_from(index).ok());
+        }
+        font.replacement = font
+            .find(REPLACEMENT_CHARACTER)
+            .or_else(|| font.find('?'))
+            .unwrap_or_default();
+
+        font
+    }
+
+    /// Returns the font built into the kernel.
+    pub fn builtin() -> &'static Font {
+        &BUILTIN
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the number of bytes of each row of a glyph.
+    pub fn pitch(&self) -> usize {
+        self.width.div_ceil(8)
+    }
+
+    fn find(&self, c: char) -> Option<usize> {
+        let index = match self.unicode_table {
+            Some(table) => table.find(c)?,
+            // Without a table, the glyphs are in the order of the code points.
+            None => c as usize,
+        };
+
+        (index < self.glyph_count).then_some(index)
+    }
+
+    /// Returns the bitmap of the glyph of a character, or of a replacement glyph if the font lacks it.
+    pub fn glyph(&self, c: char) -> &'static [u8] {
+        let index = match c.is_ascii() {
+            true => self.ascii[c as usize].map(|index| index as usize),
+            false => self.find(c),
+        };
+
+        let offset = index.unwrap_or(self.replacement) * self.bytes_per_glyph;
+        &self.glyphs[offset..offset + self.bytes_per_glyph]
+    }
+
+    /// Checks whether a pixel of a glyph is set.
+    pub fn is_set(&self, glyph: &[u8], x: usize, y: usize) -> bool {
+        glyph
+            .get(y * self.pitch() + x / 8)
+            .is_some_and(|byte| byte & (0x80 >> (x % 8)) != 0)
+    }
+}
@@ -0,0 +1,262 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::ptr;
+
+use spin::Once;
+
+/// A color with 8 bits per component, which is converted to the pixel format of the framebuffer when drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Color {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+impl Color {
+    pub const fn new(red: u8, green: u8, blue: u8) -> Self {
+        Self { red, green, blue }
+    }
+}
+
+/// The location of a color component within a pixel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ColorField {
+    /// The position of the least significant bit of the component.
+    pub position: u8,
+    /// The number of bits of the component.
+    pub size: u8,
+}
+
+impl ColorField {
+    /// Derives the location of a component from its bit mask, e.g., `0x00FF0000` for the red one of XRGB8888.
+    pub const fn from_mask(mask: u32) -> Self {
+        Self {
+            position: mask.trailing_zeros() as u8 & 0x1F,
+            size: mask.count_ones() as u8,
+        }
+    }
+
+    fn encode(&self, value: u8) -> u32 {
+        let value = value as u32;
+        let value = match self.size {
+            0 => 0,
+            1..=8 => value >> (8 - self.size),
+            _ => value << (self.size - 8),
+        };
+        value << self.position
+    }
+
+    fn decode(&self, pixel: u32) -> u8 {
+        let mask = ((1u64 << self.size) - 1) as u32;
+        let value = (pixel >> self.position) & mask;
+        match self.size {
+            0 => 0,
+            1..=8 => (value << (8 - self.size)) as u8,
+            _ => (value >> (self.size - 8)) as u8,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PixelFormat {
+    pub red: ColorField,
+    pub green: ColorField,
+    pub blue: ColorField,
+}
+
+impl PixelFormat {
+    /// The format in which each component takes up a byte, with the most significant one left unused.
+    pub const XRGB8888: PixelFormat = PixelFormat::from_masks(0x00FF0000, 0x0000FF00, 0x000000FF);
+
+    pub const fn from_masks(red: u32, green: u32, blue: u32) -> Self {
+        Self {
+            red: ColorField::from_mask(red),
+            green: ColorField::from_mask(green),
+            blue: ColorField::from_mask(blue),
+        }
+    }
+
+    pub fn encode(&self, color: Color) -> u32 {
+        self.red.encode(color.red) | self.green.encode(color.green) | self.blue.encode(color.blue)
+    }
+
+    pub fn decode(&self, pixel: u32) -> Color {
+        Color::new(
+            self.red.decode(pixel),
+            self.green.decode(pixel),
+            self.blue.decode(pixel),
+        )
+    }
+
+    /// Converts a pixel from another format into this one.
+    pub fn convert(&self, pixel: u32, from: &PixelFormat) -> u32 {
+        match self == from {
+            true => pixel,
+            false => self.encode(from.decode(pixel)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FramebufferKind {
+    /// A linear framebuffer whose pixels are made of directly encoded color components.
+    Rgb(PixelFormat),
+    /// A text mode buffer of character cells, each consisting of a code point and an attribute byte.
+    ///
+    /// OS Dev Wiki: https://wiki.osdev.org/Text_UI
+    Text,
+}
+
+/// Framebuffer
+///
+/// A region of memory that is scanned out to the display. Its dimensions are either in pixels or, in case of a text
+/// mode buffer, in character cells.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/Drawing_In_a_Linear_Framebuffer
+#[derive(Clone, Copy, Debug)]
+pub struct Framebuffer {
+    /// The virtual address at which the framebuffer is mapped.
+    pub base: usize,
+    pub phys_addr: u64,
+    pub width: usize,
+    pub height: usize,
+    /// The number of bytes between the beginning of two consecutive lines.
+    pub pitch: usize,
+    /// The number of bits per pixel, or per character cell.
+    pub bpp: u8,
+    pub kind: FramebufferKind,
+}
+
+impl Framebuffer {
+    pub fn size(&self) -> usize {
+        self.pitch * self.height
+    }
+
+    pub fn bytes_per_pixel(&self) -> usize {
+        (self.bpp as usize).div_ceil(8)
+    }
+
+    fn offset(&self, x: usize, y: usize) -> Option<usize> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        Some(y * self.pitch + x * self.bytes_per_pixel())
+    }
+
+    /// Writes the raw value of a pixel, or of a character cell.
+    pub fn write_raw(&self, x: usize, y: usize, value: u32) {
+        let offset = match self.offset(x, y) {
+            Some(offset) => offset,
+            None => return,
+        };
+
+        let addr = (self.base + offset) as *mut u8;
+        unsafe {
+            match self.bytes_per_pixel() {
+                1 => ptr::write_volatile(addr, value as u8),
+                2 => ptr::write_volatile(addr as *mut u16, value as u16),
+                3 => {
+                    let bytes = value.to_le_bytes();
+                    (0..3).for_each(|i| ptr::write_volatile(addr.add(i), bytes[i]));
+                }
+                _ => ptr::write_volatile(addr as *mut u32, value),
+            }
+        }
+    }
+
+    /// Reads the raw value of a pixel, or of a character cell.
+    pub fn read_raw(&self, x: usize, y: usize) -> u32 {
+        let offset = match self.offset(x, y) {
+            Some(offset) => offset,
+            None => return 0,
+        };
+
+        let addr = (self.base + offset) as *const u8;
+        unsafe {
+            match self.bytes_per_pixel() {
+                1 => ptr::read_volatile(addr) as u32,
+                2 => ptr::read_volatile(addr as *const u16) as u32,
+                3 => {
+                    let mut bytes = [0; 4];
+                    (0..3).for_each(|i| bytes[i] = ptr::read_volatile(addr.add(i)));
+                    u32::from_le_bytes(bytes)
+                }
+                _ => ptr::read_volatile(addr as *const u32),
+            }
+        }
+    }
+
+    /// Draws a pixel, which is ignored if the framebuffer is not in a pixel format.
+    pub fn write_pixel(&self, x: usize, y: usize, color: Color) {
+        if let FramebufferKind::Rgb(format) = self.kind {
+            self.write_raw(x, y, format.encode(color));
+        }
+    }
+
+    pub fn read_pixel(&self, x: usize, y: usize) -> Option<Color> {
+        match self.kind {
+            FramebufferKind::Rgb(format) => Some(format.decode(self.read_raw(x, y))),
+            FramebufferKind::Text => None,
+        }
+    }
+
+    /// Fills a rectangle with a raw pixel value, clipping it to the bounds of the framebuffer.
+    pub fn fill_raw(&self, x: usize, y: usize, width: usize, height: usize, value: u32) {
+        let x_end = x.saturating_add(width).min(self.width);
+        let y_end = y.saturating_add(height).min(self.height);
+        for y in y..y_end {
+            for x in x..x_end {
+                self.write_raw(x, y, value);
+            }
+        }
+    }
+
+    /// Moves a number of lines within the framebuffer, e.g., to scroll its content.
+    pub fn copy_lines(&self, src: usize, dst: usize, count: usize) {
+        let count = count.min(self.height - src.max(dst).min(self.height));
+        unsafe {
+            ptr::copy(
+                (self.base + src * self.pitch) as *const u8,
+                (self.base + dst * self.pitch) as *mut u8,
+                count * self.pitch,
+            );
+        }
+    }
+}
+
+static FRAMEBUFFER: Once<Framebuffer> = Once::new();
+
+/// Returns the framebuffer discovered at boot, if any.
+pub fn get() -> Option<&'static Framebuffer> {
+    FRAMEBUFFER.get()
+}
+
+pub(crate) fn init(framebuffer: Framebuffer) -> Result<(), ()> {
+    if FRAMEBUFFER.is_completed() {
+        return Err(());
+    }
+    FRAMEBUFFER.call_once(|| framebuffer);
+
+    Ok(())
+}
@@ -0,0 +1,367 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::ptr;
+use core::slice;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::kernel::arch;
+use crate::kernel::cmdline;
+use crate::kernel::cmdline::{Arg, Param, ParamError};
+use crate::kernel::sync::Mutex;
+
+use super::framebuffer;
+use super::framebuffer::{Color, Framebuffer, FramebufferKind, PixelFormat};
+use super::image::Image;
+
+/// The maximum number of regions tracked between flushes, beyond which they're merged.
+const MAX_DIRTY_RECTS: usize = 8;
+
+/// `splash` shows the logo on the screen in place of the framebuffer console.
+static SPLASH: Param = Param::new("splash", request_splash);
+
+static SPLASH_REQUESTED: AtomicBool = AtomicBool::new(false);
+static SPLASH_SHOWN: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub const fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn right(&self) -> usize {
+        self.x.saturating_add(self.width)
+    }
+
+    pub fn bottom(&self) -> usize {
+        self.y.saturating_add(self.height)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// Returns the area covered by both rectangles, which is empty if they don't overlap.
+    pub fn intersection(&self, other: &Rect) -> Rect {
+        let (x, y) = (self.x.max(other.x), self.y.max(other.y));
+        let (right, bottom) = (
+            self.right().min(other.right()),
+            self.bottom().min(other.bottom()),
+        );
+
+        Rect::new(x, y, right.saturating_sub(x), bottom.saturating_sub(y))
+    }
+
+    /// Returns the smallest rectangle which covers both rectangles.
+    pub fn union(&self, other: &Rect) -> Rect {
+        let (x, y) = (self.x.min(other.x), self.y.min(other.y));
+        let (right, bottom) = (
+            self.right().max(other.right()),
+            self.bottom().max(other.bottom()),
+        );
+
+        Rect::new(x, y, right - x, bottom - y)
+    }
+
+    /// Checks whether the rectangles overlap or share an edge, in which case they're cheap to merge.
+    fn touches(&self, other: &Rect) -> bool {
+        self.x <= other.right()
+            && other.x <= self.right()
+            && self.y <= other.bottom()
+            && other.y <= self.bottom()
+    }
+}
+
+/// Mixes a color into a pixel of the back buffer according to its opacity.
+fn blend(pixel: u32, color: Color, alpha: u8) -> u32 {
+    let background = PixelFormat::XRGB8888.decode(pixel);
+    let mix = |foreground: u8, background: u8| {
+        let alpha = alpha as u32;
+        ((foreground as u32 * alpha + background as u32 * (255 - alpha) + 127) / 255) as u8
+    };
+
+    PixelFormat::XRGB8888.encode(Color::new(
+        mix(color.red, background.red),
+        mix(color.green, background.green),
+        mix(color.blue, background.blue),
+    ))
+}
+
+/// Compositor
+///
+/// Draws into a back buffer in the XRGB8888 format, and copies the regions that have changed since the last flush
+/// to the framebuffer, converting the pixels into its format. This avoids both flickering and reading from the
+/// framebuffer, which tends to be slow.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/Double_Buffering
+pub struct Compositor {
+    framebuffer: &'static Framebuffer,
+    format: PixelFormat,
+    buffer: &'static mut [u32],
+    width: usize,
+    height: usize,
+    dirty: [Option<Rect>; MAX_DIRTY_RECTS],
+}
+
+impl Compositor {
+    /// Creates a compositor for a framebuffer in a pixel format, with a back buffer of at least its size.
+    pub fn new(framebuffer: &'static Framebuffer, buffer: &'static mut [u32]) -> Option<Self> {
+        let format = match framebuffer.kind {
+            FramebufferKind::Rgb(format) => format,
+            FramebufferKind::Text => return None,
+        };
+        if buffer.len() < framebuffer.width * framebuffer.height {
+            return None;
+        }
+
+        Some(Self {
+            framebuffer,
+            format,
+            buffer,
+            width: framebuffer.width,
+            height: framebuffer.height,
+            dirty: [None; MAX_DIRTY_RECTS],
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn bounds(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Option<Color> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        Some(PixelFormat::XRGB8888.decode(self.buffer[y * self.width + x]))
+    }
+
+    pub fn put_pixel(&mut self, x: usize, y: usize, color: Color) {
+        self.fill_rect(Rect::new(x, y, 1, 1), color);
+    }
+
+    pub fn clear(&mut self, color: Color) {
+        self.fill_rect(self.bounds(), color);
+    }
+
+    pub fn fill_rect(&mut self, rect: Rect, color: Color) {
+        let rect = rect.intersection(&self.bounds());
+        let value = PixelFormat::XRGB8888.encode(color);
+        for y in rect.y..rect.bottom() {
+            self.buffer[y * self.width + rect.x..y * self.width + rect.right()].fill(value);
+        }
+        self.mark_dirty(rect);
+    }
+
+    /// Draws the outline of a rectangle, one pixel thick.
+    pub fn draw_rect(&mut self, rect: Rect, color: Color) {
+        if rect.is_empty() {
+            return;
+        }
+
+        self.fill_rect(Rect::new(rect.x, rect.y, rect.width, 1), color);
+        self.fill_rect(Rect::new(rect.x, rect.bottom() - 1, rect.width, 1), color);
+        self.fill_rect(Rect::new(rect.x, rect.y, 1, rect.height), color);
+        self.fill_rect(Rect::new(rect.right() - 1, rect.y, 1, rect.height), color);
+    }
+
+    /// Draws a line between two points, which may lie off the screen, using Bresenham's algorithm.
+    ///
+    /// Wikipedia: https://en.wikipedia.org/wiki/Bresenham%27s_line_algorithm
+    pub fn draw_line(&mut self, from: (isize, isize), to: (isize, isize), color: Color) {
+        let value = PixelFormat::XRGB8888.encode(color);
+        let (mut x, mut y) = from;
+        let (dx, dy) = ((to.0 - x).abs(), -(to.1 - y).abs());
+        let (sx, sy) = ((to.0 - x).signum(), (to.1 - y).signum());
+
+        let mut error = dx + dy;
+        loop {
+            if (0..self.width as isize).contains(&x) && (0..self.height as isize).contains(&y) {
+                self.buffer[y as usize * self.width + x as usize] = value;
+            }
+            if (x, y) == to {
+                break;
+            }
+
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += sx;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += sy;
+            }
+        }
+
+        let clamp = |value: isize, limit: usize| value.clamp(0, limit as isize) as usize;
+        let (left, right) = (from.0.min(to.0), from.0.max(to.0) + 1);
+        let (top, bottom) = (from.1.min(to.1), from.1.max(to.1) + 1);
+        let (left, right) = (clamp(left, self.width), clamp(right, self.width));
+        let (top, bottom) = (clamp(top, self.height), clamp(bottom, self.height));
+        self.mark_dirty(Rect::new(left, top, right - left, bottom - top));
+    }
+
+    /// Draws an image with its top-left corner at the given point, blending it according to its opacity.
+    pub fn blit(&mut self, image: &Image, x: usize, y: usize) {
+        let rect = Rect::new(x, y, image.width, image.height).intersection(&self.bounds());
+        for dy in 0..rect.height {
+            for dx in 0..rect.width {
+                let (color, alpha) = image.pixel(dx, dy);
+                let pixel = &mut self.buffer[(rect.y + dy) * self.width + rect.x + dx];
+                *pixel = match alpha {
+                    0 => continue,
+                    u8::MAX => PixelFormat::XRGB8888.encode(color),
+                    _ => blend(*pixel, color, alpha),
+                };
+            }
+        }
+        self.mark_dirty(rect);
+    }
+
+    fn mark_dirty(&mut self, rect: Rect) {
+        let mut rect = rect.intersection(&self.bounds());
+        if rect.is_empty() {
+            return;
+        }
+
+        // The regions touched by the new one are absorbed into it, to avoid flushing any pixel twice.
+        for slot in self.dirty.iter_mut() {
+            if let Some(dirty) = slot {
+                if dirty.touches(&rect) {
+                    rect = rect.union(dirty);
+                    *slot = None;
+                }
+            }
+        }
+
+        match self.dirty.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => *slot = Some(rect),
+            None => {
+                let merged = self
+                    .dirty
+                    .iter()
+                    .flatten()
+                    .fold(rect, |merged, dirty| merged.union(dirty));
+                self.dirty = [None; MAX_DIRTY_RECTS];
+                self.dirty[0] = Some(merged);
+            }
+        }
+    }
+
+    /// Copies the regions which have changed since the last flush to the framebuffer.
+    pub fn flush(&mut self) {
+        let direct = self.format == PixelFormat::XRGB8888 && self.framebuffer.bpp == 32;
+        for rect in self.dirty.iter_mut().filter_map(Option::take) {
+            for y in rect.y..rect.bottom() {
+                let row = &self.buffer[y * self.width + rect.x..y * self.width + rect.right()];
+                if direct {
+                    let dst = self.framebuffer.base + y * self.framebuffer.pitch + rect.x * 4;
+                    unsafe { ptr::copy_nonoverlapping(row.as_ptr(), dst as *mut u32, row.len()) };
+                    continue;
+                }
+
+                for (dx, pixel) in row.iter().enumerate() {
+                    let value = self.format.convert(*pixel, &PixelFormat::XRGB8888);
+                    self.framebuffer.write_raw(rect.x + dx, y, value);
+                }
+            }
+        }
+    }
+}
+
+static COMPOSITOR: Mutex<Option<Compositor>> = Mutex::new(None);
+
+/// Runs a closure with the compositor, if the framebuffer is in a pixel format.
+pub fn with<F, R>(f: F) -> Option<R>
+where
+    F: FnOnce(&mut Compositor) -> R,
+{
+    arch::without_interrupts(|| COMPOSITOR.lock().as_mut().map(f))
+}
+
+/// Checks whether the splash screen occupies the framebuffer.
+pub fn is_splash_shown() -> bool {
+    SPLASH_SHOWN.load(Ordering::Relaxed)
+}
+
+fn request_splash(arg: &Arg) -> Result<(), ParamError> {
+    arg.flag()?;
+    SPLASH_REQUESTED.store(true, Ordering::Relaxed);
+
+    Ok(())
+}
+
+fn show_splash(compositor: &mut Compositor) {
+    let cover = Image::cover();
+    let x = compositor.width().saturating_sub(cover.width) / 2;
+    let y = compositor.height().saturating_sub(cover.height) / 2;
+
+    compositor.clear(Color::default());
+    compositor.blit(&cover, x, y);
+    compositor.flush();
+}
+
+pub(crate) fn init() -> Result<(), ()> {
+    cmdline::register(&SPLASH).map_err(|_| ())?;
+
+    let framebuffer = match framebuffer::get() {
+        Some(framebuffer) if matches!(framebuffer.kind, FramebufferKind::Rgb(_)) => framebuffer,
+        _ => return Ok(()),
+    };
+
+    // The back buffer is taken from physical memory, as it's far too large for the kernel image.
+    let len = framebuffer.width * framebuffer.height;
+    let frames = (len * 4).div_ceil(arch::frame::FRAME_SIZE);
+    let addr = arch::frame::allocate(frames).ok_or(())?;
+    let buffer = arch::paging::phys_to_virt(addr).ok_or(())?;
+    let buffer = unsafe { slice::from_raw_parts_mut(buffer.as_mut_ptr::<u32>(), len) };
+
+    let mut compositor = Compositor::new(framebuffer, buffer).ok_or(())?;
+    if SPLASH_REQUESTED.load(Ordering::Relaxed) {
+        show_splash(&mut compositor);
+        SPLASH_SHOWN.store(true, Ordering::Relaxed);
+    }
+    *COMPOSITOR.lock() = Some(compositor);
+
+    Ok(())
+}
@@ -0,0 +1,64 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use super::framebuffer::Color;
+
+// The images are converted into raw pixels by the build script, which generates their definitions.
+macro_rules! load_generated_images {
+    () => {
+        include!(concat!(env!("OUT_DIR"), "/Images.rs"));
+    };
+}
+
+load_generated_images!();
+
+/// An image made of RGBA pixels with 8 bits per channel, in rows from top to bottom.
+#[derive(Clone, Copy, Debug)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pixels: &'static [u8],
+}
+
+impl Image {
+    /// Creates an image over raw pixels, provided that there's one for each point.
+    pub fn new(width: usize, height: usize, pixels: &'static [u8]) -> Option<Self> {
+        (pixels.len() == width * height * 4).then_some(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Returns the logo of the operating system.
+    pub fn cover() -> Self {
+        Self::new(COVER_WIDTH, COVER_HEIGHT, COVER_PIXELS).expect("cover image is malformed")
+    }
+
+    /// Returns the color and the opacity of a pixel.
+    pub fn pixel(&self, x: usize, y: usize) -> (Color, u8) {
+        let offset = (y * self.width + x) * 4;
+        let pixel = &self.pixels[offset..offset + 4];
+
+        (Color::new(pixel[0], pixel[1], pixel[2]), pixel[3])
+    }
+}
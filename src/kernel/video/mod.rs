@@ -0,0 +1,63 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+pub mod console;
+pub mod font;
+pub mod framebuffer;
+pub mod graphics;
+pub mod image;
+
+/// The character of code page 437 displayed in place of those which are missing from it (a small square).
+pub const CP437_REPLACEMENT: u8 = 0xFE;
+
+/// Maps a character to code page 437, which is the character set of the VGA text mode.
+pub fn to_cp437(c: char) -> Option<u8> {
+    let byte = match c {
+        ' '..='~' => c as u8,
+        '─' => 0xC4,
+        '│' => 0xB3,
+        '┌' => 0xDA,
+        '┐' => 0xBF,
+        '└' => 0xC0,
+        '┘' => 0xD9,
+        '├' => 0xC3,
+        '┤' => 0xB4,
+        '┬' => 0xC2,
+        '┴' => 0xC1,
+        '┼' => 0xC5,
+        '░' => 0xB0,
+        '▒' => 0xB1,
+        '▓' => 0xB2,
+        '█' => 0xDB,
+        '▀' => 0xDF,
+        '▄' => 0xDC,
+        '■' => 0xFE,
+        _ => return None,
+    };
+
+    Some(byte)
+}
+
+pub(crate) fn init() -> Result<(), ()> {
+    graphics::init()?;
+    console::init()
+}
@@ -0,0 +1,473 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::task::Poll;
+
+use spin::Once;
+
+use crate::kernel::arch;
+use crate::kernel::block;
+use crate::kernel::block::{BlockDevice, BlockError, BlockInfo, Operation, Request, RequestId};
+use crate::kernel::dma;
+use crate::kernel::dma::DmaBuffer;
+use crate::kernel::pci;
+use crate::kernel::pci::{PciDevice, PciDriver, PciMatch};
+use crate::kernel::sync::Mutex;
+
+use super::queue::{Buffer, Virtqueue};
+use super::{InterruptMode, VirtioDevice, VENDOR_ID};
+
+/// The maximum number of disks, which are named in turn.
+const MAX_DISKS: usize = 4;
+const DISK_NAMES: [&str; MAX_DISKS] = ["vda", "vdb", "vdc", "vdd"];
+
+/// The maximum number of queues used per disk, even if the device has more of them.
+const MAX_QUEUES: usize = 4;
+/// The maximum number of requests in flight per queue, whose headers fill a page.
+const MAX_IN_FLIGHT: usize = 64;
+/// The maximum number of segments of the buffer of a request.
+const MAX_SEGMENTS: usize = 16;
+
+/// The largest block which is read back when a disk is probed, as the buffer is on the stack.
+const MAX_CHECKED_BLOCK_SIZE: usize = 4096;
+
+/// The unit of the sectors addressed by the requests, regardless of the size of the blocks.
+const SECTOR_SIZE: usize = 512;
+
+const F_SIZE_MAX: u64 = 1 << 1;
+const F_SEG_MAX: u64 = 1 << 2;
+const F_RO: u64 = 1 << 5;
+const F_BLK_SIZE: u64 = 1 << 6;
+const F_FLUSH: u64 = 1 << 9;
+const F_MQ: u64 = 1 << 12;
+
+// The fields of the configuration.
+const CONFIG_CAPACITY: usize = 0x00;
+const CONFIG_SIZE_MAX: usize = 0x08;
+const CONFIG_SEG_MAX: usize = 0x0C;
+const CONFIG_BLK_SIZE: usize = 0x14;
+const CONFIG_NUM_QUEUES: usize = 0x22;
+
+// The types of the requests.
+const TYPE_IN: u32 = 0;
+const TYPE_OUT: u32 = 1;
+const TYPE_FLUSH: u32 = 4;
+
+// The status written by the device once a request has completed.
+const STATUS_OK: u8 = 0;
+const STATUS_UNSUPPORTED: u8 = 2;
+/// The status of a request before the device has written its own.
+const STATUS_PENDING: u8 = 0xFF;
+
+/// The header which precedes the buffer of every request.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct RequestHeader {
+    kind: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+const HEADER_SIZE: usize = core::mem::size_of::<RequestHeader>();
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Slot {
+    Free,
+    /// The request has been added to the queue as the chain with the given head.
+    Pending {
+        head: u16,
+    },
+    Done(Result<(), BlockError>),
+}
+
+/// A virtqueue along with the headers and the states of the requests in flight.
+struct RequestQueue {
+    queue: Virtqueue,
+    /// The headers of the requests, followed by their status bytes.
+    headers: DmaBuffer,
+    slots: [Slot; MAX_IN_FLIGHT],
+}
+
+impl RequestQueue {
+    fn new(queue: Virtqueue) -> Result<Self, BlockError> {
+        let headers =
+            DmaBuffer::allocate(MAX_IN_FLIGHT * (HEADER_SIZE + 1)).ok_or(BlockError::NoMemory)?;
+
+        Ok(Self {
+            queue,
+            headers,
+            slots: [Slot::Free; MAX_IN_FLIGHT],
+        })
+    }
+
+    fn header_addr(&self, slot: usize) -> u64 {
+        self.headers.phys_addr() + (slot * HEADER_SIZE) as u64
+    }
+
+    fn status_offset(slot: usize) -> usize {
+        MAX_IN_FLIGHT * HEADER_SIZE + slot
+    }
+
+    /// Adds a request to the queue, and returns the slot which tracks it.
+    fn submit(&mut self, kind: u32, sector: u64, segments: &[Buffer]) -> Result<usize, BlockError> {
+        let slot = self
+            .slots
+            .iter()
+            .position(|slot| *slot == Slot::Free)
+            .ok_or(BlockError::Busy)?;
+
+        let header = RequestHeader {
+            kind,
+            reserved: 0,
+            sector,
+        };
+        unsafe {
+            ptr::write_volatile(self.headers.as_ptr::<RequestHeader>().add(slot), header);
+            ptr::write_volatile(
+                self.headers.as_ptr::<u8>().add(Self::status_offset(slot)),
+                STATUS_PENDING,
+            );
+        }
+
+        // The header is read by the device, while the status is written back after the buffer.
+        let mut chain = [Buffer::readable(0, 0); MAX_SEGMENTS + 2];
+        chain[0] = Buffer::readable(self.header_addr(slot), HEADER_SIZE as u32);
+        chain[1..=segments.len()].copy_from_slice(segments);
+        let status_addr = self.headers.phys_addr() + Self::status_offset(slot) as u64;
+        chain[segments.len() + 1] = Buffer::writable(status_addr, 1);
+
+        let head = self
+            .queue
+            .add(&chain[..segments.len() + 2])
+            .map_err(|_| BlockError::Busy)?;
+        self.slots[slot] = Slot::Pending { head };
+        self.queue.notify();
+
+        Ok(slot)
+    }
+
+    /// Completes the requests which the device has used.
+    fn reap(&mut self) {
+        while let Some(used) = self.queue.pop_used() {
+            let Some(slot) = self
+                .slots
+                .iter()
+                .position(|slot| *slot == Slot::Pending { head: used.head })
+            else {
+                continue;
+            };
+
+            let status = unsafe {
+                ptr::read_volatile(self.headers.as_ptr::<u8>().add(Self::status_offset(slot)))
+            };
+            self.slots[slot] = Slot::Done(match status {
+                STATUS_OK => Ok(()),
+                STATUS_UNSUPPORTED => Err(BlockError::Unsupported),
+                _ => Err(BlockError::Io),
+            });
+        }
+    }
+
+    /// Tracks a request which has completed without being passed to the device.
+    fn complete(&mut self, result: Result<(), BlockError>) -> Result<usize, BlockError> {
+        let slot = self
+            .slots
+            .iter()
+            .position(|slot| *slot == Slot::Free)
+            .ok_or(BlockError::Busy)?;
+        self.slots[slot] = Slot::Done(result);
+
+        Ok(slot)
+    }
+
+    fn poll(&mut self, slot: usize) -> Poll<Result<(), BlockError>> {
+        self.reap();
+        match self.slots[slot] {
+            Slot::Done(result) => {
+                self.slots[slot] = Slot::Free;
+                Poll::Ready(result)
+            }
+            Slot::Pending { .. } => Poll::Pending,
+            Slot::Free => Poll::Ready(Err(BlockError::UnknownRequest)),
+        }
+    }
+}
+
+/// Virtio Block Device
+///
+/// A disk whose requests are spread over several queues if the device supports it, and which completes them either
+/// through the interrupts of the queues or by polling them.
+///
+/// Spec: https://docs.oasis-open.org/virtio/virtio/v1.2/cs01/virtio-v1.2-cs01.html#x1-2740002
+pub struct VirtioBlock {
+    name: &'static str,
+    device: VirtioDevice,
+    info: BlockInfo,
+    queues: [Mutex<Option<RequestQueue>>; MAX_QUEUES],
+    queue_count: usize,
+    /// The number of sectors per block.
+    sectors_per_block: u64,
+    /// The maximum number of segments per request.
+    max_segments: usize,
+    /// The maximum length of a segment.
+    max_segment_size: usize,
+    /// The queue to which the next request is submitted first.
+    next_queue: AtomicUsize,
+}
+
+impl VirtioBlock {
+    /// Splits the buffer of a request into the segments which are passed to the device.
+    fn segments(
+        &self,
+        request: &Request,
+        segments: &mut [Buffer; MAX_SEGMENTS],
+    ) -> Result<usize, BlockError> {
+        let mut count = 0;
+        for segment in dma::segments(request.buf, request.len) {
+            let (mut addr, mut len) = segment.ok_or(BlockError::InvalidBuffer)?;
+            while len > 0 {
+                let part = len.min(self.max_segment_size);
+                if count == self.max_segments {
+                    return Err(BlockError::InvalidBuffer);
+                }
+                segments[count] = Buffer {
+                    addr,
+                    len: part as u32,
+                    writable: request.op == Operation::Read,
+                };
+                count += 1;
+                addr += part as u64;
+                len -= part;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Submits a request to the first queue which has room for it, starting with the next one in turn.
+    fn submit_to_queue<F>(&self, mut submit: F) -> Result<RequestId, BlockError>
+    where
+        F: FnMut(&mut RequestQueue) -> Result<usize, BlockError>,
+    {
+        let first = self.next_queue.fetch_add(1, Ordering::Relaxed);
+        for i in 0..self.queue_count {
+            let index = (first + i) % self.queue_count;
+            let result = arch::without_interrupts(|| {
+                let mut queue = self.queues[index].lock();
+                let queue = queue.as_mut().ok_or(BlockError::Io)?;
+                queue.reap();
+                submit(queue)
+            });
+
+            match result {
+                Ok(slot) => return Ok((index * MAX_IN_FLIGHT + slot) as RequestId),
+                Err(BlockError::Busy) => continue,
+                Err(error) => return Err(error),
+            }
+        }
+
+        Err(BlockError::Busy)
+    }
+
+    /// Completes the requests of every queue.
+    fn reap(&self) {
+        if let InterruptMode::Legacy { .. } = self.device.interrupt_mode() {
+            self.device.acknowledge_interrupt();
+        }
+        for queue in self.queues.iter().take(self.queue_count) {
+            if let Some(queue) = queue.lock().as_mut() {
+                queue.reap();
+            }
+        }
+    }
+}
+
+impl BlockDevice for VirtioBlock {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn info(&self) -> BlockInfo {
+        self.info
+    }
+
+    unsafe fn submit(&self, request: Request) -> Result<RequestId, BlockError> {
+        request.check(&self.info)?;
+
+        if request.op == Operation::Flush {
+            // Without a volatile cache, the blocks are persistent as soon as they've been written.
+            return match self.device.has_feature(F_FLUSH) {
+                true => self.submit_to_queue(|queue| queue.submit(TYPE_FLUSH, 0, &[])),
+                false => self.submit_to_queue(|queue| queue.complete(Ok(()))),
+            };
+        }
+
+        let mut segments = [Buffer::readable(0, 0); MAX_SEGMENTS];
+        let count = self.segments(&request, &mut segments)?;
+        let kind = match request.op {
+            Operation::Write => TYPE_OUT,
+            _ => TYPE_IN,
+        };
+        let sector = request.block * self.sectors_per_block;
+
+        self.submit_to_queue(|queue| queue.submit(kind, sector, &segments[..count]))
+    }
+
+    fn poll(&self, id: RequestId) -> Poll<Result<(), BlockError>> {
+        let index = id as usize / MAX_IN_FLIGHT;
+        let slot = id as usize % MAX_IN_FLIGHT;
+        if index >= self.queue_count {
+            return Poll::Ready(Err(BlockError::UnknownRequest));
+        }
+
+        arch::without_interrupts(|| match self.queues[index].lock().as_mut() {
+            Some(queue) => queue.poll(slot),
+            None => Poll::Ready(Err(BlockError::UnknownRequest)),
+        })
+    }
+}
+
+static DISKS: [Once<VirtioBlock>; MAX_DISKS] = [const { Once::new() }; MAX_DISKS];
+
+/// The disks being probed, which keeps their slots from being taken by another one.
+static PROBED: AtomicUsize = AtomicUsize::new(0);
+
+static DRIVER: PciDriver = PciDriver {
+    name: "virtio-blk",
+    matches: &[
+        PciMatch::device(VENDOR_ID, 0x1001),
+        PciMatch::device(VENDOR_ID, 0x1042),
+    ],
+    probe,
+};
+
+/// Completes the requests of every disk, as the interrupts of the queues share the handler.
+fn on_interrupt() {
+    for disk in DISKS.iter().filter_map(Once::get) {
+        disk.reap();
+    }
+}
+
+fn probe(pci: &PciDevice) -> Result<(), ()> {
+    let index = PROBED.fetch_add(1, Ordering::Relaxed);
+    if index >= MAX_DISKS {
+        log::warn!("virtio-blk: too many disks");
+        return Err(());
+    }
+
+    let features = F_SIZE_MAX | F_SEG_MAX | F_RO | F_BLK_SIZE | F_FLUSH | F_MQ;
+    let mut device = VirtioDevice::new(pci, features).map_err(|error| {
+        log::warn!("virtio-blk: failed to negotiate the features: {:?}", error);
+    })?;
+
+    let config_u32 = |feature, offset| {
+        device
+            .has_feature(feature)
+            .then(|| device.read_config_u32(offset))
+            .flatten()
+            .filter(|value| *value != 0)
+    };
+    let block_size =
+        config_u32(F_BLK_SIZE, CONFIG_BLK_SIZE).map_or(SECTOR_SIZE, |size| size as usize);
+    let max_segment_size =
+        config_u32(F_SIZE_MAX, CONFIG_SIZE_MAX).map_or(u32::MAX as usize, |size| size as usize);
+    let max_segments = config_u32(F_SEG_MAX, CONFIG_SEG_MAX)
+        .map_or(MAX_SEGMENTS, |max| (max as usize).min(MAX_SEGMENTS));
+    let capacity = device.read_config_u64(CONFIG_CAPACITY).ok_or(())?;
+    let queue_count = match device.has_feature(F_MQ) {
+        true => device
+            .read_config_u16(CONFIG_NUM_QUEUES)
+            .unwrap_or(1)
+            .clamp(1, MAX_QUEUES as u16),
+        false => 1,
+    };
+
+    if block_size < SECTOR_SIZE || block_size % SECTOR_SIZE != 0 {
+        log::warn!("virtio-blk: unsupported block size of {} bytes", block_size);
+        device.fail();
+        return Err(());
+    }
+    let sectors_per_block = (block_size / SECTOR_SIZE) as u64;
+
+    let queues = [const { Mutex::new(None) }; MAX_QUEUES];
+    for (index, queue) in queues.iter().enumerate().take(queue_count as usize) {
+        let setup = device
+            .setup_queue(index as u16, Some(on_interrupt))
+            .map_err(|error| {
+                log::warn!("virtio-blk: failed to set up queue {}: {:?}", index, error)
+            })
+            .and_then(|queue| RequestQueue::new(queue).map_err(|_| ()));
+        match setup {
+            Ok(request_queue) => *queue.lock() = Some(request_queue),
+            Err(()) => {
+                device.fail();
+                return Err(());
+            }
+        }
+    }
+
+    let info = BlockInfo {
+        block_size,
+        block_count: capacity / sectors_per_block,
+        read_only: device.has_feature(F_RO),
+    };
+    device.driver_ok();
+    log::info!(
+        "virtio-blk {}: {} queue(s), {:?} interrupts",
+        DISK_NAMES[index],
+        queue_count,
+        device.interrupt_mode()
+    );
+
+    let disk = DISKS[index].call_once(|| VirtioBlock {
+        name: DISK_NAMES[index],
+        device,
+        info,
+        queues,
+        queue_count: queue_count as usize,
+        sectors_per_block,
+        max_segments,
+        max_segment_size,
+        next_queue: AtomicUsize::new(0),
+    });
+
+    // The first block is read back as a check that the requests are carried out.
+    let mut buf = [0; MAX_CHECKED_BLOCK_SIZE];
+    if let Some(block) = buf.get_mut(..block_size) {
+        if let Err(error) = disk.read_blocks(0, block) {
+            log::warn!(
+                "virtio-blk {}: failed to read the first block: {:?}",
+                disk.name,
+                error
+            );
+            return Err(());
+        }
+    }
+
+    block::register(disk).map_err(|_| ())
+}
+
+pub(crate) fn init() -> Result<(), ()> {
+    pci::register_driver(&DRIVER).map_err(|_| ())
+}
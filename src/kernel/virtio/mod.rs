@@ -0,0 +1,375 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::ops::RangeInclusive;
+
+use crate::kernel::irq;
+use crate::kernel::irq::{IrqError, IrqHandler};
+use crate::kernel::msi;
+use crate::kernel::msi::MsiError;
+use crate::kernel::pci::PciDevice;
+
+use self::pci::Transport;
+use self::queue::{Virtqueue, MAX_QUEUE_SIZE};
+
+pub mod block;
+mod pci;
+pub mod queue;
+
+pub const VENDOR_ID: u16 = 0x1AF4;
+
+/// The transitional devices, which also have the legacy interface, take their type from the subsystem ID.
+const LEGACY_DEVICE_IDS: RangeInclusive<u16> = 0x1000..=0x103F;
+/// The modern devices take their type from their device ID, as an offset from this one.
+const MODERN_DEVICE_ID_BASE: u16 = 0x1040;
+
+// The bits of the device status, which the driver sets as it initializes the device.
+const STATUS_ACKNOWLEDGE: u8 = 1 << 0;
+const STATUS_DRIVER: u8 = 1 << 1;
+const STATUS_DRIVER_OK: u8 = 1 << 2;
+const STATUS_FEATURES_OK: u8 = 1 << 3;
+const STATUS_FAILED: u8 = 1 << 7;
+
+/// The device may place descriptors into tables referenced by other descriptors.
+pub const F_RING_INDIRECT_DESC: u64 = 1 << 28;
+/// The rings hold the indices at which the other side wants to be notified, instead of flags.
+pub const F_RING_EVENT_IDX: u64 = 1 << 29;
+/// The device complies with version 1.0 of the specification, which the modern interface requires.
+pub const F_VERSION_1: u64 = 1 << 32;
+
+/// The features handled by the core itself, which are accepted in addition to those of the driver.
+const CORE_FEATURES: u64 = F_RING_EVENT_IDX | F_VERSION_1;
+
+/// The ISR status bit which tells that a queue has been used.
+pub const ISR_QUEUE: u8 = 1 << 0;
+/// The ISR status bit which tells that the configuration of the device has changed.
+pub const ISR_CONFIG: u8 = 1 << 1;
+
+/// The MSI-X entry which disables an interrupt.
+const NO_VECTOR: u16 = 0xFFFF;
+
+fn is_legacy_device_id(device_id: u16) -> bool {
+    LEGACY_DEVICE_IDS.contains(&device_id)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VirtioError {
+    /// The function isn't a virtio device with a known interface.
+    Unsupported,
+    /// The device has rejected the features, or lacks those required.
+    FeaturesRejected,
+    /// The queue doesn't exist.
+    QueueUnavailable,
+    /// The queue is larger than `MAX_QUEUE_SIZE` and can't be shrunk, or its size isn't a power of two.
+    InvalidQueueSize,
+    /// The device has rejected the MSI-X entry of a queue.
+    VectorRejected,
+    QueueFull,
+    EmptyChain,
+    NoMemory,
+    Msi(MsiError),
+    Irq(IrqError),
+}
+
+impl From<MsiError> for VirtioError {
+    fn from(error: MsiError) -> Self {
+        VirtioError::Msi(error)
+    }
+}
+
+impl From<IrqError> for VirtioError {
+    fn from(error: IrqError) -> Self {
+        VirtioError::Irq(error)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeviceType {
+    Network,
+    Block,
+    Console,
+    Entropy,
+    MemoryBalloon,
+    Scsi,
+    Gpu,
+    Input,
+    Socket,
+    Other(u16),
+}
+
+impl DeviceType {
+    fn from_id(id: u16) -> Self {
+        match id {
+            1 => DeviceType::Network,
+            2 => DeviceType::Block,
+            3 => DeviceType::Console,
+            4 => DeviceType::Entropy,
+            5 => DeviceType::MemoryBalloon,
+            8 => DeviceType::Scsi,
+            16 => DeviceType::Gpu,
+            18 => DeviceType::Input,
+            19 => DeviceType::Socket,
+            id => DeviceType::Other(id),
+        }
+    }
+
+    /// Identifies the type of a function, or returns `None` if it isn't a virtio device.
+    pub fn of(device: &PciDevice) -> Option<Self> {
+        if device.vendor_id != VENDOR_ID {
+            return None;
+        }
+
+        match device.device_id {
+            id if is_legacy_device_id(id) => Some(Self::from_id(device.subsystem_id)),
+            id if id >= MODERN_DEVICE_ID_BASE => Some(Self::from_id(id - MODERN_DEVICE_ID_BASE)),
+            _ => None,
+        }
+    }
+}
+
+/// How the device signals that its queues have been used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InterruptMode {
+    /// The queues have no interrupts, so they must be polled.
+    Polled,
+    /// Every queue has its own vector.
+    MsiX,
+    /// The queues share the legacy interrupt of the function, which the handler must acknowledge.
+    Legacy { irq: u8 },
+}
+
+/// Virtual I/O Device (VIRTIO)
+///
+/// The paravirtualized devices provided by hypervisors such as QEMU, which exchange requests with the driver through
+/// virtqueues in the shared memory. The core negotiates the features, sets up the queues and their interrupts, while
+/// the drivers of the device types build their requests on top of it.
+///
+/// OS Dev Wiki: https://wiki.osdev.org/Virtio
+#[derive(Debug)]
+pub struct VirtioDevice {
+    pci: PciDevice,
+    transport: Transport,
+    device_type: DeviceType,
+    features: u64,
+    interrupt: InterruptMode,
+}
+
+impl VirtioDevice {
+    /// Resets the device and negotiates the features, of which those wanted by the driver are accepted if the
+    /// device offers them.
+    ///
+    /// The device becomes usable once its queues are set up and `driver_ok` is called.
+    pub fn new(pci: &PciDevice, wanted_features: u64) -> Result<Self, VirtioError> {
+        let device_type = DeviceType::of(pci).ok_or(VirtioError::Unsupported)?;
+        pci.enable(true);
+        let transport = Transport::probe(pci)?;
+
+        transport.reset();
+        transport.set_status(STATUS_ACKNOWLEDGE);
+        transport.set_status(STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+
+        let offered = transport.device_features();
+        let features = offered & (wanted_features | CORE_FEATURES);
+        let mut device = Self {
+            pci: *pci,
+            transport,
+            device_type,
+            features,
+            interrupt: InterruptMode::Polled,
+        };
+
+        // The modern interface can't be driven as a legacy device.
+        if !transport.is_legacy() && features & F_VERSION_1 == 0 {
+            device.fail();
+            return Err(VirtioError::FeaturesRejected);
+        }
+
+        transport.set_driver_features(features);
+        if !transport.is_legacy() {
+            transport.set_status(STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_FEATURES_OK);
+            if transport.status() & STATUS_FEATURES_OK == 0 {
+                device.fail();
+                return Err(VirtioError::FeaturesRejected);
+            }
+        }
+
+        // The changes of the configuration aren't signaled, which leaves the entries of MSI-X to the queues.
+        if device.pci.msi_x().is_some() {
+            device.transport.set_config_vector(NO_VECTOR);
+        }
+
+        Ok(device)
+    }
+
+    pub fn pci(&self) -> &PciDevice {
+        &self.pci
+    }
+
+    pub fn device_type(&self) -> DeviceType {
+        self.device_type
+    }
+
+    pub fn is_legacy(&self) -> bool {
+        self.transport.is_legacy()
+    }
+
+    /// Returns the negotiated features.
+    pub fn features(&self) -> u64 {
+        self.features
+    }
+
+    pub fn has_feature(&self, feature: u64) -> bool {
+        self.features & feature == feature
+    }
+
+    pub fn interrupt_mode(&self) -> InterruptMode {
+        self.interrupt
+    }
+
+    /// Returns the number of queues, which the legacy interface doesn't tell.
+    pub fn queue_count(&self) -> Option<u16> {
+        self.transport.queue_count()
+    }
+
+    /// Sets up a queue, whose interrupts are passed to the handler if one is given.
+    ///
+    /// The queues get their own vectors if the function has MSI-X, otherwise they share its legacy interrupt, in
+    /// which case the handler of the first queue serves them all and must call `acknowledge_interrupt`.
+    pub fn setup_queue(
+        &mut self,
+        index: u16,
+        handler: Option<IrqHandler>,
+    ) -> Result<Virtqueue, VirtioError> {
+        let max_size = self.transport.select_queue(index);
+        if max_size == 0 {
+            return Err(VirtioError::QueueUnavailable);
+        }
+        let size = match self.transport.can_resize_queue() {
+            true => max_size.min(MAX_QUEUE_SIZE),
+            false => max_size,
+        };
+
+        let mut queue = Virtqueue::new(index, size, self.has_feature(F_RING_EVENT_IDX))?;
+        if let Some(handler) = handler {
+            self.attach_interrupt(index, handler)?;
+        } else {
+            queue.disable_interrupts();
+        }
+
+        let notifier = self.transport.activate_queue(
+            index,
+            size,
+            queue.desc_addr(),
+            queue.avail_addr(),
+            queue.used_addr(),
+        );
+        queue.set_notifier(notifier);
+
+        Ok(queue)
+    }
+
+    /// Routes the interrupts of the selected queue to the handler.
+    fn attach_interrupt(&mut self, index: u16, handler: IrqHandler) -> Result<(), VirtioError> {
+        if self.pci.msi_x().is_some() {
+            // The first entry is left to the changes of the configuration.
+            let entry = index + 1;
+            msi::enable_msi_x(&self.pci, entry, msi::current_cpu(), handler)?;
+            if self.transport.set_queue_vector(entry) == NO_VECTOR {
+                return Err(VirtioError::VectorRejected);
+            }
+            self.interrupt = InterruptMode::MsiX;
+            return Ok(());
+        }
+
+        match self.interrupt {
+            InterruptMode::Legacy { .. } => Ok(()),
+            _ if self.pci.interrupt_pin == 0 => Ok(()),
+            _ => {
+                let irq = self.pci.interrupt_line;
+                irq::register(irq, handler)?;
+                self.pci.set_legacy_interrupt(true);
+                self.interrupt = InterruptMode::Legacy { irq };
+                Ok(())
+            }
+        }
+    }
+
+    /// Reads and clears the ISR status, which deasserts the legacy interrupt.
+    pub fn acknowledge_interrupt(&self) -> u8 {
+        self.transport.isr_status()
+    }
+
+    /// Tells the device that the driver is ready, after which it may use the queues.
+    pub fn driver_ok(&self) {
+        self.transport
+            .set_status(self.transport.status() | STATUS_DRIVER_OK);
+    }
+
+    /// Tells the device that the driver has given up on it.
+    pub fn fail(&mut self) {
+        self.transport
+            .set_status(self.transport.status() | STATUS_FAILED);
+    }
+
+    /// Reads from the configuration of the device, retrying until it didn't change during the read.
+    fn read_config<T, F>(&self, read: F) -> Option<T>
+    where
+        F: Fn(&Transport) -> Option<T>,
+    {
+        loop {
+            let generation = self.transport.config_generation();
+            let value = read(&self.transport)?;
+            if self.transport.config_generation() == generation {
+                return Some(value);
+            }
+        }
+    }
+
+    pub fn read_config_u8(&self, offset: usize) -> Option<u8> {
+        self.read_config(|transport| transport.read_config_u8(offset))
+    }
+
+    pub fn read_config_u16(&self, offset: usize) -> Option<u16> {
+        self.read_config(|transport| transport.read_config_u16(offset))
+    }
+
+    pub fn read_config_u32(&self, offset: usize) -> Option<u32> {
+        self.read_config(|transport| transport.read_config_u32(offset))
+    }
+
+    /// Reads a 64-bit field of the configuration, which is accessed as two halves.
+    pub fn read_config_u64(&self, offset: usize) -> Option<u64> {
+        self.read_config(|transport| {
+            let low = transport.read_config_u32(offset)?;
+            let high = transport.read_config_u32(offset + 4)?;
+            Some((high as u64) << 32 | low as u64)
+        })
+    }
+
+    pub fn write_config_u8(&self, offset: usize, value: u8) {
+        self.transport.write_config_u8(offset, value);
+    }
+}
+
+pub(crate) fn init() -> Result<(), ()> {
+    block::init()
+}
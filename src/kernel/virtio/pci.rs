@@ -0,0 +1,461 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::ptr;
+
+use crate::kernel::arch;
+use crate::kernel::arch::port;
+use crate::kernel::arch::PhysAddr;
+use crate::kernel::pci::capability::Capability;
+use crate::kernel::pci::{Bar, PciDevice};
+
+use super::VirtioError;
+
+// The types of the vendor-specific capabilities, which locate the structures of the modern interface.
+const CFG_TYPE_COMMON: u8 = 1;
+const CFG_TYPE_NOTIFY: u8 = 2;
+const CFG_TYPE_ISR: u8 = 3;
+const CFG_TYPE_DEVICE: u8 = 4;
+
+// The fields of the vendor-specific capabilities, relative to their offset.
+const CAP_CFG_TYPE: u16 = 3;
+const CAP_BAR: u16 = 4;
+const CAP_OFFSET: u16 = 8;
+const CAP_LENGTH: u16 = 12;
+const CAP_NOTIFY_OFF_MULTIPLIER: u16 = 16;
+
+// The registers of the legacy interface, in the I/O space of the first BAR.
+const LEGACY_DEVICE_FEATURES: usize = 0x00;
+const LEGACY_DRIVER_FEATURES: usize = 0x04;
+const LEGACY_QUEUE_PFN: usize = 0x08;
+const LEGACY_QUEUE_SIZE: usize = 0x0C;
+const LEGACY_QUEUE_SELECT: usize = 0x0E;
+const LEGACY_QUEUE_NOTIFY: usize = 0x10;
+const LEGACY_DEVICE_STATUS: usize = 0x12;
+const LEGACY_ISR_STATUS: usize = 0x13;
+const LEGACY_CONFIG_VECTOR: usize = 0x14;
+const LEGACY_QUEUE_VECTOR: usize = 0x16;
+// The configuration of the device follows the registers, which are longer while MSI-X is enabled.
+const LEGACY_DEVICE_CONFIG: usize = 0x14;
+const LEGACY_DEVICE_CONFIG_MSI_X: usize = 0x18;
+/// The legacy interface takes the address of a queue as the number of its page.
+const LEGACY_QUEUE_PAGE_SHIFT: u64 = 12;
+
+// The registers of the common configuration of the modern interface.
+const DEVICE_FEATURE_SELECT: usize = 0x00;
+const DEVICE_FEATURE: usize = 0x04;
+const DRIVER_FEATURE_SELECT: usize = 0x08;
+const DRIVER_FEATURE: usize = 0x0C;
+const CONFIG_MSIX_VECTOR: usize = 0x10;
+const NUM_QUEUES: usize = 0x12;
+const DEVICE_STATUS: usize = 0x14;
+const CONFIG_GENERATION: usize = 0x15;
+const QUEUE_SELECT: usize = 0x16;
+const QUEUE_SIZE: usize = 0x18;
+const QUEUE_MSIX_VECTOR: usize = 0x1A;
+const QUEUE_ENABLE: usize = 0x1C;
+const QUEUE_NOTIFY_OFF: usize = 0x1E;
+const QUEUE_DESC: usize = 0x20;
+const QUEUE_DRIVER: usize = 0x28;
+const QUEUE_DEVICE: usize = 0x30;
+
+/// The size of the common configuration, which is mapped as a whole.
+const COMMON_CONFIG_SIZE: u32 = 0x38;
+
+const MSI_X_CONTROL: u16 = 0x02;
+const MSI_X_ENABLE: u16 = 1 << 15;
+
+/// A range of registers, either in the I/O space or in the memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Region {
+    Io(u16),
+    Memory(usize),
+}
+
+impl Region {
+    /// Locates a range of a BAR, mapping it if it lies beyond the direct mapping.
+    fn map(device: &PciDevice, bar: u8, offset: u32, len: u32) -> Option<Self> {
+        let bar = device.bars.get(bar as usize).copied().flatten()?;
+        if offset as u64 + len as u64 > bar.size() {
+            return None;
+        }
+
+        match bar {
+            Bar::Memory { addr, .. } => {
+                let addr = PhysAddr::new(addr + offset as u64);
+                let virt_addr = arch::paging::map_mmio(addr, len as usize)?;
+                Some(Region::Memory(virt_addr.as_u64() as usize))
+            }
+            Bar::Io { port, .. } => u16::try_from(port + offset).ok().map(Region::Io),
+        }
+    }
+
+    pub(super) fn read_u8(&self, offset: usize) -> u8 {
+        match *self {
+            Region::Io(base) => unsafe { port::read_u8(base + offset as u16) },
+            Region::Memory(base) => unsafe { ptr::read_volatile((base + offset) as *const u8) },
+        }
+    }
+
+    pub(super) fn read_u16(&self, offset: usize) -> u16 {
+        match *self {
+            Region::Io(base) => unsafe { port::read_u16(base + offset as u16) },
+            Region::Memory(base) => unsafe { ptr::read_volatile((base + offset) as *const u16) },
+        }
+    }
+
+    pub(super) fn read_u32(&self, offset: usize) -> u32 {
+        match *self {
+            Region::Io(base) => unsafe { port::read_u32(base + offset as u16) },
+            Region::Memory(base) => unsafe { ptr::read_volatile((base + offset) as *const u32) },
+        }
+    }
+
+    pub(super) fn write_u8(&self, offset: usize, value: u8) {
+        match *self {
+            Region::Io(base) => unsafe { port::write_u8(base + offset as u16, value) },
+            Region::Memory(base) => unsafe {
+                ptr::write_volatile((base + offset) as *mut u8, value)
+            },
+        }
+    }
+
+    pub(super) fn write_u16(&self, offset: usize, value: u16) {
+        match *self {
+            Region::Io(base) => unsafe { port::write_u16(base + offset as u16, value) },
+            Region::Memory(base) => unsafe {
+                ptr::write_volatile((base + offset) as *mut u16, value)
+            },
+        }
+    }
+
+    pub(super) fn write_u32(&self, offset: usize, value: u32) {
+        match *self {
+            Region::Io(base) => unsafe { port::write_u32(base + offset as u16, value) },
+            Region::Memory(base) => unsafe {
+                ptr::write_volatile((base + offset) as *mut u32, value)
+            },
+        }
+    }
+
+    /// Writes a 64-bit register of the modern interface, as two halves starting with the lower one.
+    fn write_u64(&self, offset: usize, value: u64) {
+        self.write_u32(offset, value as u32);
+        self.write_u32(offset + 4, (value >> 32) as u32);
+    }
+}
+
+/// The register through which the driver tells a device that a queue has new buffers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct Notifier {
+    region: Region,
+    offset: usize,
+    queue: u16,
+}
+
+impl Notifier {
+    pub(super) fn notify(&self) {
+        self.region.write_u16(self.offset, self.queue);
+    }
+}
+
+/// The interface of a device over PCI, which is either the legacy one of the devices predating version 1.0 of the
+/// specification, or the modern one which places its structures into BARs as described by its capabilities.
+///
+/// Spec: https://docs.oasis-open.org/virtio/virtio/v1.2/cs01/virtio-v1.2-cs01.html#x1-1150001
+#[derive(Clone, Copy, Debug)]
+pub(super) enum Transport {
+    Legacy {
+        io: Region,
+        device: PciDevice,
+    },
+    Modern {
+        common: Region,
+        notify: Region,
+        notify_off_multiplier: u32,
+        isr: Region,
+        device_config: Option<Region>,
+    },
+}
+
+impl Transport {
+    /// Finds the interface of a function, preferring the modern one of the transitional devices.
+    pub(super) fn probe(device: &PciDevice) -> Result<Self, VirtioError> {
+        if let Some(transport) = Self::probe_modern(device) {
+            return Ok(transport);
+        }
+
+        match device.bars[0] {
+            Some(Bar::Io { port, .. }) if super::is_legacy_device_id(device.device_id) => {
+                let port = u16::try_from(port).map_err(|_| VirtioError::Unsupported)?;
+                Ok(Transport::Legacy {
+                    io: Region::Io(port),
+                    device: *device,
+                })
+            }
+            _ => Err(VirtioError::Unsupported),
+        }
+    }
+
+    fn probe_modern(device: &PciDevice) -> Option<Self> {
+        let mut common = None;
+        let mut notify = None;
+        let mut isr = None;
+        let mut device_config = None;
+
+        // A structure may be described more than once, in which case the first one is preferred.
+        for capability in device.capabilities() {
+            let Capability::VendorSpecific { offset } = capability else {
+                continue;
+            };
+            let offset = offset as u16;
+            let addr = device.addr;
+            let bar = addr.read_u8(offset + CAP_BAR);
+            let bar_offset = addr.read_u32(offset + CAP_OFFSET);
+            let len = addr.read_u32(offset + CAP_LENGTH);
+
+            match addr.read_u8(offset + CAP_CFG_TYPE) {
+                CFG_TYPE_COMMON if common.is_none() && len >= COMMON_CONFIG_SIZE => {
+                    common = Region::map(device, bar, bar_offset, len);
+                }
+                CFG_TYPE_NOTIFY if notify.is_none() => {
+                    let multiplier = addr.read_u32(offset + CAP_NOTIFY_OFF_MULTIPLIER);
+                    notify = Region::map(device, bar, bar_offset, len)
+                        .map(|region| (region, multiplier));
+                }
+                CFG_TYPE_ISR if isr.is_none() => isr = Region::map(device, bar, bar_offset, len),
+                CFG_TYPE_DEVICE if device_config.is_none() => {
+                    device_config = Region::map(device, bar, bar_offset, len);
+                }
+                _ => {}
+            }
+        }
+
+        let (notify, notify_off_multiplier) = notify?;
+        Some(Transport::Modern {
+            common: common?,
+            notify,
+            notify_off_multiplier,
+            isr: isr?,
+            device_config,
+        })
+    }
+
+    pub(super) fn is_legacy(&self) -> bool {
+        matches!(self, Transport::Legacy { .. })
+    }
+
+    pub(super) fn status(&self) -> u8 {
+        match self {
+            Transport::Legacy { io, .. } => io.read_u8(LEGACY_DEVICE_STATUS),
+            Transport::Modern { common, .. } => common.read_u8(DEVICE_STATUS),
+        }
+    }
+
+    pub(super) fn set_status(&self, status: u8) {
+        match self {
+            Transport::Legacy { io, .. } => io.write_u8(LEGACY_DEVICE_STATUS, status),
+            Transport::Modern { common, .. } => common.write_u8(DEVICE_STATUS, status),
+        }
+    }
+
+    /// Resets the device, which the modern interface completes once the status reads back as zero.
+    pub(super) fn reset(&self) {
+        self.set_status(0);
+        while self.status() != 0 {
+            core::hint::spin_loop();
+        }
+    }
+
+    pub(super) fn device_features(&self) -> u64 {
+        match self {
+            // The legacy interface only has the lower 32 features.
+            Transport::Legacy { io, .. } => io.read_u32(LEGACY_DEVICE_FEATURES) as u64,
+            Transport::Modern { common, .. } => {
+                common.write_u32(DEVICE_FEATURE_SELECT, 0);
+                let low = common.read_u32(DEVICE_FEATURE);
+                common.write_u32(DEVICE_FEATURE_SELECT, 1);
+                let high = common.read_u32(DEVICE_FEATURE);
+                (high as u64) << 32 | low as u64
+            }
+        }
+    }
+
+    pub(super) fn set_driver_features(&self, features: u64) {
+        match self {
+            Transport::Legacy { io, .. } => io.write_u32(LEGACY_DRIVER_FEATURES, features as u32),
+            Transport::Modern { common, .. } => {
+                common.write_u32(DRIVER_FEATURE_SELECT, 0);
+                common.write_u32(DRIVER_FEATURE, features as u32);
+                common.write_u32(DRIVER_FEATURE_SELECT, 1);
+                common.write_u32(DRIVER_FEATURE, (features >> 32) as u32);
+            }
+        }
+    }
+
+    /// Reads the ISR status, which also deasserts the legacy interrupt.
+    pub(super) fn isr_status(&self) -> u8 {
+        match self {
+            Transport::Legacy { io, .. } => io.read_u8(LEGACY_ISR_STATUS),
+            Transport::Modern { isr, .. } => isr.read_u8(0),
+        }
+    }
+
+    /// Returns the number of queues, which only the modern interface tells.
+    pub(super) fn queue_count(&self) -> Option<u16> {
+        match self {
+            Transport::Legacy { .. } => None,
+            Transport::Modern { common, .. } => Some(common.read_u16(NUM_QUEUES)),
+        }
+    }
+
+    /// Selects a queue, and returns its maximum size, which is zero if the queue doesn't exist.
+    pub(super) fn select_queue(&self, index: u16) -> u16 {
+        match self {
+            Transport::Legacy { io, .. } => {
+                io.write_u16(LEGACY_QUEUE_SELECT, index);
+                io.read_u16(LEGACY_QUEUE_SIZE)
+            }
+            Transport::Modern { common, .. } => {
+                common.write_u16(QUEUE_SELECT, index);
+                common.read_u16(QUEUE_SIZE)
+            }
+        }
+    }
+
+    /// Checks whether the size of the selected queue can be changed, which the legacy interface doesn't allow.
+    pub(super) fn can_resize_queue(&self) -> bool {
+        !self.is_legacy()
+    }
+
+    /// Routes the interrupts of the selected queue to an entry of the MSI-X table, and returns the entry which the
+    /// device has accepted.
+    pub(super) fn set_queue_vector(&self, vector: u16) -> u16 {
+        match self {
+            Transport::Legacy { io, .. } => {
+                io.write_u16(LEGACY_QUEUE_VECTOR, vector);
+                io.read_u16(LEGACY_QUEUE_VECTOR)
+            }
+            Transport::Modern { common, .. } => {
+                common.write_u16(QUEUE_MSIX_VECTOR, vector);
+                common.read_u16(QUEUE_MSIX_VECTOR)
+            }
+        }
+    }
+
+    /// Routes the interrupts of the configuration changes to an entry of the MSI-X table.
+    pub(super) fn set_config_vector(&self, vector: u16) {
+        match self {
+            Transport::Legacy { io, .. } => io.write_u16(LEGACY_CONFIG_VECTOR, vector),
+            Transport::Modern { common, .. } => common.write_u16(CONFIG_MSIX_VECTOR, vector),
+        }
+    }
+
+    /// Hands the selected queue to the device, and returns the register through which it's notified.
+    ///
+    /// The parts of the queue are laid out as required by the legacy interface, i.e. after each other with the used
+    /// ring aligned to a page.
+    pub(super) fn activate_queue(
+        &self,
+        index: u16,
+        size: u16,
+        desc: u64,
+        avail: u64,
+        used: u64,
+    ) -> Notifier {
+        match *self {
+            Transport::Legacy { io, .. } => {
+                io.write_u32(LEGACY_QUEUE_PFN, (desc >> LEGACY_QUEUE_PAGE_SHIFT) as u32);
+                Notifier {
+                    region: io,
+                    offset: LEGACY_QUEUE_NOTIFY,
+                    queue: index,
+                }
+            }
+            Transport::Modern {
+                common,
+                notify,
+                notify_off_multiplier,
+                ..
+            } => {
+                common.write_u16(QUEUE_SIZE, size);
+                common.write_u64(QUEUE_DESC, desc);
+                common.write_u64(QUEUE_DRIVER, avail);
+                common.write_u64(QUEUE_DEVICE, used);
+                common.write_u16(QUEUE_ENABLE, 1);
+
+                let notify_off = common.read_u16(QUEUE_NOTIFY_OFF) as usize;
+                Notifier {
+                    region: notify,
+                    offset: notify_off * notify_off_multiplier as usize,
+                    queue: index,
+                }
+            }
+        }
+    }
+
+    /// Returns the configuration of the device, which may be missing from a modern device.
+    fn device_config(&self) -> Option<(Region, usize)> {
+        match self {
+            Transport::Legacy { io, device } => {
+                let msi_x_enabled = device.msi_x().is_some_and(|msi_x| {
+                    device.addr.read_u16(msi_x.offset as u16 + MSI_X_CONTROL) & MSI_X_ENABLE != 0
+                });
+                let offset = match msi_x_enabled {
+                    true => LEGACY_DEVICE_CONFIG_MSI_X,
+                    false => LEGACY_DEVICE_CONFIG,
+                };
+                Some((*io, offset))
+            }
+            Transport::Modern { device_config, .. } => device_config.map(|region| (region, 0)),
+        }
+    }
+
+    /// Returns the generation of the configuration, which changes whenever the device changes its configuration.
+    pub(super) fn config_generation(&self) -> u8 {
+        match self {
+            Transport::Legacy { .. } => 0,
+            Transport::Modern { common, .. } => common.read_u8(CONFIG_GENERATION),
+        }
+    }
+
+    pub(super) fn read_config_u8(&self, offset: usize) -> Option<u8> {
+        self.device_config()
+            .map(|(region, base)| region.read_u8(base + offset))
+    }
+
+    pub(super) fn read_config_u16(&self, offset: usize) -> Option<u16> {
+        self.device_config()
+            .map(|(region, base)| region.read_u16(base + offset))
+    }
+
+    pub(super) fn read_config_u32(&self, offset: usize) -> Option<u32> {
+        self.device_config()
+            .map(|(region, base)| region.read_u32(base + offset))
+    }
+
+    pub(super) fn write_config_u8(&self, offset: usize, value: u8) {
+        if let Some((region, base)) = self.device_config() {
+            region.write_u8(base + offset, value);
+        }
+    }
+}
@@ -0,0 +1,373 @@
+// MIT License
+//
+// Copyright (c) 2023 Mansoor Ahmed Memon.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{fence, Ordering};
+
+use crate::kernel::dma::DmaBuffer;
+
+use super::pci::Notifier;
+use super::VirtioError;
+
+/// The largest queue which is set up, as the state of the descriptors is kept in fixed-size arrays.
+pub const MAX_QUEUE_SIZE: u16 = 256;
+
+/// The alignment of the used ring, as required by the legacy interface.
+const QUEUE_ALIGN: usize = 4096;
+
+// The flags of a descriptor.
+const DESC_F_NEXT: u16 = 1 << 0;
+const DESC_F_WRITE: u16 = 1 << 1;
+
+/// Asks the device not to interrupt when it uses a buffer.
+const AVAIL_F_NO_INTERRUPT: u16 = 1 << 0;
+/// Tells the driver that the device doesn't need to be notified of new buffers.
+const USED_F_NO_NOTIFY: u16 = 1 << 0;
+
+// The offsets of the fields of the rings, which are followed by the event fields of `VIRTIO_F_EVENT_IDX`.
+const RING_FLAGS: usize = 0;
+const RING_IDX: usize = 2;
+const RING_ENTRIES: usize = 4;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct UsedElement {
+    id: u32,
+    len: u32,
+}
+
+/// A part of a request, which the device either reads or writes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Buffer {
+    pub addr: u64,
+    pub len: u32,
+    /// Whether the device writes to the buffer, which must follow all the buffers read by the device in a chain.
+    pub writable: bool,
+}
+
+impl Buffer {
+    pub fn readable(addr: u64, len: u32) -> Self {
+        Self {
+            addr,
+            len,
+            writable: false,
+        }
+    }
+
+    pub fn writable(addr: u64, len: u32) -> Self {
+        Self {
+            addr,
+            len,
+            writable: true,
+        }
+    }
+}
+
+/// A chain of buffers which the device has used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UsedChain {
+    /// The descriptor at the head of the chain, as returned when it was added.
+    pub head: u16,
+    /// The number of bytes which the device has written to the chain.
+    pub len: u32,
+}
+
+/// Split Virtqueue
+///
+/// The descriptors, the available ring written by the driver and the used ring written by the device are allocated
+/// together in DMA memory, laid out as the legacy interface requires. A request is passed as a chain of descriptors,
+/// which is identified by its head until the device hands it back through the used ring.
+///
+/// Spec: https://docs.oasis-open.org/virtio/virtio/v1.2/cs01/virtio-v1.2-cs01.html#x1-350007
+#[derive(Debug)]
+pub struct Virtqueue {
+    index: u16,
+    size: u16,
+    memory: DmaBuffer,
+    avail_offset: usize,
+    used_offset: usize,
+    notifier: Option<Notifier>,
+    /// Whether `VIRTIO_F_EVENT_IDX` has been negotiated, which replaces the flags of the rings with event indices.
+    event_idx: bool,
+    free_head: u16,
+    free_count: u16,
+    next_avail: u16,
+    /// The index of the available ring when the device was last notified.
+    notified_avail: u16,
+    last_used: u16,
+    /// The number of descriptors of each chain, by its head.
+    chain_len: [u16; MAX_QUEUE_SIZE as usize],
+}
+
+impl Virtqueue {
+    pub(super) fn new(index: u16, size: u16, event_idx: bool) -> Result<Self, VirtioError> {
+        if size == 0 || size > MAX_QUEUE_SIZE || !size.is_power_of_two() {
+            return Err(VirtioError::InvalidQueueSize);
+        }
+
+        let entries = size as usize;
+        let avail_offset = entries * mem::size_of::<Descriptor>();
+        let avail_len = RING_ENTRIES + entries * mem::size_of::<u16>() + mem::size_of::<u16>();
+        let used_offset = (avail_offset + avail_len).next_multiple_of(QUEUE_ALIGN);
+        let used_len =
+            RING_ENTRIES + entries * mem::size_of::<UsedElement>() + mem::size_of::<u16>();
+        let memory = DmaBuffer::allocate(used_offset + used_len).ok_or(VirtioError::NoMemory)?;
+
+        let queue = Self {
+            index,
+            size,
+            memory,
+            avail_offset,
+            used_offset,
+            notifier: None,
+            event_idx,
+            free_head: 0,
+            free_count: size,
+            next_avail: 0,
+            notified_avail: 0,
+            last_used: 0,
+            chain_len: [0; MAX_QUEUE_SIZE as usize],
+        };
+
+        // The free descriptors are linked through their next fields.
+        for id in 0..size {
+            unsafe { (*queue.descriptor(id)).next = id.wrapping_add(1) };
+        }
+
+        Ok(queue)
+    }
+
+    pub(super) fn set_notifier(&mut self, notifier: Notifier) {
+        self.notifier = Some(notifier);
+    }
+
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    /// Returns the number of descriptors which aren't part of a chain.
+    pub fn free_count(&self) -> u16 {
+        self.free_count
+    }
+
+    pub(super) fn desc_addr(&self) -> u64 {
+        self.memory.phys_addr()
+    }
+
+    pub(super) fn avail_addr(&self) -> u64 {
+        self.memory.phys_addr() + self.avail_offset as u64
+    }
+
+    pub(super) fn used_addr(&self) -> u64 {
+        self.memory.phys_addr() + self.used_offset as u64
+    }
+
+    fn descriptor(&self, id: u16) -> *mut Descriptor {
+        unsafe { self.memory.as_ptr::<Descriptor>().add(id as usize) }
+    }
+
+    fn avail(&self, offset: usize) -> *mut u16 {
+        unsafe { self.memory.as_ptr::<u8>().add(self.avail_offset + offset) as *mut u16 }
+    }
+
+    fn used(&self, offset: usize) -> *mut u16 {
+        unsafe { self.memory.as_ptr::<u8>().add(self.used_offset + offset) as *mut u16 }
+    }
+
+    /// The index up to which the device has used the chains, written by the device.
+    fn used_idx(&self) -> u16 {
+        unsafe { ptr::read_volatile(self.used(RING_IDX)) }
+    }
+
+    /// The index of the available ring at which the driver wants to be interrupted next (`used_event`).
+    fn set_used_event(&self, idx: u16) {
+        let offset = RING_ENTRIES + self.size as usize * mem::size_of::<u16>();
+        unsafe { ptr::write_volatile(self.avail(offset), idx) };
+    }
+
+    /// The index of the used ring at which the device wants to be notified next (`avail_event`).
+    fn avail_event(&self) -> u16 {
+        let offset = RING_ENTRIES + self.size as usize * mem::size_of::<UsedElement>();
+        unsafe { ptr::read_volatile(self.used(offset)) }
+    }
+
+    /// Makes a chain of the buffers available to the device, and returns the head which identifies it.
+    ///
+    /// The device is only told about the chain by `notify`, so that several chains can be added at once.
+    pub fn add(&mut self, buffers: &[Buffer]) -> Result<u16, VirtioError> {
+        if buffers.is_empty() {
+            return Err(VirtioError::EmptyChain);
+        }
+        if buffers.len() > self.free_count as usize {
+            return Err(VirtioError::QueueFull);
+        }
+
+        let head = self.free_head;
+        let mut id = head;
+        for (i, buffer) in buffers.iter().enumerate() {
+            let descriptor = unsafe { &mut *self.descriptor(id) };
+            let mut flags = if buffer.writable { DESC_F_WRITE } else { 0 };
+            if i + 1 < buffers.len() {
+                flags |= DESC_F_NEXT;
+            }
+
+            // The next field still links the free descriptors, which are taken in turn.
+            descriptor.addr = buffer.addr;
+            descriptor.len = buffer.len;
+            descriptor.flags = flags;
+            self.free_head = descriptor.next;
+            id = descriptor.next;
+        }
+        self.free_count -= buffers.len() as u16;
+        self.chain_len[head as usize] = buffers.len() as u16;
+
+        let slot = RING_ENTRIES + (self.next_avail % self.size) as usize * mem::size_of::<u16>();
+        unsafe { ptr::write_volatile(self.avail(slot), head) };
+
+        // The device must see the descriptors and the ring entry before the index which publishes them.
+        fence(Ordering::Release);
+        self.next_avail = self.next_avail.wrapping_add(1);
+        unsafe { ptr::write_volatile(self.avail(RING_IDX), self.next_avail) };
+
+        Ok(head)
+    }
+
+    /// Notifies the device of the chains added since the last notification, unless it has asked not to be.
+    pub fn notify(&mut self) {
+        // The index must be visible to the device before its suppression of notifications is checked.
+        fence(Ordering::SeqCst);
+
+        let old = self.notified_avail;
+        let new = self.next_avail;
+        self.notified_avail = new;
+        if old == new {
+            return;
+        }
+
+        let needed = match self.event_idx {
+            true => new.wrapping_sub(self.avail_event()).wrapping_sub(1) < new.wrapping_sub(old),
+            false => {
+                let flags = unsafe { ptr::read_volatile(self.used(RING_FLAGS)) };
+                flags & USED_F_NO_NOTIFY == 0
+            }
+        };
+        if needed {
+            if let Some(notifier) = self.notifier {
+                notifier.notify();
+            }
+        }
+    }
+
+    /// Checks whether the device has used chains which haven't been popped yet.
+    pub fn has_used(&self) -> bool {
+        self.used_idx() != self.last_used
+    }
+
+    /// Takes the next chain which the device has used, and frees its descriptors.
+    pub fn pop_used(&mut self) -> Option<UsedChain> {
+        if !self.has_used() {
+            return None;
+        }
+        // The element must not be read before the index which publishes it.
+        fence(Ordering::Acquire);
+
+        let slot =
+            RING_ENTRIES + (self.last_used % self.size) as usize * mem::size_of::<UsedElement>();
+        let element = unsafe { ptr::read_volatile(self.used(slot) as *const UsedElement) };
+        self.last_used = self.last_used.wrapping_add(1);
+
+        let head = element.id as u16;
+        if head >= self.size || self.chain_len[head as usize] == 0 {
+            log::warn!(
+                "virtqueue {}: device used an unknown chain {}",
+                self.index,
+                head
+            );
+            return None;
+        }
+
+        // The descriptors of the chain are returned to the front of the free list.
+        let len = mem::take(&mut self.chain_len[head as usize]);
+        let mut tail = head;
+        for _ in 1..len {
+            tail = unsafe { (*self.descriptor(tail)).next };
+        }
+        unsafe { (*self.descriptor(tail)).next = self.free_head };
+        self.free_head = head;
+        self.free_count += len;
+
+        if self.event_idx {
+            self.set_used_event(self.last_used);
+        }
+
+        Some(UsedChain {
+            head,
+            len: element.len,
+        })
+    }
+
+    /// Asks the device to interrupt when it uses a chain, and returns whether it has used any in the meantime.
+    ///
+    /// A chain used before interrupts were enabled doesn't raise one, so the caller must pop it itself.
+    pub fn enable_interrupts(&mut self) -> bool {
+        match self.event_idx {
+            true => self.set_used_event(self.last_used),
+            false => {
+                let flags = unsafe { ptr::read_volatile(self.avail(RING_FLAGS)) };
+                unsafe {
+                    ptr::write_volatile(self.avail(RING_FLAGS), flags & !AVAIL_F_NO_INTERRUPT)
+                };
+            }
+        }
+        fence(Ordering::SeqCst);
+
+        self.has_used()
+    }
+
+    /// Asks the device not to interrupt, which it may still do as the request is only a hint.
+    pub fn disable_interrupts(&mut self) {
+        match self.event_idx {
+            // The interrupt is postponed until the index wraps around.
+            true => self.set_used_event(self.last_used.wrapping_sub(1)),
+            false => {
+                let flags = unsafe { ptr::read_volatile(self.avail(RING_FLAGS)) };
+                unsafe {
+                    ptr::write_volatile(self.avail(RING_FLAGS), flags | AVAIL_F_NO_INTERRUPT)
+                };
+            }
+        }
+    }
+}